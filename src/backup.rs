@@ -0,0 +1,504 @@
+use crate::{
+    checkpoint,
+    file::{
+        copy_file, copy_file_or_folder, copy_folder, is_segment_sidecar, remove_file_or_folder,
+        rename_file_or_folder, rewrite_atomic, BACKUPS_FOLDER, BACKUP_MANIFEST_FILE, BLOBS_FOLDER,
+        JOURNALS_FOLDER, LSM_MARKER, SEGMENTS_FOLDER, SNAPSHOTS_FILE,
+    },
+    levels::manifest,
+    time::unix_timestamp_millis,
+    Tree,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Prefix of the temporary checkpoint folders that backups are copied from
+const CHECKPOINT_TEMP_PREFIX: &str = ".checkpoint";
+
+/// Unique backup ID, increasing with every backup
+pub type BackupId = u64;
+
+/// Describes a finished backup
+///
+/// The info is stored as the manifest of the backup, so a
+/// backup without a manifest is incomplete and never used.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BackupInfo {
+    /// Backup ID
+    pub id: BackupId,
+
+    /// Creation time as unix timestamp (in ms)
+    pub timestamp: u64,
+
+    /// IDs of the segments that are part of the backup
+    pub segment_ids: Vec<Arc<str>>,
+
+    /// IDs of the blob files that are part of the backup
+    pub blob_ids: Vec<Arc<str>>,
+}
+
+/// Creates incremental backups of trees, and restores them
+///
+/// Segments and blob files are immutable and have unique IDs,
+/// so they are shared between all backups of a backup folder:
+/// every backup only copies the files that are not already backed up.
+///
+/// The backup folder looks like this:
+///
+/// ```text
+/// segments/<segment id>           shared segments
+/// blobs/<blob id>                 shared blob files
+/// backups/<backup id>/backup.json backup manifest
+/// backups/<backup id>/...         journals, levels manifest & snapshots of the backup
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use lsm_tree::{BackupEngine, Config};
+///
+/// let tree = Config::new(folder.path().join("tree")).open()?;
+/// tree.insert("a", "abc")?;
+///
+/// let engine = BackupEngine::open(folder.path().join("backup"))?;
+/// let backup = engine.create_backup(&tree)?;
+///
+/// engine.restore_backup(backup.id, folder.path().join("restored"))?;
+///
+/// let restored = Config::new(folder.path().join("restored")).open()?;
+/// assert!(restored.contains_key("a")?);
+/// #
+/// # Ok::<(), lsm_tree::Error>(())
+/// ```
+pub struct BackupEngine {
+    path: PathBuf,
+}
+
+impl BackupEngine {
+    /// Opens a backup folder, creating it if it does not exist
+    ///
+    /// Leftovers of incomplete backups are deleted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn open<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        log::info!("Opening backup folder at {}", path.display());
+
+        std::fs::create_dir_all(path.join(SEGMENTS_FOLDER))?;
+        std::fs::create_dir_all(path.join(BLOBS_FOLDER))?;
+        std::fs::create_dir_all(path.join(BACKUPS_FOLDER))?;
+
+        let engine = Self {
+            path: path.to_path_buf(),
+        };
+
+        for dirent in std::fs::read_dir(path.join(BACKUPS_FOLDER))? {
+            let dirent = dirent?;
+
+            if !dirent.path().join(BACKUP_MANIFEST_FILE).exists() {
+                log::debug!("Deleting incomplete backup {}", dirent.path().display());
+                std::fs::remove_dir_all(dirent.path())?;
+            }
+        }
+
+        for dirent in std::fs::read_dir(path)? {
+            let dirent = dirent?;
+
+            if dirent
+                .file_name()
+                .to_string_lossy()
+                .starts_with(CHECKPOINT_TEMP_PREFIX)
+            {
+                log::debug!("Deleting leftover checkpoint {}", dirent.path().display());
+                std::fs::remove_dir_all(dirent.path())?;
+            }
+        }
+
+        engine.delete_unreferenced_files()?;
+
+        Ok(engine)
+    }
+
+    /// Creates a new backup of the tree
+    ///
+    /// Only segments and blob files that are not already part of
+    /// the backup folder are copied.
+    ///
+    /// The backup is taken from a temporary checkpoint (see [`Tree::checkpoint`]) in the
+    /// backup folder, which is cheap if the backup folder is on the same file system as the tree.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn create_backup(&self, tree: &Tree) -> crate::Result<BackupInfo> {
+        let start = std::time::Instant::now();
+
+        let id = self.allocate_backup_id()?;
+
+        log::info!("Creating backup {id} in {}", self.path.display());
+
+        // NOTE: A checkpoint only holds the tree's locks while hard linking,
+        // so the (slow) copying does not stall writes
+        let temp_folder = tempfile::Builder::new()
+            .prefix(CHECKPOINT_TEMP_PREFIX)
+            .tempdir_in(&self.path)?;
+        let src = temp_folder.path().join("checkpoint");
+        let segments = checkpoint::create(tree, &src)?;
+
+        let mut segment_ids = Vec::with_capacity(segments.len());
+        let mut blob_ids = HashSet::new();
+
+        for segment in &segments {
+            let segment_id = &segment.metadata.id;
+            segment_ids.push(segment_id.clone());
+
+            // NOTE: Only blob files referenced by segments are backed up,
+            // unreferenced blob files may still be written to by a flush
+            blob_ids.extend(segment.metadata.blob_refs.keys().cloned());
+
+            let dest = self.path.join(SEGMENTS_FOLDER).join(&**segment_id);
+
+            if dest.exists() {
+                log::trace!("backup: segment {segment_id} is already backed up");
+                continue;
+            }
+
+            log::trace!("backup: copying segment {segment_id}");
+
+            // NOTE: Copy into a temporary folder first, so a partially copied
+            // segment is never mistaken for a backed up one
+            let temp_segment_folder = tempfile::tempdir_in(self.path.join(SEGMENTS_FOLDER))?;
+            let temp_segment_path = temp_segment_folder.path().join("segment");
+            copy_file_or_folder(
+                &src.join(SEGMENTS_FOLDER).join(&**segment_id),
+                &temp_segment_path,
+            )?;
+            rename_file_or_folder(&temp_segment_path, &dest)?;
+        }
+
+        let mut blob_ids = blob_ids.into_iter().collect::<Vec<_>>();
+        blob_ids.sort();
+
+        for blob_id in &blob_ids {
+            let dest = self.path.join(BLOBS_FOLDER).join(&**blob_id);
+
+            if dest.exists() {
+                log::trace!("backup: blob file {blob_id} is already backed up");
+                continue;
+            }
+
+            log::trace!("backup: copying blob file {blob_id}");
+
+            let temp_blob_folder = tempfile::tempdir_in(self.path.join(BLOBS_FOLDER))?;
+            let temp_blob_path = temp_blob_folder.path().join("blob");
+            copy_file(&src.join(BLOBS_FOLDER).join(&**blob_id), &temp_blob_path)?;
+            std::fs::rename(&temp_blob_path, &dest)?;
+        }
+
+        let backup_folder = self.backup_folder(id);
+
+        copy_journals(
+            &src.join(JOURNALS_FOLDER),
+            &backup_folder.join(JOURNALS_FOLDER),
+        )?;
+
+        manifest::copy(&src, &backup_folder)?;
+
+        if src.join(SNAPSHOTS_FILE).exists() {
+            copy_file(
+                &src.join(SNAPSHOTS_FILE),
+                &backup_folder.join(SNAPSHOTS_FILE),
+            )?;
+        }
+
+        copy_file(&src.join(LSM_MARKER), &backup_folder.join(LSM_MARKER))?;
+
+        drop(temp_folder);
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            // fsync folders on Unix
+            for folder in [
+                self.path.join(SEGMENTS_FOLDER),
+                self.path.join(BLOBS_FOLDER),
+                backup_folder.clone(),
+            ] {
+                File::open(folder)?.sync_all()?;
+            }
+        }
+
+        let info = BackupInfo {
+            id,
+            timestamp: unix_timestamp_millis(),
+            segment_ids,
+            blob_ids,
+        };
+
+        // NOTE: Lastly, write the manifest
+        // -> the backup is complete
+        let json = serde_json::to_string_pretty(&info).map_err(std::io::Error::from)?;
+        rewrite_atomic(backup_folder.join(BACKUP_MANIFEST_FILE), json.as_bytes())?;
+
+        log::info!("Created backup {id} in {}s", start.elapsed().as_secs_f32());
+
+        Ok(info)
+    }
+
+    /// Allocates the next backup ID by creating its (incomplete) backup folder
+    ///
+    /// Creating the folder fails if it already exists, so concurrent backups never get the same ID.
+    fn allocate_backup_id(&self) -> crate::Result<BackupId> {
+        let mut id = self
+            .list_backups()?
+            .last()
+            .map_or(0, |backup| backup.id + 1);
+
+        loop {
+            match std::fs::create_dir(self.backup_folder(id)) {
+                Ok(()) => return Ok(id),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => id += 1,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Returns all (complete) backups, sorted by ID
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn list_backups(&self) -> crate::Result<Vec<BackupInfo>> {
+        let mut backups = vec![];
+
+        for dirent in std::fs::read_dir(self.path.join(BACKUPS_FOLDER))? {
+            let dirent = dirent?;
+
+            match std::fs::read_to_string(dirent.path().join(BACKUP_MANIFEST_FILE)) {
+                Ok(json) => {
+                    let info: BackupInfo =
+                        serde_json::from_str(&json).map_err(std::io::Error::from)?;
+                    backups.push(info);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        backups.sort_by_key(|backup| backup.id);
+
+        Ok(backups)
+    }
+
+    /// Deletes a backup
+    ///
+    /// Returns `true` if the backup existed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn delete_backup(&self, id: BackupId) -> crate::Result<bool> {
+        let backup_folder = self.backup_folder(id);
+
+        if !backup_folder.exists() {
+            return Ok(false);
+        }
+
+        log::info!("Deleting backup {id}");
+
+        // NOTE: Delete the manifest first, so the backup is never partially used
+        std::fs::remove_file(backup_folder.join(BACKUP_MANIFEST_FILE))?;
+        std::fs::remove_dir_all(&backup_folder)?;
+
+        self.delete_unreferenced_files()?;
+
+        Ok(true)
+    }
+
+    /// Deletes the oldest backups, so only the latest `keep` backups are retained
+    ///
+    /// Returns the amount of deleted backups.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn purge_old_backups(&self, keep: usize) -> crate::Result<usize> {
+        let backups = self.list_backups()?;
+        let purge_count = backups.len().saturating_sub(keep);
+
+        for backup in backups.iter().take(purge_count) {
+            log::info!("Purging backup {}", backup.id);
+
+            let backup_folder = self.backup_folder(backup.id);
+            std::fs::remove_file(backup_folder.join(BACKUP_MANIFEST_FILE))?;
+            std::fs::remove_dir_all(&backup_folder)?;
+        }
+
+        if purge_count > 0 {
+            self.delete_unreferenced_files()?;
+        }
+
+        Ok(purge_count)
+    }
+
+    /// Restores a backup into the given (new) folder
+    ///
+    /// The restored tree can then be opened using [`crate::Config::open`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, the backup does not exist, or the folder already exists.
+    pub fn restore_backup<P: AsRef<Path>>(&self, id: BackupId, path: P) -> crate::Result<()> {
+        let dest = path.as_ref();
+        let backup_folder = self.backup_folder(id);
+
+        let json = std::fs::read_to_string(backup_folder.join(BACKUP_MANIFEST_FILE))?;
+        let info: BackupInfo = serde_json::from_str(&json).map_err(std::io::Error::from)?;
+
+        log::info!("Restoring backup {id} into {}", dest.display());
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::create_dir(dest)?;
+
+        let segments_folder = dest.join(SEGMENTS_FOLDER);
+        std::fs::create_dir_all(&segments_folder)?;
+
+        for segment_id in &info.segment_ids {
+            log::trace!("restore: copying segment {segment_id}");
+
+            copy_file_or_folder(
+                &self.path.join(SEGMENTS_FOLDER).join(&**segment_id),
+                &segments_folder.join(&**segment_id),
+            )?;
+        }
+
+        let blobs_folder = dest.join(BLOBS_FOLDER);
+        std::fs::create_dir_all(&blobs_folder)?;
+
+        for blob_id in &info.blob_ids {
+            log::trace!("restore: copying blob file {blob_id}");
+
+            copy_file(
+                &self.path.join(BLOBS_FOLDER).join(&**blob_id),
+                &blobs_folder.join(&**blob_id),
+            )?;
+        }
+
+        copy_journals(
+            &backup_folder.join(JOURNALS_FOLDER),
+            &dest.join(JOURNALS_FOLDER),
+        )?;
+
+        manifest::copy(&backup_folder, dest)?;
+
+        if backup_folder.join(SNAPSHOTS_FILE).exists() {
+            copy_file(
+                &backup_folder.join(SNAPSHOTS_FILE),
+                &dest.join(SNAPSHOTS_FILE),
+            )?;
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            // fsync folders on Unix
+            for folder in [&segments_folder, &blobs_folder, &dest.join(JOURNALS_FOLDER)] {
+                File::open(folder)?.sync_all()?;
+            }
+        }
+
+        // NOTE: Lastly, copy .lsm marker, which contains the version
+        // -> the restored tree is fully initialized
+        copy_file(&backup_folder.join(LSM_MARKER), &dest.join(LSM_MARKER))?;
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            let folder = File::open(dest)?;
+            folder.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    fn backup_folder(&self, id: BackupId) -> PathBuf {
+        self.path.join(BACKUPS_FOLDER).join(id.to_string())
+    }
+
+    /// Deletes shared segments and blob files that are not part of any backup
+    fn delete_unreferenced_files(&self) -> crate::Result<()> {
+        let backups = self.list_backups()?;
+
+        let segment_ids = backups
+            .iter()
+            .flat_map(|backup| backup.segment_ids.iter())
+            .map(|id| &**id)
+            .collect::<HashSet<_>>();
+
+        let blob_ids = backups
+            .iter()
+            .flat_map(|backup| backup.blob_ids.iter())
+            .map(|id| &**id)
+            .collect::<HashSet<_>>();
+
+        for dirent in std::fs::read_dir(self.path.join(SEGMENTS_FOLDER))? {
+            let dirent = dirent?;
+            let path = dirent.path();
+
+            // NOTE: Metadata sidecars are deleted together with their segment file
+            if is_segment_sidecar(&path) {
+                let segment_id = path.file_stem().unwrap_or_default().to_string_lossy();
+
+                if !segment_ids.contains(&*segment_id) && path.try_exists()? {
+                    std::fs::remove_file(&path)?;
+                }
+
+                continue;
+            }
+
+            if !segment_ids.contains(&*dirent.file_name().to_string_lossy()) {
+                log::debug!("Deleting unreferenced segment {}", path.display());
+                remove_file_or_folder(&path)?;
+            }
+        }
+
+        for dirent in std::fs::read_dir(self.path.join(BLOBS_FOLDER))? {
+            let dirent = dirent?;
+
+            if !blob_ids.contains(&*dirent.file_name().to_string_lossy()) {
+                log::debug!(
+                    "Deleting unreferenced blob file {}",
+                    dirent.path().display()
+                );
+
+                if dirent.file_type()?.is_dir() {
+                    std::fs::remove_dir_all(dirent.path())?;
+                } else {
+                    std::fs::remove_file(dirent.path())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Copies all journal folders of a tree (or backup)
+fn copy_journals(src: &Path, dest: &Path) -> crate::Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for dirent in std::fs::read_dir(src)? {
+        let dirent = dirent?;
+        copy_folder(&dirent.path(), &dest.join(dirent.file_name()))?;
+    }
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use crate::{
+    journal::shard::JournalShard,
     value::{UserData, UserKey, ValueType},
     Tree, Value,
 };
@@ -19,14 +20,17 @@ impl Batch {
         }
     }
 
+    /// Creates a write batch from items of another tree, keeping their value types
+    pub(crate) fn from_items(tree: Tree, items: Vec<Value>) -> Self {
+        Self { data: items, tree }
+    }
+
     /// Inserts a key-value pair into the batch
     pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) {
-        self.data.push(Value::new(
-            key.as_ref(),
-            value.as_ref(),
-            0,
-            ValueType::Value,
-        ));
+        self.data.push(
+            Value::new(key.as_ref(), value.as_ref(), 0, ValueType::Value)
+                .expires_at(self.tree.default_expiry()),
+        );
     }
 
     /// Adds a tombstone marker for a key
@@ -40,8 +44,30 @@ impl Batch {
     /// # Errors
     ///
     /// Will return `Err` if an IO error occurs.
-    pub fn commit(mut self) -> crate::Result<()> {
-        let mut shard = self.tree.journal.lock_shard();
+    pub fn commit(self) -> crate::Result<()> {
+        let tree = self.tree.clone();
+        tree.check_writable()?;
+
+        let mut shard = tree.journal.lock_shard();
+        let memtable_size = self.write_to_shard(&mut shard)?;
+        drop(shard);
+
+        if memtable_size > tree.config.max_memtable_size {
+            log::debug!("Memtable reached threshold size");
+            crate::flush::start(&tree)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the batch into the given (locked) journal shard, and applies it to the memtable
+    ///
+    /// Returns the size of the active memtable before applying the batch,
+    /// so the caller can start a flush after releasing the journal lock.
+    pub(crate) fn write_to_shard(mut self, shard: &mut JournalShard) -> crate::Result<u32> {
+        if self.data.is_empty() {
+            return Ok(0);
+        }
 
         // NOTE: Fully (write) lock, so the batch can be committed atomically
         let memtable_lock = self.tree.active_memtable.write().expect("lock is poisoned");
@@ -56,6 +82,8 @@ impl Batch {
         let bytes_written_to_disk = shard.write_batch(&items)?;
         shard.flush()?;
 
+        self.tree.journal.changes.publish(&items);
+
         // NOTE: Add some pointers to better approximate memory usage of memtable
         // Because the data is stored with less overhead than in memory
         let size = bytes_written_to_disk
@@ -72,13 +100,7 @@ impl Batch {
         }
 
         drop(memtable_lock);
-        drop(shard);
 
-        if memtable_size > self.tree.config.max_memtable_size {
-            log::debug!("Memtable reached threshold size");
-            crate::flush::start(&self.tree)?;
-        }
-
-        Ok(())
+        Ok(memtable_size)
     }
 }
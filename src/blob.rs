@@ -0,0 +1,656 @@
+use crate::{
+    descriptor_table::FileDescriptorTable,
+    id::generate_segment_id,
+    levels::Levels,
+    serde::{read_exact_len, Deserializable, DeserializeError, Serializable, SerializeError},
+    value::{UserData, UserKey, ValueType},
+    varint::{read_varint, varint_len, write_varint},
+    version::Version,
+    Value,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+/// Points to a value that is stored in a blob file
+///
+/// # Disk representation
+///
+/// \[blob id length; 1 byte] \[blob id; N bytes] \[offset; 8 bytes] \[value size; 4 bytes]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlobPointer {
+    /// ID of the blob file
+    pub blob_id: Arc<str>,
+
+    /// Position of the blob entry in the blob file
+    pub offset: u64,
+
+    /// Size of the value
+    pub size: u32,
+}
+
+impl BlobPointer {
+    /// Parses the blob pointer that is stored as value of an indirection
+    pub fn from_value(item: &Value) -> crate::Result<Self> {
+        Ok(Self::deserialize(&mut &*item.value)?)
+    }
+
+    /// Returns the size of the blob entry the pointer points to, in bytes
+    ///
+    /// For blob files written before [`Version::V1`], this is off by a byte,
+    /// which does not matter for estimating the amount of stale data.
+    pub fn entry_size(&self, key: &[u8]) -> u64 {
+        entry_size(key.len(), self.size)
+    }
+
+    /// Serializes the blob pointer, so it can be stored as value of an indirection
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(1 + self.blob_id.len() + 8 + 4);
+        self.serialize(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Serializable for BlobPointer {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        // NOTE: IDs are way shorter than 256 characters
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_u8(self.blob_id.len() as u8)?;
+        writer.write_all(self.blob_id.as_bytes())?;
+        writer.write_u64::<BigEndian>(self.offset)?;
+        writer.write_u32::<BigEndian>(self.size)?;
+        Ok(())
+    }
+}
+
+impl Deserializable for BlobPointer {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        let id_len = reader.read_u8()?;
+        let id = read_exact_len(reader, id_len.into())?;
+        let blob_id = String::from_utf8_lossy(&id).into();
+
+        let offset = reader.read_u64::<BigEndian>()?;
+        let size = reader.read_u32::<BigEndian>()?;
+
+        Ok(Self {
+            blob_id,
+            offset,
+            size,
+        })
+    }
+}
+
+/// Writes values into a new blob file
+///
+/// # Disk representation
+///
+/// A blob file is a version header, followed by a list of entries:
+///
+/// \[key length; varint] \[key; N bytes] \[value length; 4 bytes] \[value; N bytes]
+///
+/// Blob files written before [`Version::V1`] have no header and store the key length as 2 bytes.
+pub struct BlobWriter {
+    pub id: Arc<str>,
+    writer: BufWriter<File>,
+    file_pos: u64,
+}
+
+impl BlobWriter {
+    /// Appends a value to the blob file, returning a pointer to it
+    pub fn write(&mut self, key: &[u8], value: &[u8]) -> crate::Result<BlobPointer> {
+        let offset = self.file_pos;
+
+        // NOTE: Values are limited to 2^32 bytes (see `Value::new`)
+        #[allow(clippy::cast_possible_truncation)]
+        let value_len = value.len() as u32;
+
+        write_varint(&mut self.writer, key.len() as u64)?;
+        self.writer.write_all(key)?;
+        self.writer.write_u32::<BigEndian>(value_len)?;
+        self.writer.write_all(value)?;
+
+        self.file_pos += entry_size(key.len(), value_len);
+
+        Ok(BlobPointer {
+            blob_id: self.id.clone(),
+            offset,
+            size: value_len,
+        })
+    }
+
+    /// Finishes the blob file, making sure all data is written durably
+    ///
+    /// Returns the blob file ID and its size in bytes
+    pub fn finish(mut self) -> crate::Result<(Arc<str>, u64)> {
+        self.writer.flush()?;
+        self.writer.get_mut().sync_all()?;
+        Ok((self.id, self.file_pos))
+    }
+}
+
+/// Reads all entries of a blob file
+pub struct BlobReader {
+    blob_id: Arc<str>,
+    version: Version,
+    reader: BufReader<File>,
+    file_pos: u64,
+    file_size: u64,
+}
+
+impl Iterator for BlobReader {
+    type Item = crate::Result<(BlobPointer, UserKey, UserData)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.file_pos >= self.file_size {
+            return None;
+        }
+
+        let offset = self.file_pos;
+
+        let entry = read_entry(&mut self.reader, self.version).map(|(key, value)| {
+            // NOTE: The value length was read as u32
+            #[allow(clippy::cast_possible_truncation)]
+            let size = value.len() as u32;
+
+            self.file_pos += if self.version < Version::V1 {
+                2 + key.len() as u64 + 4 + u64::from(size)
+            } else {
+                entry_size(key.len(), size)
+            };
+
+            let pointer = BlobPointer {
+                blob_id: self.blob_id.clone(),
+                offset,
+                size,
+            };
+
+            (pointer, key, value)
+        });
+
+        if entry.is_err() {
+            // NOTE: Don't try to read past a broken entry
+            self.file_pos = self.file_size;
+        }
+
+        Some(entry)
+    }
+}
+
+/// Returns the size of a blob entry in bytes
+fn entry_size(key_len: usize, value_len: u32) -> u64 {
+    varint_len(key_len as u64) as u64 + key_len as u64 + 4 + u64::from(value_len)
+}
+
+/// Reads the version header of a blob file, leaving the reader positioned after it
+///
+/// Blob files written before [`Version::V1`] have no header, in which case the reader is rewound.
+fn read_version<R: Read + Seek>(reader: &mut R) -> crate::Result<Version> {
+    let mut header = [0; 5];
+
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    if let Some(version) = Version::parse_file_header(&header) {
+        Ok(version)
+    } else {
+        reader.seek(SeekFrom::Start(0))?;
+        Ok(Version::V0)
+    }
+}
+
+/// Reads a blob entry in the disk representation of the given blob file version
+fn read_entry<R: Read>(reader: &mut R, version: Version) -> crate::Result<(UserKey, UserData)> {
+    let key_len = if version < Version::V1 {
+        reader.read_u16::<BigEndian>()?.into()
+    } else {
+        read_varint(reader)?
+    };
+    let key = read_exact_len(reader, key_len)?;
+
+    let value_len = reader.read_u32::<BigEndian>()?;
+    let value = read_exact_len(reader, value_len.into())?;
+
+    Ok((key.into(), value.into()))
+}
+
+/// Keeps track of the blob files of a tree
+///
+/// Blob files are referenced by segments (see `Metadata::blob_refs`), so the amount of
+/// live data in a blob file is known from the segments, without having to scan the blob file.
+///
+/// Only registered blob files may be deleted, so blob files that are still being
+/// written to (because their segment does not exist yet) are never deleted.
+pub struct BlobStore {
+    folder: PathBuf,
+
+    /// Registered blob files and their size in bytes
+    files: RwLock<HashMap<Arc<str>, u64>>,
+
+    /// Open blob files, so point reads do not need to open the file every time
+    readers: RwLock<HashMap<Arc<str>, Arc<BlobFileReader>>>,
+
+    /// Blob files whose values are moved into new blob files when compacted,
+    /// and the amount of value bytes that were moved so far
+    relocating: RwLock<HashMap<Arc<str>, u64>>,
+}
+
+/// Open blob file
+struct BlobFileReader {
+    descriptor_table: FileDescriptorTable,
+    version: Version,
+}
+
+// NOTE: Locks are only poisoned if another thread panicked while holding them
+#[allow(clippy::expect_used)]
+impl BlobStore {
+    fn from_folder(folder: PathBuf) -> Self {
+        Self {
+            folder,
+            files: RwLock::default(),
+            readers: RwLock::default(),
+            relocating: RwLock::default(),
+        }
+    }
+
+    /// Creates a new blob store in the given folder
+    pub fn create_new<P: Into<PathBuf>>(folder: P) -> crate::Result<Self> {
+        let folder = folder.into();
+        std::fs::create_dir_all(&folder)?;
+
+        Ok(Self::from_folder(folder))
+    }
+
+    /// Recovers all blob files from the given folder
+    pub fn recover<P: Into<PathBuf>>(folder: P) -> crate::Result<Self> {
+        let folder = folder.into();
+        std::fs::create_dir_all(&folder)?;
+
+        Self::recover_read_only(folder)
+    }
+
+    /// Recovers all blob files from the given folder, without creating it if it does not exist
+    pub fn recover_read_only<P: Into<PathBuf>>(folder: P) -> crate::Result<Self> {
+        let store = Self::from_folder(folder.into());
+        store.reload()?;
+
+        Ok(store)
+    }
+
+    /// Rescans the blob folder, replacing all registered blob files
+    ///
+    /// Used by read-only trees to pick up blob files written by another process.
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn reload(&self) -> crate::Result<()> {
+        let mut files = HashMap::new();
+
+        if self.folder.exists() {
+            for dirent in std::fs::read_dir(&self.folder)? {
+                let dirent = dirent?;
+                let blob_id: Arc<str> = dirent.file_name().to_string_lossy().into();
+                let size = dirent.metadata()?.len();
+
+                log::debug!("Recovered blob file {blob_id} ({size} bytes)");
+                files.insert(blob_id, size);
+            }
+        }
+
+        self.readers
+            .write()
+            .expect("lock is poisoned")
+            .retain(|id, _| files.contains_key(id));
+
+        *self.files.write().expect("lock is poisoned") = files;
+
+        Ok(())
+    }
+
+    /// Starts a new blob file
+    pub fn writer(&self) -> crate::Result<BlobWriter> {
+        let id = generate_segment_id();
+        let mut writer = BufWriter::new(File::create(self.folder.join(&*id))?);
+        let header_len = Version::V1.write_file_header(&mut writer)?;
+
+        Ok(BlobWriter {
+            id,
+            writer,
+            file_pos: header_len as u64,
+        })
+    }
+
+    /// Marks a blob file as referenced by a segment
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn register(&self, blob_id: Arc<str>, size: u64) {
+        self.files
+            .write()
+            .expect("lock is poisoned")
+            .insert(blob_id, size);
+    }
+
+    /// Returns the amount of blob files
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn len(&self) -> usize {
+        self.files.read().expect("lock is poisoned").len()
+    }
+
+    /// Returns the disk space used by blob files in bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn disk_space(&self) -> u64 {
+        self.files.read().expect("lock is poisoned").values().sum()
+    }
+
+    /// Returns the open blob file, opening it if needed
+    fn get_reader(&self, blob_id: &Arc<str>) -> crate::Result<Arc<BlobFileReader>> {
+        if let Some(reader) = self.readers.read().expect("lock is poisoned").get(blob_id) {
+            return Ok(reader.clone());
+        }
+
+        let path = self.folder.join(&**blob_id);
+        let version = read_version(&mut BufReader::new(File::open(&path)?))?;
+
+        let reader = Arc::new(BlobFileReader {
+            descriptor_table: FileDescriptorTable::new(&path)?,
+            version,
+        });
+
+        Ok(self
+            .readers
+            .write()
+            .expect("lock is poisoned")
+            .entry(blob_id.clone())
+            .or_insert(reader)
+            .clone())
+    }
+
+    /// Reads the value a pointer points to
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn get(&self, pointer: &BlobPointer) -> crate::Result<UserData> {
+        let reader = self.get_reader(&pointer.blob_id)?;
+
+        let mut file = reader.descriptor_table.access();
+        file.seek(SeekFrom::Start(pointer.offset))?;
+
+        let (_, value) = read_entry(&mut BufReader::new(&mut *file), reader.version)?;
+        drop(file);
+
+        Ok(value)
+    }
+
+    /// Replaces an indirection by the value it points to
+    pub fn resolve(&self, item: Value) -> crate::Result<Value> {
+        if item.value_type != ValueType::Indirection {
+            return Ok(item);
+        }
+
+        let pointer = BlobPointer::from_value(&item)?;
+
+        Ok(Value {
+            key: item.key,
+            value: self.get(&pointer)?,
+            seqno: item.seqno,
+            value_type: ValueType::Value,
+            expires_at: item.expires_at,
+        })
+    }
+
+    /// Marks blob files, so compactions move their values into new blob files
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn mark_for_relocation(&self, blob_ids: &[Arc<str>]) {
+        let mut relocating = self.relocating.write().expect("lock is poisoned");
+
+        for blob_id in blob_ids {
+            relocating.entry(blob_id.clone()).or_default();
+        }
+    }
+
+    /// Unmarks blob files that were marked using [`BlobStore::mark_for_relocation`]
+    ///
+    /// Returns the amount of value bytes that were moved out of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn unmark_for_relocation(&self, blob_ids: &[Arc<str>]) -> u64 {
+        let mut relocating = self.relocating.write().expect("lock is poisoned");
+
+        blob_ids
+            .iter()
+            .filter_map(|blob_id| relocating.remove(blob_id))
+            .sum()
+    }
+
+    /// Moves the value of an indirection into the given blob writer,
+    /// if it points into a blob file that is marked for relocation
+    ///
+    /// Only the pointer is rewritten, the item keeps its seqno, so
+    /// relocating a value is invisible to transactions and change feeds.
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn relocate(&self, item: Value, writer: &mut Option<BlobWriter>) -> crate::Result<Value> {
+        if item.value_type != ValueType::Indirection
+            || self.relocating.read().expect("lock is poisoned").is_empty()
+        {
+            return Ok(item);
+        }
+
+        let pointer = BlobPointer::from_value(&item)?;
+
+        if !self
+            .relocating
+            .read()
+            .expect("lock is poisoned")
+            .contains_key(&pointer.blob_id)
+        {
+            return Ok(item);
+        }
+
+        let value = self.get(&pointer)?;
+
+        let writer = match writer {
+            Some(writer) => writer,
+            None => writer.insert(self.writer()?),
+        };
+        let new_pointer = writer.write(&item.key, &value)?;
+
+        if let Some(bytes) = self
+            .relocating
+            .write()
+            .expect("lock is poisoned")
+            .get_mut(&pointer.blob_id)
+        {
+            *bytes += u64::from(pointer.size);
+        }
+
+        Ok(Value {
+            value: new_pointer.to_bytes()?.into(),
+            ..item
+        })
+    }
+
+    /// Returns an iterator over all entries of a blob file
+    pub fn scan(&self, blob_id: &Arc<str>) -> crate::Result<BlobReader> {
+        let file = File::open(self.folder.join(&**blob_id))?;
+        let file_size = file.metadata()?.len();
+
+        let mut reader = BufReader::new(file);
+        let version = read_version(&mut reader)?;
+        let file_pos = reader.stream_position()?;
+
+        Ok(BlobReader {
+            blob_id: blob_id.clone(),
+            version,
+            reader,
+            file_pos,
+            file_size,
+        })
+    }
+
+    /// Deletes all registered blob files that are not referenced by any segment anymore
+    ///
+    /// The levels need to be locked, so no segment can be added concurrently.
+    ///
+    /// Returns the amount of deleted blob files.
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn drop_unreferenced(&self, levels: &Levels) -> crate::Result<usize> {
+        let refs = levels.get_blob_refs();
+
+        let mut files = self.files.write().expect("lock is poisoned");
+
+        let unreferenced = files
+            .keys()
+            .filter(|id| !refs.contains_key(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for blob_id in &unreferenced {
+            log::debug!("Deleting unreferenced blob file {blob_id}");
+            self.readers
+                .write()
+                .expect("lock is poisoned")
+                .remove(blob_id);
+            std::fs::remove_file(self.folder.join(&**blob_id))?;
+            files.remove(blob_id);
+        }
+
+        drop(files);
+
+        Ok(unreferenced.len())
+    }
+
+    /// Returns all blob files whose ratio of unreferenced bytes is at least `stale_threshold`
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn get_stale_files(&self, levels: &Levels, stale_threshold: f32) -> Vec<Arc<str>> {
+        let refs = levels.get_blob_refs();
+
+        self.files
+            .read()
+            .expect("lock is poisoned")
+            .iter()
+            .filter(|(id, size)| {
+                let live_bytes = refs.get(*id).copied().unwrap_or_default();
+
+                // NOTE: Precision is not important here
+                #[allow(clippy::cast_precision_loss)]
+                let live_ratio = live_bytes as f32 / (**size).max(1) as f32;
+
+                1.0 - live_ratio >= stale_threshold
+            })
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn blob_write_read() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let store = BlobStore::create_new(folder.path())?;
+
+        let mut writer = store.writer()?;
+        let a = writer.write(b"a", b"abc")?;
+        let b = writer.write(b"b", &[7; 100_000])?;
+        let (blob_id, _) = writer.finish()?;
+
+        assert_eq!(&*store.get(&a)?, b"abc");
+        assert_eq!(&*store.get(&b)?, &[7; 100_000]);
+
+        let entries = store.scan(&blob_id)?.collect::<crate::Result<Vec<_>>>()?;
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            (a.clone(), (*b"a").into()),
+            (entries[0].0.clone(), entries[0].1.clone())
+        );
+        assert_eq!(b, entries[1].0);
+
+        assert_eq!(a, BlobPointer::deserialize(&mut &*a.to_bytes()?)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn blob_large_key() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let store = BlobStore::create_new(folder.path())?;
+
+        let key = vec![1; 100_000];
+
+        let mut writer = store.writer()?;
+        let a = writer.write(&key, b"abc")?;
+        let b = writer.write(b"b", b"def")?;
+        let (blob_id, size) = writer.finish()?;
+
+        assert_eq!(&*store.get(&a)?, b"abc");
+        assert_eq!(&*store.get(&b)?, b"def");
+        assert_eq!(size, 5 + a.entry_size(&key) + b.entry_size(b"b"));
+
+        let entries = store.scan(&blob_id)?.collect::<crate::Result<Vec<_>>>()?;
+        assert_eq!(2, entries.len());
+        assert_eq!(&*entries[0].1, &*key);
+        assert_eq!(b, entries[1].0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn blob_legacy_file() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let store = BlobStore::create_new(folder.path())?;
+
+        // NOTE: Blob files before V1 have no header and use 2-byte key lengths
+        let mut bytes = vec![];
+        for (key, value) in [(&b"a"[..], &b"abc"[..]), (b"bb", b"defg")] {
+            bytes.write_u16::<BigEndian>(key.len() as u16)?;
+            bytes.write_all(key)?;
+            bytes.write_u32::<BigEndian>(value.len() as u32)?;
+            bytes.write_all(value)?;
+        }
+        std::fs::write(folder.path().join("legacy"), bytes)?;
+
+        let entries = store
+            .scan(&"legacy".into())?
+            .collect::<crate::Result<Vec<_>>>()?;
+        assert_eq!(2, entries.len());
+        assert_eq!(&*entries[1].1, b"bb");
+        assert_eq!(&*entries[1].2, b"defg");
+        assert_eq!(&*store.get(&entries[1].0)?, b"defg");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use crate::serde::{
+    read_exact_len, Deserializable, DeserializeError, Serializable, SerializeError,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// Two hashes that are used for double hashing
+pub type CompositeHash = (u64, u64);
+
+/// A standard bloom filter
+///
+/// Allows buffering the key hashes before initializing the filter
+/// of a certain size.
+///
+/// The filter uses double hashing instead of `k` independent hash functions,
+/// so a key only needs to be hashed once per lookup.
+///
+/// # Disk representation
+///
+/// \[bit count; 8 bytes] \[hash function count; 1 byte] \[byte count; 4 bytes] \[bytes; N bytes]
+#[derive(Debug, Eq, PartialEq)]
+pub struct BloomFilter {
+    /// Raw bytes exposed as bit array
+    inner: Vec<u8>,
+
+    /// Bit count
+    m: usize,
+
+    /// Number of hash functions
+    k: usize,
+}
+
+impl Serializable for BloomFilter {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        writer.write_u64::<BigEndian>(self.m as u64)?;
+
+        // NOTE: k is never larger than 255 (see `with_bits_per_key`)
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_u8(self.k as u8)?;
+
+        // NOTE: Filters are never bigger than 4 GB anyway
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_u32::<BigEndian>(self.inner.len() as u32)?;
+        writer.write_all(&self.inner)?;
+
+        Ok(())
+    }
+}
+
+impl Deserializable for BloomFilter {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        // NOTE: Filters are never bigger than the address space anyway
+        #[allow(clippy::cast_possible_truncation)]
+        let m = reader.read_u64::<BigEndian>()? as usize;
+        let k = reader.read_u8()?.into();
+
+        let byte_count = reader.read_u32::<BigEndian>()?;
+        let inner = read_exact_len(reader, byte_count.into())?;
+
+        // NOTE: Lookups divide by m and index into the bit array,
+        // so a corrupted header would make them panic
+        if m == 0 || k == 0 || m > inner.len() * 8 {
+            return Err(DeserializeError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid bloom filter header",
+            )));
+        }
+
+        Ok(Self { inner, m, k })
+    }
+}
+
+impl BloomFilter {
+    /// Constructs a bloom filter that can hold `item_count` items
+    /// while using `bits_per_key` bits per key.
+    ///
+    /// 10 bits per key result in a false positive rate of roughly 1%.
+    #[must_use]
+    pub fn with_bits_per_key(item_count: usize, bits_per_key: u8) -> Self {
+        let bits_per_key = usize::from(bits_per_key.max(1));
+
+        // NOTE: Round up to full bytes
+        let m = (item_count.max(1) * bits_per_key).div_ceil(8) * 8;
+
+        // NOTE: k = ln(2) * m/n is the optimal hash function count
+        let k = ((bits_per_key * 69) / 100).clamp(1, 30);
+
+        Self {
+            inner: vec![0; m / 8],
+            m,
+            k,
+        }
+    }
+
+    /// Returns `true` if the item may be contained.
+    ///
+    /// Will never have a false negative.
+    #[must_use]
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.contains_hash(Self::get_hash(key))
+    }
+
+    /// Returns `true` if the hash may be contained.
+    ///
+    /// Will never have a false negative.
+    #[must_use]
+    pub fn contains_hash(&self, hash: CompositeHash) -> bool {
+        let (mut h1, h2) = hash;
+
+        for i in 0..(self.k as u64) {
+            let idx = h1 % (self.m as u64);
+
+            // NOTE: idx is always smaller than m, which is a usize
+            #[allow(clippy::cast_possible_truncation)]
+            if !self.has_bit(idx as usize) {
+                return false;
+            }
+
+            h1 = h1.wrapping_add(h2);
+            h1 = h1.wrapping_add(i);
+        }
+
+        true
+    }
+
+    /// Adds the key to the filter
+    pub fn set_with_hash(&mut self, (mut h1, h2): CompositeHash) {
+        for i in 0..(self.k as u64) {
+            let idx = h1 % (self.m as u64);
+
+            // NOTE: idx is always smaller than m, which is a usize
+            #[allow(clippy::cast_possible_truncation)]
+            self.enable_bit(idx as usize);
+
+            h1 = h1.wrapping_add(h2);
+            h1 = h1.wrapping_add(i);
+        }
+    }
+
+    fn has_bit(&self, idx: usize) -> bool {
+        let byte = self.inner[idx / 8];
+        (byte >> (idx % 8)) & 1 == 1
+    }
+
+    fn enable_bit(&mut self, idx: usize) {
+        self.inner[idx / 8] |= 1 << (idx % 8);
+    }
+
+    /// Gets the hash of a key
+    #[must_use]
+    pub fn get_hash(key: &[u8]) -> CompositeHash {
+        let h0 = xxhash_rust::xxh3::xxh3_128(key);
+
+        // NOTE: Truncation is intended, we split the 128-bit hash into two halves
+        #[allow(clippy::cast_possible_truncation)]
+        let h1 = (h0 >> 64) as u64;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let h2 = h0 as u64;
+
+        (h1, h2)
+    }
+
+    /// Writes the bloom filter to a file
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.serialize(&mut writer)?;
+        writer.flush()?;
+        writer.get_mut().sync_all()?;
+        Ok(())
+    }
+
+    /// Reads a bloom filter from a file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(Self::deserialize(&mut reader)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn bloom_serde_round_trip() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("bloom");
+
+        let mut filter = BloomFilter::with_bits_per_key(10, 10);
+
+        let keys = &[
+            b"item0", b"item1", b"item2", b"item3", b"item4", b"item5", b"item6", b"item7",
+            b"item8", b"item9",
+        ];
+
+        for key in keys {
+            filter.set_with_hash(BloomFilter::get_hash(*key));
+        }
+
+        filter.write_to_file(&path)?;
+        let filter_copy = BloomFilter::from_file(&path)?;
+
+        assert_eq!(filter, filter_copy);
+
+        for key in keys {
+            assert!(filter_copy.contains(*key));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn bloom_deserialize_invalid() -> crate::Result<()> {
+        let mut bytes = vec![];
+        BloomFilter::with_bits_per_key(10, 10).serialize(&mut bytes)?;
+
+        // NOTE: m = 0
+        let mut zero_m = bytes.clone();
+        zero_m[..8].copy_from_slice(&0u64.to_be_bytes());
+
+        // NOTE: k = 0
+        let mut zero_k = bytes.clone();
+        zero_k[8] = 0;
+
+        // NOTE: m is bigger than the bit array
+        let mut big_m = bytes.clone();
+        big_m[..8].copy_from_slice(&u64::MAX.to_be_bytes());
+
+        for bytes in [zero_m, zero_k, big_m, bytes[..bytes.len() - 1].to_vec()] {
+            assert!(BloomFilter::deserialize(&mut &bytes[..]).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn bloom_basic() {
+        let mut filter = BloomFilter::with_bits_per_key(10, 10);
+
+        for key in [
+            b"item0", b"item1", b"item2", b"item3", b"item4", b"item5", b"item6", b"item7",
+            b"item8", b"item9",
+        ] {
+            assert!(!filter.contains(key));
+            filter.set_with_hash(BloomFilter::get_hash(key));
+            assert!(filter.contains(key));
+
+            assert!(!filter.contains(b"asdasdasdasdasdasdasd"));
+        }
+    }
+
+    #[test]
+    fn bloom_fpr() {
+        let item_count = 100_000;
+
+        let mut filter = BloomFilter::with_bits_per_key(item_count, 10);
+
+        for key in (0..item_count).map(|_| nanoid::nanoid!()) {
+            filter.set_with_hash(BloomFilter::get_hash(key.as_bytes()));
+        }
+
+        let mut false_positives = 0;
+
+        for key in (0..item_count).map(|_| nanoid::nanoid!()) {
+            if filter.contains(key.as_bytes()) {
+                false_positives += 1;
+            }
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let fpr = false_positives as f32 / item_count as f32;
+        assert!(fpr < 0.02);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    file::{JOURNALS_FOLDER, RETAINED_JOURNALS_FOLDER},
+    journal::{reader::JournalBatchReader, shard::write_batch, Journal},
+    value::SeqNo,
+    version::Version,
+    Config, Tree, Value,
+};
+use std::{
+    collections::VecDeque,
+    path::Path,
+    sync::{
+        atomic::AtomicBool,
+        mpsc::{Receiver, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// A committed write (a single insert/remove or an entire [`crate::Batch`])
+///
+/// All items of a batch share the same seqno.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ChangeBatch {
+    /// Seqno the batch was committed with
+    pub seqno: SeqNo,
+
+    /// Written items
+    ///
+    /// The value type of an item tells if a value was inserted, removed,
+    /// merged or a key range was removed.
+    pub items: Vec<Value>,
+}
+
+impl ChangeBatch {
+    /// Serializes the batch in the journal format, e.g. to ship it to a follower.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the batch is empty, or serialization fails.
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        if self.items.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "change batch may not be empty",
+            )
+            .into());
+        }
+
+        // NOTE: The journal writes all items with the seqno of the first item
+        let mut items = self.items.clone();
+        items[0].seqno = self.seqno;
+
+        let mut bytes = vec![];
+        write_batch(&mut bytes, &items.iter().collect::<Vec<_>>())?;
+        Ok(bytes)
+    }
+
+    /// Deserializes a batch that was serialized using [`ChangeBatch::to_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the bytes are not a complete batch, or the checksum does not match.
+    pub fn from_bytes(bytes: &[u8]) -> crate::Result<Self> {
+        let (seqno, items) = JournalBatchReader::from_reader(bytes, Version::V1)
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid change batch")
+            })??;
+
+        Ok(Self { seqno, items })
+    }
+}
+
+/// Keeps track of the subscribers of a tree's changes
+#[derive(Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ChangeFeed {
+    subscribers: Mutex<Vec<Sender<ChangeBatch>>>,
+
+    /// Is set while there are subscribers
+    ///
+    /// Writes then only use a single journal shard, so they
+    /// are published in commit order.
+    active: AtomicBool,
+}
+
+// NOTE: Sending into a channel does not panic, so the subscribers lock is never poisoned
+#[allow(clippy::expect_used)]
+impl ChangeFeed {
+    /// Returns `true` if there may be subscribers
+    pub fn is_active(&self) -> bool {
+        self.active.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// Registers a subscriber
+    ///
+    /// Needs to be called while holding the full journal lock.
+    fn subscribe(&self, sender: Sender<ChangeBatch>) {
+        self.subscribers
+            .lock()
+            .expect("lock is poisoned")
+            .push(sender);
+
+        self.active
+            .store(true, std::sync::atomic::Ordering::Release);
+    }
+
+    /// Sends a written batch to all subscribers
+    ///
+    /// Needs to be called while still holding the journal shard lock the batch was written with.
+    pub fn publish(&self, items: &[&Value]) {
+        if !self.is_active() {
+            return;
+        }
+
+        let Some(first) = items.first() else {
+            return;
+        };
+
+        let batch = ChangeBatch {
+            seqno: first.seqno,
+            items: items.iter().map(|&item| item.clone()).collect(),
+        };
+
+        let mut subscribers = self.subscribers.lock().expect("lock is poisoned");
+
+        // NOTE: Dropped subscriptions are removed here
+        subscribers.retain(|sender| sender.send(batch.clone()).is_ok());
+
+        if subscribers.is_empty() {
+            self.active
+                .store(false, std::sync::atomic::Ordering::Release);
+        }
+    }
+}
+
+/// A stream of committed writes of a tree, in commit order
+///
+/// The subscription blocks until the next batch is committed, and ends when the tree is dropped.
+///
+/// Batches are buffered in memory until they are consumed,
+/// so a subscription should be read from continuously, or dropped.
+pub struct Subscription {
+    /// Batches that were read from the journals when subscribing
+    backlog: VecDeque<ChangeBatch>,
+
+    receiver: Receiver<ChangeBatch>,
+}
+
+impl Subscription {
+    /// Returns the next batch, if one is available without blocking.
+    #[must_use]
+    pub fn try_next(&mut self) -> Option<ChangeBatch> {
+        self.backlog
+            .pop_front()
+            .or_else(|| self.receiver.try_recv().ok())
+    }
+
+    /// Waits for the next batch, for at most the given duration.
+    ///
+    /// Returns `None` if the timeout elapsed or the tree was dropped.
+    #[must_use]
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<ChangeBatch> {
+        if let Some(batch) = self.backlog.pop_front() {
+            return Some(batch);
+        }
+
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = ChangeBatch;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.backlog
+            .pop_front()
+            .or_else(|| self.receiver.recv().ok())
+    }
+}
+
+/// Subscribes to the changes of a tree
+///
+/// If `from` is given, all retained batches with a seqno `>= from` are delivered first.
+///
+/// See [`Tree::subscribe`] and [`Tree::subscribe_from`]
+// NOTE: Locks are only poisoned if another thread panicked while holding them
+#[allow(clippy::expect_used)]
+pub fn subscribe(tree: &Tree, from: Option<SeqNo>) -> crate::Result<Subscription> {
+    // NOTE: Lock all shards, so no write can happen while subscribing
+    log::debug!("subscribe: acquiring journal full lock");
+    let mut journal_lock = tree.journal.shards.full_lock().expect("lock is poisoned");
+
+    let mut backlog = VecDeque::new();
+
+    if let Some(from) = from {
+        for shard in &mut journal_lock {
+            shard.flush()?;
+        }
+
+        // NOTE: Flushes move their journals into the retained journals folder
+        // while holding the levels lock
+        log::debug!("subscribe: acquiring levels manifest read lock");
+        let levels = tree.levels.read().expect("lock is poisoned");
+
+        let mut batches = vec![];
+
+        for folder in [RETAINED_JOURNALS_FOLDER, JOURNALS_FOLDER] {
+            let folder = tree.config.path.join(folder);
+
+            if !folder.exists() {
+                continue;
+            }
+
+            for dirent in std::fs::read_dir(folder)? {
+                batches.extend(Journal::read_batches(dirent?.path())?);
+            }
+        }
+
+        drop(levels);
+
+        batches.sort_by_key(|(seqno, _)| *seqno);
+
+        let next_seqno = tree.next_lsn.load(std::sync::atomic::Ordering::Acquire);
+        let first_seqno = batches.first().map_or(next_seqno, |(seqno, _)| *seqno);
+
+        if from < first_seqno {
+            log::debug!("Changes starting at seqno {from} are not retained anymore");
+            return Err(crate::Error::ChangesNotRetained);
+        }
+
+        backlog.extend(
+            batches
+                .into_iter()
+                .filter(|(seqno, _)| *seqno >= from)
+                .map(|(seqno, items)| ChangeBatch { seqno, items }),
+        );
+    }
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    tree.journal.changes.subscribe(sender);
+
+    drop(journal_lock);
+
+    Ok(Subscription { backlog, receiver })
+}
+
+/// Deletes a journal that has been flushed, or moves it into the retained journals
+/// folder, if journals are retained for change subscriptions
+pub fn retire_journal(config: &Config, journal_path: &Path) -> crate::Result<()> {
+    if config.max_retained_journals == 0 {
+        log::debug!("Deleting old journal folder: {}", journal_path.display());
+        std::fs::remove_dir_all(journal_path)?;
+        return Ok(());
+    }
+
+    let Some(first_seqno) = Journal::first_seqno(journal_path)? else {
+        log::debug!("Deleting empty journal folder: {}", journal_path.display());
+        std::fs::remove_dir_all(journal_path)?;
+        return Ok(());
+    };
+
+    let retained_folder = config.path.join(RETAINED_JOURNALS_FOLDER);
+    std::fs::create_dir_all(&retained_folder)?;
+
+    // NOTE: Zero-padded, so the folders are sorted from oldest to newest
+    let retained_path = retained_folder.join(format!("{first_seqno:0>20}"));
+
+    log::debug!(
+        "Retaining old journal folder: {} -> {}",
+        journal_path.display(),
+        retained_path.display()
+    );
+    std::fs::rename(journal_path, retained_path)?;
+
+    let mut retained = std::fs::read_dir(&retained_folder)?
+        .map(|dirent| dirent.map(|dirent| dirent.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    retained.sort();
+
+    let excess = retained
+        .len()
+        .saturating_sub(config.max_retained_journals.into());
+
+    for path in retained.into_iter().take(excess) {
+        log::debug!("Deleting retained journal folder: {}", path.display());
+        std::fs::remove_dir_all(path)?;
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        // Fsync folder on Unix
+        let folder = std::fs::File::open(&retained_folder)?;
+        folder.sync_all()?;
+    }
+
+    Ok(())
+}
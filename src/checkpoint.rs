@@ -0,0 +1,124 @@
+use crate::{
+    file::{
+        copy_file, copy_folder, link_file_or_folder, link_folder, BLOBS_FOLDER, JOURNALS_FOLDER,
+        LSM_MARKER, SEGMENTS_FOLDER, SNAPSHOTS_FILE,
+    },
+    levels::manifest,
+    segment::Segment,
+    Tree,
+};
+use std::{fs::File, path::Path, sync::Arc};
+
+/// Creates a checkpoint of the tree in the given (new) folder
+///
+/// Returns the segments that are part of the checkpoint.
+///
+/// See [`Tree::checkpoint`]
+// NOTE: Locks are only poisoned if another thread panicked while holding them
+#[allow(clippy::expect_used)]
+pub fn create(tree: &Tree, dest: &Path) -> crate::Result<Vec<Arc<Segment>>> {
+    log::info!("Creating checkpoint in {}", dest.display());
+
+    let start = std::time::Instant::now();
+
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::create_dir(dest)?;
+
+    let src = &tree.config.path;
+
+    // NOTE: Lock all journal shards first (like flush::start), so no writes
+    // happen while the journals are copied
+    log::debug!("checkpoint: acquiring journal full lock");
+    let mut journal_lock = tree.journal.shards.full_lock().expect("lock is poisoned");
+
+    for shard in &mut journal_lock {
+        shard.flush()?;
+    }
+
+    // NOTE: Holding the levels manifest lock pauses flushes and compactions,
+    // so no segment can be added or deleted while the checkpoint is created
+    log::debug!("checkpoint: acquiring levels manifest read lock");
+    let levels = tree.levels.read().expect("lock is poisoned");
+
+    let segments_folder = dest.join(SEGMENTS_FOLDER);
+    std::fs::create_dir_all(&segments_folder)?;
+
+    let segments = levels.get_all_segments_flattened();
+
+    for segment in &segments {
+        let segment_id = &segment.metadata.id;
+
+        log::trace!("checkpoint: linking segment {segment_id}");
+
+        link_file_or_folder(
+            &src.join(SEGMENTS_FOLDER).join(&**segment_id),
+            &segments_folder.join(&**segment_id),
+        )?;
+    }
+
+    // NOTE: Blob files are only appended to while they are written by a flush,
+    // unfinished blob files are not referenced, so they are deleted when the checkpoint is opened
+    link_folder(&src.join(BLOBS_FOLDER), &dest.join(BLOBS_FOLDER))?;
+
+    // NOTE: Journals of memtables that are still being flushed need to be copied as well,
+    // they are flushed again when the checkpoint is opened
+    let journals_folder = dest.join(JOURNALS_FOLDER);
+    std::fs::create_dir_all(&journals_folder)?;
+
+    for dirent in std::fs::read_dir(src.join(JOURNALS_FOLDER))? {
+        let dirent = dirent?;
+        let dest_journal_folder = journals_folder.join(dirent.file_name());
+
+        match copy_folder(&dirent.path(), &dest_journal_folder) {
+            Ok(()) => {}
+
+            // NOTE: A flush worker deletes the journal of its memtable after the
+            // segment was added to the levels manifest, so the segment is part of the checkpoint
+            Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!(
+                    "checkpoint: skipping journal {} that was flushed in the meantime",
+                    dirent.path().display()
+                );
+
+                if dest_journal_folder.exists() {
+                    std::fs::remove_dir_all(&dest_journal_folder)?;
+                }
+            }
+
+            Err(e) => return Err(e),
+        }
+    }
+
+    manifest::copy(src, dest)?;
+
+    if src.join(SNAPSHOTS_FILE).exists() {
+        copy_file(&src.join(SNAPSHOTS_FILE), &dest.join(SNAPSHOTS_FILE))?;
+    }
+
+    drop(levels);
+    drop(journal_lock);
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        // fsync folders on Unix
+        for folder in [&segments_folder, &journals_folder, &dest.join(BLOBS_FOLDER)] {
+            File::open(folder)?.sync_all()?;
+        }
+    }
+
+    // NOTE: Lastly, copy .lsm marker, which contains the version
+    // -> the checkpoint is fully initialized
+    copy_file(&src.join(LSM_MARKER), &dest.join(LSM_MARKER))?;
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let folder = File::open(dest)?;
+        folder.sync_all()?;
+    }
+
+    log::info!("Created checkpoint in {}s", start.elapsed().as_secs_f32());
+
+    Ok(segments)
+}
@@ -1,5 +1,5 @@
-use super::{Choice, CompactionStrategy};
-use crate::{levels::Levels, segment::Segment, Config};
+use super::{worker::is_expired_segment, Choice, CompactionStrategy};
+use crate::{levels::Levels, segment::Segment, time::unix_timestamp_millis, Config};
 use std::sync::Arc;
 
 const L0_SEGMENT_CAP: usize = 24;
@@ -9,6 +9,9 @@ const L0_SEGMENT_CAP: usize = 24;
 /// Limits the tree size to roughly `limit` bytes, deleting the oldest segment(s)
 /// when the threshold is reached.
 ///
+/// Segments whose items have all expired (see [`Config::default_ttl`]) are deleted as well,
+/// unless they still shadow older versions in other segments.
+///
 /// Will also merge segments if the amount of segments in level 0 grows too much, which
 /// could cause write stalls.
 ///
@@ -80,6 +83,21 @@ impl CompactionStrategy for Strategy {
 
         let mut first_level = resolved_view[0].clone();
 
+        let now = unix_timestamp_millis();
+
+        let expired_ids = resolved_view
+            .iter()
+            .flat_map(|level| level.iter())
+            // NOTE: Deleting a segment also deletes its range tombstones,
+            // which may cover keys outside of its key range
+            .filter(|x| x.range_tombstones.is_empty() && is_expired_segment(levels, x, now))
+            .map(|x| x.metadata.id.clone())
+            .collect::<Vec<_>>();
+
+        if !expired_ids.is_empty() {
+            return Choice::DeleteSegments(expired_ids);
+        }
+
         let db_size = levels
             .get_all_segments()
             .values()
@@ -133,7 +151,6 @@ mod tests {
         block_cache::BlockCache,
         compaction::{Choice, CompactionStrategy},
         descriptor_table::FileDescriptorTable,
-        file::LEVELS_MANIFEST_FILE,
         levels::Levels,
         segment::{index::BlockIndex, meta::Metadata, Segment},
         Config,
@@ -141,8 +158,16 @@ mod tests {
     use std::sync::Arc;
     use test_log::test;
 
-    #[allow(clippy::expect_used)]
     fn fixture_segment(id: Arc<str>, created_at: u128) -> Arc<Segment> {
+        fixture_expiring_segment(id, created_at, None)
+    }
+
+    #[allow(clippy::expect_used)]
+    fn fixture_expiring_segment(
+        id: Arc<str>,
+        created_at: u128,
+        expires_at: Option<u64>,
+    ) -> Arc<Segment> {
         let block_cache = Arc::new(BlockCache::with_capacity_blocks(0));
 
         Arc::new(Segment {
@@ -165,8 +190,14 @@ mod tests {
                 tombstone_count: 0,
                 uncompressed_size: 0,
                 seqnos: (0, 0),
+                blob_refs: std::collections::HashMap::default(),
+                expires_at,
+                global_seqno: None,
+                footer: crate::segment::footer::Footer::default(),
             },
             block_cache,
+            bloom_filter: None,
+            range_tombstones: Vec::new(),
         })
     }
 
@@ -175,7 +206,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::new(1);
 
-        let levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let levels = Levels::create_new(4, tempdir.path())?;
 
         assert_eq!(
             compactor.choose(&levels, &Config::default()),
@@ -190,7 +221,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::new(4);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
 
         levels.add(fixture_segment("1".into(), 1));
         assert_eq!(
@@ -224,7 +255,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::new(2);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
         levels.add(fixture_segment("1".into(), 1));
         levels.add(fixture_segment("2".into(), 2));
         levels.add(fixture_segment("3".into(), 3));
@@ -237,4 +268,22 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn expired_segments() -> crate::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let compactor = Strategy::new(u64::MAX);
+
+        let mut levels = Levels::create_new(4, tempdir.path())?;
+        levels.add(fixture_expiring_segment("1".into(), 1, Some(1)));
+        levels.add(fixture_expiring_segment("2".into(), 2, Some(u64::MAX)));
+        levels.add(fixture_segment("3".into(), 3));
+
+        assert_eq!(
+            compactor.choose(&levels, &Config::default()),
+            Choice::DeleteSegments(vec!["1".into()])
+        );
+
+        Ok(())
+    }
 }
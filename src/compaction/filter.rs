@@ -0,0 +1,247 @@
+use crate::{
+    blob::BlobStore,
+    value::{UserData, ValueType},
+    Value,
+};
+
+/// Information about the compaction that a [`CompactionFilter`] is applied in
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Context {
+    /// Level the compacted items are written into
+    pub dest_level: u8,
+
+    /// `true` if the destination level is the last level of the tree
+    pub is_last_level: bool,
+}
+
+/// Decision of a [`CompactionFilter`] about a single item
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Decision {
+    /// Keeps the item as it is
+    Keep,
+
+    /// Drops the item
+    ///
+    /// If the item is not compacted into the last level, a tombstone is written instead,
+    /// so older versions of the key in lower levels do not become visible again.
+    ///
+    /// Merge operands are dropped without a tombstone, so only the operand itself is removed,
+    /// not the older versions it is merged with.
+    Drop,
+
+    /// Replaces the value of the item
+    Rewrite(UserData),
+}
+
+/// User-defined compaction filter
+///
+/// A compaction filter sees every item that is written by a compaction and decides if
+/// it is kept, dropped or rewritten. This allows application-level garbage collection
+/// (e.g. removing the data of deleted tenants) without scanning the tree.
+///
+/// Tombstones are not passed to the filter. Values that are stored in blob files
+/// (see [`Config::blob_threshold`](crate::Config::blob_threshold)) are read from their
+/// blob file, so the filter always sees the actual value.
+///
+/// ###### Caution
+///
+/// The filter is also applied to old versions of keys that are still visible to open snapshots,
+/// so snapshots may observe the filtered data.
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use lsm_tree::{
+///     compaction::{CompactionFilter, FilterContext, FilterDecision},
+///     Config, Value,
+/// };
+/// use std::sync::Arc;
+///
+/// struct DropTenant;
+///
+/// impl CompactionFilter for DropTenant {
+///     fn filter(&self, _: &FilterContext, item: &Value) -> FilterDecision {
+///         if item.key.starts_with(b"tenant-1:") {
+///             FilterDecision::Drop
+///         } else {
+///             FilterDecision::Keep
+///         }
+///     }
+/// }
+///
+/// let tree = Config::new(folder)
+///     .compaction_filter(Arc::new(DropTenant))
+///     .open()?;
+///
+/// tree.insert("tenant-1:a", "abc")?;
+/// tree.insert("tenant-2:a", "abc")?;
+/// tree.flush()?;
+/// tree.wait_for_memtable_flush()?;
+///
+/// tree.do_major_compaction(u64::MAX).join().expect("should join")?;
+///
+/// assert!(!tree.contains_key("tenant-1:a")?);
+/// assert!(tree.contains_key("tenant-2:a")?);
+/// #
+/// # Ok::<(), lsm_tree::Error>(())
+/// ```
+#[allow(clippy::module_name_repetitions)]
+pub trait CompactionFilter {
+    /// Decides what to do with an item that is being compacted
+    fn filter(&self, context: &Context, item: &Value) -> Decision;
+}
+
+/// Applies the compaction filter to an item
+///
+/// Returns `None` if the item should not be written at all
+///
+/// If `can_evict` is `false`, older versions of the key may be kept (for open snapshots),
+/// so dropped items are always replaced by a tombstone.
+///
+/// # Errors
+///
+/// Will return `Err` if the value of an indirection could not be read from its blob file.
+pub fn apply(
+    filter: &(dyn CompactionFilter + Send + Sync),
+    context: Context,
+    item: Value,
+    can_evict: bool,
+    blobs: &BlobStore,
+) -> crate::Result<Option<Value>> {
+    if item.is_tombstone() {
+        return Ok(Some(item));
+    }
+
+    let decision = if item.value_type == ValueType::Indirection {
+        // NOTE: The filter gets the actual value, but a kept item
+        // still points into the blob file
+        filter.filter(&context, &blobs.resolve(item.clone())?)
+    } else {
+        filter.filter(&context, &item)
+    };
+
+    Ok(match decision {
+        Decision::Keep => Some(item),
+        Decision::Drop if item.value_type == ValueType::Merge => None,
+        Decision::Drop if context.is_last_level && can_evict => None,
+        Decision::Drop => Some(Value::new(
+            item.key,
+            vec![],
+            item.seqno,
+            ValueType::Tombstone,
+        )),
+        Decision::Rewrite(value) => {
+            let value_type = match item.value_type {
+                // NOTE: The new value is stored inline, not in a blob file
+                ValueType::Indirection => ValueType::Value,
+                value_type => value_type,
+            };
+
+            Some(Value::new(item.key, value, item.seqno, value_type).expires_at(item.expires_at))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    struct DropAll;
+
+    impl CompactionFilter for DropAll {
+        fn filter(&self, _: &Context, _: &Value) -> Decision {
+            Decision::Drop
+        }
+    }
+
+    struct DropValue(&'static [u8]);
+
+    impl CompactionFilter for DropValue {
+        fn filter(&self, _: &Context, item: &Value) -> Decision {
+            if &*item.value == self.0 {
+                Decision::Drop
+            } else {
+                Decision::Keep
+            }
+        }
+    }
+
+    #[test]
+    fn filter_drop() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let blobs = BlobStore::create_new(folder.path())?;
+
+        let item = Value::new(*b"a", *b"abc", 5, ValueType::Value);
+
+        let context = Context {
+            dest_level: 6,
+            is_last_level: true,
+        };
+        assert_eq!(None, apply(&DropAll, context, item.clone(), true, &blobs)?);
+        assert_eq!(
+            Some(Value::new(*b"a", vec![], 5, ValueType::Tombstone)),
+            apply(&DropAll, context, item.clone(), false, &blobs)?
+        );
+
+        let context = Context {
+            dest_level: 1,
+            is_last_level: false,
+        };
+        assert_eq!(
+            Some(Value::new(*b"a", vec![], 5, ValueType::Tombstone)),
+            apply(&DropAll, context, item, true, &blobs)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn filter_drop_merge_operand() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let blobs = BlobStore::create_new(folder.path())?;
+
+        let item = Value::new(*b"a", *b"abc", 5, ValueType::Merge);
+
+        let context = Context {
+            dest_level: 1,
+            is_last_level: false,
+        };
+
+        // NOTE: A tombstone would also delete the value the operand is merged with
+        assert_eq!(None, apply(&DropAll, context, item, false, &blobs)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn filter_indirection() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let blobs = BlobStore::create_new(folder.path())?;
+
+        let mut writer = blobs.writer()?;
+        let a = writer.write(b"a", b"abc")?;
+        let b = writer.write(b"b", b"def")?;
+        writer.finish()?;
+
+        let context = Context {
+            dest_level: 6,
+            is_last_level: true,
+        };
+
+        let item = Value::new(*b"a", a.to_bytes()?, 5, ValueType::Indirection);
+        assert_eq!(
+            None,
+            apply(&DropValue(b"abc"), context, item, true, &blobs)?
+        );
+
+        let item = Value::new(*b"b", b.to_bytes()?, 5, ValueType::Indirection);
+        assert_eq!(
+            Some(item.clone()),
+            apply(&DropValue(b"abc"), context, item, true, &blobs)?
+        );
+
+        Ok(())
+    }
+}
@@ -178,7 +178,6 @@ mod tests {
         block_cache::BlockCache,
         compaction::{CompactionStrategy, Input as CompactionInput},
         descriptor_table::FileDescriptorTable,
-        file::LEVELS_MANIFEST_FILE,
         levels::Levels,
         segment::{index::BlockIndex, meta::Metadata, Segment},
         time::unix_timestamp,
@@ -212,8 +211,14 @@ mod tests {
                 tombstone_count: 0,
                 uncompressed_size: 0,
                 seqnos: (0, 0),
+                blob_refs: std::collections::HashMap::default(),
+                expires_at: None,
+                global_seqno: None,
+                footer: crate::segment::footer::Footer::default(),
             },
             block_cache,
+            bloom_filter: None,
+            range_tombstones: Vec::new(),
         })
     }
 
@@ -222,7 +227,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::default();
 
-        let levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let levels = Levels::create_new(4, tempdir.path())?;
 
         assert_eq!(
             compactor.choose(&levels, &Config::default()),
@@ -237,7 +242,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::default();
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
 
         levels.add(fixture_segment(
             "1".into(),
@@ -298,7 +303,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::default();
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
         levels.add(fixture_segment(
             "1".into(),
             ("h".as_bytes().into(), "t".as_bytes().into()),
@@ -370,7 +375,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::default();
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
         levels.add(fixture_segment(
             "1".into(),
             ("a".as_bytes().into(), "g".as_bytes().into()),
@@ -458,7 +463,7 @@ mod tests {
         };
         let config = Config::default().level_ratio(2);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
 
         levels.insert_into_level(
             2,
@@ -518,7 +523,7 @@ mod tests {
         };
         let config = Config::default().level_ratio(2);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
 
         levels.insert_into_level(
             3,
@@ -1,6 +1,7 @@
 //! Contains compaction strategies
 
 pub(crate) mod fifo;
+pub(crate) mod filter;
 pub(crate) mod levelled;
 pub(crate) mod major;
 pub(crate) mod tiered;
@@ -55,5 +56,6 @@ pub trait CompactionStrategy {
 }
 
 pub use fifo::Strategy as Fifo;
+pub use filter::{CompactionFilter, Context as FilterContext, Decision as FilterDecision};
 pub use levelled::Strategy as Levelled;
 pub use tiered::Strategy as SizeTiered;
@@ -92,7 +92,6 @@ mod tests {
         block_cache::BlockCache,
         compaction::{Choice, CompactionStrategy, Input as CompactionInput},
         descriptor_table::FileDescriptorTable,
-        file::LEVELS_MANIFEST_FILE,
         levels::Levels,
         segment::{index::BlockIndex, meta::Metadata, Segment},
         Config,
@@ -124,8 +123,14 @@ mod tests {
                 tombstone_count: 0,
                 uncompressed_size: 0,
                 seqnos: (0, 0),
+                blob_refs: std::collections::HashMap::default(),
+                expires_at: None,
+                global_seqno: None,
+                footer: crate::segment::footer::Footer::default(),
             },
             block_cache,
+            bloom_filter: None,
+            range_tombstones: Vec::new(),
         })
     }
 
@@ -134,7 +139,7 @@ mod tests {
         let tempdir = tempfile::tempdir()?;
         let compactor = Strategy::default();
 
-        let levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let levels = Levels::create_new(4, tempdir.path())?;
 
         assert_eq!(
             compactor.choose(&levels, &Config::default()),
@@ -150,7 +155,7 @@ mod tests {
         let compactor = Strategy::default();
         let config = Config::default().level_ratio(4);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
 
         levels.add(fixture_segment("1".into()));
         assert_eq!(compactor.choose(&levels, &config), Choice::DoNothing);
@@ -180,7 +185,7 @@ mod tests {
         let compactor = Strategy::default(/* 2, 8 */);
         let config = Config::default().level_ratio(4);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
         levels.add(fixture_segment("1".into()));
         levels.add(fixture_segment("2".into()));
         levels.add(fixture_segment("3".into()));
@@ -209,7 +214,7 @@ mod tests {
         let compactor = Strategy::default(/* 2, 2 */);
         let config = Config::default().level_ratio(2);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
         levels.add(fixture_segment("1".into()));
         levels.add(fixture_segment("2".into()));
         levels.add(fixture_segment("3".into()));
@@ -233,7 +238,7 @@ mod tests {
         let compactor = Strategy::default(/* 2, 4 */);
         let config = Config::default().level_ratio(2);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
         levels.add(fixture_segment("1".into()));
 
         levels.insert_into_level(1, fixture_segment("2".into()));
@@ -249,7 +254,7 @@ mod tests {
         );
 
         let tempdir = tempfile::tempdir()?;
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
 
         levels.insert_into_level(2, fixture_segment("2".into()));
         levels.insert_into_level(2, fixture_segment("3".into()));
@@ -272,7 +277,7 @@ mod tests {
         let compactor = Strategy::default(/* 2, 4 */);
         let config = Config::default().level_ratio(2);
 
-        let mut levels = Levels::create_new(4, tempdir.path().join(LEVELS_MANIFEST_FILE))?;
+        let mut levels = Levels::create_new(4, tempdir.path())?;
         levels.insert_into_level(3, fixture_segment("2".into()));
         levels.insert_into_level(3, fixture_segment("3".into()));
 
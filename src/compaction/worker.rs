@@ -1,29 +1,170 @@
-use super::CompactionStrategy;
+use super::{filter, CompactionStrategy, FilterContext};
 use crate::{
+    blob::{BlobStore, BlobWriter},
     block_cache::BlockCache,
     compaction::Choice,
-    descriptor_table::FileDescriptorTable,
-    file::{BLOCKS_FILE, SEGMENTS_FOLDER},
+    file::{remove_file_or_folder, SEGMENTS_FOLDER},
     levels::Levels,
     memtable::MemTable,
     merge::MergeIterator,
-    segment::{index::BlockIndex, writer::MultiWriter, Segment},
+    range_tombstone::RangeTombstone,
+    segment::{writer::MultiWriter, Segment},
+    snapshot_tracker::SnapshotTracker,
     stop_signal::StopSignal,
+    time::unix_timestamp_millis,
+    value::SeqNo,
     Config, Tree,
 };
 use std::{
     collections::BTreeMap,
-    sync::{atomic::AtomicU32, Arc, RwLock},
+    ops::Bound,
+    sync::{Arc, RwLock},
     time::Instant,
 };
 
+/// Removes all range tombstones that are not needed anymore after compacting into the last level
+///
+/// A range tombstone can only be dropped if there is no other segment
+/// that may still contain items it covers
+fn retain_needed_range_tombstones(
+    levels: &Levels,
+    payload: &crate::compaction::Input,
+    range_tombstones: &mut Vec<RangeTombstone>,
+) {
+    let other_segments = levels
+        .get_all_segments()
+        .into_values()
+        .filter(|x| !payload.segment_ids.contains(&x.metadata.id))
+        .collect::<Vec<_>>();
+
+    range_tombstones.retain(|tombstone| {
+        other_segments.iter().any(|segment| {
+            let (lo, hi) = &segment.metadata.key_range;
+            segment.metadata.seqnos.0 < tombstone.seqno && tombstone.overlaps(lo, hi)
+        })
+    });
+}
+
+/// Returns `true` if all items of the segment have expired, and there is no
+/// older data in any other segment they may still shadow
+pub fn is_expired_segment(levels: &Levels, segment: &Segment, now: u64) -> bool {
+    if !segment.metadata.is_expired(now) {
+        return false;
+    }
+
+    let (lo, hi) = &segment.metadata.key_range;
+    let (_, max_seqno) = segment.metadata.seqnos;
+    let bounds = (Bound::Included(lo.clone()), Bound::Included(hi.clone()));
+
+    !levels.get_all_segments_flattened().iter().any(|other| {
+        other.metadata.id != segment.metadata.id
+            && other.metadata.seqnos.0 < max_seqno
+            && other.check_key_range_overlap(&bounds)
+    })
+}
+
+/// Removes all segments whose items are all deleted by a newer range tombstone,
+/// or have all expired
+///
+/// Those segments do not need to be rewritten, they are simply dropped after compaction.
+///
+/// Their range tombstones still need to be collected, because they may cover other segments.
+fn skip_deleted_segments(levels: &Levels, segments: Vec<Arc<Segment>>) -> Vec<Arc<Segment>> {
+    let now = unix_timestamp_millis();
+
+    segments
+        .into_iter()
+        .filter(|segment| {
+            let (lo, hi) = &segment.metadata.key_range;
+            let (_, max_seqno) = segment.metadata.seqnos;
+            let bounds = (Bound::Included(lo.clone()), Bound::Included(hi.clone()));
+
+            let is_deleted = levels
+                .get_overlapping_range_tombstones(&bounds)
+                .any(|x| x.seqno > max_seqno && x.contains_key_range(lo, hi));
+
+            if is_deleted {
+                log::debug!(
+                    "Dropping segment {} because it is covered by a range tombstone",
+                    segment.metadata.id
+                );
+                return false;
+            }
+
+            if is_expired_segment(levels, segment, now) {
+                log::debug!(
+                    "Dropping segment {} because all its items have expired",
+                    segment.metadata.id
+                );
+                return false;
+            }
+
+            true
+        })
+        .collect()
+}
+
+/// Creates the iterator over all items of the segments to compact
+///
+/// Also returns the range tombstones that need to be carried over into the new segments
+fn create_merge_iter(
+    config: &Config,
+    levels: &Levels,
+    snapshot_seqnos: &[SeqNo],
+    payload: &crate::compaction::Input,
+    should_evict_tombstones: bool,
+) -> (MergeIterator<'static>, Vec<RangeTombstone>) {
+    let to_merge: Vec<Arc<Segment>> = {
+        let segments = levels.get_segments();
+        payload
+            .segment_ids
+            .iter()
+            .filter_map(|x| segments.get(x))
+            .cloned()
+            .collect()
+    };
+
+    let no_snapshots_open = snapshot_seqnos.is_empty();
+
+    let mut range_tombstones: Vec<RangeTombstone> = to_merge
+        .iter()
+        .flat_map(|segment| segment.range_tombstones.iter().cloned())
+        .collect();
+
+    let to_merge = if no_snapshots_open {
+        skip_deleted_segments(levels, to_merge)
+    } else {
+        to_merge
+    };
+
+    let mut merge_iter = MergeIterator::from_segments(&to_merge)
+        .evict_old_versions(true)
+        .retain_snapshot_versions(snapshot_seqnos.to_vec())
+        .merge_operator(config.merge_operator.clone())
+        .bottommost(should_evict_tombstones);
+
+    if no_snapshots_open {
+        // NOTE: Items that are covered by a range tombstone can be dropped,
+        // because the range tombstone is carried over into the new segments
+        merge_iter = merge_iter.range_tombstones(range_tombstones.clone());
+
+        if should_evict_tombstones {
+            retain_needed_range_tombstones(levels, payload, &mut range_tombstones);
+        }
+    }
+
+    (merge_iter, range_tombstones)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn do_compaction(
     config: &Config,
     levels: &Arc<RwLock<Levels>>,
     stop_signal: &StopSignal,
     immutable_memtables: &Arc<RwLock<BTreeMap<Arc<str>, Arc<MemTable>>>>,
-    open_snapshots: &Arc<AtomicU32>,
+    open_snapshots: &Arc<SnapshotTracker>,
     block_cache: &Arc<BlockCache>,
+    blobs: &BlobStore,
     payload: &crate::compaction::Input,
 ) -> crate::Result<()> {
     if stop_signal.is_stopped() {
@@ -42,45 +183,69 @@ pub fn do_compaction(
     log::debug!("compaction worker: acquiring levels manifest write lock");
     let mut segments_lock = levels.write().expect("lock is poisoned");
 
-    let merge_iter = {
-        let to_merge: Vec<Arc<Segment>> = {
-            let segments = segments_lock.get_segments();
-            payload
-                .segment_ids
-                .iter()
-                .filter_map(|x| segments.get(x))
-                .cloned()
-                .collect()
-        };
+    // NOTE: The levels lock was released after choosing the segments,
+    // so another compaction may have picked them up in the meantime
+    if !segments_lock.can_compact(&payload.segment_ids) {
+        log::debug!("compaction worker: segments are already being compacted");
+        return Ok(());
+    }
 
-        // NOTE: When there are open snapshots
-        // we don't want to GC old versions of items
-        // otherwise snapshots will lose data
-        let snapshot_count = open_snapshots.load(std::sync::atomic::Ordering::Acquire);
-        let no_snapshots_open = snapshot_count == 0;
+    // NOTE: Only evict tombstones when reaching the last level,
+    // That way we don't resurrect data beneath the tombstone
+    let should_evict_tombstones = payload.dest_level == (config.level_count - 1);
 
-        MergeIterator::from_segments(&to_merge).evict_old_versions(no_snapshots_open)
-    };
+    // NOTE: When there are open snapshots
+    // we don't want to GC the versions of items they can see
+    // otherwise snapshots will lose data
+    let snapshot_seqnos = open_snapshots.seqnos();
+    let no_snapshots_open = snapshot_seqnos.is_empty();
+
+    let (merge_iter, range_tombstones) = create_merge_iter(
+        config,
+        &segments_lock,
+        &snapshot_seqnos,
+        payload,
+        should_evict_tombstones,
+    );
 
     segments_lock.hide_segments(&payload.segment_ids);
     drop(segments_lock);
     log::trace!("Freed levels manifest lock");
 
-    // NOTE: Only evict tombstones when reaching the last level,
-    // That way we don't resurrect data beneath the tombstone
-    let should_evict_tombstones = payload.dest_level == (config.level_count - 1);
-
     let mut segment_writer = MultiWriter::new(
         payload.target_size,
         crate::segment::writer::Options {
             block_size: config.block_size,
-            evict_tombstones: should_evict_tombstones,
+            // NOTE: With open snapshots, a tombstone may shadow an older version that is kept
+            // for a snapshot, so the merge iterator decides which tombstones can be dropped
+            evict_tombstones: should_evict_tombstones && no_snapshots_open,
             path: config.path.join(SEGMENTS_FOLDER),
+            bloom_bits_per_key: config.bloom_bits_per_key,
+            compression: config.compression_for_level(payload.dest_level),
         },
     )?;
 
+    segment_writer.set_range_tombstones(range_tombstones);
+
+    let filter_context = FilterContext {
+        dest_level: payload.dest_level,
+        is_last_level: should_evict_tombstones,
+    };
+
+    let mut blob_writer = None;
+
     for (idx, item) in merge_iter.enumerate() {
-        segment_writer.write(item?)?;
+        let item = match &config.compaction_filter {
+            Some(filter) => {
+                filter::apply(&**filter, filter_context, item?, no_snapshots_open, blobs)?
+            }
+            None => Some(item?),
+        };
+
+        if let Some(item) = item {
+            let item = blobs.relocate(item, &mut blob_writer)?;
+            segment_writer.write(item)?;
+        }
 
         if idx % 100_000 == 0 && stop_signal.is_stopped() {
             log::debug!("compaction worker: stopping amidst compaction because of stop signal");
@@ -88,6 +253,9 @@ pub fn do_compaction(
         }
     }
 
+    // NOTE: The blob file needs to be durable before the segments that point into it
+    let blob_file = blob_writer.map(BlobWriter::finish).transpose()?;
+
     let created_segments = segment_writer.finish()?;
 
     for metadata in &created_segments {
@@ -96,26 +264,7 @@ pub fn do_compaction(
 
     let created_segments = created_segments
         .into_iter()
-        .map(|metadata| -> crate::Result<Segment> {
-            let segment_id = metadata.id.clone();
-            let path = metadata.path.clone();
-
-            let descriptor_table =
-                Arc::new(FileDescriptorTable::new(metadata.path.join(BLOCKS_FILE))?);
-
-            Ok(Segment {
-                descriptor_table: Arc::clone(&descriptor_table),
-                metadata,
-                block_cache: Arc::clone(block_cache),
-                block_index: BlockIndex::from_file(
-                    segment_id,
-                    descriptor_table,
-                    path,
-                    Arc::clone(block_cache),
-                )?
-                .into(),
-            })
-        })
+        .map(|metadata| Segment::load(metadata, Arc::clone(block_cache)))
         .collect::<crate::Result<Vec<_>>>()?;
 
     log::debug!("compaction worker: acquiring levels manifest write lock");
@@ -146,9 +295,13 @@ pub fn do_compaction(
     // Otherwise the folder is deleted, but the segment is still referenced!
     segments_lock.write_to_disk()?;
 
+    if let Some((blob_id, size)) = blob_file {
+        blobs.register(blob_id, size);
+    }
+
     for key in &payload.segment_ids {
-        log::trace!("rm -rf segment folder {}", key);
-        std::fs::remove_dir_all(config.path.join(SEGMENTS_FOLDER).join(&**key))?;
+        log::trace!("rm -rf segment {key}");
+        remove_file_or_folder(&config.path.join(SEGMENTS_FOLDER).join(&**key))?;
     }
 
     segments_lock.show_segments(&payload.segment_ids);
@@ -161,14 +314,16 @@ pub fn do_compaction(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn compaction_worker(
     config: &Config,
     levels: &Arc<RwLock<Levels>>,
     stop_signal: &StopSignal,
     compaction_strategy: &Arc<dyn CompactionStrategy + Send + Sync>,
     immutable_memtables: &Arc<RwLock<BTreeMap<Arc<str>, Arc<MemTable>>>>,
-    open_snapshots: &Arc<AtomicU32>,
+    open_snapshots: &Arc<SnapshotTracker>,
     block_cache: &Arc<BlockCache>,
+    blobs: &BlobStore,
 ) -> crate::Result<()> {
     loop {
         log::debug!("compaction: acquiring levels manifest write lock");
@@ -192,6 +347,7 @@ pub fn compaction_worker(
                     immutable_memtables,
                     open_snapshots,
                     block_cache,
+                    blobs,
                     &payload,
                 )?;
             }
@@ -214,8 +370,8 @@ pub fn compaction_worker(
                 drop(segments_lock);
 
                 for key in &payload {
-                    log::trace!("rm -rf segment folder {}", key);
-                    std::fs::remove_dir_all(config.path.join(SEGMENTS_FOLDER).join(&**key))?;
+                    log::trace!("rm -rf segment {key}");
+                    remove_file_or_folder(&config.path.join(SEGMENTS_FOLDER).join(&**key))?;
                 }
 
                 log::trace!("Deleted {} segments", payload.len());
@@ -237,6 +393,7 @@ pub fn start_compaction_thread(tree: &Tree) -> std::thread::JoinHandle<crate::Re
     let open_snapshots = Arc::clone(&tree.open_snapshots);
     let block_cache = Arc::clone(&tree.block_cache);
     let compaction_semaphore = Arc::clone(&tree.compaction_semaphore);
+    let blobs = Arc::clone(&tree.blobs);
 
     std::thread::spawn(move || {
         // TODO: maybe change compaction semaphore to atomic u8
@@ -251,8 +408,14 @@ pub fn start_compaction_thread(tree: &Tree) -> std::thread::JoinHandle<crate::Re
             &immutable_memtables,
             &open_snapshots,
             &block_cache,
+            &blobs,
         )?;
 
+        // NOTE: Compactions may have dropped the last references to some blob files
+        // (locks are only poisoned if another thread panicked while holding them)
+        #[allow(clippy::expect_used)]
+        blobs.drop_unreferenced(&levels.write().expect("lock is poisoned"))?;
+
         log::trace!("Post compaction semaphore");
         compaction_semaphore.release();
 
@@ -0,0 +1,132 @@
+use crate::serde::{Deserializable, DeserializeError, Serializable, SerializeError};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use lz4_flex::{compress_prepend_size, decompress_size_prepended};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Compression algorithm to use for data blocks
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CompressionType {
+    /// No compression
+    ///
+    /// Useful when the values are already compressed (e.g. images, videos)
+    None,
+
+    /// LZ4 compression
+    ///
+    /// Fast compression & decompression, recommended for most workloads
+    Lz4,
+
+    /// Zstandard compression with the given compression level
+    ///
+    /// Achieves better compression ratios than LZ4, at the cost of CPU time,
+    /// so it is best used for cold data (e.g. the last level)
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl std::fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "no compression"),
+            Self::Lz4 => write!(f, "lz4"),
+
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => write!(f, "zstd (level {level})"),
+        }
+    }
+}
+
+impl Serializable for CompressionType {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        match self {
+            Self::None => writer.write_u8(0)?,
+            Self::Lz4 => writer.write_u8(1)?,
+
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => {
+                writer.write_u8(2)?;
+                writer.write_i32::<byteorder::BigEndian>(*level)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Deserializable for CompressionType {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        match reader.read_u8()? {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+
+            #[cfg(feature = "zstd")]
+            2 => Ok(Self::Zstd(reader.read_i32::<byteorder::BigEndian>()?)),
+
+            tag => Err(DeserializeError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl CompressionType {
+    /// Compresses the given bytes
+    // NOTE: Only zstd compression can fail
+    #[allow(clippy::unnecessary_wraps)]
+    pub(crate) fn compress(self, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+        Ok(match self {
+            Self::None => bytes.to_vec(),
+            Self::Lz4 => compress_prepend_size(bytes),
+
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => zstd::encode_all(bytes, level)?,
+        })
+    }
+
+    /// Decompresses the given bytes
+    pub(crate) fn decompress(self, bytes: Vec<u8>) -> crate::Result<Vec<u8>> {
+        Ok(match self {
+            Self::None => bytes,
+            Self::Lz4 => decompress_size_prepended(&bytes)?,
+
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => zstd::decode_all(&*bytes)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn round_trip(compression: CompressionType) -> crate::Result<()> {
+        let bytes = b"abcdefghijklmnopqrstuvwxyz".repeat(100);
+
+        let compressed = compression.compress(&bytes)?;
+        let decompressed = compression.decompress(compressed)?;
+        assert_eq!(bytes, decompressed);
+
+        let mut encoded = vec![];
+        Serializable::serialize(&compression, &mut encoded)?;
+        let decoded = <CompressionType as Deserializable>::deserialize(&mut &encoded[..])?;
+        assert_eq!(compression, decoded);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compression_round_trip_none() -> crate::Result<()> {
+        round_trip(CompressionType::None)
+    }
+
+    #[test]
+    fn compression_round_trip_lz4() -> crate::Result<()> {
+        round_trip(CompressionType::Lz4)
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn compression_round_trip_zstd() -> crate::Result<()> {
+        round_trip(CompressionType::Zstd(3))
+    }
+}
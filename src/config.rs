@@ -1,10 +1,11 @@
 use crate::{
-    compaction::{self, CompactionStrategy},
-    BlockCache, Tree,
+    compaction::{self, CompactionFilter, CompactionStrategy},
+    BlockCache, CompressionType, MergeOperator, Tree,
 };
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 #[derive(Clone)]
@@ -19,6 +20,19 @@ pub struct Config {
     /// Block cache
     pub block_cache: Arc<BlockCache>,
 
+    /// Bits per key for the segments' bloom filters
+    ///
+    /// 0 = no bloom filters
+    pub bloom_bits_per_key: u8,
+
+    /// Compression to use for data blocks
+    pub compression: CompressionType,
+
+    /// Per-level compression overrides for data blocks
+    ///
+    /// If empty, `compression` is used for every level
+    pub compression_per_level: Vec<CompressionType>,
+
     /// Maximum size in bytes of the write buffer
     pub max_memtable_size: u32,
 
@@ -41,6 +55,28 @@ pub struct Config {
 
     /// Compaction strategy to use
     pub(crate) compaction_strategy: Arc<dyn CompactionStrategy + Send + Sync>,
+
+    /// Merge operator to fold merge operands with
+    pub(crate) merge_operator: Option<Arc<dyn MergeOperator + Send + Sync>>,
+
+    /// Compaction filter that is applied to all items written by compactions
+    pub(crate) compaction_filter: Option<Arc<dyn CompactionFilter + Send + Sync>>,
+
+    /// Values larger than this (in bytes) are stored in blob files
+    ///
+    /// None = no key-value separation
+    pub blob_threshold: Option<u32>,
+
+    /// Time-to-live of inserted items, if not given explicitly
+    ///
+    /// None = items do not expire
+    pub default_ttl: Option<Duration>,
+
+    /// Maximum time a pessimistic transaction waits for a key lock
+    pub lock_timeout: Duration,
+
+    /// Amount of flushed journals that are kept for change subscriptions
+    pub max_retained_journals: u16,
 }
 
 const DEFAULT_FILE_FOLDER: &str = ".lsm.data";
@@ -51,12 +87,21 @@ impl Default for Config {
             path: DEFAULT_FILE_FOLDER.into(),
             block_size: 4_096,
             block_cache: Arc::new(BlockCache::with_capacity_blocks(4_096)),
+            bloom_bits_per_key: 10,
+            compression: CompressionType::Lz4,
+            compression_per_level: Vec::new(),
             max_memtable_size: 16 * 1_024 * 1_024,
             level_count: 7,
             level_ratio: 8,
             compaction_strategy: Arc::new(compaction::Levelled::default()),
             flush_threads: 4,
             fsync_ms: Some(1_000),
+            merge_operator: None,
+            compaction_filter: None,
+            blob_threshold: None,
+            default_ttl: None,
+            lock_timeout: Duration::from_secs(1),
+            max_retained_journals: 0,
         }
     }
 }
@@ -176,6 +221,64 @@ impl Config {
         self
     }
 
+    /// Sets the bits per key of the bloom filters that are built for each segment.
+    ///
+    /// Bloom filters allow skipping segments that definitely don't contain a key,
+    /// which greatly speeds up point reads of non-existing keys.
+    ///
+    /// 10 bits per key result in a false positive rate of roughly 1%.
+    /// Setting it to 0 disables bloom filters.
+    ///
+    /// Defaults to 10.
+    #[must_use]
+    pub fn bloom_bits_per_key(mut self, bits: u8) -> Self {
+        self.bloom_bits_per_key = bits;
+        self
+    }
+
+    /// Sets the compression algorithm to use for data blocks.
+    ///
+    /// Existing segments are not rewritten when this is changed,
+    /// each segment remembers the compression it was written with.
+    ///
+    /// Defaults to [`CompressionType::Lz4`].
+    #[must_use]
+    pub fn compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the compression algorithm to use for each level.
+    ///
+    /// The first entry is used for L0, the second for L1 and so on.
+    /// If there are more levels than entries, the last entry is used
+    /// for the remaining levels.
+    ///
+    /// This allows using a fast compression (or none) for the upper levels,
+    /// and a denser compression for the last levels.
+    ///
+    /// Defaults to using [`Config::compression`] for every level.
+    #[must_use]
+    pub fn compression_per_level(mut self, compression: Vec<CompressionType>) -> Self {
+        self.compression_per_level = compression;
+        self
+    }
+
+    /// Returns the compression to use for data blocks of the given level.
+    ///
+    /// Levels past the end of [`Config::compression_per_level`] use its last entry.
+    /// If the list is empty, [`Config::compression`] is used.
+    ///
+    /// Index blocks are always compressed using [`CompressionType::Lz4`],
+    /// regardless of this setting.
+    pub(crate) fn compression_for_level(&self, level: u8) -> CompressionType {
+        self.compression_per_level
+            .get(usize::from(level))
+            .or_else(|| self.compression_per_level.last())
+            .copied()
+            .unwrap_or(self.compression)
+    }
+
     /// Sets the compaction strategy to use.
     ///
     /// Defaults to [`compaction::Levelled`]
@@ -188,6 +291,78 @@ impl Config {
         self
     }
 
+    /// Sets the merge operator, which is used to fold the operands
+    /// written by [`Tree::merge`].
+    ///
+    /// Defaults to none.
+    #[must_use]
+    pub fn merge_operator(mut self, operator: Arc<dyn MergeOperator + Send + Sync>) -> Self {
+        self.merge_operator = Some(operator);
+        self
+    }
+
+    /// Sets the compaction filter, which can keep, drop or rewrite
+    /// every item that is written by a compaction.
+    ///
+    /// Defaults to none.
+    #[must_use]
+    pub fn compaction_filter(mut self, filter: Arc<dyn CompactionFilter + Send + Sync>) -> Self {
+        self.compaction_filter = Some(filter);
+        self
+    }
+
+    /// Sets the value size (in bytes) above which values are stored in separate blob files
+    /// (key-value separation).
+    ///
+    /// Segments then only store a small pointer to the value, which makes compactions much
+    /// cheaper for large values, at the cost of an additional read per large value.
+    ///
+    /// Space of overwritten or deleted values is reclaimed using [`Tree::blob_gc`].
+    ///
+    /// Defaults to none (values are always stored inline).
+    #[must_use]
+    pub fn blob_threshold(mut self, bytes: u32) -> Self {
+        self.blob_threshold = Some(bytes);
+        self
+    }
+
+    /// Sets the time-to-live of inserted items.
+    ///
+    /// Expired items are not returned by reads anymore, and are
+    /// physically dropped by compactions.
+    ///
+    /// The TTL can also be set per item, using [`Tree::insert_with_ttl`].
+    ///
+    /// Defaults to none (items do not expire).
+    #[must_use]
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the maximum time a pessimistic transaction waits for a key lock,
+    /// before failing with [`Error::LockTimeout`](crate::Error::LockTimeout).
+    ///
+    /// Defaults to 1 second.
+    #[must_use]
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Sets the amount of journals that are kept after their memtable has been flushed.
+    ///
+    /// Retained journals allow [`Tree::subscribe_from`] to deliver changes
+    /// that are not in the active journal anymore, e.g. to catch up after a disconnect.
+    /// When more journals are retained, the oldest ones are deleted.
+    ///
+    /// Defaults to 0 (journals are deleted after flushing).
+    #[must_use]
+    pub fn max_retained_journals(mut self, n: u16) -> Self {
+        self.max_retained_journals = n;
+        self
+    }
+
     /// Opens a tree using the config.
     ///
     /// # Errors
@@ -196,4 +371,36 @@ impl Config {
     pub fn open(self) -> crate::Result<Tree> {
         Tree::open(self)
     }
+
+    /// Opens an existing tree without modifying its folder, e.g. to inspect a tree
+    /// that is in use by another process.
+    ///
+    /// Journals are read without being repaired or flushed, and no background
+    /// threads (fsync, flush, compaction) are started.
+    /// All writes fail with [`Error::ReadOnly`](crate::Error::ReadOnly).
+    ///
+    /// Call [`Tree::try_catch_up`] to see writes that happened after opening the tree.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the tree does not exist.
+    pub fn open_read_only(self) -> crate::Result<Tree> {
+        Tree::open_read_only(self)
+    }
+
+    /// Opens a tree as a follower, which does not accept writes, and instead
+    /// applies the writes of a leader tree using [`Tree::apply_batch`].
+    ///
+    /// A follower can be created from a checkpoint of the leader (see [`Tree::checkpoint`]),
+    /// and is turned into a normal tree using [`Tree::promote`].
+    ///
+    /// The follower mode is persisted, so the tree stays a follower when it is
+    /// reopened (also using [`Config::open`]), until it is promoted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn open_follower(self) -> crate::Result<Tree> {
+        Tree::open_follower(self)
+    }
 }
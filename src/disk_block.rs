@@ -1,6 +1,8 @@
-use crate::serde::{Deserializable, DeserializeError, Serializable, SerializeError};
+use crate::{
+    serde::{Deserializable, DeserializeError, Serializable, SerializeError},
+    CompressionType,
+};
 use byteorder::{BigEndian, ReadBytesExt};
-use lz4_flex::decompress_size_prepended;
 use std::io::{Cursor, Read, Write};
 
 /// Contains the items of a block after decompressing & deserializing.
@@ -13,11 +15,15 @@ pub struct DiskBlock<T: Clone + Serializable + Deserializable> {
 }
 
 impl<T: Clone + Serializable + Deserializable> DiskBlock<T> {
-    pub fn from_reader_compressed<R: Read>(reader: &mut R, size: u32) -> crate::Result<Self> {
+    pub fn from_reader_compressed<R: Read>(
+        reader: &mut R,
+        size: u32,
+        compression: CompressionType,
+    ) -> crate::Result<Self> {
         let mut bytes = vec![0u8; size as usize];
         reader.read_exact(&mut bytes)?;
 
-        let bytes = decompress_size_prepended(&bytes)?;
+        let bytes = compression.decompress(bytes)?;
         let mut bytes = Cursor::new(bytes);
 
         let block = Self::deserialize(&mut bytes)?;
@@ -29,10 +35,11 @@ impl<T: Clone + Serializable + Deserializable> DiskBlock<T> {
         reader: &mut R,
         offset: u64,
         size: u32,
+        compression: CompressionType,
     ) -> crate::Result<Self> {
         // Read bytes from disk
         reader.seek(std::io::SeekFrom::Start(offset))?;
-        Self::from_reader_compressed(reader, size)
+        Self::from_reader_compressed(reader, size, compression)
     }
 }
 
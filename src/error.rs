@@ -20,6 +20,35 @@ pub enum Error {
 
     /// Error during journal recovery
     JournalRecovery(JournalRecoveryError),
+
+    /// A transaction could not be committed, because a key it has read
+    /// was written by someone else in the meantime
+    TransactionConflict,
+
+    /// A pessimistic transaction could not acquire a lock
+    /// within the configured lock timeout
+    LockTimeout,
+
+    /// A pessimistic transaction could not acquire a lock,
+    /// because waiting for it would result in a deadlock
+    Deadlock,
+
+    /// A merge operand could not be written, because no merge operator
+    /// is configured (see [`Config::merge_operator`](crate::Config::merge_operator))
+    MergeOperatorMissing,
+
+    /// A change subscription could not be created, because the journals
+    /// containing the requested changes are not retained anymore
+    ChangesNotRetained,
+
+    /// A write was attempted on a tree that does not accept writes, because it
+    /// was opened read-only (see [`Config::open_read_only`](crate::Config::open_read_only))
+    /// or as a follower (see [`Config::open_follower`](crate::Config::open_follower))
+    ReadOnly,
+
+    /// A batch could not be applied to a follower, because batches
+    /// with lower seqnos are missing (see [`Tree::apply_batch`](crate::Tree::apply_batch))
+    ReplicationGap,
 }
 
 impl std::fmt::Display for Error {
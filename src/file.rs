@@ -1,15 +1,30 @@
-use std::{fs::File, io::Write, path::Path};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 pub const LSM_MARKER: &str = ".lsm";
 pub const FLUSH_MARKER: &str = ".flush";
+pub const FOLLOWER_MARKER: &str = ".follower";
 pub const LEVELS_MANIFEST_FILE: &str = "levels.json";
+pub const MANIFEST_FILE: &str = "manifest";
+pub const SNAPSHOTS_FILE: &str = "snapshots.json";
 pub const JOURNALS_FOLDER: &str = "journals";
+pub const RETAINED_JOURNALS_FOLDER: &str = "retained_journals";
 
 pub const SEGMENTS_FOLDER: &str = "segments";
 pub const BLOCKS_FILE: &str = "blocks";
-pub const INDEX_BLOCKS_FILE: &str = "index_blocks";
 pub const TOP_LEVEL_INDEX_FILE: &str = "index";
-pub const SEGMENT_METADATA_FILE: &str = "meta.json";
+pub const SEGMENT_METADATA_FILE: &str = "meta";
+pub const LEGACY_SEGMENT_METADATA_FILE: &str = "meta.json";
+pub const BLOOM_FILTER_FILE: &str = "bloom";
+pub const RANGE_TOMBSTONES_FILE: &str = "range_tombstones";
+
+pub const BLOBS_FOLDER: &str = "blobs";
+
+pub const BACKUPS_FOLDER: &str = "backups";
+pub const BACKUP_MANIFEST_FILE: &str = "backup.json";
 
 /// Atomically rewrites a file
 pub fn rewrite_atomic<P: AsRef<Path>>(path: P, content: &[u8]) -> std::io::Result<()> {
@@ -31,6 +46,119 @@ pub fn rewrite_atomic<P: AsRef<Path>>(path: P, content: &[u8]) -> std::io::Resul
     Ok(())
 }
 
+/// Returns the path of the file that stores the rewritten metadata of a segment file
+///
+/// See `Metadata::write_to_file`.
+pub fn segment_sidecar_path(segment_path: &Path) -> PathBuf {
+    segment_path.with_extension(SEGMENT_METADATA_FILE)
+}
+
+/// Returns `true` if the path points to the metadata sidecar of a segment file
+pub fn is_segment_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|x| x == SEGMENT_METADATA_FILE)
+}
+
+/// Hard links a file, or copies it if that is not possible
+/// (e.g. because the destination is on another file system)
+pub fn link_or_copy_file(src: &Path, dest: &Path) -> crate::Result<()> {
+    match std::fs::hard_link(src, dest) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(e.into()),
+        Err(_) => copy_file(src, dest),
+    }
+}
+
+/// Hard links (or copies) all files of a folder into a new folder
+pub fn link_folder(src: &Path, dest: &Path) -> crate::Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for dirent in std::fs::read_dir(src)? {
+        let dirent = dirent?;
+        link_or_copy_file(&dirent.path(), &dest.join(dirent.file_name()))?;
+    }
+
+    Ok(())
+}
+
+/// Hard links (or copies) a segment file (and its metadata sidecar), or all files of a segment folder
+pub fn link_file_or_folder(src: &Path, dest: &Path) -> crate::Result<()> {
+    if src.is_dir() {
+        return link_folder(src, dest);
+    }
+
+    link_or_copy_file(src, dest)?;
+
+    let sidecar = segment_sidecar_path(src);
+    if sidecar.try_exists()? {
+        link_or_copy_file(&sidecar, &segment_sidecar_path(dest))?;
+    }
+
+    Ok(())
+}
+
+/// Copies a file, and fsyncs the copy
+pub fn copy_file(src: &Path, dest: &Path) -> crate::Result<()> {
+    std::fs::copy(src, dest)?;
+    File::open(dest)?.sync_all()?;
+    Ok(())
+}
+
+/// Copies all files of a folder into a new folder
+pub fn copy_folder(src: &Path, dest: &Path) -> crate::Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for dirent in std::fs::read_dir(src)? {
+        let dirent = dirent?;
+        copy_file(&dirent.path(), &dest.join(dirent.file_name()))?;
+    }
+
+    Ok(())
+}
+
+/// Copies a segment file (and its metadata sidecar), or all files of a segment folder
+pub fn copy_file_or_folder(src: &Path, dest: &Path) -> crate::Result<()> {
+    if src.is_dir() {
+        return copy_folder(src, dest);
+    }
+
+    copy_file(src, dest)?;
+
+    let sidecar = segment_sidecar_path(src);
+    if sidecar.try_exists()? {
+        copy_file(&sidecar, &segment_sidecar_path(dest))?;
+    }
+
+    Ok(())
+}
+
+/// Moves a segment file (and its metadata sidecar), or a segment folder
+///
+/// The sidecar is moved first, so the segment is complete once it exists at `dest`.
+pub fn rename_file_or_folder(src: &Path, dest: &Path) -> std::io::Result<()> {
+    let sidecar = segment_sidecar_path(src);
+    if !src.is_dir() && sidecar.try_exists()? {
+        std::fs::rename(sidecar, segment_sidecar_path(dest))?;
+    }
+
+    std::fs::rename(src, dest)
+}
+
+/// Deletes a segment file (and its metadata sidecar), or a segment folder
+pub fn remove_file_or_folder(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        return std::fs::remove_dir_all(path);
+    }
+
+    std::fs::remove_file(path)?;
+
+    // NOTE: The sidecar is removed last, so the segment file
+    // never loses its rewritten metadata
+    match std::fs::remove_file(segment_sidecar_path(path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,11 +1,13 @@
 use crate::{
+    blob::BlobWriter,
+    change_feed::retire_journal,
     compaction::worker::start_compaction_thread,
-    descriptor_table::FileDescriptorTable,
-    file::{BLOCKS_FILE, FLUSH_MARKER, JOURNALS_FOLDER, SEGMENTS_FOLDER},
+    file::{FLUSH_MARKER, JOURNALS_FOLDER, SEGMENTS_FOLDER},
     id::generate_segment_id,
     journal::Journal,
     memtable::MemTable,
-    segment::{index::BlockIndex, meta::Metadata, writer::Writer, Segment},
+    segment::{meta::Metadata, writer::Writer, Segment},
+    value::ValueType,
     Tree,
 };
 use std::{fs::File, path::Path, sync::Arc};
@@ -16,12 +18,14 @@ fn flush_worker(
     segment_id: &str,
     old_journal_folder: &Path,
 ) -> crate::Result<()> {
-    let segment_folder = tree.config.path.join(SEGMENTS_FOLDER).join(segment_id);
+    let segment_path = tree.config.path.join(SEGMENTS_FOLDER).join(segment_id);
 
     let mut segment_writer = Writer::new(crate::segment::writer::Options {
-        path: segment_folder.clone(),
+        path: segment_path,
         evict_tombstones: false,
         block_size: tree.config.block_size,
+        bloom_bits_per_key: tree.config.bloom_bits_per_key,
+        compression: tree.config.compression_for_level(0),
     })?;
 
     log::debug!(
@@ -29,20 +33,40 @@ fn flush_worker(
         segment_writer.opts.path.display()
     );
 
+    segment_writer.set_range_tombstones(old_memtable.get_range_tombstones());
+
+    let mut blob_writer = None;
+
     for entry in &old_memtable.items {
         let key = entry.key();
         let value = entry.value();
-        segment_writer.write(crate::Value::from(((key.clone()), value.clone())))?;
+        let mut item = crate::Value::from(((key.clone()), value.clone()));
+
+        if let Some(threshold) = tree.config.blob_threshold {
+            if item.value_type == ValueType::Value && item.value.len() > threshold as usize {
+                let blob_writer = match &mut blob_writer {
+                    Some(writer) => writer,
+                    None => blob_writer.insert(tree.blobs.writer()?),
+                };
+
+                let pointer = blob_writer.write(&item.key, &item.value)?;
+                item.value = pointer.to_bytes()?.into();
+                item.value_type = ValueType::Indirection;
+            }
+        }
+
+        segment_writer.write(item)?;
     }
 
+    // NOTE: The blob file needs to be durable before the segment that points into it
+    let blob_file = blob_writer.map(BlobWriter::finish).transpose()?;
+
     segment_writer.finish()?;
     log::debug!("Finalized segment write");
 
     let metadata = Metadata::from_writer(segment_id.into(), segment_writer)?;
     metadata.write_to_file()?;
 
-    let descriptor_table = Arc::new(FileDescriptorTable::new(metadata.path.join(BLOCKS_FILE))?);
-
     /* // TODO:: Don't use global block cache for L0 segments maybe
     // similar to RocksDB's `pin_l0_filter_and_index_blocks_in_cache`
     // Benchmarking didn't yield great results yet though
@@ -50,42 +74,31 @@ fn flush_worker(
         metadata.block_count as usize + 512,
     )); */
 
-    match BlockIndex::from_file(
-        segment_id.into(),
-        Arc::clone(&descriptor_table),
-        &segment_folder,
-        Arc::clone(&tree.block_cache),
-    )
-    .map(Arc::new)
-    {
-        Ok(block_index) => {
+    match Segment::load(metadata, Arc::clone(&tree.block_cache)) {
+        Ok(created_segment) => {
             /* log::debug!("Preloading BlockIndex");
-            block_index.preload()?; */
-
-            let created_segment = Segment {
-                descriptor_table,
-                metadata,
-                block_index,
-                block_cache: Arc::clone(&tree.block_cache),
-            };
+            created_segment.block_index.preload()?; */
 
             log::debug!("flush: acquiring levels manifest write lock");
             let mut levels = tree.levels.write().expect("lock is poisoned");
             levels.add(Arc::new(created_segment));
             levels.write_to_disk()?;
 
+            if let Some((blob_id, size)) = blob_file {
+                tree.blobs.register(blob_id, size);
+            }
+
             log::debug!("flush: acquiring immu memtables write lock");
             let mut memtable_lock = tree.immutable_memtables.write().expect("lock is poisoned");
             memtable_lock.remove(segment_id);
 
             drop(memtable_lock);
-            drop(levels);
 
-            log::debug!(
-                "Deleting old journal folder: {}",
-                old_journal_folder.display()
-            );
-            std::fs::remove_dir_all(old_journal_folder)?;
+            // NOTE: Retire the journal while holding the levels lock,
+            // so change subscriptions do not miss it while it is being moved
+            retire_journal(&tree.config, old_journal_folder)?;
+
+            drop(levels);
         }
         Err(error) => {
             log::error!("Flush worker error: {:?}", error);
@@ -98,6 +111,8 @@ fn flush_worker(
 }
 
 pub fn start(tree: &Tree) -> crate::Result<std::thread::JoinHandle<crate::Result<()>>> {
+    tree.check_not_read_only()?;
+
     log::debug!("Acquiring flush semaphore");
     tree.flush_semaphore.acquire();
     log::trace!("Got flush semaphore");
@@ -108,7 +123,7 @@ pub fn start(tree: &Tree) -> crate::Result<std::thread::JoinHandle<crate::Result
     log::debug!("flush: acquiring memtable write lock");
     let mut memtable_lock = tree.active_memtable.write().expect("lock is poisoned");
 
-    if memtable_lock.items.is_empty() {
+    if memtable_lock.is_empty() {
         log::debug!("MemTable is empty (so another thread beat us to it) - aborting flush");
 
         // TODO: this is a bit stupid, change it
@@ -0,0 +1,176 @@
+use crate::{
+    compaction::worker::start_compaction_thread,
+    file::{
+        link_or_copy_file, LEGACY_SEGMENT_METADATA_FILE, SEGMENTS_FOLDER, SEGMENT_METADATA_FILE,
+    },
+    id::generate_segment_id,
+    segment::{meta::Metadata, Segment},
+    time::unix_timestamp,
+    Tree,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+fn invalid_input(msg: &str) -> crate::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, msg).into()
+}
+
+/// Hard links (or if that fails, copies) the files of a built segment into
+/// the tree's segment folder
+///
+/// The metadata is not linked, because it is rewritten for the ingested segment,
+/// which does not modify the linked files (see [`Metadata::write_to_file`]).
+fn link_segment_files(src: &Path, dest: &Path) -> crate::Result<()> {
+    if !src.is_dir() {
+        return link_or_copy_file(src, dest);
+    }
+
+    std::fs::create_dir_all(dest)?;
+
+    for dirent in std::fs::read_dir(src)? {
+        let dirent = dirent?;
+
+        if dirent.file_name() == SEGMENT_METADATA_FILE
+            || dirent.file_name() == LEGACY_SEGMENT_METADATA_FILE
+        {
+            continue;
+        }
+
+        link_or_copy_file(&dirent.path(), &dest.join(dirent.file_name()))?;
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        // fsync folder on Unix
+        let folder = std::fs::File::open(dest)?;
+        folder.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Ingests segments that were built using [`crate::SegmentBuilder`]
+///
+/// See [`Tree::ingest`]
+// NOTE: A panic while holding the levels or memtable locks
+// would leave the tree unusable anyway
+#[allow(clippy::expect_used)]
+pub fn ingest<P: AsRef<Path>>(tree: &Tree, paths: &[P]) -> crate::Result<()> {
+    log::info!("Ingesting {} segments", paths.len());
+
+    let start = std::time::Instant::now();
+
+    let mut built_segments = paths
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            Metadata::recover(path).map(|meta| (path, meta))
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    if built_segments.is_empty() {
+        return Ok(());
+    }
+
+    // NOTE: All ingested items get the same seqno,
+    // so a key may not be contained in multiple ingested segments
+    built_segments.sort_by(|(_, a), (_, b)| a.key_range.0.cmp(&b.key_range.0));
+
+    for window in built_segments.windows(2) {
+        if window[0].1.key_range.1 >= window[1].1.key_range.0 {
+            return Err(invalid_input("ingested segments may not overlap"));
+        }
+    }
+
+    if built_segments
+        .iter()
+        .any(|(_, meta)| !meta.blob_refs.is_empty())
+    {
+        return Err(invalid_input(
+            "ingested segments may not reference blob files",
+        ));
+    }
+
+    // NOTE: Flush the active memtable, so the ingested items
+    // shadow all items that were written before
+    tree.wait_for_memtable_flush()?;
+
+    let mut linked_segments: Vec<(Arc<str>, PathBuf, Metadata)> =
+        Vec::with_capacity(built_segments.len());
+
+    for (src, metadata) in built_segments {
+        let segment_id = generate_segment_id();
+        let segment_path = tree.config.path.join(SEGMENTS_FOLDER).join(&*segment_id);
+
+        log::debug!(
+            "ingest: linking {} -> {}",
+            src.display(),
+            segment_path.display()
+        );
+
+        link_segment_files(src, &segment_path)?;
+        linked_segments.push((segment_id, segment_path, metadata));
+    }
+
+    // NOTE: Immutable memtables are read before segments,
+    // so wait for all pending flushes to finish
+    let mut levels = loop {
+        log::debug!("ingest: acquiring levels manifest write lock");
+        let levels = tree.levels.write().expect("lock is poisoned");
+
+        if tree
+            .immutable_memtables
+            .read()
+            .expect("lock is poisoned")
+            .is_empty()
+        {
+            break levels;
+        }
+
+        drop(levels);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    };
+
+    let seqno = tree.increment_lsn();
+
+    let mut segments = Vec::with_capacity(linked_segments.len());
+
+    for (segment_id, segment_path, mut metadata) in linked_segments {
+        metadata.id = segment_id;
+        metadata.path = segment_path;
+        metadata.created_at = unix_timestamp().as_micros();
+        metadata.seqnos = (seqno, seqno);
+        metadata.global_seqno = Some(seqno);
+        metadata.write_to_file()?;
+
+        segments.push(Segment::load(metadata, Arc::clone(&tree.block_cache))?);
+    }
+
+    for segment in segments {
+        let level_no = levels.ingestion_level(&segment.metadata.key_range);
+
+        log::debug!(
+            "ingest: inserting segment {} into level {level_no}",
+            segment.metadata.id
+        );
+
+        levels.insert_into_level(level_no, Arc::new(segment));
+    }
+
+    // NOTE: The ingestion is atomic, because the segments only become
+    // part of the tree once the levels manifest is written,
+    // unfinished segments are deleted when the tree is recovered
+    levels.write_to_disk()?;
+    drop(levels);
+
+    log::info!(
+        "Ingested segments with seqno {seqno} in {}s",
+        start.elapsed().as_secs_f32()
+    );
+
+    start_compaction_thread(tree);
+
+    Ok(())
+}
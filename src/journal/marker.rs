@@ -1,6 +1,8 @@
 use crate::{
-    serde::{Deserializable, DeserializeError, Serializable, SerializeError},
-    value::{SeqNo, UserData, UserKey, ValueType},
+    serde::{read_exact_len, Deserializable, DeserializeError, Serializable, SerializeError},
+    value::{read_value_type, write_value_type, SeqNo, UserData, UserKey, ValueType},
+    varint::{read_varint, write_varint},
+    version::Version,
 };
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io::{Read, Write};
@@ -17,7 +19,11 @@ use std::io::{Read, Write};
 ///
 /// start: \[tag (0x0); 1 byte] \[item count; 4 bytes] \[seqno; 8 bytes]
 ///
-/// item: \[tag (0x1); 1 byte] \[tombstone; 1 byte] \[key length; 2 bytes] \[key; N bytes] \[value length; 2 bytes] \[value: N bytes]
+/// item: \[tag (0x1); 1 byte] \[tombstone; 1 byte] \[expiry; 8 bytes, optional] \[key length; varint] \[key; N bytes] \[value length; varint] \[value: N bytes]
+///
+/// The expiry is only present if the highest bit of the tombstone byte is set.
+///
+/// In [`Version::V0`] journals, the key and value lengths are stored as 2 bytes each.
 ///
 /// end: \[tag (0x2): 1 byte] \[crc value; 4 bytes]
 #[derive(Debug, Eq, PartialEq)]
@@ -30,6 +36,7 @@ pub enum Marker {
         key: UserKey,
         value: UserData,
         value_type: ValueType,
+        expires_at: Option<u64>,
     },
     End(u32),
 }
@@ -61,8 +68,37 @@ impl From<Tag> for u8 {
     }
 }
 
-impl Serializable for Marker {
-    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+/// Writes a key or value length in the given journal version
+fn write_len<W: Write>(writer: &mut W, len: usize, version: Version) -> Result<(), SerializeError> {
+    match version {
+        Version::V0 => {
+            // NOTE: Truncation is okay and actually needed
+            #[allow(clippy::cast_possible_truncation)]
+            writer.write_u16::<BigEndian>(len as u16)?;
+        }
+        Version::V1 | Version::V2 | Version::V3 | Version::V4 => {
+            write_varint(writer, len as u64)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a key or value length in the given journal version
+fn read_len<R: Read>(reader: &mut R, version: Version) -> Result<u64, DeserializeError> {
+    Ok(match version {
+        Version::V0 => reader.read_u16::<BigEndian>()?.into(),
+        Version::V1 | Version::V2 | Version::V3 | Version::V4 => read_varint(reader)?,
+    })
+}
+
+impl Marker {
+    /// Serializes the marker in the disk representation of the given journal version
+    pub fn serialize_versioned<W: Write>(
+        &self,
+        writer: &mut W,
+        version: Version,
+    ) -> Result<(), SerializeError> {
         use Marker::{End, Item, Start};
 
         match self {
@@ -75,19 +111,16 @@ impl Serializable for Marker {
                 key,
                 value,
                 value_type,
+                expires_at,
             } => {
                 writer.write_u8(Tag::Item.into())?;
 
-                writer.write_u8(u8::from(*value_type))?;
+                write_value_type(writer, *value_type, *expires_at)?;
 
-                // NOTE: Truncation is okay and actually needed
-                #[allow(clippy::cast_possible_truncation)]
-                writer.write_u16::<BigEndian>(key.len() as u16)?;
+                write_len(writer, key.len(), version)?;
                 writer.write_all(key)?;
 
-                // NOTE: Truncation is okay and actually needed
-                #[allow(clippy::cast_possible_truncation)]
-                writer.write_u16::<BigEndian>(value.len() as u16)?;
+                write_len(writer, value.len(), version)?;
                 writer.write_all(value)?;
             }
             End(val) => {
@@ -97,10 +130,12 @@ impl Serializable for Marker {
         }
         Ok(())
     }
-}
 
-impl Deserializable for Marker {
-    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+    /// Deserializes a marker in the disk representation of the given journal version
+    pub fn deserialize_versioned<R: Read>(
+        reader: &mut R,
+        version: Version,
+    ) -> Result<Self, DeserializeError> {
         match reader.read_u8()?.try_into()? {
             Tag::Start => {
                 let item_count = reader.read_u32::<BigEndian>()?;
@@ -108,20 +143,19 @@ impl Deserializable for Marker {
                 Ok(Self::Start { item_count, seqno })
             }
             Tag::Item => {
-                let value_type = reader.read_u8()?.into();
+                let (value_type, expires_at) = read_value_type(reader)?;
 
-                let key_len = reader.read_u16::<BigEndian>()?;
-                let mut key = vec![0; key_len.into()];
-                reader.read_exact(&mut key)?;
+                let key_len = read_len(reader, version)?;
+                let key = read_exact_len(reader, key_len)?;
 
-                let value_len = reader.read_u16::<BigEndian>()?;
-                let mut value = vec![0; value_len as usize];
-                reader.read_exact(&mut value)?;
+                let value_len = read_len(reader, version)?;
+                let value = read_exact_len(reader, value_len)?;
 
                 Ok(Self::Item {
                     value_type,
                     key: key.into(),
                     value: value.into(),
+                    expires_at,
                 })
             }
             Tag::End => {
@@ -132,6 +166,18 @@ impl Deserializable for Marker {
     }
 }
 
+impl Serializable for Marker {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        self.serialize_versioned(writer, Version::V1)
+    }
+}
+
+impl Deserializable for Marker {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        Self::deserialize_versioned(reader, Version::V1)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,6 +189,7 @@ mod tests {
             key: vec![1, 2, 3].into(),
             value: vec![].into(),
             value_type: ValueType::Value,
+            expires_at: None,
         };
 
         // Serialize
@@ -158,6 +205,27 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_serialize_and_deserialize_v0() -> crate::Result<()> {
+        let item = Marker::Item {
+            key: vec![1, 2, 3].into(),
+            value: vec![4, 5, 6].into(),
+            value_type: ValueType::Value,
+            expires_at: None,
+        };
+
+        let mut serialized_data = Vec::new();
+        item.serialize_versioned(&mut serialized_data, Version::V0)?;
+        assert_eq!(1 + 1 + 2 + 3 + 2 + 3, serialized_data.len());
+
+        let mut reader = &serialized_data[..];
+        let deserialized_item = Marker::deserialize_versioned(&mut reader, Version::V0)?;
+
+        assert_eq!(item, deserialized_item);
+
+        Ok(())
+    }
+
     #[test]
     fn test_invalid_deserialize() {
         let invalid_data = [Tag::Start as u8; 1]; // Should be followed by a u32
@@ -1,10 +1,15 @@
 mod marker;
+pub mod reader;
 mod recovery;
 pub mod shard;
 
-use self::shard::JournalShard;
-use crate::{memtable::MemTable, sharded::Sharded};
+use self::{reader::JournalBatchReader, recovery::read_shard_version, shard::JournalShard};
+use crate::{
+    change_feed::ChangeFeed, memtable::MemTable, sharded::Sharded, value::SeqNo, version::Version,
+    Value,
+};
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     sync::{RwLock, RwLockWriteGuard},
 };
@@ -18,6 +23,9 @@ fn get_shard_path<P: AsRef<Path>>(base: P, idx: u8) -> PathBuf {
 pub struct Journal {
     pub path: PathBuf,
     pub shards: Sharded<JournalShard>,
+
+    /// Subscribers that are notified about every batch written into the journal
+    pub(crate) changes: ChangeFeed,
 }
 
 impl Journal {
@@ -53,11 +61,33 @@ impl Journal {
             Self {
                 shards: Sharded::new(shards),
                 path: path.to_path_buf(),
+                changes: ChangeFeed::default(),
             },
             memtable,
         ))
     }
 
+    /// Returns the disk format version of a journal
+    ///
+    /// If any shard was written in [`Version::V0`], the journal is considered to be [`Version::V0`].
+    pub fn get_version<P: AsRef<Path>>(path: P) -> crate::Result<Version> {
+        for idx in 0..SHARD_COUNT {
+            let shard_path = get_shard_path(&path, idx);
+
+            if !shard_path.exists() {
+                continue;
+            }
+
+            let mut file = std::fs::File::open(shard_path)?;
+
+            if read_shard_version(&mut file)? == Some(Version::V0) {
+                return Ok(Version::V0);
+            }
+        }
+
+        Ok(Version::V1)
+    }
+
     pub fn rotate<P: AsRef<Path>>(
         path: P,
         shards: &mut [RwLockWriteGuard<'_, JournalShard>],
@@ -98,11 +128,117 @@ impl Journal {
         Ok(Self {
             shards: Sharded::new(shards),
             path: path.to_path_buf(),
+            changes: ChangeFeed::default(),
         })
     }
 
+    /// Creates a journal without shards, which can not be written to
+    ///
+    /// Used by trees that are opened read-only.
+    pub fn read_only<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            shards: Sharded::new(vec![]),
+            path: path.as_ref().to_path_buf(),
+            changes: ChangeFeed::default(),
+        }
+    }
+
+    /// Reads all committed batches of a journal, sorted by seqno
+    ///
+    /// The journal is not repaired, so this can be used on journals that are still written to.
+    pub fn read_batches<P: AsRef<Path>>(path: P) -> crate::Result<Vec<(SeqNo, Vec<Value>)>> {
+        let mut batches = vec![];
+
+        for idx in 0..SHARD_COUNT {
+            let shard_path = get_shard_path(&path, idx);
+
+            if shard_path.exists() {
+                for batch in JournalBatchReader::new(shard_path)? {
+                    batches.push(batch?);
+                }
+            }
+        }
+
+        batches.sort_by_key(|(seqno, _)| *seqno);
+
+        Ok(batches)
+    }
+
+    /// Reads the batches that were committed to a journal since the last call, sorted by seqno
+    ///
+    /// The read position of every shard file is kept in `positions`.
+    pub fn read_new_batches<P: AsRef<Path>>(
+        path: P,
+        positions: &mut HashMap<PathBuf, u64>,
+    ) -> crate::Result<Vec<(SeqNo, Vec<Value>)>> {
+        let mut batches = vec![];
+
+        for idx in 0..SHARD_COUNT {
+            let shard_path = get_shard_path(&path, idx);
+
+            if !shard_path.exists() {
+                continue;
+            }
+
+            let pos = positions.get(&shard_path).copied().unwrap_or(0);
+            let mut reader = JournalBatchReader::new_at(&shard_path, pos)?;
+
+            // NOTE: Only remember the end of complete batches,
+            // incomplete batches are read again next time
+            while let Some(batch) = reader.next() {
+                batches.push(batch?);
+                positions.insert(shard_path.clone(), reader.position()?);
+            }
+        }
+
+        batches.sort_by_key(|(seqno, _)| *seqno);
+
+        Ok(batches)
+    }
+
+    /// Returns the lowest seqno that was written into a journal
+    ///
+    /// Returns `None` if the journal contains no batches.
+    pub fn first_seqno<P: AsRef<Path>>(path: P) -> crate::Result<Option<SeqNo>> {
+        let mut first_seqno = None;
+
+        for idx in 0..SHARD_COUNT {
+            let shard_path = get_shard_path(&path, idx);
+
+            if !shard_path.exists() {
+                continue;
+            }
+
+            // NOTE: Seqnos are allocated while holding the shard lock,
+            // so the first batch of a shard has its lowest seqno
+            if let Some(batch) = JournalBatchReader::new(shard_path)?.next() {
+                let (seqno, _) = batch?;
+                first_seqno = Some(first_seqno.map_or(seqno, |lo: SeqNo| lo.min(seqno)));
+            }
+        }
+
+        Ok(first_seqno)
+    }
+
+    // NOTE: The journal is always created with at least one shard
+    #[allow(clippy::expect_used)]
     pub(crate) fn lock_shard(&self) -> RwLockWriteGuard<'_, JournalShard> {
-        self.shards.write_one()
+        if !self.changes.is_active() {
+            let shard = self.shards.write_one();
+
+            // NOTE: A subscription may have been created while we were waiting for the shard
+            if !self.changes.is_active() {
+                return shard;
+            }
+        }
+
+        // NOTE: While there are subscribers, all writes go through the first shard,
+        // so batches are published in the order of their seqnos
+        self.shards
+            .first()
+            .expect("journal should have shards")
+            .write()
+            .expect("lock is poisoned")
     }
 
     pub fn flush(&self) -> crate::Result<()> {
@@ -360,6 +496,7 @@ mod tests {
                 key: "zzz".as_bytes().into(),
                 value: "".as_bytes().into(),
                 value_type: ValueType::Tombstone,
+                expires_at: None,
             }
             .serialize(&mut file)?;
 
@@ -387,6 +524,7 @@ mod tests {
                 key: "zzz".as_bytes().into(),
                 value: "".as_bytes().into(),
                 value_type: ValueType::Tombstone,
+                expires_at: None,
             }
             .serialize(&mut file)?;
 
@@ -409,4 +547,60 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_journal_recover_v0() -> crate::Result<()> {
+        let dir = tempdir()?;
+        let shard_path = dir.path().join("0");
+
+        // NOTE: Write a legacy shard without version header and with 2-byte lengths
+        {
+            let mut file = std::fs::File::create(&shard_path)?;
+
+            let item = Marker::Item {
+                key: "abc".as_bytes().into(),
+                value: "def".as_bytes().into(),
+                value_type: ValueType::Value,
+                expires_at: None,
+            };
+            let mut bytes = vec![];
+            item.serialize_versioned(&mut bytes, Version::V0)?;
+
+            Marker::Start {
+                item_count: 1,
+                seqno: 0,
+            }
+            .serialize(&mut file)?;
+            file.write_all(&bytes)?;
+            Marker::End(crc32fast::hash(&bytes)).serialize(&mut file)?;
+            file.sync_all()?;
+        }
+
+        assert_eq!(Version::V0, Journal::get_version(&dir)?);
+
+        let (_, memtable) = Journal::recover(&dir)?;
+        assert_eq!(1, memtable.items.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_large_value() -> crate::Result<()> {
+        let dir = tempdir()?;
+
+        let value = Value::new(*b"abc", vec![7; 100_000], 0, ValueType::Value);
+
+        {
+            let journal = Journal::create_new(&dir)?;
+            journal.lock_shard().write(&value)?;
+            journal.flush()?;
+        }
+
+        assert_eq!(Version::V1, Journal::get_version(&dir)?);
+
+        let (_, memtable) = Journal::recover(&dir)?;
+        assert_eq!(Some(value), memtable.get("abc", None));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,126 @@
+use super::{marker::Marker, recovery::read_shard_version};
+use crate::{serde::DeserializeError, value::SeqNo, version::Version, Value};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Reads the committed batches of a journal shard file
+///
+/// Unlike [`super::recovery::JournalShardReader`], the file is opened read-only
+/// and never repaired, so it is safe to read journals that are still in use.
+/// Reading stops at the first incomplete or corrupt batch.
+#[allow(clippy::module_name_repetitions)]
+pub struct JournalBatchReader<R: Read = BufReader<File>> {
+    reader: R,
+    version: Version,
+}
+
+impl JournalBatchReader {
+    pub fn new<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let version = read_shard_version(&mut reader)?.unwrap_or(Version::V1);
+
+        Ok(Self { reader, version })
+    }
+
+    /// Opens a journal shard file, and starts reading at the given position
+    ///
+    /// The position needs to be the end of a batch (see [`JournalBatchReader::position`]),
+    /// or 0 to start at the beginning of the file.
+    pub fn new_at<P: AsRef<Path>>(path: P, pos: u64) -> crate::Result<Self> {
+        let mut reader = Self::new(path)?;
+
+        if pos > 0 {
+            reader.reader.seek(SeekFrom::Start(pos))?;
+        }
+
+        Ok(reader)
+    }
+
+    /// Returns the current read position, which is the end of the last read batch
+    pub fn position(&mut self) -> crate::Result<u64> {
+        Ok(self.reader.stream_position()?)
+    }
+}
+
+impl<R: Read> JournalBatchReader<R> {
+    /// Reads batches that were written without a version header
+    pub fn from_reader(reader: R, version: Version) -> Self {
+        Self { reader, version }
+    }
+
+    /// Reads the next marker, returns `None` if the end of the valid data is reached
+    fn read_marker(&mut self) -> Option<crate::Result<Marker>> {
+        match Marker::deserialize_versioned(&mut self.reader, self.version) {
+            Ok(marker) => Some(Ok(marker)),
+            Err(DeserializeError::Io(e)) => match e.kind() {
+                std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::Other => None,
+                _ => Some(Err(crate::Error::Io(e))),
+            },
+            Err(DeserializeError::InvalidTag(_)) => None,
+        }
+    }
+
+    fn read_batch(&mut self) -> Option<crate::Result<(SeqNo, Vec<Value>)>> {
+        let (item_count, seqno) = match self.read_marker()? {
+            Ok(Marker::Start { item_count, seqno }) => (item_count, seqno),
+            Ok(_) => return None,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let mut hasher = crc32fast::Hasher::new();
+
+        // NOTE: The item count is read from the file (or a shipped change batch),
+        // so it is not trusted for preallocation
+        let mut items = Vec::with_capacity(item_count.min(1_024) as usize);
+
+        for _ in 0..item_count {
+            let item = match self.read_marker()? {
+                Ok(item @ Marker::Item { .. }) => item,
+                Ok(_) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let mut bytes = Vec::new();
+            if let Err(e) = item.serialize_versioned(&mut bytes, self.version) {
+                return Some(Err(e.into()));
+            }
+            hasher.update(&bytes);
+
+            if let Marker::Item {
+                key,
+                value,
+                value_type,
+                expires_at,
+            } = item
+            {
+                items.push(Value {
+                    key,
+                    value,
+                    seqno,
+                    value_type,
+                    expires_at,
+                });
+            }
+        }
+
+        match self.read_marker()? {
+            Ok(Marker::End(checksum)) if checksum == hasher.finalize() => Some(Ok((seqno, items))),
+            Ok(_) => {
+                log::warn!("Invalid journal batch with seqno {seqno}, stopping to read shard");
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<R: Read> Iterator for JournalBatchReader<R> {
+    type Item = crate::Result<(SeqNo, Vec<Value>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_batch()
+    }
+}
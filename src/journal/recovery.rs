@@ -1,11 +1,36 @@
 use super::marker::Marker;
-use crate::serde::Deserializable;
+use crate::version::Version;
 use std::{
     fs::{File, OpenOptions},
-    io::{BufReader, Seek},
+    io::{BufReader, Read, Seek, SeekFrom},
     path::Path,
 };
 
+/// Reads the version header of a journal shard
+///
+/// Shards without a header were written in [`Version::V0`], in which case the
+/// reader is rewound to the start of the file.
+///
+/// Returns `None` if the shard is empty.
+pub fn read_shard_version<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<Version>> {
+    let mut header = vec![];
+    reader
+        .by_ref()
+        .take(u64::from(Version::len()))
+        .read_to_end(&mut header)?;
+
+    if header.is_empty() {
+        return Ok(None);
+    }
+
+    if let Some(version) = Version::parse_file_header(&header) {
+        return Ok(Some(version));
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(Some(Version::V0))
+}
+
 /// Reads and emits through the entries in a journal shard file, but doesn't
 /// check the validity of batches
 ///
@@ -15,15 +40,27 @@ use std::{
 pub struct JournalShardReader {
     reader: BufReader<File>,
     last_valid_pos: u64,
+
+    /// Position of the first marker (right after the version header)
+    pub(crate) start_pos: u64,
+
+    /// Disk format version of the shard
+    pub(crate) version: Version,
 }
 
 impl JournalShardReader {
     pub fn new<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
         let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut reader = BufReader::new(file);
+
+        let version = read_shard_version(&mut reader)?.unwrap_or(Version::V1);
+        let last_valid_pos = reader.stream_position()?;
 
         Ok(Self {
-            reader: BufReader::new(file),
-            last_valid_pos: 0,
+            reader,
+            last_valid_pos,
+            start_pos: last_valid_pos,
+            version,
         })
     }
 
@@ -43,7 +80,7 @@ impl Iterator for JournalShardReader {
     type Item = crate::Result<(u64, Marker)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match Marker::deserialize(&mut self.reader) {
+        match Marker::deserialize_versioned(&mut self.reader, self.version) {
             Ok(abc) => {
                 self.last_valid_pos = self
                     .reader
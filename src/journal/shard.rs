@@ -1,7 +1,7 @@
 use super::marker::Marker;
 use crate::{
     journal::recovery::JournalShardReader, memtable::MemTable, serde::Serializable, value::SeqNo,
-    SerializeError, Value,
+    version::Version, SerializeError, Value,
 };
 use std::{
     fs::{File, OpenOptions},
@@ -32,8 +32,8 @@ pub struct JournalShard {
 }
 
 /// Writes a batch start marker to the journal
-fn write_start(
-    writer: &mut BufWriter<File>,
+fn write_start<W: Write>(
+    writer: &mut W,
     item_count: u32,
     seqno: SeqNo,
 ) -> Result<usize, SerializeError> {
@@ -45,7 +45,7 @@ fn write_start(
 }
 
 /// Writes a batch end marker to the journal
-fn write_end(writer: &mut BufWriter<File>, crc: u32) -> Result<usize, SerializeError> {
+fn write_end<W: Write>(writer: &mut W, crc: u32) -> Result<usize, SerializeError> {
     let mut bytes = Vec::new();
     Marker::End(crc).serialize(&mut bytes)?;
 
@@ -55,18 +55,22 @@ fn write_end(writer: &mut BufWriter<File>, crc: u32) -> Result<usize, SerializeE
 
 impl JournalShard {
     pub fn rotate<P: AsRef<Path>>(&mut self, path: P) -> crate::Result<()> {
-        let file = File::create(&path)?;
-        self.file = BufWriter::new(file);
+        let mut file = BufWriter::new(File::create(&path)?);
+        Version::V1.write_file_header(&mut file)?;
+
+        self.file = file;
         self.path = path.as_ref().to_path_buf();
         Ok(())
     }
 
     pub fn create_new<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
         let path = path.as_ref();
-        let file = File::create(path)?;
+
+        let mut file = BufWriter::new(File::create(path)?);
+        Version::V1.write_file_header(&mut file)?;
 
         Ok(Self {
-            file: BufWriter::new(file),
+            file,
             path: path.to_path_buf(),
         })
     }
@@ -82,7 +86,8 @@ impl JournalShard {
         let mut is_in_batch = false;
         let mut batch_counter = 0;
         let mut batch_seqno = SeqNo::default();
-        let mut last_valid_pos = 0;
+        let mut last_valid_pos = recoverer.start_pos;
+        let version = recoverer.version;
 
         let mut items = vec![];
 
@@ -151,14 +156,16 @@ impl JournalShard {
                     key,
                     value,
                     value_type,
+                    expires_at,
                 } => {
                     let item = Marker::Item {
                         value_type,
                         key: key.clone(),
                         value: value.clone(),
+                        expires_at,
                     };
                     let mut bytes = Vec::with_capacity(100);
-                    item.serialize(&mut bytes)?;
+                    item.serialize_versioned(&mut bytes, version)?;
 
                     hasher.update(&bytes);
 
@@ -186,6 +193,7 @@ impl JournalShard {
                         value,
                         seqno: batch_seqno,
                         value_type,
+                        expires_at,
                     });
                 }
             }
@@ -204,23 +212,25 @@ impl JournalShard {
         Ok(())
     }
 
+    /// Opens a journal shard for appending
+    ///
+    /// If the shard does not exist yet or is empty, a version header is written.
     pub fn from_file<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
         let path = path.as_ref();
 
-        if !path.exists() {
-            return Ok(Self {
-                file: BufWriter::new(
-                    std::fs::OpenOptions::new()
-                        .create_new(true)
-                        .append(true)
-                        .open(path)?,
-                ),
-                path: path.to_path_buf(),
-            });
+        let mut file = BufWriter::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+        );
+
+        if file.get_ref().metadata()?.len() == 0 {
+            Version::V1.write_file_header(&mut file)?;
         }
 
         Ok(Self {
-            file: BufWriter::new(std::fs::OpenOptions::new().append(true).open(path)?),
+            file,
             path: path.to_path_buf(),
         })
     }
@@ -238,33 +248,41 @@ impl JournalShard {
     }
 
     pub fn write_batch(&mut self, items: &[&Value]) -> crate::Result<usize> {
-        // NOTE: entries.len() is surely never > u32::MAX
-        #[allow(clippy::cast_possible_truncation)]
-        let item_count = items.len() as u32;
-
-        let mut hasher = crc32fast::Hasher::new();
-        let mut byte_count = 0;
-
-        byte_count += write_start(&mut self.file, item_count, items[0].seqno)?;
-
-        for item in items {
-            let item = Marker::Item {
-                value_type: item.value_type,
-                key: item.key.clone(),
-                value: item.value.clone(),
-            };
-            let mut bytes = Vec::new();
-            item.serialize(&mut bytes)?;
-
-            self.file.write_all(&bytes)?;
+        write_batch(&mut self.file, items)
+    }
+}
 
-            hasher.update(&bytes);
-            byte_count += bytes.len();
-        }
+/// Writes a batch (start marker, items, end marker with checksum)
+///
+/// All items are written with the seqno of the first item.
+pub fn write_batch<W: Write>(writer: &mut W, items: &[&Value]) -> crate::Result<usize> {
+    // NOTE: entries.len() is surely never > u32::MAX
+    #[allow(clippy::cast_possible_truncation)]
+    let item_count = items.len() as u32;
+
+    let mut hasher = crc32fast::Hasher::new();
+    let mut byte_count = 0;
+
+    byte_count += write_start(writer, item_count, items[0].seqno)?;
+
+    for item in items {
+        let item = Marker::Item {
+            value_type: item.value_type,
+            key: item.key.clone(),
+            value: item.value.clone(),
+            expires_at: item.expires_at,
+        };
+        let mut bytes = Vec::new();
+        item.serialize(&mut bytes)?;
+
+        writer.write_all(&bytes)?;
+
+        hasher.update(&bytes);
+        byte_count += bytes.len();
+    }
 
-        let crc = hasher.finalize();
-        byte_count += write_end(&mut self.file, crc)?;
+    let crc = hasher.finalize();
+    byte_count += write_end(writer, crc)?;
 
-        Ok(byte_count)
-    }
+    Ok(byte_count)
 }
@@ -3,7 +3,7 @@ use crate::{segment::Segment, value::UserKey};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, ops::DerefMut, sync::Arc};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Level(Vec<Arc<str>>);
 
 impl Level {
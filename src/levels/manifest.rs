@@ -0,0 +1,731 @@
+use super::level::Level;
+use crate::{
+    file::{rewrite_atomic, LEVELS_MANIFEST_FILE, MANIFEST_FILE},
+    serde::{Deserializable, DeserializeError, Serializable, SerializeError},
+    value::SeqNo,
+    version::Version,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Amount of records after which the manifest log is compacted into a single record
+const COMPACTION_THRESHOLD: usize = 1_000;
+
+/// Size of a record header
+const RECORD_HEADER_LEN: usize = 8;
+
+/// A change to the levels manifest
+///
+/// There is no edit for the next segment ID, because segment IDs are
+/// not allocated from a counter, but generated from the current time and
+/// random bits (see [`generate_segment_id`](crate::id::generate_segment_id)),
+/// so there is no state to recover.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Edit {
+    /// Removes all segments and sets the amount of levels
+    ///
+    /// Starts a compacted manifest log.
+    Reset { level_count: u8 },
+
+    /// Adds a segment to a level
+    Add { level: u8, segment_id: Arc<str> },
+
+    /// Removes a segment from its level
+    Remove { segment_id: Arc<str> },
+
+    /// Sets the seqno after the highest seqno that was ever written into a segment
+    NextSeqno(SeqNo),
+}
+
+impl Serializable for Edit {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        match self {
+            Self::Reset { level_count } => {
+                writer.write_u8(0)?;
+                writer.write_u8(*level_count)?;
+            }
+            Self::Add { level, segment_id } => {
+                writer.write_u8(1)?;
+                writer.write_u8(*level)?;
+                write_segment_id(writer, segment_id)?;
+            }
+            Self::Remove { segment_id } => {
+                writer.write_u8(2)?;
+                write_segment_id(writer, segment_id)?;
+            }
+            Self::NextSeqno(seqno) => {
+                writer.write_u8(3)?;
+                writer.write_u64::<BigEndian>(*seqno)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Deserializable for Edit {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        match reader.read_u8()? {
+            0 => Ok(Self::Reset {
+                level_count: reader.read_u8()?,
+            }),
+            1 => Ok(Self::Add {
+                level: reader.read_u8()?,
+                segment_id: read_segment_id(reader)?,
+            }),
+            2 => Ok(Self::Remove {
+                segment_id: read_segment_id(reader)?,
+            }),
+            3 => Ok(Self::NextSeqno(reader.read_u64::<BigEndian>()?)),
+            tag => Err(DeserializeError::InvalidTag(tag)),
+        }
+    }
+}
+
+fn write_segment_id<W: Write>(writer: &mut W, segment_id: &str) -> std::io::Result<()> {
+    // NOTE: Segment IDs are short
+    #[allow(clippy::cast_possible_truncation)]
+    writer.write_u16::<BigEndian>(segment_id.len() as u16)?;
+    writer.write_all(segment_id.as_bytes())
+}
+
+fn read_segment_id<R: Read>(reader: &mut R) -> Result<Arc<str>, DeserializeError> {
+    let len = reader.read_u16::<BigEndian>()?;
+    let mut bytes = vec![0; len.into()];
+    reader.read_exact(&mut bytes)?;
+
+    let segment_id = String::from_utf8(bytes)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    Ok(segment_id.into())
+}
+
+fn corrupted(msg: &str) -> crate::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("corrupted levels manifest: {msg}"),
+    )
+    .into()
+}
+
+/// Encodes a record of edits that are applied atomically
+///
+/// # Disk representation
+///
+/// \[payload length; 4 bytes\] \[crc; 4 bytes\] \[edit count; 4 bytes\] \[edits\]
+///
+/// The CRC covers the payload length and the payload (the edit count and edits),
+/// so a corrupted length is detected as well.
+fn encode_record(edits: &[Edit]) -> crate::Result<Vec<u8>> {
+    let mut payload = vec![];
+
+    // NOTE: There are never 4 billion segments
+    #[allow(clippy::cast_possible_truncation)]
+    payload.write_u32::<BigEndian>(edits.len() as u32)?;
+
+    for edit in edits {
+        edit.serialize(&mut payload)?;
+    }
+
+    // NOTE: Records are never bigger than 4 GB anyway
+    #[allow(clippy::cast_possible_truncation)]
+    let payload_len = payload.len() as u32;
+
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+    record.write_u32::<BigEndian>(payload_len)?;
+    record.write_u32::<BigEndian>(record_checksum(payload_len, &payload))?;
+    record.extend_from_slice(&payload);
+
+    Ok(record)
+}
+
+/// Applies an edit to the level layout
+fn apply(levels: &mut Vec<Level>, next_seqno: &mut SeqNo, edit: Edit) -> crate::Result<()> {
+    match edit {
+        Edit::Reset { level_count } => {
+            *levels = (0..level_count).map(|_| Level::default()).collect();
+        }
+        Edit::Add { level, segment_id } => {
+            levels
+                .get_mut(usize::from(level))
+                .ok_or_else(|| corrupted("segment added to non-existing level"))?
+                .push(segment_id);
+        }
+        Edit::Remove { segment_id } => {
+            for level in levels {
+                level.retain(|id| *id != segment_id);
+            }
+        }
+        Edit::NextSeqno(seqno) => {
+            *next_seqno = seqno;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the edits that recreate the given level layout from scratch
+fn snapshot(levels: &[Level], next_seqno: SeqNo) -> Vec<Edit> {
+    // NOTE: There are never that many levels
+    #[allow(clippy::cast_possible_truncation)]
+    let level_count = levels.len() as u8;
+
+    let mut edits = vec![Edit::Reset { level_count }];
+
+    for (idx, level) in (0..).zip(levels) {
+        edits.extend(level.iter().map(|segment_id| Edit::Add {
+            level: idx,
+            segment_id: segment_id.clone(),
+        }));
+    }
+
+    edits.push(Edit::NextSeqno(next_seqno));
+
+    edits
+}
+
+/// Returns the edits that turn the `old` level layout into the `new` one
+fn diff(old: &[Level], new: &[Level]) -> Vec<Edit> {
+    let level_of = |levels: &[Level]| {
+        (0u8..)
+            .zip(levels)
+            .flat_map(|(idx, level)| level.iter().map(move |id| (id.clone(), idx)))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let old_levels = level_of(old);
+    let new_levels = level_of(new);
+
+    let mut edits = vec![];
+
+    for (segment_id, level) in &old_levels {
+        if new_levels.get(segment_id) != Some(level) {
+            edits.push(Edit::Remove {
+                segment_id: segment_id.clone(),
+            });
+        }
+    }
+
+    for (idx, level) in (0u8..).zip(new) {
+        for segment_id in level.iter() {
+            if old_levels.get(segment_id) != Some(&idx) {
+                edits.push(Edit::Add {
+                    level: idx,
+                    segment_id: segment_id.clone(),
+                });
+            }
+        }
+    }
+
+    edits
+}
+
+/// Append-only log of the changes to the levels manifest
+///
+/// Every time the levels change, a record with the edits since the last write
+/// is appended, instead of rewriting the whole manifest.
+/// Once the log contains enough records, it is compacted into a single record.
+///
+/// Before, the levels were stored as a JSON file (see [`LEVELS_MANIFEST_FILE`]),
+/// which is converted into a log when the levels are written the next time.
+///
+/// # Disk representation
+///
+/// \[version header; 5 bytes\] \[records\]
+pub struct ManifestLog {
+    folder: PathBuf,
+
+    /// Length of the valid part of the log file
+    ///
+    /// A record that was only partially written because of a crash is overwritten.
+    len: u64,
+
+    /// Amount of records in the log
+    record_count: usize,
+
+    /// Level layout as of the last written record
+    persisted_levels: Vec<Level>,
+
+    /// Next seqno as of the last written record
+    persisted_next_seqno: SeqNo,
+
+    /// The levels were read from the JSON manifest
+    is_legacy: bool,
+}
+
+/// The state of a recovered levels manifest
+pub struct Recovered {
+    pub log: ManifestLog,
+    pub levels: Vec<Level>,
+    pub next_seqno: SeqNo,
+}
+
+impl ManifestLog {
+    /// Creates a new manifest log in the tree folder
+    pub fn create_new(folder: &Path, levels: &[Level]) -> crate::Result<Self> {
+        let mut log = Self {
+            folder: folder.to_path_buf(),
+            len: 0,
+            record_count: 0,
+            persisted_levels: Vec::new(),
+            persisted_next_seqno: 0,
+            is_legacy: false,
+        };
+        log.compact(levels, 0)?;
+
+        Ok(log)
+    }
+
+    /// Reads the manifest log of a tree folder, falling back to the JSON manifest
+    ///
+    /// A record that was only partially written (because of a crash) at the end of the log
+    /// is ignored, corrupted records before it result in an error.
+    pub fn recover(folder: &Path) -> crate::Result<Recovered> {
+        let path = folder.join(MANIFEST_FILE);
+
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Self::recover_legacy(folder);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let header_len = usize::from(Version::len());
+
+        if Version::parse_file_header(&bytes).is_none() {
+            return Err(corrupted("invalid file header"));
+        }
+
+        let mut levels = Vec::new();
+        let mut next_seqno = 0;
+        let mut record_count = 0;
+        let mut pos = header_len;
+
+        while pos < bytes.len() {
+            let record_len = match read_record(&bytes[pos..]) {
+                Some((payload, record_len)) => {
+                    let mut reader = payload;
+                    let edit_count = reader.read_u32::<BigEndian>()?;
+
+                    for _ in 0..edit_count {
+                        apply(
+                            &mut levels,
+                            &mut next_seqno,
+                            Edit::deserialize(&mut reader)?,
+                        )?;
+                    }
+
+                    if !reader.is_empty() {
+                        return Err(corrupted("trailing bytes in record"));
+                    }
+
+                    record_len
+                }
+
+                // NOTE: Only the last record may be torn, because every record is synced
+                // before the next one is appended
+                None if is_tail(&bytes[pos..]) => {
+                    log::warn!("Ignoring torn record at the end of the levels manifest");
+                    break;
+                }
+
+                None => return Err(corrupted("checksum mismatch")),
+            };
+
+            pos += record_len;
+            record_count += 1;
+        }
+
+        if levels.is_empty() {
+            return Err(corrupted("no levels"));
+        }
+
+        Ok(Recovered {
+            log: Self {
+                folder: folder.to_path_buf(),
+                len: pos as u64,
+                record_count,
+                persisted_levels: levels.clone(),
+                persisted_next_seqno: next_seqno,
+                is_legacy: false,
+            },
+            levels,
+            next_seqno,
+        })
+    }
+
+    fn recover_legacy(folder: &Path) -> crate::Result<Recovered> {
+        let json = std::fs::read_to_string(folder.join(LEVELS_MANIFEST_FILE))?;
+
+        let levels: Vec<Level> = serde_json::from_str(&json).map_err(|e| {
+            log::error!("Failed to parse levels manifest: {e:?}");
+            corrupted("invalid JSON")
+        })?;
+
+        if levels.is_empty() {
+            return Err(corrupted("no levels"));
+        }
+
+        Ok(Recovered {
+            log: Self {
+                folder: folder.to_path_buf(),
+                len: 0,
+                record_count: 0,
+                persisted_levels: Vec::new(),
+                persisted_next_seqno: 0,
+                is_legacy: true,
+            },
+            levels,
+            next_seqno: 0,
+        })
+    }
+
+    /// Appends the changes since the last write to the log
+    pub fn write(&mut self, levels: &[Level], next_seqno: SeqNo) -> crate::Result<()> {
+        if self.is_legacy || self.record_count >= COMPACTION_THRESHOLD {
+            return self.compact(levels, next_seqno);
+        }
+
+        let mut edits = diff(&self.persisted_levels, levels);
+
+        if next_seqno != self.persisted_next_seqno {
+            edits.push(Edit::NextSeqno(next_seqno));
+        }
+
+        if edits.is_empty() {
+            return Ok(());
+        }
+
+        let record = encode_record(&edits)?;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(self.folder.join(MANIFEST_FILE))?;
+
+        // NOTE: Overwrite a torn record
+        file.set_len(self.len)?;
+        file.seek(std::io::SeekFrom::Start(self.len))?;
+
+        file.write_all(&record)?;
+        file.sync_all()?;
+
+        log::trace!("Appended {} edits to levels manifest", edits.len());
+
+        self.len += record.len() as u64;
+        self.record_count += 1;
+        self.persisted_levels = levels.to_vec();
+        self.persisted_next_seqno = next_seqno;
+
+        Ok(())
+    }
+
+    /// Atomically replaces the log with a single record containing the given level layout
+    fn compact(&mut self, levels: &[Level], next_seqno: SeqNo) -> crate::Result<()> {
+        log::debug!("Compacting levels manifest");
+
+        let mut bytes = vec![];
+        Version::V3.write_file_header(&mut bytes)?;
+        bytes.extend(encode_record(&snapshot(levels, next_seqno))?);
+
+        rewrite_atomic(self.folder.join(MANIFEST_FILE), &bytes)?;
+
+        if self.is_legacy {
+            log::info!("Converted levels manifest into a manifest log");
+            std::fs::remove_file(self.folder.join(LEVELS_MANIFEST_FILE))?;
+            self.is_legacy = false;
+        }
+
+        self.len = bytes.len() as u64;
+        self.record_count = 1;
+        self.persisted_levels = levels.to_vec();
+        self.persisted_next_seqno = next_seqno;
+
+        Ok(())
+    }
+}
+
+/// Reads a record, returning its payload and length,
+/// or `None` if the record is incomplete or its checksum does not match
+fn read_record(bytes: &[u8]) -> Option<(&[u8], usize)> {
+    let mut header = bytes.get(..RECORD_HEADER_LEN)?;
+
+    let payload_len = header.read_u32::<BigEndian>().ok()?;
+    let crc = header.read_u32::<BigEndian>().ok()?;
+
+    let record_len = RECORD_HEADER_LEN + payload_len as usize;
+    let payload = bytes.get(RECORD_HEADER_LEN..record_len)?;
+
+    (record_checksum(payload_len, payload) == crc).then_some((payload, record_len))
+}
+
+/// Calculates the checksum of a record over its payload length and payload
+fn record_checksum(payload_len: u32, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&payload_len.to_be_bytes());
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// Returns `true` if the invalid record at the start of the bytes was only partially written
+///
+/// A complete record with a checksum mismatch is corrupted, not torn,
+/// because the checksum also covers the payload length.
+fn is_tail(bytes: &[u8]) -> bool {
+    let Some(mut header) = bytes.get(..RECORD_HEADER_LEN) else {
+        return true;
+    };
+
+    header.read_u32::<BigEndian>().map_or(true, |payload_len| {
+        RECORD_HEADER_LEN + payload_len as usize > bytes.len()
+    })
+}
+
+/// Copies the levels manifest of a tree folder into another folder
+pub fn copy(src: &Path, dest: &Path) -> crate::Result<()> {
+    for file_name in [MANIFEST_FILE, LEVELS_MANIFEST_FILE] {
+        if src.join(file_name).try_exists()? {
+            return crate::file::copy_file(&src.join(file_name), &dest.join(file_name));
+        }
+    }
+
+    Err(std::io::Error::new(std::io::ErrorKind::NotFound, "levels manifest not found").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn level(ids: &[&str]) -> Level {
+        let mut level = Level::default();
+        for id in ids {
+            level.push((*id).into());
+        }
+        level
+    }
+
+    fn ids(levels: &[Level]) -> Vec<Vec<Arc<str>>> {
+        levels
+            .iter()
+            .map(|level| level.iter().cloned().collect())
+            .collect()
+    }
+
+    #[test]
+    fn manifest_edit_round_trip() -> crate::Result<()> {
+        let edits = vec![
+            Edit::Reset { level_count: 7 },
+            Edit::Add {
+                level: 3,
+                segment_id: "abc".into(),
+            },
+            Edit::Remove {
+                segment_id: "def".into(),
+            },
+            Edit::NextSeqno(42),
+        ];
+
+        for edit in edits {
+            let mut bytes = vec![];
+            edit.serialize(&mut bytes)?;
+            assert_eq!(edit, Edit::deserialize(&mut &bytes[..])?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_replay() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+
+        let mut levels = vec![Level::default(); 3];
+        let mut log = ManifestLog::create_new(folder.path(), &levels)?;
+
+        levels[0] = level(&["a", "b"]);
+        log.write(&levels, 10)?;
+
+        levels[0] = level(&["c"]);
+        levels[1] = level(&["a", "b"]);
+        log.write(&levels, 20)?;
+
+        levels[1] = level(&["d"]);
+        levels[2] = level(&["b"]);
+        log.write(&levels, 20)?;
+
+        // NOTE: Nothing changed, so nothing is appended
+        let len = log.len;
+        log.write(&levels, 20)?;
+        assert_eq!(len, log.len);
+
+        let recovered = ManifestLog::recover(folder.path())?;
+        assert_eq!(ids(&levels), ids(&recovered.levels));
+        assert_eq!(20, recovered.next_seqno);
+        assert_eq!(4, recovered.log.record_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_torn_tail() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join(MANIFEST_FILE);
+
+        let mut levels = vec![Level::default(); 2];
+        let mut log = ManifestLog::create_new(folder.path(), &levels)?;
+
+        levels[0] = level(&["a"]);
+        log.write(&levels, 5)?;
+
+        let valid_len = std::fs::metadata(&path)?.len();
+
+        levels[0] = level(&["a", "b"]);
+        log.write(&levels, 6)?;
+
+        // NOTE: Simulate a crash while appending the last record
+        let file = OpenOptions::new().write(true).open(&path)?;
+        file.set_len(std::fs::metadata(&path)?.len() - 3)?;
+        drop(file);
+
+        let mut recovered = ManifestLog::recover(folder.path())?;
+        assert_eq!(
+            vec![vec![Arc::<str>::from("a")], vec![]],
+            ids(&recovered.levels)
+        );
+        assert_eq!(5, recovered.next_seqno);
+        assert_eq!(valid_len, recovered.log.len);
+
+        // NOTE: The torn record is overwritten by the next write
+        levels[1] = level(&["c"]);
+        recovered.log.write(&levels, 7)?;
+
+        let recovered = ManifestLog::recover(folder.path())?;
+        assert_eq!(ids(&levels), ids(&recovered.levels));
+        assert_eq!(7, recovered.next_seqno);
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_corrupted_record() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join(MANIFEST_FILE);
+
+        let mut levels = vec![Level::default(); 2];
+        let mut log = ManifestLog::create_new(folder.path(), &levels)?;
+
+        levels[0] = level(&["a"]);
+        log.write(&levels, 5)?;
+
+        levels[0] = level(&["a", "b"]);
+        log.write(&levels, 6)?;
+
+        // NOTE: Flip a bit in the payload of the snapshot record, which is not the last one
+        let mut bytes = std::fs::read(&path)?;
+        let idx = usize::from(Version::len()) + RECORD_HEADER_LEN + 2;
+        bytes[idx] ^= 1;
+        std::fs::write(&path, bytes)?;
+
+        assert!(ManifestLog::recover(folder.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_corrupted_last_record() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join(MANIFEST_FILE);
+
+        let mut levels = vec![Level::default(); 2];
+        let mut log = ManifestLog::create_new(folder.path(), &levels)?;
+
+        levels[0] = level(&["a"]);
+        log.write(&levels, 5)?;
+
+        let valid_len = std::fs::metadata(&path)?.len();
+
+        levels[0] = level(&["a", "b"]);
+        log.write(&levels, 6)?;
+
+        // NOTE: The last record is complete, so a checksum mismatch is not a torn write
+        let mut bytes = std::fs::read(&path)?;
+        let idx = bytes.len() - 1;
+        bytes[idx] ^= 1;
+        std::fs::write(&path, &bytes)?;
+
+        assert!(ManifestLog::recover(folder.path()).is_err());
+
+        // NOTE: A corrupted length is detected as well, even if it is consistent with the file length
+        bytes[idx] ^= 1;
+        bytes.push(0);
+        let len_idx = usize::try_from(valid_len).expect("should fit") + 3;
+        bytes[len_idx] += 1;
+        std::fs::write(&path, &bytes)?;
+
+        assert!(ManifestLog::recover(folder.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_compaction() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join(MANIFEST_FILE);
+
+        let mut levels = vec![Level::default(); 2];
+        let mut log = ManifestLog::create_new(folder.path(), &levels)?;
+
+        for seqno in 1..=COMPACTION_THRESHOLD as u64 * 2 {
+            levels[0] = level(&[&seqno.to_string()]);
+            log.write(&levels, seqno)?;
+            assert!(log.record_count <= COMPACTION_THRESHOLD);
+        }
+
+        // NOTE: The log does not grow unbounded
+        assert!(std::fs::metadata(&path)?.len() < 50_000);
+
+        let recovered = ManifestLog::recover(folder.path())?;
+        assert_eq!(ids(&levels), ids(&recovered.levels));
+        assert_eq!(COMPACTION_THRESHOLD as u64 * 2, recovered.next_seqno);
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_legacy_json() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let legacy_path = folder.path().join(LEVELS_MANIFEST_FILE);
+
+        std::fs::write(&legacy_path, r#"[["a","b"],[],["c"]]"#)?;
+
+        let mut recovered = ManifestLog::recover(folder.path())?;
+        let levels = vec![level(&["a", "b"]), Level::default(), level(&["c"])];
+        assert_eq!(ids(&levels), ids(&recovered.levels));
+
+        // NOTE: The next write converts the JSON manifest into a log
+        recovered.log.write(&recovered.levels, 3)?;
+        assert!(!legacy_path.try_exists()?);
+
+        let recovered = ManifestLog::recover(folder.path())?;
+        assert_eq!(ids(&levels), ids(&recovered.levels));
+        assert_eq!(3, recovered.next_seqno);
+
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_legacy_json_corrupted() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+
+        std::fs::write(folder.path().join(LEVELS_MANIFEST_FILE), r#"[["a","b"],["#)?;
+        assert!(ManifestLog::recover(folder.path()).is_err());
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 mod level;
+pub mod manifest;
 
 #[cfg(feature = "segment_history")]
 mod segment_history;
@@ -8,12 +9,19 @@ use crate::time::unix_timestamp;
 #[cfg(feature = "segment_history")]
 use serde_json::json;
 
-use self::level::{Level, ResolvedLevel};
-use crate::{file::rewrite_atomic, segment::Segment};
+use self::{
+    level::{Level, ResolvedLevel},
+    manifest::ManifestLog,
+};
+use crate::{
+    range_tombstone::RangeTombstone,
+    segment::Segment,
+    value::{SeqNo, UserKey},
+};
 use std::{
     collections::{HashMap, HashSet},
-    fs::{self},
-    path::{Path, PathBuf},
+    ops::Bound,
+    path::Path,
     sync::Arc,
 };
 
@@ -22,7 +30,12 @@ pub type ResolvedView = Vec<ResolvedLevel>;
 
 /// Represents the levels of a log-structured merge tree.
 pub struct Levels {
-    path: PathBuf,
+    manifest: ManifestLog,
+
+    /// Seqno after the highest seqno that was ever written into a segment
+    ///
+    /// Items may be dropped by compactions, so this can not be derived from the segments.
+    next_seqno: SeqNo,
 
     /// Amount of levels of the LSM tree
     ///
@@ -32,6 +45,12 @@ pub struct Levels {
     segments: HashMap<Arc<str>, Arc<Segment>>,
     levels: Vec<Level>,
 
+    /// Range tombstones of all segments, sorted by start key
+    ///
+    /// Range tombstones are not reflected in the segments' key ranges,
+    /// so they are collected here to not need to visit every segment on each read.
+    range_tombstones: Vec<RangeTombstone>,
+
     /// Set of segment IDs that are masked
     ///
     /// While consuming segments (because of compaction) they will not appear in the list of segments
@@ -56,24 +75,32 @@ impl Levels {
         !self.hidden_set.is_empty()
     }
 
-    pub(crate) fn create_new<P: AsRef<Path>>(level_count: u8, path: P) -> crate::Result<Self> {
+    /// Returns the seqno after the highest seqno that was ever written into a segment
+    pub(crate) fn next_seqno(&self) -> SeqNo {
+        self.next_seqno
+    }
+
+    /// Creates the levels manifest in the given tree folder
+    pub(crate) fn create_new<P: AsRef<Path>>(level_count: u8, folder: P) -> crate::Result<Self> {
         assert!(level_count > 0, "level_count should be >= 1");
 
         let levels = (0..level_count)
             .map(|_| Level::default())
             .collect::<Vec<_>>();
 
+        #[allow(unused_mut)]
         let mut levels = Self {
-            path: path.as_ref().to_path_buf(),
+            manifest: ManifestLog::create_new(folder.as_ref(), &levels)?,
+            next_seqno: 0,
             segments: HashMap::new(),
             level_count,
             levels,
+            range_tombstones: Vec::new(),
             hidden_set: HashSet::new(),
 
             #[cfg(feature = "segment_history")]
             segment_history_writer: segment_history::Writer::new()?,
         };
-        levels.write_to_disk()?;
 
         #[cfg(feature = "segment_history")]
         levels.write_segment_history_entry("create_new")?;
@@ -108,54 +135,56 @@ impl Levels {
         self.segment_history_writer.write(&line)
     }
 
+    /// Recovers the levels manifest of the given tree folder
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the manifest is corrupted.
     pub(crate) fn recover<P: AsRef<Path>>(
-        path: P,
+        folder: P,
         segments: HashMap<Arc<str>, Arc<Segment>>,
     ) -> crate::Result<Self> {
-        let level_manifest = fs::read_to_string(&path)?;
-        let levels: Vec<_> = serde_json::from_str(&level_manifest).expect("deserialize error");
+        let recovered = ManifestLog::recover(folder.as_ref())?;
+        let levels = recovered.levels;
 
         // NOTE: There are never that many levels
         // so it's fine to just truncate it
         #[allow(clippy::cast_possible_truncation)]
         let level_count = levels.len() as u8;
 
-        // NOTE: See segment_history feature
-        #[allow(unused_mut)]
+        let next_seqno = segments
+            .values()
+            .map(|segment| segment.metadata.seqnos.1 + 1)
+            .fold(recovered.next_seqno, SeqNo::max);
+
         let mut levels = Self {
+            manifest: recovered.log,
+            next_seqno,
             segments,
             level_count,
             levels,
+            range_tombstones: Vec::new(),
             hidden_set: HashSet::new(),
-            path: path.as_ref().to_path_buf(),
 
             #[cfg(feature = "segment_history")]
             segment_history_writer: segment_history::Writer::new()?,
         };
 
+        levels.update_range_tombstones();
+
         #[cfg(feature = "segment_history")]
         levels.write_segment_history_entry("load_from_disk")?;
 
         Ok(levels)
     }
 
+    /// Persists the changes to the levels since the last write
     pub(crate) fn write_to_disk(&mut self) -> crate::Result<()> {
         log::trace!("Writing level manifest");
 
-        // NOTE: Serialization can't fail here
-        #[allow(clippy::expect_used)]
-        let json = serde_json::to_string_pretty(&self.levels).expect("should serialize");
-
         // NOTE: Compaction threads don't have concurrent access to the level manifest
         // because it is behind a mutex
-        // *However*, the file still needs to be rewritten atomically, because
-        // the system could crash at any moment, so
-        //
-        // a) truncating is not an option, because for a short moment, the file is empty
-        // b) just overwriting corrupts the file content
-        rewrite_atomic(&self.path, json.as_bytes())?;
-
-        Ok(())
+        self.manifest.write(&self.levels, self.next_seqno)
     }
 
     pub(crate) fn add(&mut self, segment: Arc<Segment>) {
@@ -189,19 +218,64 @@ impl Levels {
             .expect("level should exist");
 
         level.push(segment.metadata.id.clone());
+
+        self.next_seqno = self.next_seqno.max(segment.metadata.seqnos.1 + 1);
+
+        let has_range_tombstones = !segment.range_tombstones.is_empty();
         self.segments.insert(segment.metadata.id.clone(), segment);
 
+        if has_range_tombstones {
+            self.update_range_tombstones();
+        }
+
         self.sort_levels();
 
         #[cfg(feature = "segment_history")]
         self.write_segment_history_entry("insert").ok();
     }
 
+    /// Returns the deepest level a segment with the given key range can be inserted into,
+    /// so no segment in that level (or any level above it) overlaps the key range
+    ///
+    /// If the first level already overlaps, the segment needs to be inserted into the first level.
+    // NOTE: Every segment ID in a level is also contained in the segment map
+    #[allow(clippy::expect_used)]
+    pub(crate) fn ingestion_level(&self, key_range: &(UserKey, UserKey)) -> u8 {
+        let bounds = (
+            Bound::Included(key_range.0.clone()),
+            Bound::Included(key_range.1.clone()),
+        );
+
+        let mut level_no = 0;
+
+        for (idx, level) in (0..).zip(self.levels.iter()) {
+            let overlaps = level.iter().any(|segment_id| {
+                self.segments
+                    .get(segment_id)
+                    .expect("where's the segment at")
+                    .check_key_range_overlap(&bounds)
+            });
+
+            if overlaps {
+                break;
+            }
+
+            level_no = idx;
+        }
+
+        level_no
+    }
+
     pub(crate) fn remove(&mut self, segment_id: &Arc<str>) {
         for level in &mut self.levels {
             level.retain(|x| segment_id != x);
         }
-        self.segments.remove(segment_id);
+
+        if let Some(segment) = self.segments.remove(segment_id) {
+            if !segment.range_tombstones.is_empty() {
+                self.update_range_tombstones();
+            }
+        }
 
         #[cfg(feature = "segment_history")]
         self.write_segment_history_entry("remove").ok();
@@ -293,6 +367,90 @@ impl Levels {
         output
     }
 
+    /// Rebuilds the range tombstone index from the segments
+    fn update_range_tombstones(&mut self) {
+        self.range_tombstones = self
+            .segments
+            .values()
+            .flat_map(|x| x.range_tombstones.iter().cloned())
+            .collect();
+
+        self.range_tombstones.sort_by(|a, b| a.start.cmp(&b.start));
+    }
+
+    /// Returns all range tombstones whose key range overlaps the given key bounds
+    ///
+    /// Range tombstones may be stored in segments that do not overlap the bounds themselves,
+    /// so all segments' tombstones are checked.
+    pub(crate) fn get_overlapping_range_tombstones<'a>(
+        &'a self,
+        bounds: &'a (Bound<UserKey>, Bound<UserKey>),
+    ) -> impl Iterator<Item = &'a RangeTombstone> + 'a {
+        self.range_tombstones
+            .iter()
+            .filter(move |x| x.overlaps_bounds(bounds))
+    }
+
+    /// Returns `true` if the item is deleted by any range tombstone in any segment
+    pub(crate) fn is_covered_by_range_tombstone(
+        &self,
+        key: &[u8],
+        item_seqno: SeqNo,
+        seqno: Option<SeqNo>,
+    ) -> bool {
+        // NOTE: Only tombstones that start at or before the key can contain it
+        let end = self.range_tombstones.partition_point(|x| &*x.start <= key);
+
+        self.range_tombstones[..end]
+            .iter()
+            .any(|x| x.covers(key, item_seqno, seqno))
+    }
+
+    /// Returns the bytes referenced in each blob file, summed over all segments
+    pub(crate) fn get_blob_refs(&self) -> HashMap<Arc<str>, u64> {
+        let mut refs: HashMap<Arc<str>, u64> = HashMap::new();
+
+        for segment in self.segments.values() {
+            for (blob_id, bytes) in &segment.metadata.blob_refs {
+                *refs.entry(blob_id.clone()).or_default() += bytes;
+            }
+        }
+
+        refs
+    }
+
+    /// Returns the level index and ID of all segments that point into any of the given blob files,
+    /// skipping segments that are currently being compacted
+    pub(crate) fn get_segments_referencing_blobs(
+        &self,
+        blob_ids: &[Arc<str>],
+    ) -> Vec<(u8, Arc<str>)> {
+        let mut output = Vec::new();
+
+        for (idx, level) in self.levels.iter().enumerate() {
+            for segment_id in level.iter() {
+                if self.hidden_set.contains(segment_id) {
+                    continue;
+                }
+
+                let Some(segment) = self.segments.get(segment_id) else {
+                    continue;
+                };
+
+                if blob_ids
+                    .iter()
+                    .any(|id| segment.metadata.blob_refs.contains_key(id))
+                {
+                    // NOTE: There are at most 255 levels
+                    #[allow(clippy::cast_possible_truncation)]
+                    output.push((idx as u8, segment_id.clone()));
+                }
+            }
+        }
+
+        output
+    }
+
     pub(crate) fn get_segments(&self) -> HashMap<Arc<str>, Arc<Segment>> {
         self.get_all_segments()
             .into_iter()
@@ -309,6 +467,12 @@ impl Levels {
         self.write_segment_history_entry("show").ok();
     }
 
+    /// Returns `true` if all given segments exist, and are not being compacted
+    pub(crate) fn can_compact(&self, keys: &[Arc<str>]) -> bool {
+        keys.iter()
+            .all(|key| self.segments.contains_key(key) && !self.hidden_set.contains(key))
+    }
+
     pub(crate) fn hide_segments(&mut self, keys: &[Arc<str>]) {
         for key in keys {
             self.hidden_set.insert(key.clone());
@@ -321,10 +485,11 @@ impl Levels {
 
 #[cfg(test)]
 mod tests {
-    use super::ResolvedLevel;
+    use super::{Levels, ResolvedLevel};
     use crate::{
         block_cache::BlockCache,
         descriptor_table::FileDescriptorTable,
+        range_tombstone::RangeTombstone,
         segment::{index::BlockIndex, meta::Metadata, Segment},
         value::UserKey,
     };
@@ -354,8 +519,14 @@ mod tests {
                 tombstone_count: 0,
                 uncompressed_size: 0,
                 seqnos: (0, 0),
+                blob_refs: std::collections::HashMap::default(),
+                expires_at: None,
+                global_seqno: None,
+                footer: crate::segment::footer::Footer::default(),
             },
             block_cache,
+            bloom_filter: None,
+            range_tombstones: Vec::new(),
         })
     }
 
@@ -381,4 +552,58 @@ mod tests {
             level.get_overlapping_segments(b"f".to_vec().into(), b"x".to_vec().into()),
         );
     }
+
+    #[test]
+    fn level_ingestion_level() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let mut levels = Levels::create_new(4, folder.path())?;
+
+        let range = |lo: &[u8], hi: &[u8]| -> (UserKey, UserKey) { (lo.into(), hi.into()) };
+
+        assert_eq!(3, levels.ingestion_level(&range(b"a", b"z")));
+
+        levels.insert_into_level(2, fixture_segment("1".into(), range(b"c", b"k")));
+        assert_eq!(1, levels.ingestion_level(&range(b"a", b"d")));
+        assert_eq!(3, levels.ingestion_level(&range(b"l", b"z")));
+
+        levels.insert_into_level(0, fixture_segment("2".into(), range(b"l", b"z")));
+        assert_eq!(0, levels.ingestion_level(&range(b"x", b"y")));
+        assert_eq!(1, levels.ingestion_level(&range(b"a", b"d")));
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn level_range_tombstones() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let mut levels = Levels::create_new(4, folder.path())?;
+
+        // NOTE: The tombstone lies outside the key range of its segment
+        let mut segment = fixture_segment("1".into(), (b"a".to_vec().into(), b"b".to_vec().into()));
+        Arc::get_mut(&mut segment)
+            .expect("should be unique")
+            .range_tombstones = vec![RangeTombstone {
+            start: b"c".to_vec().into(),
+            end: Some(b"e".to_vec().into()),
+            seqno: 5,
+        }];
+
+        levels.insert_into_level(0, segment);
+        levels.insert_into_level(
+            1,
+            fixture_segment("2".into(), (b"c".to_vec().into(), b"z".to_vec().into())),
+        );
+
+        assert!(levels.is_covered_by_range_tombstone(b"d", 4, None));
+        assert!(!levels.is_covered_by_range_tombstone(b"d", 5, None));
+        assert!(!levels.is_covered_by_range_tombstone(b"d", 4, Some(5)));
+        assert!(!levels.is_covered_by_range_tombstone(b"b", 4, None));
+        assert!(!levels.is_covered_by_range_tombstone(b"e", 4, None));
+
+        levels.remove(&"1".into());
+        assert!(!levels.is_covered_by_range_tombstone(b"d", 4, None));
+
+        Ok(())
+    }
 }
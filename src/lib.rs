@@ -66,9 +66,15 @@
 #![warn(clippy::expect_used)]
 #![allow(clippy::missing_const_for_fn)]
 
+mod backup;
 mod batch;
+mod blob;
 mod block_cache;
+mod bloom;
+mod change_feed;
+mod checkpoint;
 pub mod compaction;
+mod compression;
 mod config;
 mod descriptor_table;
 mod disk_block;
@@ -78,6 +84,7 @@ mod error;
 mod file;
 mod flush;
 mod id;
+mod ingest;
 mod journal;
 mod levels;
 
@@ -85,18 +92,25 @@ mod levels;
 pub mod memtable;
 
 mod merge;
+mod merge_operator;
+mod persistent_snapshot;
 mod prefix;
 mod range;
+mod range_tombstone;
 mod recovery;
+mod secondary;
 mod segment;
 mod serde;
 mod sharded;
 mod snapshot;
+mod snapshot_tracker;
 mod stop_signal;
 mod time;
+mod transaction;
 mod tree;
 mod tree_inner;
 mod value;
+mod varint;
 mod version;
 
 #[doc(hidden)]
@@ -104,12 +118,18 @@ pub use value::{Value, ValueType};
 
 pub use {
     crate::serde::{DeserializeError, SerializeError},
+    backup::{BackupEngine, BackupId, BackupInfo},
     batch::Batch,
     block_cache::BlockCache,
+    change_feed::{ChangeBatch, Subscription},
+    compression::CompressionType,
     config::Config,
     entry::Entry,
     error::{Error, Result},
     journal::shard::RecoveryError as JournalRecoveryError,
+    merge_operator::MergeOperator,
+    segment::builder::SegmentBuilder,
     snapshot::Snapshot,
+    transaction::{PessimisticTransaction, Transaction},
     tree::Tree,
 };
@@ -1,6 +1,8 @@
+use crate::range_tombstone::RangeTombstone;
 use crate::value::{ParsedInternalKey, SeqNo, UserData, ValueType};
 use crate::Value;
 use crossbeam_skiplist::SkipMap;
+use std::sync::RwLock;
 
 /// The `MemTable` serves as an intermediary storage for new items
 ///
@@ -10,6 +12,10 @@ use crossbeam_skiplist::SkipMap;
 #[derive(Default)]
 pub struct MemTable {
     pub(crate) items: SkipMap<ParsedInternalKey, UserData>,
+
+    /// Range tombstones, which are kept apart from the items,
+    /// because they cover other keys than their own
+    pub(crate) range_tombstones: RwLock<Vec<RangeTombstone>>,
 }
 
 impl MemTable {
@@ -63,10 +69,77 @@ impl MemTable {
         None
     }
 
+    /// Returns `true` if the `MemTable` contains neither items nor range tombstones
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning.
+    // NOTE: See `insert_range_tombstone`, the lock cannot be poisoned
+    #[allow(clippy::expect_used)]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+            && self
+                .range_tombstones
+                .read()
+                .expect("lock is poisoned")
+                .is_empty()
+    }
+
     /// Inserts an item into the `MemTable`
     pub fn insert(&self, entry: Value) {
-        let key = ParsedInternalKey::new(entry.key, entry.seqno, entry.value_type);
-        self.items.insert(key, entry.value);
+        if entry.value_type == ValueType::RangeTombstone {
+            self.insert_range_tombstone(entry);
+            return;
+        }
+
+        let value = entry.value.clone();
+        self.items.insert(ParsedInternalKey::from(entry), value);
+    }
+
+    /// Inserts a range tombstone (encoded as [`ValueType::RangeTombstone`] item)
+    // NOTE: Pushing into the vector cannot panic, so the lock cannot be poisoned
+    #[allow(clippy::expect_used)]
+    fn insert_range_tombstone(&self, entry: Value) {
+        let tombstone = RangeTombstone {
+            start: entry.key,
+            end: if entry.value.is_empty() {
+                None
+            } else {
+                Some(entry.value)
+            },
+            seqno: entry.seqno,
+        };
+
+        self.range_tombstones
+            .write()
+            .expect("lock is poisoned")
+            .push(tombstone);
+    }
+
+    /// Returns a copy of the range tombstones in the `MemTable`
+    // NOTE: See `insert_range_tombstone`, the lock cannot be poisoned
+    #[allow(clippy::expect_used)]
+    pub(crate) fn get_range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones
+            .read()
+            .expect("lock is poisoned")
+            .clone()
+    }
+
+    /// Returns `true` if the item is deleted by any range tombstone in the `MemTable`
+    // NOTE: See `insert_range_tombstone`, the lock cannot be poisoned
+    #[allow(clippy::expect_used)]
+    pub(crate) fn is_covered_by_range_tombstone(
+        &self,
+        key: &[u8],
+        item_seqno: SeqNo,
+        seqno: Option<SeqNo>,
+    ) -> bool {
+        self.range_tombstones
+            .read()
+            .expect("lock is poisoned")
+            .iter()
+            .any(|x| x.covers(key, item_seqno, seqno))
     }
 }
 
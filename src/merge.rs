@@ -1,6 +1,13 @@
-use crate::{segment::Segment, value::SeqNo, Value};
+use crate::{
+    blob::BlobStore,
+    range_tombstone::RangeTombstone,
+    segment::Segment,
+    time::unix_timestamp_millis,
+    value::{SeqNo, ValueType},
+    MergeOperator, Value,
+};
 use min_max_heap::MinMaxHeap;
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 
 // TODO: use (ParsedInternalKey, UserValue) instead of Value...
 
@@ -43,12 +50,40 @@ impl Ord for IteratorValue {
 /// and merging using a simple k-way merge algorithm
 ///
 /// If multiple iterators yield the same key value, the freshest one (by seqno) will be picked
+///
+/// Items that are deleted by a range tombstone are skipped
+///
+/// If a merge operator is set (and old versions are evicted), merge operands are folded together
+///
+/// If old versions are evicted, the newest version that is visible to each retained snapshot is kept
+///
+/// Expired items are treated like tombstones
 #[allow(clippy::module_name_repetitions)]
 pub struct MergeIterator<'a> {
     iterators: Vec<BoxedIterator<'a>>,
     heap: MinMaxHeap<IteratorValue>,
     evict_old_versions: bool,
     seqno: Option<SeqNo>,
+
+    /// Seqnos of open snapshots (ascending), whose visible versions are not evicted
+    snapshot_seqnos: Vec<SeqNo>,
+
+    range_tombstones: Vec<RangeTombstone>,
+    merge_operator: Option<Arc<dyn MergeOperator + Send + Sync>>,
+
+    /// Used to read the base value of merge operands, if it is stored in a blob file
+    blobs: Option<Arc<BlobStore>>,
+
+    /// If `false`, older versions of a key may exist outside of this iterator,
+    /// so merge operands that do not reach a base value cannot be folded
+    is_bottommost: bool,
+
+    /// Unix timestamp (in ms) at which items are checked for expiry
+    now: u64,
+
+    /// Items that are yet to be emitted, because a key resolved to multiple items
+    pending: VecDeque<Value>,
+    pending_back: VecDeque<Value>,
 }
 
 impl<'a> MergeIterator<'a> {
@@ -59,6 +94,14 @@ impl<'a> MergeIterator<'a> {
             heap: MinMaxHeap::new(),
             evict_old_versions: false,
             seqno: None,
+            snapshot_seqnos: Vec::new(),
+            range_tombstones: Vec::new(),
+            merge_operator: None,
+            blobs: None,
+            is_bottommost: true,
+            now: unix_timestamp_millis(),
+            pending: VecDeque::new(),
+            pending_back: VecDeque::new(),
         }
     }
 
@@ -72,6 +115,282 @@ impl<'a> MergeIterator<'a> {
         self
     }
 
+    pub fn retain_snapshot_versions(mut self, v: Vec<SeqNo>) -> Self {
+        self.snapshot_seqnos = v;
+        self
+    }
+
+    pub fn range_tombstones(mut self, v: Vec<RangeTombstone>) -> Self {
+        self.range_tombstones = v;
+        self
+    }
+
+    pub fn merge_operator(mut self, v: Option<Arc<dyn MergeOperator + Send + Sync>>) -> Self {
+        self.merge_operator = v;
+        self
+    }
+
+    pub fn blobs(mut self, v: Arc<BlobStore>) -> Self {
+        self.blobs = Some(v);
+        self
+    }
+
+    pub fn bottommost(mut self, v: bool) -> Self {
+        self.is_bottommost = v;
+        self
+    }
+
+    /// Returns `true` if all versions of a key are resolved together (see `resolve_key`)
+    fn resolves_versions(&self) -> bool {
+        self.evict_old_versions
+            && (self.merge_operator.is_some() || !self.snapshot_seqnos.is_empty())
+    }
+
+    /// Resolves all versions of a single key (newest first) into the items to emit (newest first)
+    ///
+    /// The versions are split into stripes by the snapshot seqnos: each snapshot can only see
+    /// the newest version of the stripe below it, so each stripe is resolved on its own.
+    fn resolve_key(&self, versions: Vec<Value>) -> crate::Result<Vec<Value>> {
+        if self.snapshot_seqnos.is_empty() {
+            return self.resolve_versions(versions, self.is_bottommost);
+        }
+
+        let mut stripes: Vec<(usize, Vec<Value>)> = Vec::new();
+
+        for version in versions {
+            let stripe = self
+                .snapshot_seqnos
+                .partition_point(|x| *x <= version.seqno);
+
+            match stripes.last_mut() {
+                Some((idx, items)) if *idx == stripe => items.push(version),
+                _ => stripes.push((stripe, vec![version])),
+            }
+        }
+
+        let stripe_count = stripes.len();
+        let mut items = Vec::new();
+
+        for (idx, (_, stripe)) in stripes.into_iter().enumerate() {
+            // NOTE: Only the oldest stripe cannot have older versions beneath it
+            let is_bottommost = self.is_bottommost && idx == stripe_count - 1;
+            items.extend(self.resolve_versions(stripe, is_bottommost)?);
+        }
+
+        if self.is_bottommost {
+            // NOTE: Tombstones that do not shadow anything anymore can be dropped
+            while items.last().is_some_and(Value::is_tombstone) {
+                items.pop();
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Resolves versions of a single key (newest first) into the items to emit (newest first)
+    fn resolve_versions(
+        &self,
+        versions: Vec<Value>,
+        is_bottommost: bool,
+    ) -> crate::Result<Vec<Value>> {
+        let mut operands = Vec::new();
+
+        // NOTE: None = no base value was found (yet)
+        let mut base: Option<Option<Value>> = None;
+
+        for version in versions {
+            if let Some(seqno) = self.seqno {
+                if version.seqno >= seqno {
+                    // Filter out seqnos that are too high
+                    continue;
+                }
+            }
+
+            if self.is_covered_by_range_tombstone(&version) {
+                base = Some(None);
+                break;
+            }
+
+            match version.value_type {
+                ValueType::Merge => operands.push(version),
+                _ if operands.is_empty() => return Ok(vec![version]),
+                ValueType::Value => {
+                    base = Some(Some(version));
+                    break;
+                }
+                ValueType::Indirection => {
+                    let Some(blobs) = &self.blobs else {
+                        // NOTE: The base value cannot be read, so keep everything as it is
+                        operands.push(version);
+                        return Ok(operands);
+                    };
+
+                    base = Some(Some(blobs.resolve(version)?));
+                    break;
+                }
+                ValueType::Tombstone | ValueType::RangeTombstone => {
+                    base = Some(None);
+                    break;
+                }
+            }
+        }
+
+        let Some(merge_operator) = &self.merge_operator else {
+            return Ok(operands);
+        };
+
+        if base.is_none() && is_bottommost {
+            base = Some(None);
+        }
+
+        let Some(base) = base else {
+            // NOTE: The base value may be in another segment
+            // so we need to keep the operands as they are
+            return Ok(operands);
+        };
+
+        let Some(newest) = operands.first() else {
+            return Ok(operands);
+        };
+        let key = newest.key.clone();
+        let seqno = newest.seqno;
+
+        let merged = merge_operator.merge(
+            &key,
+            base.as_ref().map(|x| &*x.value),
+            &operands.iter().rev().map(|x| &*x.value).collect::<Vec<_>>(),
+        );
+
+        Ok(vec![Value::new(key, merged, seqno, ValueType::Value)])
+    }
+
+    /// Pops all versions of the next key (newest first)
+    fn pop_versions(&mut self) -> crate::Result<Vec<Value>> {
+        let mut versions = Vec::new();
+
+        let Some(IteratorValue((idx, head))) = self.heap.pop_min() else {
+            return Ok(versions);
+        };
+        self.advance_iter(idx)?;
+
+        while let Some(next) = self.heap.pop_min() {
+            if next.key == head.key {
+                let IteratorValue((idx, next)) = next;
+                self.advance_iter(idx)?;
+                versions.push(next);
+            } else {
+                // Reached next user key now
+                // Push back non-conflicting item and exit
+                self.heap.push(next);
+                break;
+            }
+        }
+
+        versions.insert(0, head);
+
+        Ok(versions)
+    }
+
+    /// Pops all versions of the previous key (newest first)
+    fn pop_versions_back(&mut self) -> crate::Result<Vec<Value>> {
+        let mut versions = Vec::new();
+
+        let Some(IteratorValue((idx, head))) = self.heap.pop_max() else {
+            return Ok(versions);
+        };
+        self.advance_iter_backwards(idx)?;
+
+        while let Some(next) = self.heap.pop_max() {
+            if next.key == head.key {
+                let IteratorValue((idx, next)) = next;
+                self.advance_iter_backwards(idx)?;
+                versions.push(next);
+            } else {
+                // Reached next user key now
+                // Push back non-conflicting item and exit
+                self.heap.push(next);
+                break;
+            }
+        }
+
+        // NOTE: Reverse iteration yields the oldest version first
+        versions.insert(0, head);
+        versions.reverse();
+
+        Ok(versions)
+    }
+
+    fn next_merged(&mut self) -> Option<crate::Result<Value>> {
+        if let Some(item) = self.pending.pop_front() {
+            return Some(Ok(item));
+        }
+
+        if self.heap.is_empty() {
+            if let Err(e) = self.push_next() {
+                return Some(Err(e));
+            }
+        }
+
+        loop {
+            let versions = match self.pop_versions() {
+                Ok(versions) => versions,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if versions.is_empty() {
+                return None;
+            }
+
+            match self.resolve_key(versions) {
+                Ok(items) => self.pending.extend(items),
+                Err(e) => return Some(Err(e)),
+            }
+
+            if let Some(item) = self.pending.pop_front() {
+                return Some(Ok(item));
+            }
+        }
+    }
+
+    fn next_back_merged(&mut self) -> Option<crate::Result<Value>> {
+        if let Some(item) = self.pending_back.pop_front() {
+            return Some(Ok(item));
+        }
+
+        if self.heap.is_empty() {
+            if let Err(e) = self.push_next_back() {
+                return Some(Err(e));
+            }
+        }
+
+        loop {
+            let versions = match self.pop_versions_back() {
+                Ok(versions) => versions,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if versions.is_empty() {
+                return None;
+            }
+
+            // NOTE: Reverse iteration yields the oldest version first
+            match self.resolve_key(versions) {
+                Ok(items) => self.pending_back.extend(items.into_iter().rev()),
+                Err(e) => return Some(Err(e)),
+            }
+
+            if let Some(item) = self.pending_back.pop_front() {
+                return Some(Ok(item));
+            }
+        }
+    }
+
+    fn is_covered_by_range_tombstone(&self, item: &Value) -> bool {
+        self.range_tombstones
+            .iter()
+            .any(|x| x.covers(&item.key, item.seqno, self.seqno))
+    }
+
     pub fn from_segments(segments: &[Arc<Segment>]) -> Box<MergeIterator<'a>> {
         let mut iter_vec: Vec<Box<dyn DoubleEndedIterator<Item = crate::Result<Value>>>> =
             Vec::new();
@@ -84,11 +403,21 @@ impl<'a> MergeIterator<'a> {
         Box::new(MergeIterator::new(iter_vec))
     }
 
+    /// Replaces an expired item by a tombstone, so it still shadows older versions
+    fn expire(&self, item: Value) -> Value {
+        if item.is_expired(self.now) {
+            Value::new(item.key, vec![], item.seqno, ValueType::Tombstone)
+        } else {
+            item
+        }
+    }
+
     fn advance_iter(&mut self, idx: usize) -> crate::Result<()> {
         let iterator = self.iterators.get_mut(idx).expect("iter should exist");
 
         if let Some(value) = iterator.next() {
-            self.heap.push(IteratorValue((idx, value?)));
+            let value = self.expire(value?);
+            self.heap.push(IteratorValue((idx, value)));
         }
 
         Ok(())
@@ -98,7 +427,8 @@ impl<'a> MergeIterator<'a> {
         let iterator = self.iterators.get_mut(idx).expect("iter should exist");
 
         if let Some(value) = iterator.next_back() {
-            self.heap.push(IteratorValue((idx, value?)));
+            let value = self.expire(value?);
+            self.heap.push(IteratorValue((idx, value)));
         }
 
         Ok(())
@@ -125,6 +455,10 @@ impl<'a> Iterator for MergeIterator<'a> {
     type Item = crate::Result<Value>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.resolves_versions() {
+            return self.next_merged();
+        }
+
         if self.heap.is_empty() {
             if let Err(e) = self.push_next() {
                 return Some(Err(e));
@@ -171,6 +505,10 @@ impl<'a> Iterator for MergeIterator<'a> {
                 }
             }
 
+            if self.is_covered_by_range_tombstone(&head) {
+                continue;
+            }
+
             return Some(Ok(head.clone()));
         }
 
@@ -180,6 +518,10 @@ impl<'a> Iterator for MergeIterator<'a> {
 
 impl<'a> DoubleEndedIterator for MergeIterator<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
+        if self.resolves_versions() {
+            return self.next_back_merged();
+        }
+
         if self.heap.is_empty() {
             if let Err(e) = self.push_next_back() {
                 return Some(Err(e));
@@ -248,6 +590,10 @@ impl<'a> DoubleEndedIterator for MergeIterator<'a> {
                 continue;
             }
 
+            if self.is_covered_by_range_tombstone(&head) {
+                continue;
+            }
+
             return Some(Ok(head.clone()));
         }
 
@@ -476,4 +822,155 @@ mod tests {
 
         Ok(())
     }
+
+    struct Concat;
+
+    impl MergeOperator for Concat {
+        fn merge(&self, _: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8> {
+            let mut value = existing.unwrap_or_default().to_vec();
+            for operand in operands {
+                value.extend_from_slice(operand);
+            }
+            value
+        }
+    }
+
+    #[test]
+    fn test_retain_snapshot_versions() -> crate::Result<()> {
+        let vec0 = vec![
+            crate::Value::new(*b"a", *b"5", 5, ValueType::Value),
+            crate::Value::new(*b"a", *b"4", 4, ValueType::Value),
+            crate::Value::new(*b"a", *b"3", 3, ValueType::Value),
+            crate::Value::new(*b"a", *b"1", 1, ValueType::Value),
+            crate::Value::new(*b"a", *b"0", 0, ValueType::Value),
+            crate::Value::new(*b"b", vec![], 4, ValueType::Tombstone),
+            crate::Value::new(*b"b", *b"2", 2, ValueType::Value),
+            crate::Value::new(*b"c", *b"3", 3, ValueType::Value),
+            crate::Value::new(*b"c", vec![], 1, ValueType::Tombstone),
+            crate::Value::new(*b"d", vec![], 5, ValueType::Tombstone),
+            crate::Value::new(*b"d", *b"4", 4, ValueType::Value),
+        ];
+
+        {
+            let iter0 = Box::new(vec0.iter().cloned().map(Ok));
+
+            let merge_iter = MergeIterator::new(vec![iter0])
+                .evict_old_versions(true)
+                .retain_snapshot_versions(vec![2, 4]);
+            let items = merge_iter.collect::<crate::Result<Vec<_>>>()?;
+
+            assert_eq!(
+                items,
+                vec![
+                    crate::Value::new(*b"a", *b"5", 5, ValueType::Value),
+                    crate::Value::new(*b"a", *b"3", 3, ValueType::Value),
+                    crate::Value::new(*b"a", *b"1", 1, ValueType::Value),
+                    crate::Value::new(*b"b", vec![], 4, ValueType::Tombstone),
+                    crate::Value::new(*b"b", *b"2", 2, ValueType::Value),
+                    crate::Value::new(*b"c", *b"3", 3, ValueType::Value),
+                ]
+            );
+        }
+
+        {
+            // NOTE: Not the bottommost level, so tombstones still need to shadow older versions
+            let iter0 = Box::new(vec0.iter().cloned().map(Ok));
+
+            let merge_iter = MergeIterator::new(vec![iter0])
+                .evict_old_versions(true)
+                .retain_snapshot_versions(vec![2, 4])
+                .bottommost(false);
+            let items = merge_iter.collect::<crate::Result<Vec<_>>>()?;
+
+            assert_eq!(
+                items,
+                vec![
+                    crate::Value::new(*b"a", *b"5", 5, ValueType::Value),
+                    crate::Value::new(*b"a", *b"3", 3, ValueType::Value),
+                    crate::Value::new(*b"a", *b"1", 1, ValueType::Value),
+                    crate::Value::new(*b"b", vec![], 4, ValueType::Tombstone),
+                    crate::Value::new(*b"b", *b"2", 2, ValueType::Value),
+                    crate::Value::new(*b"c", *b"3", 3, ValueType::Value),
+                    crate::Value::new(*b"c", vec![], 1, ValueType::Tombstone),
+                    crate::Value::new(*b"d", vec![], 5, ValueType::Tombstone),
+                ]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_operands() -> crate::Result<()> {
+        let vec0 = vec![
+            crate::Value::new(*b"a", *b"1", 0, ValueType::Value),
+            crate::Value::new(*b"b", *b"1", 1, ValueType::Merge),
+        ];
+
+        let vec1 = vec![
+            crate::Value::new(*b"a", *b"2", 3, ValueType::Merge),
+            crate::Value::new(*b"a", *b"3", 2, ValueType::Merge),
+            crate::Value::new(*b"b", *b"2", 4, ValueType::Merge),
+        ];
+
+        {
+            let iter0 = Box::new(vec0.iter().cloned().map(Ok));
+            let iter1 = Box::new(vec1.iter().cloned().map(Ok));
+
+            let merge_iter = MergeIterator::new(vec![iter0, iter1])
+                .evict_old_versions(true)
+                .merge_operator(Some(Arc::new(Concat)));
+            let items = merge_iter.collect::<crate::Result<Vec<_>>>()?;
+
+            assert_eq!(
+                items,
+                vec![
+                    crate::Value::new(*b"a", *b"132", 3, ValueType::Value),
+                    crate::Value::new(*b"b", *b"12", 4, ValueType::Value),
+                ]
+            );
+        }
+
+        {
+            let iter0 = Box::new(vec0.iter().cloned().map(Ok));
+            let iter1 = Box::new(vec1.iter().cloned().map(Ok));
+
+            let merge_iter = MergeIterator::new(vec![iter0, iter1])
+                .evict_old_versions(true)
+                .merge_operator(Some(Arc::new(Concat)))
+                .snapshot_seqno(3);
+            let items = merge_iter.rev().collect::<crate::Result<Vec<_>>>()?;
+
+            assert_eq!(
+                items,
+                vec![
+                    crate::Value::new(*b"b", *b"1", 1, ValueType::Value),
+                    crate::Value::new(*b"a", *b"13", 2, ValueType::Value),
+                ]
+            );
+        }
+
+        {
+            // NOTE: Not the bottommost level, so "b" may have an older base value somewhere else
+            let iter0 = Box::new(vec0.iter().cloned().map(Ok));
+            let iter1 = Box::new(vec1.iter().cloned().map(Ok));
+
+            let merge_iter = MergeIterator::new(vec![iter0, iter1])
+                .evict_old_versions(true)
+                .merge_operator(Some(Arc::new(Concat)))
+                .bottommost(false);
+            let items = merge_iter.collect::<crate::Result<Vec<_>>>()?;
+
+            assert_eq!(
+                items,
+                vec![
+                    crate::Value::new(*b"a", *b"132", 3, ValueType::Value),
+                    crate::Value::new(*b"b", *b"2", 4, ValueType::Merge),
+                    crate::Value::new(*b"b", *b"1", 1, ValueType::Merge),
+                ]
+            );
+        }
+
+        Ok(())
+    }
 }
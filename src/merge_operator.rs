@@ -0,0 +1,52 @@
+/// User-defined merge operator
+///
+/// A merge operator allows read-modify-write operations (like incrementing a counter,
+/// or appending to a list) without having to read the current value first.
+///
+/// Instead, [`Tree::merge`](crate::Tree::merge) blindly writes an operand, and all operands
+/// of a key are folded together lazily when the key is read or compacted.
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use lsm_tree::{Config, MergeOperator};
+/// use std::sync::Arc;
+///
+/// struct Counter;
+///
+/// impl MergeOperator for Counter {
+///     fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8> {
+///         let mut counter = existing
+///             .map(|x| u64::from_be_bytes(x.try_into().expect("should be u64")))
+///             .unwrap_or_default();
+///
+///         for operand in operands {
+///             counter += u64::from_be_bytes((*operand).try_into().expect("should be u64"));
+///         }
+///
+///         counter.to_be_bytes().to_vec()
+///     }
+/// }
+///
+/// let tree = Config::new(folder).merge_operator(Arc::new(Counter)).open()?;
+///
+/// tree.merge("hits", 1u64.to_be_bytes())?;
+/// tree.merge("hits", 1u64.to_be_bytes())?;
+/// tree.merge("hits", 5u64.to_be_bytes())?;
+///
+/// let item = tree.get("hits")?.expect("should exist");
+/// assert_eq!(&7u64.to_be_bytes(), &*item);
+/// #
+/// # Ok::<(), lsm_tree::Error>(())
+/// ```
+#[allow(clippy::module_name_repetitions)]
+pub trait MergeOperator {
+    /// Folds the merge operands of a key into a new value
+    ///
+    /// `existing` is the value the operands are applied to, or `None` if the key
+    /// did not exist (or was deleted) before the first operand.
+    ///
+    /// The operands are given in the order they were written (oldest first).
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8>;
+}
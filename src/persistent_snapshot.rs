@@ -0,0 +1,129 @@
+use crate::{file::rewrite_atomic, value::SeqNo};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{RwLock, RwLockWriteGuard},
+};
+
+/// Named snapshots that are persisted in the tree folder, so they survive restarts
+///
+/// Every persistent snapshot pins the versions it can see,
+/// until it is deleted (see `SnapshotTracker`).
+pub struct PersistentSnapshots {
+    path: PathBuf,
+
+    /// Snapshot name -> seqno of the snapshot
+    snapshots: RwLock<BTreeMap<String, SeqNo>>,
+}
+
+// NOTE: The snapshot list is only changed using infallible map operations,
+// so the lock is never poisoned
+#[allow(clippy::expect_used)]
+impl PersistentSnapshots {
+    /// Creates an empty snapshot list
+    ///
+    /// The file is only written once a snapshot is created.
+    pub fn create_new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            snapshots: RwLock::default(),
+        }
+    }
+
+    /// Recovers the snapshot list from the given file
+    pub fn recover<P: Into<PathBuf>>(path: P) -> crate::Result<Self> {
+        let path = path.into();
+
+        let snapshots = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(std::io::Error::from)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        log::debug!("Recovered persistent snapshots: {snapshots:?}");
+
+        Ok(Self {
+            path,
+            snapshots: RwLock::new(snapshots),
+        })
+    }
+
+    /// Returns the seqnos of all snapshots
+    pub fn seqnos(&self) -> Vec<SeqNo> {
+        self.snapshots
+            .read()
+            .expect("lock is poisoned")
+            .values()
+            .copied()
+            .collect()
+    }
+
+    /// Returns the seqno of a snapshot
+    pub fn get(&self, name: &str) -> Option<SeqNo> {
+        self.snapshots
+            .read()
+            .expect("lock is poisoned")
+            .get(name)
+            .copied()
+    }
+
+    /// Returns the names of all snapshots
+    pub fn names(&self) -> Vec<String> {
+        self.snapshots
+            .read()
+            .expect("lock is poisoned")
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Write-locks the snapshot list
+    pub fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<String, SeqNo>> {
+        self.snapshots.write().expect("lock is poisoned")
+    }
+
+    /// Atomically rewrites the snapshot file
+    pub fn write_to_disk(&self, snapshots: &BTreeMap<String, SeqNo>) -> crate::Result<()> {
+        log::trace!("Writing persistent snapshots");
+
+        // NOTE: Serialization can't fail here
+        #[allow(clippy::expect_used)]
+        let json = serde_json::to_string_pretty(snapshots).expect("should serialize");
+
+        rewrite_atomic(&self.path, json.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn persistent_snapshots_recover() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("snapshots.json");
+
+        {
+            let snapshots = PersistentSnapshots::recover(&path)?;
+            assert!(snapshots.seqnos().is_empty());
+
+            let mut lock = snapshots.write();
+            lock.insert("a".into(), 5);
+            lock.insert("b".into(), 7);
+            snapshots.write_to_disk(&lock)?;
+        }
+
+        {
+            let snapshots = PersistentSnapshots::recover(&path)?;
+            assert_eq!(vec![5, 7], snapshots.seqnos());
+            assert_eq!(Some(5), snapshots.get("a"));
+            assert_eq!(Some(7), snapshots.get("b"));
+            assert_eq!(vec!["a", "b"], snapshots.names());
+        }
+
+        Ok(())
+    }
+}
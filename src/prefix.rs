@@ -1,9 +1,11 @@
 use crate::{
+    blob::BlobStore,
     merge::{BoxedIterator, MergeIterator},
     range::MemTableGuard,
+    range_tombstone::RangeTombstone,
     segment::Segment,
     value::{ParsedInternalKey, SeqNo, UserData, UserKey, ValueType},
-    Value,
+    MergeOperator, Value,
 };
 use std::sync::Arc;
 
@@ -11,7 +13,10 @@ pub struct Prefix<'a> {
     guard: MemTableGuard<'a>,
     prefix: UserKey,
     segments: Vec<Arc<Segment>>,
+    range_tombstones: Vec<RangeTombstone>,
     seqno: Option<SeqNo>,
+    merge_operator: Option<Arc<dyn MergeOperator + Send + Sync>>,
+    blobs: Arc<BlobStore>,
 }
 
 impl<'a> Prefix<'a> {
@@ -19,13 +24,19 @@ impl<'a> Prefix<'a> {
         guard: MemTableGuard<'a>,
         prefix: UserKey,
         segments: Vec<Arc<Segment>>,
+        range_tombstones: Vec<RangeTombstone>,
         seqno: Option<SeqNo>,
+        merge_operator: Option<Arc<dyn MergeOperator + Send + Sync>>,
+        blobs: Arc<BlobStore>,
     ) -> Self {
         Self {
             guard,
             prefix,
             segments,
+            range_tombstones,
             seqno,
+            merge_operator,
+            blobs,
         }
     }
 }
@@ -45,7 +56,15 @@ impl<'a> PrefixIterator<'a> {
             segment_iters.push(Box::new(reader));
         }
 
-        let mut iters: Vec<BoxedIterator<'a>> = vec![Box::new(MergeIterator::new(segment_iters))];
+        let mut segment_iter = MergeIterator::new(segment_iters);
+
+        // NOTE: A segment may contain a tombstone and an older version of the same key
+        // that is kept for a snapshot, so the inner iterator needs to know the snapshot seqno too
+        if let Some(seqno) = seqno {
+            segment_iter = segment_iter.snapshot_seqno(seqno);
+        }
+
+        let mut iters: Vec<BoxedIterator<'a>> = vec![Box::new(segment_iter)];
 
         for (_, memtable) in lock.guard.immutable.iter() {
             iters.push(Box::new(
@@ -77,16 +96,26 @@ impl<'a> PrefixIterator<'a> {
 
         iters.push(Box::new(memtable_iter));
 
-        let mut iter = MergeIterator::new(iters).evict_old_versions(true);
+        let mut range_tombstones = lock.guard.range_tombstones();
+        range_tombstones.extend(lock.range_tombstones.iter().cloned());
+
+        let mut iter = MergeIterator::new(iters)
+            .evict_old_versions(true)
+            .range_tombstones(range_tombstones)
+            .merge_operator(lock.merge_operator.clone())
+            .blobs(lock.blobs.clone());
 
         if let Some(seqno) = seqno {
             iter = iter.snapshot_seqno(seqno);
         }
 
-        let iter = Box::new(iter.filter(|x| match x {
-            Ok(value) => value.value_type != ValueType::Tombstone,
-            Err(_) => true,
-        }));
+        let iter = Box::new(
+            iter.filter(|x| {
+                x.as_ref()
+                    .map_or(true, |value| value.value_type != ValueType::Tombstone)
+            })
+            .map(|x| x.and_then(|value| lock.blobs.resolve(value))),
+        );
 
         Self { iter }
     }
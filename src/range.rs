@@ -1,9 +1,11 @@
 use crate::{
+    blob::BlobStore,
     memtable::MemTable,
     merge::{BoxedIterator, MergeIterator},
+    range_tombstone::RangeTombstone,
     segment::Segment,
     value::{ParsedInternalKey, SeqNo, UserData, UserKey, ValueType},
-    Value,
+    MergeOperator, Value,
 };
 use std::{
     collections::BTreeMap,
@@ -16,11 +18,27 @@ pub struct MemTableGuard<'a> {
     pub(crate) immutable: RwLockReadGuard<'a, BTreeMap<Arc<str>, Arc<MemTable>>>,
 }
 
+impl MemTableGuard<'_> {
+    /// Collects the range tombstones of all memtables
+    pub(crate) fn range_tombstones(&self) -> Vec<RangeTombstone> {
+        let mut tombstones = self.active.get_range_tombstones();
+
+        for memtable in self.immutable.values() {
+            tombstones.extend(memtable.get_range_tombstones());
+        }
+
+        tombstones
+    }
+}
+
 pub struct Range<'a> {
     guard: MemTableGuard<'a>,
     bounds: (Bound<UserKey>, Bound<UserKey>),
     segments: Vec<Arc<Segment>>,
+    tombstones: Vec<RangeTombstone>,
     seqno: Option<SeqNo>,
+    merge_operator: Option<Arc<dyn MergeOperator + Send + Sync>>,
+    blobs: Arc<BlobStore>,
 }
 
 impl<'a> Range<'a> {
@@ -28,13 +46,19 @@ impl<'a> Range<'a> {
         guard: MemTableGuard<'a>,
         bounds: (Bound<UserKey>, Bound<UserKey>),
         segments: Vec<Arc<Segment>>,
+        range_tombstones: Vec<RangeTombstone>,
         seqno: Option<SeqNo>,
+        merge_operator: Option<Arc<dyn MergeOperator + Send + Sync>>,
+        blobs: Arc<BlobStore>,
     ) -> Self {
         Self {
             guard,
             bounds,
             segments,
+            tombstones: range_tombstones,
             seqno,
+            merge_operator,
+            blobs,
         }
     }
 }
@@ -99,7 +123,15 @@ impl<'a> RangeIterator<'a> {
             segment_iters.push(Box::new(reader));
         }
 
-        let mut iters: Vec<BoxedIterator<'a>> = vec![Box::new(MergeIterator::new(segment_iters))];
+        let mut segment_iter = MergeIterator::new(segment_iters);
+
+        // NOTE: A segment may contain a tombstone and an older version of the same key
+        // that is kept for a snapshot, so the inner iterator needs to know the snapshot seqno too
+        if let Some(seqno) = seqno {
+            segment_iter = segment_iter.snapshot_seqno(seqno);
+        }
+
+        let mut iters: Vec<BoxedIterator<'a>> = vec![Box::new(segment_iter)];
 
         for (_, memtable) in lock.guard.immutable.iter() {
             iters.push(Box::new(memtable.items.range(range.clone()).map(|entry| {
@@ -117,21 +149,38 @@ impl<'a> RangeIterator<'a> {
 
         iters.push(Box::new(memtable_iter));
 
-        let mut iter = MergeIterator::new(iters).evict_old_versions(true);
+        let mut range_tombstones = lock.guard.range_tombstones();
+        range_tombstones.extend(lock.tombstones.iter().cloned());
+
+        let mut iter = MergeIterator::new(iters)
+            .evict_old_versions(true)
+            .range_tombstones(range_tombstones)
+            .merge_operator(lock.merge_operator.clone())
+            .blobs(lock.blobs.clone());
 
         if let Some(seqno) = seqno {
             iter = iter.snapshot_seqno(seqno);
         }
 
-        let iter = Box::new(iter.filter(|x| match x {
-            Ok(value) => value.value_type != ValueType::Tombstone,
-            Err(_) => true,
-        }));
+        let iter = Box::new(
+            iter.filter(|x| {
+                x.as_ref()
+                    .map_or(true, |value| value.value_type != ValueType::Tombstone)
+            })
+            .map(|x| x.and_then(|value| lock.blobs.resolve(value))),
+        );
 
         Self { iter }
     }
 }
 
+impl<'a> RangeIterator<'a> {
+    /// Returns the items (including their expiry) instead of key-value pairs
+    pub(crate) fn into_values(self) -> BoxedIterator<'a> {
+        self.iter
+    }
+}
+
 impl<'a> Iterator for RangeIterator<'a> {
     type Item = crate::Result<(UserKey, UserData)>;
 
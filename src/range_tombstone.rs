@@ -0,0 +1,341 @@
+use crate::{
+    serde::{read_exact_len, Deserializable, DeserializeError, Serializable, SerializeError},
+    value::{SeqNo, UserKey},
+    varint::{read_varint, write_varint},
+    version::Version,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    ops::Bound,
+    path::Path,
+};
+
+/// A range tombstone deletes all items in `[start, end)` that are older than itself
+///
+/// If `end` is `None`, the range is unbounded, so every key `>= start` is deleted.
+///
+/// # Disk representation
+///
+/// \[seqno; 8 bytes] \[start length; varint] \[start; N bytes] \[has end; 1 byte] \[end length; varint] \[end; N bytes]
+///
+/// Before [`Version::V1`], the key lengths are stored as 2 bytes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RangeTombstone {
+    /// Start key (inclusive)
+    pub start: UserKey,
+
+    /// End key (exclusive)
+    pub end: Option<UserKey>,
+
+    /// Sequence number
+    pub seqno: SeqNo,
+}
+
+impl RangeTombstone {
+    /// Creates a range tombstone from arbitrary key bounds.
+    ///
+    /// Returns `None` if the range is empty.
+    pub fn from_bounds<K: AsRef<[u8]>>(bounds: (Bound<K>, Bound<K>), seqno: SeqNo) -> Option<Self> {
+        // NOTE: Keys cannot be empty, so [0x00] is the lowest possible key
+        // and [key, 0x00] is the lowest key that is greater than `key`
+        let start: UserKey = match bounds.0 {
+            Bound::Included(key) => key.as_ref().into(),
+            Bound::Excluded(key) => [key.as_ref(), &[0]].concat().into(),
+            Bound::Unbounded => [0].into(),
+        };
+
+        let end: Option<UserKey> = match bounds.1 {
+            Bound::Included(key) => Some([key.as_ref(), &[0]].concat().into()),
+            Bound::Excluded(key) => Some(key.as_ref().into()),
+            Bound::Unbounded => None,
+        };
+
+        if let Some(end) = &end {
+            if start >= *end {
+                return None;
+            }
+        }
+
+        Some(Self { start, end, seqno })
+    }
+
+    /// Creates a range tombstone that deletes every key starting with `prefix`.
+    pub fn from_prefix(prefix: &[u8], seqno: SeqNo) -> Self {
+        if prefix.is_empty() {
+            return Self {
+                start: [0].into(),
+                end: None,
+                seqno,
+            };
+        }
+
+        Self {
+            start: prefix.into(),
+            end: prefix_successor(prefix),
+            seqno,
+        }
+    }
+
+    /// Returns `true` if the key is inside the tombstone's key range.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        key >= &*self.start && self.end.as_ref().map_or(true, |end| key < &**end)
+    }
+
+    /// Returns `true` if the tombstone deletes the given item version.
+    ///
+    /// Only tombstones that are visible to the given snapshot seqno are considered.
+    pub fn covers(&self, key: &[u8], item_seqno: SeqNo, snapshot_seqno: Option<SeqNo>) -> bool {
+        if let Some(snapshot_seqno) = snapshot_seqno {
+            if self.seqno >= snapshot_seqno {
+                return false;
+            }
+        }
+
+        item_seqno < self.seqno && self.contains_key(key)
+    }
+
+    /// Returns `true` if the tombstone's key range overlaps the given (inclusive) key range.
+    pub fn overlaps(&self, lo: &[u8], hi: &[u8]) -> bool {
+        hi >= &*self.start && self.end.as_ref().map_or(true, |end| lo < &**end)
+    }
+
+    /// Returns `true` if the tombstone's key range overlaps the given key bounds.
+    pub fn overlaps_bounds(&self, bounds: &(Bound<UserKey>, Bound<UserKey>)) -> bool {
+        let above_lo = match (&bounds.0, &self.end) {
+            (Bound::Included(key) | Bound::Excluded(key), Some(end)) => key < end,
+            _ => true,
+        };
+
+        let below_hi = match &bounds.1 {
+            Bound::Included(key) => self.start <= *key,
+            Bound::Excluded(key) => self.start < *key,
+            Bound::Unbounded => true,
+        };
+
+        above_lo && below_hi
+    }
+
+    /// Returns `true` if the given (inclusive) key range is fully inside the tombstone's key range.
+    pub fn contains_key_range(&self, lo: &[u8], hi: &[u8]) -> bool {
+        self.contains_key(lo) && self.contains_key(hi)
+    }
+}
+
+/// Returns the key bounds that contain exactly the keys starting with `prefix`
+pub fn prefix_bounds(prefix: &[u8]) -> (Bound<UserKey>, Bound<UserKey>) {
+    if prefix.is_empty() {
+        return (Bound::Unbounded, Bound::Unbounded);
+    }
+
+    let hi = prefix_successor(prefix).map_or(Bound::Unbounded, Bound::Excluded);
+    (Bound::Included(prefix.into()), hi)
+}
+
+/// Returns the lowest key that is greater than all keys starting with `prefix`
+///
+/// Returns `None` if there is no such key (the prefix only consists of 0xFF bytes).
+fn prefix_successor(prefix: &[u8]) -> Option<UserKey> {
+    let idx = prefix.iter().rposition(|&byte| byte < u8::MAX)?;
+
+    let mut successor = prefix[..=idx].to_vec();
+    successor[idx] += 1;
+
+    Some(successor.into())
+}
+
+impl Serializable for RangeTombstone {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        writer.write_u64::<BigEndian>(self.seqno)?;
+
+        write_varint(writer, self.start.len() as u64)?;
+        writer.write_all(&self.start)?;
+
+        if let Some(end) = &self.end {
+            writer.write_u8(1)?;
+
+            write_varint(writer, end.len() as u64)?;
+            writer.write_all(end)?;
+        } else {
+            writer.write_u8(0)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Deserializable for RangeTombstone {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        Self::deserialize_versioned(reader, Version::V1)
+    }
+}
+
+impl RangeTombstone {
+    /// Deserializes a range tombstone in the disk representation of the given segment version
+    pub fn deserialize_versioned<R: Read>(
+        reader: &mut R,
+        version: Version,
+    ) -> Result<Self, DeserializeError> {
+        let seqno = reader.read_u64::<BigEndian>()?;
+
+        let start = read_key(reader, version)?;
+
+        let end = if reader.read_u8()? == 1 {
+            Some(read_key(reader, version)?.into())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            start: start.into(),
+            end,
+            seqno,
+        })
+    }
+}
+
+/// Reads a length-prefixed key in the given segment version
+fn read_key<R: Read>(reader: &mut R, version: Version) -> Result<Vec<u8>, DeserializeError> {
+    let len = if version < Version::V1 {
+        reader.read_u16::<BigEndian>()?.into()
+    } else {
+        read_varint(reader)?
+    };
+
+    Ok(read_exact_len(reader, len)?)
+}
+
+/// Writes a list of range tombstones
+pub fn write_into<W: Write>(writer: &mut W, tombstones: &[RangeTombstone]) -> crate::Result<()> {
+    // NOTE: There are never 4 billion range tombstones in a segment
+    #[allow(clippy::cast_possible_truncation)]
+    writer.write_u32::<BigEndian>(tombstones.len() as u32)?;
+
+    for tombstone in tombstones {
+        tombstone.serialize(writer)?;
+    }
+
+    Ok(())
+}
+
+/// Reads a list of range tombstones in the disk representation of the given segment version
+pub fn read_from<R: Read>(reader: &mut R, version: Version) -> crate::Result<Vec<RangeTombstone>> {
+    let count = reader.read_u32::<BigEndian>()?;
+
+    // NOTE: The count is not trusted for preallocation, as it may be corrupted
+    let mut tombstones = Vec::with_capacity(count.min(1_024) as usize);
+
+    for _ in 0..count {
+        tombstones.push(RangeTombstone::deserialize_versioned(reader, version)?);
+    }
+
+    Ok(tombstones)
+}
+
+/// Reads a list of range tombstones from a file
+pub fn read_from_file<P: AsRef<Path>>(
+    path: P,
+    version: Version,
+) -> crate::Result<Vec<RangeTombstone>> {
+    read_from(&mut BufReader::new(File::open(path)?), version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufWriter;
+    use test_log::test;
+
+    #[test]
+    fn range_tombstone_serde_round_trip() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("range_tombstones");
+
+        let tombstones = vec![
+            RangeTombstone {
+                start: (*b"a").into(),
+                end: Some((*b"c").into()),
+                seqno: 5,
+            },
+            RangeTombstone {
+                start: (*b"x").into(),
+                end: None,
+                seqno: 7,
+            },
+        ];
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        write_into(&mut writer, &tombstones)?;
+        writer.flush()?;
+        drop(writer);
+
+        assert_eq!(tombstones, read_from_file(&path, Version::V1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn range_tombstone_covers() {
+        let tombstone =
+            RangeTombstone::from_bounds((Bound::Included("b"), Bound::Excluded("d")), 5)
+                .expect("should not be empty");
+
+        assert!(!tombstone.covers(b"a", 0, None));
+        assert!(tombstone.covers(b"b", 0, None));
+        assert!(tombstone.covers(b"c", 4, None));
+        assert!(tombstone.covers(b"czzz", 4, None));
+        assert!(!tombstone.covers(b"d", 0, None));
+
+        // Newer items are not deleted
+        assert!(!tombstone.covers(b"c", 5, None));
+        assert!(!tombstone.covers(b"c", 6, None));
+
+        // Not visible to old snapshot
+        assert!(!tombstone.covers(b"c", 4, Some(5)));
+        assert!(tombstone.covers(b"c", 4, Some(6)));
+    }
+
+    #[test]
+    fn range_tombstone_bounds() {
+        let tombstone =
+            RangeTombstone::from_bounds((Bound::Excluded("b"), Bound::Included("d")), 0)
+                .expect("should not be empty");
+        assert!(!tombstone.contains_key(b"b"));
+        assert!(tombstone.contains_key(b"b\0"));
+        assert!(tombstone.contains_key(b"d"));
+        assert!(!tombstone.contains_key(b"d\0"));
+
+        let tombstone =
+            RangeTombstone::from_bounds::<&str>((Bound::Unbounded, Bound::Unbounded), 0)
+                .expect("should not be empty");
+        assert!(tombstone.contains_key(b"\0"));
+        assert!(tombstone.contains_key(b"zzzzzz"));
+
+        assert!(
+            RangeTombstone::from_bounds((Bound::Included("d"), Bound::Excluded("b")), 0).is_none()
+        );
+        assert!(
+            RangeTombstone::from_bounds((Bound::Included("b"), Bound::Excluded("b")), 0).is_none()
+        );
+    }
+
+    #[test]
+    fn range_tombstone_prefix() {
+        let tombstone = RangeTombstone::from_prefix(b"ab", 0);
+        assert!(!tombstone.contains_key(b"a"));
+        assert!(tombstone.contains_key(b"ab"));
+        assert!(tombstone.contains_key(b"ab\xFF\xFF"));
+        assert!(!tombstone.contains_key(b"ac"));
+        assert!(tombstone.contains_key_range(b"ab", b"abzzz"));
+        assert!(!tombstone.contains_key_range(b"ab", b"b"));
+
+        let tombstone = RangeTombstone::from_prefix(b"a\xFF", 0);
+        assert_eq!(Some((*b"b").into()), tombstone.end);
+
+        let tombstone = RangeTombstone::from_prefix(b"\xFF\xFF", 0);
+        assert_eq!(None, tombstone.end);
+        assert!(tombstone.contains_key(b"\xFF\xFF\xFF"));
+        assert!(!tombstone.contains_key(b"\xFF"));
+    }
+}
@@ -1,17 +1,22 @@
 use crate::{
+    blob::BlobStore,
+    change_feed::retire_journal,
     compaction::worker::start_compaction_thread,
-    descriptor_table::FileDescriptorTable,
     file::{
-        BLOCKS_FILE, FLUSH_MARKER, JOURNALS_FOLDER, LEVELS_MANIFEST_FILE, LSM_MARKER,
-        SEGMENTS_FOLDER,
+        is_segment_sidecar, remove_file_or_folder, BLOBS_FOLDER, FLUSH_MARKER, FOLLOWER_MARKER,
+        JOURNALS_FOLDER, LSM_MARKER, SEGMENTS_FOLDER, SNAPSHOTS_FILE,
     },
     id::generate_segment_id,
     journal::Journal,
     levels::Levels,
     memtable::MemTable,
+    persistent_snapshot::PersistentSnapshots,
     segment::{self, Segment},
+    snapshot_tracker::SnapshotTracker,
     stop_signal::StopSignal,
+    transaction::lock::LockManager,
     tree_inner::TreeInner,
+    value::SeqNo,
     version::Version,
     BlockCache, Config, Tree,
 };
@@ -19,8 +24,8 @@ use std::{
     collections::HashMap,
     path::Path,
     sync::{
-        atomic::{AtomicU32, AtomicU64},
-        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU32, AtomicU64},
+        Arc, Mutex, RwLock,
     },
 };
 use std_semaphore::Semaphore;
@@ -28,7 +33,7 @@ use std_semaphore::Semaphore;
 pub fn recover_active_journal(config: &Config) -> crate::Result<Option<(Journal, MemTable)>> {
     // Load previous levels manifest
     // Add all flushed segments to it, then recover properly
-    let mut levels = Levels::recover(config.path.join(LEVELS_MANIFEST_FILE), HashMap::new())?;
+    let mut levels = Levels::recover(&config.path, HashMap::new())?;
 
     let mut active_journal = None;
 
@@ -40,7 +45,7 @@ pub fn recover_active_journal(config: &Config) -> crate::Result<Option<(Journal,
 
         // TODO: replace fs extra with Journal::disk_space
         let journal_size = fs_extra::dir::get_size(&journal_path)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "fs_extra error"))?;
+            .map_err(|_| std::io::Error::other("fs_extra error"))?;
 
         if journal_size == 0 {
             std::fs::remove_dir_all(&journal_path)?;
@@ -51,7 +56,9 @@ pub fn recover_active_journal(config: &Config) -> crate::Result<Option<(Journal,
             // TODO: handle this
             assert!(active_journal.is_none(), "Second active journal found :(");
 
-            if journal_size < config.max_memtable_size.into() {
+            let is_legacy = Journal::get_version(&journal_path)? == Version::V0;
+
+            if journal_size < config.max_memtable_size.into() && !is_legacy {
                 log::info!("Setting {} as active journal", journal_path.display());
 
                 let (recovered_journal, memtable) = Journal::recover(journal_path.clone())?;
@@ -60,13 +67,23 @@ pub fn recover_active_journal(config: &Config) -> crate::Result<Option<(Journal,
                 continue;
             }
 
-            log::info!(
-                "Flushing active journal because it is too large: {}",
-                dirent.path().to_string_lossy()
-            );
-
-            // Journal is too large to be continued to be used
-            // Just flush it
+            if is_legacy {
+                log::info!(
+                    "Flushing active journal because it uses an old disk format: {}",
+                    dirent.path().to_string_lossy()
+                );
+
+                // Journal can not be appended to in the current disk format
+                // Just flush it
+            } else {
+                log::info!(
+                    "Flushing active journal because it is too large: {}",
+                    dirent.path().to_string_lossy()
+                );
+
+                // Journal is too large to be continued to be used
+                // Just flush it
+            }
         }
 
         log::info!(
@@ -87,22 +104,26 @@ pub fn recover_active_journal(config: &Config) -> crate::Result<Option<(Journal,
             .to_string()
             .into();
 
-        let segment_folder = config.path.join(SEGMENTS_FOLDER).join(&*segment_id);
+        let segment_path = config.path.join(SEGMENTS_FOLDER).join(&*segment_id);
 
         if !levels.contains_id(&segment_id) {
             // The level manifest does not contain the segment
             // If the segment is maybe half written, clean it up here
             // and then write it
-            if segment_folder.exists() {
-                std::fs::remove_dir_all(&segment_folder)?;
+            if segment_path.exists() {
+                remove_file_or_folder(&segment_path)?;
             }
 
             let mut segment_writer = segment::writer::Writer::new(segment::writer::Options {
-                path: segment_folder.clone(),
+                path: segment_path,
                 evict_tombstones: false,
                 block_size: config.block_size,
+                bloom_bits_per_key: config.bloom_bits_per_key,
+                compression: config.compression_for_level(0),
             })?;
 
+            segment_writer.set_range_tombstones(memtable.get_range_tombstones());
+
             for (key, value) in memtable.items {
                 segment_writer.write(crate::Value::from((key, value)))?;
             }
@@ -120,12 +141,32 @@ pub fn recover_active_journal(config: &Config) -> crate::Result<Option<(Journal,
             }
         }
 
-        std::fs::remove_dir_all(journal_path)?;
+        retire_journal(config, &journal_path)?;
     }
 
     Ok(active_journal)
 }
 
+/// Returns the seqno after the highest seqno in a memtable
+fn get_next_lsn(memtable: &MemTable) -> SeqNo {
+    // TODO: optimize this... do on journal load...
+    memtable
+        .items
+        .iter()
+        .map(|x| {
+            let key = x.key();
+            key.seqno + 1
+        })
+        .chain(
+            memtable
+                .get_range_tombstones()
+                .into_iter()
+                .map(|x| x.seqno + 1),
+        )
+        .max()
+        .unwrap_or(0)
+}
+
 pub fn recover_segments<P: AsRef<Path>>(
     folder: P,
     block_cache: &Arc<BlockCache>,
@@ -135,7 +176,7 @@ pub fn recover_segments<P: AsRef<Path>>(
     // NOTE: First we load the level manifest without any
     // segments just to get the IDs
     // Then we recover the segments and build the actual level manifest
-    let levels = Levels::recover(folder.join(LEVELS_MANIFEST_FILE), HashMap::new())?;
+    let levels = Levels::recover(folder, HashMap::new())?;
     let segment_ids_to_recover = levels.list_ids();
 
     let mut segments = HashMap::new();
@@ -144,23 +185,33 @@ pub fn recover_segments<P: AsRef<Path>>(
         let dirent = dirent?;
         let path = dirent.path();
 
-        assert!(path.is_dir());
+        // NOTE: Metadata sidecars are recovered together with their segment file
+        if is_segment_sidecar(&path) {
+            let segment_id: Arc<str> = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into();
+
+            if !segment_ids_to_recover.contains(&segment_id) && path.try_exists()? {
+                log::debug!("Deleting orphaned segment metadata: {}", path.display());
+                std::fs::remove_file(&path)?;
+            }
+
+            continue;
+        }
 
         let segment_id = dirent
             .file_name()
             .to_str()
-            .expect("invalid segment folder name")
+            .expect("invalid segment file name")
             .to_owned()
             .into();
 
         log::debug!("Recovering segment from {}", path.display());
 
         if segment_ids_to_recover.contains(&segment_id) {
-            let segment = Segment::recover(
-                &path,
-                Arc::clone(block_cache),
-                Arc::new(FileDescriptorTable::new(path.join(BLOCKS_FILE))?),
-            )?;
+            let segment = Segment::recover(&path, Arc::clone(block_cache))?;
             segments.insert(segment.metadata.id.clone(), Arc::new(segment));
             log::debug!("Recovered segment from {}", path.display());
         } else {
@@ -168,7 +219,7 @@ pub fn recover_segments<P: AsRef<Path>>(
                 "Deleting unfinished segment (not part of level manifest): {}",
                 path.to_string_lossy()
             );
-            std::fs::remove_dir_all(path)?;
+            remove_file_or_folder(&path)?;
         }
     }
 
@@ -186,7 +237,10 @@ pub fn recover_segments<P: AsRef<Path>>(
     Ok(segments)
 }
 
-pub fn recover_tree(config: Config) -> crate::Result<Tree> {
+/// Recovers a tree
+///
+/// If `read_only` is set, the tree folder is not modified.
+pub fn recover_tree(config: Config, read_only: bool) -> crate::Result<Tree> {
     log::info!("Recovering tree from {}", config.path.display());
 
     let start = std::time::Instant::now();
@@ -197,80 +251,130 @@ pub fn recover_tree(config: Config) -> crate::Result<Tree> {
     assert!(version.is_some(), "Invalid LSM-tree version");
 
     log::info!("Restoring journal");
-    let active_journal = crate::recovery::recover_active_journal(&config)?;
 
-    log::info!("Restoring memtable");
+    let block_cache = Arc::clone(&config.block_cache);
+
+    let is_follower = config.path.join(FOLLOWER_MARKER).try_exists()?;
 
-    let (journal, memtable) = if let Some(active_journal) = active_journal {
-        active_journal
+    // NOTE: Read-only trees read the journals and segments like
+    // a secondary instance catching up, see below
+    let (journal, memtable, lsn, levels) = if read_only {
+        let levels =
+            crate::secondary::load_levels_retrying(&config.path, &block_cache, &HashMap::new())?;
+
+        (
+            Journal::read_only(config.path.join(JOURNALS_FOLDER)),
+            MemTable::default(),
+            0,
+            levels,
+        )
     } else {
-        let next_journal_path = config
-            .path
-            .join(JOURNALS_FOLDER)
-            .join(&*generate_segment_id());
-        (Journal::create_new(next_journal_path)?, MemTable::default())
-    };
+        let active_journal = crate::recovery::recover_active_journal(&config)?;
 
-    // TODO: optimize this... do on journal load...
-    let lsn = memtable
-        .items
-        .iter()
-        .map(|x| {
-            let key = x.key();
-            key.seqno + 1
-        })
-        .max()
-        .unwrap_or(0);
+        log::info!("Restoring memtable");
 
-    // Load segments
-    log::info!("Restoring segments");
+        let (journal, memtable) = if let Some(active_journal) = active_journal {
+            active_journal
+        } else {
+            let next_journal_path = config
+                .path
+                .join(JOURNALS_FOLDER)
+                .join(&*generate_segment_id());
+            (Journal::create_new(next_journal_path)?, MemTable::default())
+        };
+
+        let lsn = get_next_lsn(&memtable);
+
+        // Load segments
+        log::info!("Restoring segments");
+
+        let segments = crate::recovery::recover_segments(&config.path, &block_cache)?;
+
+        // Check if a segment has a higher seqno and then take it
+        let lsn = lsn.max(
+            segments
+                .values()
+                .map(|x| x.metadata.seqnos.1 + 1)
+                .max()
+                .unwrap_or(0),
+        );
 
-    let block_cache = Arc::clone(&config.block_cache);
+        // Finalize Tree
+        log::debug!("Loading level manifest");
 
-    let segments = crate::recovery::recover_segments(&config.path, &block_cache)?;
+        let mut levels = Levels::recover(&config.path, segments)?;
+        levels.sort_levels();
 
-    // Check if a segment has a higher seqno and then take it
-    let lsn = lsn.max(
-        segments
-            .values()
-            .map(|x| x.metadata.seqnos.1 + 1)
-            .max()
-            .unwrap_or(0),
-    );
+        // NOTE: Converts a JSON levels manifest into a manifest log
+        levels.write_to_disk()?;
 
-    // Finalize Tree
-    log::debug!("Loading level manifest");
+        // NOTE: Compactions may have dropped the items with the highest seqnos
+        let lsn = lsn.max(levels.next_seqno());
 
-    let mut levels = Levels::recover(config.path.join(LEVELS_MANIFEST_FILE), segments)?;
-    levels.sort_levels();
+        (journal, memtable, lsn, levels)
+    };
+
+    log::info!("Restoring blob files");
+
+    let blobs = if read_only {
+        BlobStore::recover_read_only(config.path.join(BLOBS_FOLDER))?
+    } else {
+        // NOTE: Blob files of unfinished flushes are not referenced by any segment
+        let blobs = BlobStore::recover(config.path.join(BLOBS_FOLDER))?;
+        blobs.drop_unreferenced(&levels)?;
+        blobs
+    };
+
+    let persistent_snapshots = PersistentSnapshots::recover(config.path.join(SNAPSHOTS_FILE))?;
+
+    // NOTE: Persistent snapshots pin versions like open snapshots
+    let open_snapshots = SnapshotTracker::default();
+    for seqno in persistent_snapshots.seqnos() {
+        open_snapshots.open(seqno);
+    }
 
     let compaction_threads = 4; // TODO: config
     let flush_threads = config.flush_threads.into();
 
     // TODO: replace fs extra with Journal::disk_space
-    let active_journal_size = fs_extra::dir::get_size(&journal.path)
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "fs_extra error"))?;
+    let active_journal_size = if read_only {
+        0
+    } else {
+        fs_extra::dir::get_size(&journal.path)
+            .map_err(|_| std::io::Error::other("fs_extra error"))?
+    };
 
     let inner = TreeInner {
         config,
         journal: Arc::new(journal),
         active_memtable: Arc::new(RwLock::new(memtable)),
         immutable_memtables: Arc::default(),
+        blobs: Arc::new(blobs),
         block_cache,
         next_lsn: AtomicU64::new(lsn),
         levels: Arc::new(RwLock::new(levels)),
         flush_semaphore: Arc::new(Semaphore::new(flush_threads)),
         compaction_semaphore: Arc::new(Semaphore::new(compaction_threads)),
         approx_active_memtable_size: AtomicU32::new(active_journal_size as u32),
-        open_snapshots: Arc::new(AtomicU32::new(0)),
+        open_snapshots: Arc::new(open_snapshots),
+        persistent_snapshots,
+        lock_manager: LockManager::default(),
         stop_signal: StopSignal::default(),
+        is_follower: AtomicBool::new(is_follower),
+        read_only,
+        journal_tail: Mutex::default(),
     };
 
     let tree = Tree(Arc::new(inner));
 
-    log::debug!("Starting {compaction_threads} compaction threads");
-    for _ in 0..compaction_threads {
-        start_compaction_thread(&tree);
+    if read_only {
+        log::info!("Reading journals");
+        tree.try_catch_up()?;
+    } else {
+        log::debug!("Starting {compaction_threads} compaction threads");
+        for _ in 0..compaction_threads {
+            start_compaction_thread(&tree);
+        }
     }
 
     log::info!("Tree loaded in {}s", start.elapsed().as_secs_f32());
@@ -307,6 +411,7 @@ mod tests {
                 value: "def".as_bytes().into(),
                 seqno: 0,
                 value_type: crate::value::ValueType::Value,
+                expires_at: None,
             })?;
             shard.flush()?;
         }
@@ -0,0 +1,305 @@
+use crate::{
+    file::{FLUSH_MARKER, JOURNALS_FOLDER, SEGMENTS_FOLDER},
+    journal::Journal,
+    levels::Levels,
+    memtable::MemTable,
+    segment::Segment,
+    value::SeqNo,
+    BlockCache, Config, Tree,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+/// How often catching up is retried if the primary flushes or compacts in the meantime
+const MAX_CATCH_UP_ATTEMPTS: usize = 10;
+
+/// Keeps track of how far a read-only tree has read the journals of the primary
+#[derive(Default)]
+pub struct JournalTail {
+    /// ID of the journal the active memtable is read from
+    active_journal: Option<Arc<str>>,
+
+    /// Position after the last read batch of every journal shard file
+    positions: HashMap<PathBuf, u64>,
+}
+
+fn is_not_found(error: &crate::Error) -> bool {
+    matches!(error, crate::Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound)
+}
+
+fn read_segment_ids(folder: &Path) -> crate::Result<HashSet<Arc<str>>> {
+    let levels = Levels::recover(folder, HashMap::new())?;
+    Ok(levels.list_ids().into_iter().collect())
+}
+
+/// Loads the levels manifest, reusing segments that are already loaded
+///
+/// Returns `None` if a segment was deleted in the meantime.
+fn load_levels(
+    folder: &Path,
+    block_cache: &Arc<BlockCache>,
+    loaded: &HashMap<Arc<str>, Arc<Segment>>,
+) -> crate::Result<Option<Levels>> {
+    let segment_ids = read_segment_ids(folder)?;
+
+    let mut segments = HashMap::with_capacity(segment_ids.len());
+
+    for segment_id in &segment_ids {
+        if let Some(segment) = loaded.get(segment_id) {
+            segments.insert(segment_id.clone(), Arc::clone(segment));
+            continue;
+        }
+
+        let segment_path = folder.join(SEGMENTS_FOLDER).join(&**segment_id);
+        log::debug!("Loading segment from {}", segment_path.display());
+
+        match Segment::recover(&segment_path, Arc::clone(block_cache)) {
+            Ok(segment) => {
+                segments.insert(segment_id.clone(), Arc::new(segment));
+            }
+            Err(e) if is_not_found(&e) => {
+                log::debug!("Segment {segment_id} was deleted while loading it");
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut levels = Levels::recover(folder, segments)?;
+
+    // NOTE: The manifest may have been rewritten since reading the segment IDs
+    if levels.list_ids().into_iter().collect::<HashSet<_>>() != segment_ids {
+        return Ok(None);
+    }
+
+    levels.sort_levels();
+
+    Ok(Some(levels))
+}
+
+/// Reads the batches that were written into the journals since the last call into memtables
+///
+/// Journals of segments in `flushed_ids` are skipped, and their memtables are dropped.
+///
+/// Returns the seqno after the highest read seqno, or 0 if nothing was read.
+// NOTE: Locks are only poisoned if another thread panicked while holding them
+#[allow(clippy::expect_used)]
+pub fn tail_journals(
+    config: &Config,
+    flushed_ids: &HashSet<Arc<str>>,
+    tail: &mut JournalTail,
+    active_memtable: &RwLock<MemTable>,
+    immutable_memtables: &RwLock<BTreeMap<Arc<str>, Arc<MemTable>>>,
+) -> crate::Result<SeqNo> {
+    let mut journals = std::fs::read_dir(config.path.join(JOURNALS_FOLDER))?
+        .map(|dirent| {
+            let dirent = dirent?;
+
+            let journal_id: Arc<str> = dirent
+                .file_name()
+                .to_str()
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "invalid journal folder name",
+                    )
+                })?
+                .into();
+
+            Ok((journal_id, dirent.path()))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    journals.sort();
+
+    // NOTE: The primary marks the old journal as flushable before creating the next one,
+    // so checking the markers after listing the folder finds at most one active journal
+    //
+    // The folder is checked after the marker, because a retired journal has no marker anymore
+    let active_journal = journals
+        .iter()
+        .find(|(journal_id, path)| {
+            !flushed_ids.contains(journal_id) && !path.join(FLUSH_MARKER).exists() && path.exists()
+        })
+        .map(|(journal_id, _)| journal_id.clone());
+
+    if tail.active_journal != active_journal {
+        log::debug!("tail: active journal changed to {active_journal:?}");
+
+        let mut memtable_lock = active_memtable.write().expect("lock is poisoned");
+        let old_memtable = std::mem::take(&mut *memtable_lock);
+
+        let mut immutable_memtables = immutable_memtables.write().expect("lock is poisoned");
+
+        if let Some(old_journal) = tail.active_journal.take() {
+            if !old_memtable.is_empty() {
+                immutable_memtables.insert(old_journal, Arc::new(old_memtable));
+            }
+        }
+
+        drop(immutable_memtables);
+        drop(memtable_lock);
+
+        tail.active_journal.clone_from(&active_journal);
+    }
+
+    let mut next_lsn = 0;
+
+    for (journal_id, path) in &journals {
+        if flushed_ids.contains(journal_id) {
+            continue;
+        }
+
+        let batches = Journal::read_new_batches(path, &mut tail.positions)?;
+
+        if batches.is_empty() {
+            continue;
+        }
+
+        let memtable = if Some(journal_id) == active_journal.as_ref() {
+            None
+        } else {
+            let mut immutable_memtables = immutable_memtables.write().expect("lock is poisoned");
+            Some(Arc::clone(
+                immutable_memtables.entry(journal_id.clone()).or_default(),
+            ))
+        };
+
+        let active_memtable = active_memtable.read().expect("lock is poisoned");
+        let memtable = memtable.as_deref().unwrap_or(&active_memtable);
+
+        for (seqno, items) in batches {
+            for item in items {
+                memtable.insert(item);
+            }
+
+            next_lsn = next_lsn.max(seqno + 1);
+        }
+
+        drop(active_memtable);
+    }
+
+    // NOTE: Journals that were flushed, or were deleted
+    // because they are flushed, are not needed anymore
+    let listed_ids = journals
+        .iter()
+        .map(|(journal_id, _)| journal_id)
+        .collect::<HashSet<_>>();
+
+    immutable_memtables
+        .write()
+        .expect("lock is poisoned")
+        .retain(|journal_id, _| {
+            !flushed_ids.contains(journal_id) && listed_ids.contains(journal_id)
+        });
+
+    tail.positions.retain(|path, _| {
+        journals.iter().any(|(journal_id, journal_path)| {
+            !flushed_ids.contains(journal_id) && path.starts_with(journal_path)
+        })
+    });
+
+    Ok(next_lsn)
+}
+
+/// Loads the levels of a read-only tree, retrying if segments are deleted in the meantime
+pub fn load_levels_retrying(
+    folder: &Path,
+    block_cache: &Arc<BlockCache>,
+    loaded: &HashMap<Arc<str>, Arc<Segment>>,
+) -> crate::Result<Levels> {
+    for _ in 0..MAX_CATCH_UP_ATTEMPTS {
+        if let Some(levels) = load_levels(folder, block_cache, loaded)? {
+            return Ok(levels);
+        }
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Interrupted,
+        "levels manifest changed while loading it",
+    )
+    .into())
+}
+
+/// Tries to catch up once, returns `false` if the primary changed
+/// the levels in the meantime, so the read state may be inconsistent
+// NOTE: See `tail_journals`
+#[allow(clippy::expect_used)]
+fn catch_up_once(tree: &Tree, tail: &mut JournalTail) -> crate::Result<bool> {
+    let loaded = tree
+        .levels
+        .read()
+        .expect("lock is poisoned")
+        .get_all_segments();
+
+    let Some(levels) = load_levels(&tree.config.path, &tree.block_cache, &loaded)? else {
+        return Ok(false);
+    };
+
+    let segment_ids = levels.list_ids().into_iter().collect::<HashSet<_>>();
+
+    let segment_lsn = levels
+        .get_all_segments_flattened()
+        .iter()
+        .map(|segment| segment.metadata.seqnos.1 + 1)
+        .max()
+        .unwrap_or(0)
+        .max(levels.next_seqno());
+
+    // NOTE: Swap in the segments before dropping the memtables of their journals
+    *tree.levels.write().expect("lock is poisoned") = levels;
+
+    tree.blobs.reload()?;
+
+    let journal_lsn = match tail_journals(
+        &tree.config,
+        &segment_ids,
+        tail,
+        &tree.active_memtable,
+        &tree.immutable_memtables,
+    ) {
+        Ok(lsn) => lsn,
+
+        // NOTE: The journal was retired while reading it
+        Err(e) if is_not_found(&e) => return Ok(false),
+
+        Err(e) => return Err(e),
+    };
+
+    tree.next_lsn.fetch_max(
+        segment_lsn.max(journal_lsn),
+        std::sync::atomic::Ordering::AcqRel,
+    );
+
+    Ok(read_segment_ids(&tree.config.path)? == segment_ids)
+}
+
+/// Reloads the levels, segments, blob files and journals
+/// that were written by the primary since the last call
+///
+/// See [`Tree::try_catch_up`]
+// NOTE: See `tail_journals`
+#[allow(clippy::expect_used)]
+pub fn try_catch_up(tree: &Tree) -> crate::Result<()> {
+    if !tree.read_only {
+        return Ok(());
+    }
+
+    let mut tail = tree.journal_tail.lock().expect("lock is poisoned");
+
+    for _ in 0..MAX_CATCH_UP_ATTEMPTS {
+        if catch_up_once(tree, &mut tail)? {
+            return Ok(());
+        }
+
+        log::debug!("Tree was changed while catching up, retrying");
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Interrupted,
+        "tree was changed while catching up",
+    )
+    .into())
+}
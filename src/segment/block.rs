@@ -1,18 +1,391 @@
 use super::index::{block_handle::BlockHandle, BlockIndex};
-use crate::{descriptor_table::FileDescriptorTable, disk_block::DiskBlock, BlockCache, Value};
-use std::sync::Arc;
+use crate::{
+    descriptor_table::FileDescriptorTable,
+    disk_block::DiskBlock,
+    value::{read_value_type, write_value_type, SeqNo, ValueType, ValueV0},
+    varint::{read_varint, write_varint},
+    version::Version,
+    BlockCache, CompressionType, Value,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::{
+    io::{Cursor, Read, Write},
+    ops::Range,
+    sync::Arc,
+};
 
-/// Value blocks are the building blocks of a [`Segment`]. Each block is a sorted list of [`Value`]s,
+/// Every n-th item of a value block stores its full key (a "restart point"),
+/// all other items only store the part of their key that differs from the previous key
+const RESTART_INTERVAL: usize = 16;
+
+/// Size of the CRC and item count that precede the items of a value block
+const HEADER_LEN: usize = 8;
+
+/// Value block of a [`Version::V0`] segment
+type ValueBlockV0 = DiskBlock<ValueV0>;
+
+/// Value block of a [`Version::V1`] segment
+type ValueBlockV1 = DiskBlock<Value>;
+
+/// Value blocks are the building blocks of a [`Segment`](super::Segment). Each block is a sorted list of [`Value`]s,
 /// and stored in compressed form on disk, in sorted order.
 ///
+/// Items are kept serialized after decompressing the block, and are only decoded when read,
+/// so point reads can binary search over the restart points of the block.
+///
+/// # Disk representation (since [`Version::V2`])
+///
+/// \[crc; 4 bytes] \[item count; 4 bytes] \[items; N bytes] \[restart points; 4 bytes each] \[restart point count; 4 bytes]
+///
+/// Each item is stored as
+///
+/// \[shared key length; varint] \[unshared key length; varint] \[unshared key; N bytes] \[seqno; 8 bytes] \[value type; 1 byte] \[expiry; 8 bytes, optional] \[value length; varint] \[value; N bytes]
+///
+/// where the shared key length is the length of the prefix the key has in common with the previous key.
+/// Items at restart points store their full key, the restart points are the offsets of those items.
+///
 /// The integrity of a block can be checked using the CRC value that is saved in it.
-pub type ValueBlock = DiskBlock<Value>;
+#[derive(Clone, Debug)]
+pub struct ValueBlock {
+    /// Serialized items
+    data: Vec<u8>,
+
+    /// Offsets of the items that store their full key
+    restart_points: Vec<u32>,
+
+    item_count: u32,
+
+    crc: u32,
+
+    /// Seqno that replaces the seqnos of all items (see `Metadata::global_seqno`)
+    global_seqno: Option<SeqNo>,
+}
+
+/// Item of a value block, with its value not decoded yet
+struct RawItem {
+    seqno: SeqNo,
+    value_type: ValueType,
+    expires_at: Option<u64>,
+    value: Range<usize>,
+}
+
+fn invalid_block(msg: &str) -> crate::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg).into()
+}
+
+fn shared_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Returns the position of a cursor over an in-memory block
+fn position(reader: &Cursor<&[u8]>) -> usize {
+    // NOTE: The cursor is over an in-memory slice, so its position fits into usize
+    #[allow(clippy::cast_possible_truncation)]
+    let pos = reader.position() as usize;
+    pos
+}
+
+/// Reads a length that was written from a `usize`
+fn read_len<R: Read>(reader: &mut R) -> std::io::Result<usize> {
+    // NOTE: Lengths are written from usize, so they fit into usize
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(read_varint(reader)? as usize)
+}
+
+impl ValueBlock {
+    /// Serializes sorted items into a value block
+    pub fn encode(items: &[Value]) -> crate::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(u16::MAX.into());
+        let mut restart_points = Vec::with_capacity(items.len() / RESTART_INTERVAL + 1);
+        let mut prev_key: &[u8] = &[];
+
+        for (idx, item) in items.iter().enumerate() {
+            let shared_len = if idx % RESTART_INTERVAL == 0 {
+                // NOTE: Blocks are never bigger than 4 GB anyway,
+                // so it's fine to just truncate it
+                #[allow(clippy::cast_possible_truncation)]
+                restart_points.push(data.len() as u32);
+                0
+            } else {
+                shared_prefix_len(prev_key, &item.key)
+            };
+
+            let unshared_key = &item.key[shared_len..];
+
+            write_varint(&mut data, shared_len as u64)?;
+            write_varint(&mut data, unshared_key.len() as u64)?;
+            data.write_all(unshared_key)?;
+
+            data.write_u64::<BigEndian>(item.seqno)?;
+            write_value_type(&mut data, item.value_type, item.expires_at)?;
+
+            write_varint(&mut data, item.value.len() as u64)?;
+            data.write_all(&item.value)?;
+
+            prev_key = &item.key;
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + data.len() + restart_points.len() * 4 + 4);
+
+        // NOTE: The CRC is written below
+        bytes.write_u32::<BigEndian>(0)?;
+
+        // NOTE: Truncation is okay and actually needed
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.write_u32::<BigEndian>(items.len() as u32)?;
+
+        bytes.write_all(&data)?;
+
+        for offset in &restart_points {
+            bytes.write_u32::<BigEndian>(*offset)?;
+        }
+
+        // NOTE: Truncation is okay and actually needed
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.write_u32::<BigEndian>(restart_points.len() as u32)?;
+
+        let crc = crc32fast::hash(&bytes[4..]);
+        bytes[0..4].copy_from_slice(&crc.to_be_bytes());
+
+        Ok(bytes)
+    }
+
+    /// Parses a decompressed value block
+    fn from_bytes(mut bytes: Vec<u8>, global_seqno: Option<SeqNo>) -> crate::Result<Self> {
+        if bytes.len() < HEADER_LEN + 4 {
+            return Err(invalid_block("value block is too short"));
+        }
+
+        let mut reader = &bytes[..];
+        let crc = reader.read_u32::<BigEndian>()?;
+        let item_count = reader.read_u32::<BigEndian>()?;
+
+        let mut reader = &bytes[bytes.len() - 4..];
+        let restart_count = reader.read_u32::<BigEndian>()? as usize;
+
+        let data_end = (bytes.len() - 4)
+            .checked_sub(restart_count * 4)
+            .filter(|end| *end >= HEADER_LEN)
+            .ok_or_else(|| invalid_block("invalid value block restart point count"))?;
+
+        let mut reader = &bytes[data_end..bytes.len() - 4];
+        let restart_points = (0..restart_count)
+            .map(|_| reader.read_u32::<BigEndian>())
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        bytes.truncate(data_end);
+        bytes.drain(..HEADER_LEN);
+
+        if restart_points
+            .iter()
+            .any(|offset| *offset as usize >= bytes.len())
+        {
+            return Err(invalid_block("invalid value block restart point"));
+        }
+
+        Ok(Self {
+            data: bytes,
+            restart_points,
+            item_count,
+            crc,
+            global_seqno,
+        })
+    }
+
+    /// Creates a value block from items of a segment that was written in an older version
+    fn from_items(items: &[Value], global_seqno: Option<SeqNo>) -> crate::Result<Self> {
+        Self::from_bytes(Self::encode(items)?, global_seqno)
+    }
+
+    /// Returns `true` if the items match the CRC that is saved in the block
+    #[allow(unused)]
+    pub(crate) fn check_crc(&self) -> bool {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.item_count.to_be_bytes());
+        hasher.update(&self.data);
+
+        for offset in &self.restart_points {
+            hasher.update(&offset.to_be_bytes());
+        }
+
+        // NOTE: Truncation is okay and actually needed
+        #[allow(clippy::cast_possible_truncation)]
+        hasher.update(&(self.restart_points.len() as u32).to_be_bytes());
+
+        hasher.finalize() == self.crc
+    }
+
+    /// Reads the next item, `key` needs to contain the key of the previous item
+    fn read_item(&self, reader: &mut Cursor<&[u8]>, key: &mut Vec<u8>) -> crate::Result<RawItem> {
+        let shared_len = read_len(reader)?;
+        let unshared_len = read_len(reader)?;
+
+        if shared_len > key.len() {
+            return Err(invalid_block("invalid value block key prefix"));
+        }
+
+        key.truncate(shared_len);
+
+        let start = position(reader);
+        let unshared_key = self
+            .data
+            .get(start..start + unshared_len)
+            .ok_or_else(|| invalid_block("value block item is out of bounds"))?;
+        key.extend_from_slice(unshared_key);
+        reader.set_position((start + unshared_len) as u64);
+
+        let seqno = reader.read_u64::<BigEndian>()?;
+        let (value_type, expires_at) = read_value_type(reader)?;
+
+        let value_len = read_len(reader)?;
+        let start = position(reader);
+
+        if start + value_len > self.data.len() {
+            return Err(invalid_block("value block item is out of bounds"));
+        }
+        reader.set_position((start + value_len) as u64);
+
+        Ok(RawItem {
+            seqno: self.global_seqno.unwrap_or(seqno),
+            value_type,
+            expires_at,
+            value: start..start + value_len,
+        })
+    }
+
+    fn to_value(&self, key: &[u8], item: RawItem) -> Value {
+        Value {
+            key: key.into(),
+            value: self.data[item.value].into(),
+            seqno: item.seqno,
+            value_type: item.value_type,
+            expires_at: item.expires_at,
+        }
+    }
+
+    /// Returns the full key of the item at a restart point
+    fn restart_key(&self, idx: usize) -> crate::Result<&[u8]> {
+        let offset = self.restart_points[idx] as usize;
+        let mut reader = Cursor::new(&self.data[offset..]);
+
+        if read_len(&mut reader)? != 0 {
+            return Err(invalid_block("value block restart point has no full key"));
+        }
+
+        let key_len = read_len(&mut reader)?;
+        let start = offset + position(&reader);
+
+        self.data
+            .get(start..start + key_len)
+            .ok_or_else(|| invalid_block("value block item is out of bounds"))
+    }
+
+    /// Returns the newest version of a key, that is older than the given seqno, if any
+    ///
+    /// Binary searches over the restart points, so only the items of a single
+    /// restart interval (and following items of the same key) are decoded.
+    pub fn get(&self, key: &[u8], seqno: Option<SeqNo>) -> crate::Result<Option<Value>> {
+        // NOTE: Versions of a key may span multiple restart intervals,
+        // so search the last restart point with a key that is lower than the key
+        let mut lo = 0;
+        let mut hi = self.restart_points.len();
+
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+
+            if self.restart_key(mid)? < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let Some(offset) = self.restart_points.get(lo.saturating_sub(1)) else {
+            return Ok(None);
+        };
+
+        let mut reader = Cursor::new(&self.data[..]);
+        reader.set_position(u64::from(*offset));
+
+        let mut item_key = Vec::new();
+
+        while position(&reader) < self.data.len() {
+            let item = self.read_item(&mut reader, &mut item_key)?;
+
+            match (*item_key).cmp(key) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => {
+                    if seqno.map_or(true, |seqno| item.seqno < seqno) {
+                        return Ok(Some(self.to_value(&item_key, item)));
+                    }
+                }
+                std::cmp::Ordering::Greater => return Ok(None),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Decodes all items of the block
+    pub fn items(&self) -> crate::Result<Vec<Value>> {
+        let mut items = Vec::with_capacity(self.item_count as usize);
+
+        let mut reader = Cursor::new(&self.data[..]);
+        let mut key = Vec::new();
+
+        while position(&reader) < self.data.len() {
+            let item = self.read_item(&mut reader, &mut key)?;
+            items.push(self.to_value(&key, item));
+        }
+
+        Ok(items)
+    }
+
+    /// Reads a value block, decoding it according to the segment's version
+    ///
+    /// If the segment has a global seqno (see `Metadata::global_seqno`),
+    /// it replaces the seqnos of all items.
+    pub fn from_file_versioned<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        block_handle: &BlockHandle,
+        compression: CompressionType,
+        version: Version,
+        global_seqno: Option<SeqNo>,
+    ) -> crate::Result<Self> {
+        let BlockHandle { offset, size, .. } = *block_handle;
+
+        // NOTE: Blocks of older segments are converted, so all blocks
+        // can be cached and searched the same way
+        match version {
+            Version::V0 => {
+                let block = ValueBlockV0::from_file_compressed(reader, offset, size, compression)?;
+                let items = block.items.into_iter().map(|x| x.0).collect::<Vec<_>>();
+                Self::from_items(&items, global_seqno)
+            }
+            Version::V1 => {
+                let block = ValueBlockV1::from_file_compressed(reader, offset, size, compression)?;
+                Self::from_items(&block.items, global_seqno)
+            }
+            Version::V2 | Version::V3 | Version::V4 => {
+                reader.seek(std::io::SeekFrom::Start(offset))?;
+
+                let mut bytes = vec![0u8; size as usize];
+                reader.read_exact(&mut bytes)?;
+
+                Self::from_bytes(compression.decompress(bytes)?, global_seqno)
+            }
+        }
+    }
+}
 
 pub fn load_and_cache_by_block_handle(
     descriptor_table: &FileDescriptorTable,
     block_cache: &BlockCache,
     segment_id: &str,
     block_handle: &BlockHandle,
+    compression: CompressionType,
+    version: Version,
+    global_seqno: Option<SeqNo>,
 ) -> crate::Result<Option<Arc<ValueBlock>>> {
     Ok(
         if let Some(block) = block_cache.get_disk_block(segment_id, &block_handle.start_key) {
@@ -24,10 +397,12 @@ pub fn load_and_cache_by_block_handle(
 
             let mut file_reader = descriptor_table.access();
 
-            let block = ValueBlock::from_file_compressed(
+            let block = ValueBlock::from_file_versioned(
                 &mut *file_reader,
-                block_handle.offset,
-                block_handle.size,
+                block_handle,
+                compression,
+                version,
+                global_seqno,
             )?;
 
             drop(file_reader);
@@ -45,12 +420,16 @@ pub fn load_and_cache_by_block_handle(
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn load_and_cache_block_by_item_key<K: AsRef<[u8]>>(
     descriptor_table: &FileDescriptorTable,
     block_index: &BlockIndex,
     block_cache: &BlockCache,
     segment_id: &str,
     item_key: K,
+    compression: CompressionType,
+    version: Version,
+    global_seqno: Option<SeqNo>,
 ) -> crate::Result<Option<Arc<ValueBlock>>> {
     Ok(
         if let Some(block_handle) = block_index.get_lower_bound_block_info(item_key.as_ref())? {
@@ -59,9 +438,159 @@ pub fn load_and_cache_block_by_item_key<K: AsRef<[u8]>>(
                 block_cache,
                 segment_id,
                 &block_handle,
+                compression,
+                version,
+                global_seqno,
             )?
         } else {
             None
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn block(items: &[Value]) -> crate::Result<ValueBlock> {
+        ValueBlock::from_bytes(ValueBlock::encode(items)?, None)
+    }
+
+    #[test]
+    fn value_block_round_trip() -> crate::Result<()> {
+        let items = (0u64..100)
+            .map(|x| {
+                Value::new(
+                    format!("composite:key:{x:0>5}").as_bytes(),
+                    x.to_be_bytes(),
+                    x,
+                    ValueType::Value,
+                )
+                .expires_at((x % 2 == 0).then_some(x))
+            })
+            .collect::<Vec<_>>();
+
+        let block = block(&items)?;
+        assert!(block.check_crc());
+        assert_eq!(100 / RESTART_INTERVAL + 1, block.restart_points.len());
+        assert_eq!(items, block.items()?);
+
+        // NOTE: Shared key prefixes are only stored once
+        let plain_size = items
+            .iter()
+            .map(|item| item.key.len() + item.value.len())
+            .sum::<usize>();
+        assert!(block.data.len() < plain_size);
+
+        for item in &items {
+            assert_eq!(Some(item), block.get(&item.key, None)?.as_ref());
+        }
+
+        assert!(block.get(b"composite", None)?.is_none());
+        assert!(block.get(b"composite:key:00010a", None)?.is_none());
+        assert!(block.get(b"zzz", None)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn value_block_get_mvcc() -> crate::Result<()> {
+        // NOTE: The versions of "b" span multiple restart intervals
+        let mut items = vec![Value::new(*b"a", *b"a", 0, ValueType::Value)];
+        items.extend(
+            (0u64..50)
+                .rev()
+                .map(|seqno| Value::new(*b"b", seqno.to_be_bytes(), seqno, ValueType::Value)),
+        );
+        items.push(Value::new(*b"c", *b"c", 0, ValueType::Tombstone));
+
+        let block = block(&items)?;
+
+        let item = block.get(b"b", None)?.expect("should exist");
+        assert_eq!(49, item.seqno);
+
+        let item = block.get(b"b", Some(20))?.expect("should exist");
+        assert_eq!(19, item.seqno);
+        assert_eq!(19u64.to_be_bytes(), &*item.value);
+
+        assert!(block.get(b"b", Some(0))?.is_none());
+        assert!(block.get(b"a", Some(0))?.is_none());
+
+        let item = block.get(b"c", None)?.expect("should exist");
+        assert_eq!(ValueType::Tombstone, item.value_type);
+
+        Ok(())
+    }
+
+    #[test]
+    fn value_block_global_seqno() -> crate::Result<()> {
+        let items = vec![
+            Value::new(*b"a", *b"a", 0, ValueType::Value),
+            Value::new(*b"b", *b"b", 0, ValueType::Value),
+        ];
+
+        let block = ValueBlock::from_bytes(ValueBlock::encode(&items)?, Some(7))?;
+
+        assert!(block.items()?.iter().all(|item| item.seqno == 7));
+        assert!(block.get(b"a", Some(7))?.is_none());
+        assert_eq!(7, block.get(b"a", Some(8))?.expect("should exist").seqno);
+
+        Ok(())
+    }
+
+    #[test]
+    fn value_block_legacy_v1() -> crate::Result<()> {
+        use crate::serde::Serializable;
+
+        let items = vec![
+            Value::new(*b"a", *b"a", 1, ValueType::Value),
+            Value::new(*b"b", *b"b", 0, ValueType::Tombstone),
+        ];
+
+        let legacy = ValueBlockV1 {
+            crc: ValueBlockV1::create_crc(&items)?,
+            items: items.clone(),
+        };
+
+        let mut bytes = vec![];
+        legacy.serialize(&mut bytes)?;
+        let bytes = CompressionType::Lz4.compress(&bytes)?;
+
+        // NOTE: Truncation is okay, the block is tiny
+        #[allow(clippy::cast_possible_truncation)]
+        let handle = BlockHandle {
+            start_key: items[0].key.clone(),
+            offset: 0,
+            size: bytes.len() as u32,
+        };
+
+        let block = ValueBlock::from_file_versioned(
+            &mut Cursor::new(bytes),
+            &handle,
+            CompressionType::Lz4,
+            Version::V1,
+            None,
+        )?;
+
+        assert_eq!(items, block.items()?);
+        assert_eq!(Some(&items[1]), block.get(b"b", None)?.as_ref());
+
+        Ok(())
+    }
+
+    #[test]
+    fn value_block_invalid() -> crate::Result<()> {
+        let items = vec![Value::new(*b"a", *b"a", 0, ValueType::Value)];
+        let bytes = ValueBlock::encode(&items)?;
+
+        assert!(ValueBlock::from_bytes(bytes[..8].to_vec(), None).is_err());
+
+        let mut bytes = bytes;
+        let len = bytes.len();
+        bytes[len - 4..].copy_from_slice(&100u32.to_be_bytes());
+        assert!(ValueBlock::from_bytes(bytes, None).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use super::{
+    meta::Metadata,
+    writer::{Options, Writer},
+};
+use crate::{id::generate_segment_id, value::ValueType, Config, Value};
+use std::path::Path;
+
+/// Builds a segment from sorted data, without going through a tree
+///
+/// The segment can then be ingested into a tree using [`crate::Tree::ingest`],
+/// which is much faster than inserting the data item by item.
+///
+/// # Examples
+///
+/// ```
+/// # let folder = tempfile::tempdir()?;
+/// use lsm_tree::{Config, SegmentBuilder};
+///
+/// let config = Config::new(folder.path().join("tree"));
+///
+/// let mut builder = SegmentBuilder::new(folder.path().join("segment"), &config)?;
+/// builder.insert("a", "abc")?;
+/// builder.insert("b", "def")?;
+/// builder.finish()?;
+///
+/// let tree = config.open()?;
+/// tree.ingest(&[folder.path().join("segment")])?;
+/// assert!(tree.contains_key("a")?);
+/// #
+/// # Ok::<(), lsm_tree::Error>(())
+/// ```
+#[allow(clippy::module_name_repetitions)]
+pub struct SegmentBuilder {
+    writer: Writer,
+}
+
+impl SegmentBuilder {
+    /// Creates a builder that writes a segment into the given (new) file
+    ///
+    /// The block size, bloom filter and compression settings
+    /// are taken from the config of the tree the segment is ingested into.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the file already exists.
+    pub fn new<P: AsRef<Path>>(path: P, config: &Config) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if path.try_exists()? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "segment file already exists",
+            )
+            .into());
+        }
+
+        let writer = Writer::new(Options {
+            path: path.to_path_buf(),
+            evict_tombstones: false,
+            block_size: config.block_size,
+            bloom_bits_per_key: config.bloom_bits_per_key,
+            compression: config.compression_for_level(0),
+        })?;
+
+        Ok(Self { writer })
+    }
+
+    /// Writes a key-value pair into the segment.
+    ///
+    /// Keys need to be written in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the key is not greater than the previous key.
+    pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> crate::Result<()> {
+        self.write(Value::new(
+            key.as_ref(),
+            value.as_ref(),
+            0,
+            ValueType::Value,
+        ))
+    }
+
+    /// Writes a tombstone into the segment, deleting the key
+    /// when the segment is ingested.
+    ///
+    /// Keys need to be written in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the key is not greater than the previous key.
+    pub fn remove<K: AsRef<[u8]>>(&mut self, key: K) -> crate::Result<()> {
+        self.write(Value::new(key.as_ref(), vec![], 0, ValueType::Tombstone))
+    }
+
+    /// Writes an item into the segment, keeping its expiry
+    pub(crate) fn write(&mut self, item: Value) -> crate::Result<()> {
+        // NOTE: All items get the same seqno when the segment is ingested,
+        // so every key can only be written once
+        if self
+            .writer
+            .last_key
+            .as_ref()
+            .is_some_and(|last_key| *last_key >= item.key)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "keys need to be written in ascending order",
+            )
+            .into());
+        }
+
+        self.writer.write(item)
+    }
+
+    /// Finishes the segment, making sure all data is written durably.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or no item was written.
+    pub fn finish(mut self) -> crate::Result<()> {
+        self.writer.finish()?;
+
+        // NOTE: The writer already deleted the empty segment file
+        if self.writer.item_count == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "segment needs to contain at least one item",
+            )
+            .into());
+        }
+
+        // NOTE: The segment gets a new ID when it is ingested
+        let metadata = Metadata::from_writer(generate_segment_id(), self.writer)?;
+        metadata.write_to_file()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    serde::{Deserializable, DeserializeError, Serializable, SerializeError},
+    version::Version,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Position, size and checksum of a section of a single-file segment
+///
+/// A size of 0 means the section does not exist.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SectionHandle {
+    pub offset: u64,
+    pub size: u32,
+    pub crc: u32,
+}
+
+impl SectionHandle {
+    /// Writes a section at the given position of the writer
+    pub fn write<W: Write>(writer: &mut W, offset: u64, bytes: &[u8]) -> crate::Result<Self> {
+        writer.write_all(bytes)?;
+
+        // NOTE: Sections are never bigger than 4 GB anyway
+        #[allow(clippy::cast_possible_truncation)]
+        let size = bytes.len() as u32;
+
+        Ok(Self {
+            offset,
+            size,
+            crc: crc32fast::hash(bytes),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Reads the section, and checks its checksum
+    pub fn read<R: Read + Seek>(&self, reader: &mut R) -> crate::Result<Vec<u8>> {
+        let mut bytes = vec![0; self.size as usize];
+
+        reader.seek(SeekFrom::Start(self.offset))?;
+        reader.read_exact(&mut bytes)?;
+
+        if crc32fast::hash(&bytes) != self.crc {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "segment section checksum mismatch",
+            )
+            .into());
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl Serializable for SectionHandle {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        writer.write_u64::<BigEndian>(self.offset)?;
+        writer.write_u32::<BigEndian>(self.size)?;
+        writer.write_u32::<BigEndian>(self.crc)?;
+        Ok(())
+    }
+}
+
+impl Deserializable for SectionHandle {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        let offset = reader.read_u64::<BigEndian>()?;
+        let size = reader.read_u32::<BigEndian>()?;
+        let crc = reader.read_u32::<BigEndian>()?;
+        Ok(Self { offset, size, crc })
+    }
+}
+
+/// Fixed-size trailer of a single-file segment, pointing to its sections
+///
+/// # Disk representation
+///
+/// \[top level index; handle\] \[bloom filter; handle\] \[range tombstones; handle\] \[metadata; handle\] \[crc; 4 bytes\] \[version header; 5 bytes\]
+///
+/// The data and index blocks are located before the top level index.
+///
+/// If the metadata is rewritten (e.g. when ingesting a segment), it is stored in
+/// a sidecar file, so the footer is written exactly once.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Footer {
+    pub top_level_index: SectionHandle,
+    pub bloom_filter: SectionHandle,
+    pub range_tombstones: SectionHandle,
+    pub metadata: SectionHandle,
+}
+
+impl Footer {
+    /// Size of the footer in bytes
+    pub const LEN: usize = 4 * 16 + 4 + 5;
+
+    fn handles(&self) -> [SectionHandle; 4] {
+        [
+            self.top_level_index,
+            self.bloom_filter,
+            self.range_tombstones,
+            self.metadata,
+        ]
+    }
+
+    /// Appends the footer to the end of the segment file
+    pub fn write<W: Write>(&self, writer: &mut W, version: Version) -> crate::Result<()> {
+        let mut bytes = Vec::with_capacity(Self::LEN);
+
+        for handle in self.handles() {
+            handle.serialize(&mut bytes)?;
+        }
+
+        let crc = crc32fast::hash(&bytes);
+        bytes.write_u32::<BigEndian>(crc)?;
+        version.write_file_header(&mut bytes)?;
+
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+
+    /// Reads the footer from the end of a segment file, returning the segment version
+    pub fn read<R: Read + Seek>(reader: &mut R) -> crate::Result<(Self, Version)> {
+        let invalid = |msg| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        let footer_len = Self::LEN as u64;
+
+        if file_size < footer_len {
+            return Err(invalid("segment file is too small to contain a footer").into());
+        }
+
+        let mut bytes = [0; Self::LEN];
+        reader.seek(SeekFrom::Start(file_size - footer_len))?;
+        reader.read_exact(&mut bytes)?;
+
+        let (handles, trailer) = bytes.split_at(4 * 16);
+
+        let version = Version::parse_file_header(&trailer[4..])
+            .ok_or_else(|| invalid("invalid segment footer version"))?;
+
+        let crc = (&trailer[..4]).read_u32::<BigEndian>()?;
+
+        if crc != crc32fast::hash(handles) {
+            return Err(invalid("segment footer checksum mismatch").into());
+        }
+
+        let mut reader = handles;
+
+        let footer = Self {
+            top_level_index: SectionHandle::deserialize(&mut reader)?,
+            bloom_filter: SectionHandle::deserialize(&mut reader)?,
+            range_tombstones: SectionHandle::deserialize(&mut reader)?,
+            metadata: SectionHandle::deserialize(&mut reader)?,
+        };
+
+        Ok((footer, version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use test_log::test;
+
+    #[test]
+    fn footer_round_trip() -> crate::Result<()> {
+        let mut file = Cursor::new(vec![]);
+        file.write_all(b"blocks")?;
+
+        let footer = Footer {
+            top_level_index: SectionHandle::write(&mut file, 6, b"index")?,
+            bloom_filter: SectionHandle::default(),
+            range_tombstones: SectionHandle::write(&mut file, 11, b"tombstones")?,
+            metadata: SectionHandle::write(&mut file, 21, b"metadata")?,
+        };
+        footer.write(&mut file, Version::V3)?;
+
+        assert_eq!(29 + Footer::LEN, file.get_ref().len());
+
+        let (recovered, version) = Footer::read(&mut file)?;
+        assert_eq!(footer, recovered);
+        assert_eq!(Version::V3, version);
+
+        assert!(recovered.bloom_filter.is_empty());
+        assert_eq!(b"index", &*recovered.top_level_index.read(&mut file)?);
+        assert_eq!(b"tombstones", &*recovered.range_tombstones.read(&mut file)?);
+        assert_eq!(b"metadata", &*recovered.metadata.read(&mut file)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn footer_corrupt() -> crate::Result<()> {
+        let mut file = Cursor::new(vec![]);
+
+        let footer = Footer {
+            metadata: SectionHandle::write(&mut file, 0, b"metadata")?,
+            ..Default::default()
+        };
+        footer.write(&mut file, Version::V3)?;
+
+        // NOTE: Corrupt the metadata section
+        file.get_mut()[0] = b'x';
+
+        let (recovered, _) = Footer::read(&mut file)?;
+        assert!(recovered.metadata.read(&mut file).is_err());
+
+        // NOTE: Corrupt the footer
+        let len = file.get_ref().len();
+        file.get_mut()[len - 10] ^= 1;
+        assert!(Footer::read(&mut file).is_err());
+
+        assert!(Footer::read(&mut Cursor::new(b"short".to_vec())).is_err());
+
+        Ok(())
+    }
+}
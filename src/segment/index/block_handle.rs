@@ -1,5 +1,6 @@
-use crate::serde::{Deserializable, Serializable};
+use crate::serde::{read_exact_len, Deserializable, Serializable};
 use crate::value::UserKey;
+use crate::varint::{read_varint, write_varint};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io::{Read, Write};
 use std::sync::Arc;
@@ -8,7 +9,7 @@ use std::sync::Arc;
 ///
 /// # Disk representation
 ///
-/// \[offset; 8 bytes] - \[size; 4 bytes] - \[key length; 2 bytes] - \[key; N bytes]
+/// \[offset; 8 bytes] - \[size; 4 bytes] - \[key length; varint] - \[key; N bytes]
 #[derive(Clone, Debug)]
 pub struct BlockHandle {
     /// Key of first item in block
@@ -26,10 +27,7 @@ impl Serializable for BlockHandle {
         writer.write_u64::<BigEndian>(self.offset)?;
         writer.write_u32::<BigEndian>(self.size)?;
 
-        // NOTE: Truncation is okay and actually needed
-        #[allow(clippy::cast_possible_truncation)]
-        writer.write_u16::<BigEndian>(self.start_key.len() as u16)?;
-
+        write_varint(writer, self.start_key.len() as u64)?;
         writer.write_all(&self.start_key)?;
 
         Ok(())
@@ -44,10 +42,8 @@ impl Deserializable for BlockHandle {
         let offset = reader.read_u64::<BigEndian>()?;
         let size = reader.read_u32::<BigEndian>()?;
 
-        let key_len = reader.read_u16::<BigEndian>()?;
-
-        let mut key = vec![0; key_len.into()];
-        reader.read_exact(&mut key)?;
+        let key_len = read_varint(reader)?;
+        let key = read_exact_len(reader, key_len)?;
 
         Ok(Self {
             offset,
@@ -56,3 +52,71 @@ impl Deserializable for BlockHandle {
         })
     }
 }
+
+/// [`BlockHandle`] in the disk representation of segments before [`Version::V1`](crate::version::Version::V1),
+/// used to read index blocks that store the key length as 2 bytes
+///
+/// # Disk representation
+///
+/// \[offset; 8 bytes] - \[size; 4 bytes] - \[key length; 2 bytes] - \[key; N bytes]
+#[derive(Clone, Debug)]
+pub struct BlockHandleV0(pub BlockHandle);
+
+impl Serializable for BlockHandleV0 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), crate::SerializeError> {
+        writer.write_u64::<BigEndian>(self.0.offset)?;
+        writer.write_u32::<BigEndian>(self.0.size)?;
+
+        // NOTE: Truncation is okay and actually needed
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_u16::<BigEndian>(self.0.start_key.len() as u16)?;
+
+        writer.write_all(&self.0.start_key)?;
+
+        Ok(())
+    }
+}
+
+impl Deserializable for BlockHandleV0 {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, crate::DeserializeError>
+    where
+        Self: Sized,
+    {
+        let offset = reader.read_u64::<BigEndian>()?;
+        let size = reader.read_u32::<BigEndian>()?;
+
+        let key_len = reader.read_u16::<BigEndian>()?;
+        let key = read_exact_len(reader, key_len.into())?;
+
+        Ok(Self(BlockHandle {
+            offset,
+            size,
+            start_key: Arc::from(key),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn block_handle_large_key() -> crate::Result<()> {
+        let handle = BlockHandle {
+            start_key: vec![7; 100_000].into(),
+            offset: 5,
+            size: 10,
+        };
+
+        let mut bytes = vec![];
+        handle.serialize(&mut bytes)?;
+
+        let decoded = BlockHandle::deserialize(&mut &bytes[..])?;
+        assert_eq!(handle.start_key, decoded.start_key);
+        assert_eq!(5, decoded.offset);
+        assert_eq!(10, decoded.size);
+
+        Ok(())
+    }
+}
@@ -2,15 +2,16 @@ pub mod block_handle;
 pub mod top_level;
 pub mod writer;
 
-use self::block_handle::BlockHandle;
+use self::block_handle::{BlockHandle, BlockHandleV0};
 use crate::block_cache::BlockCache;
 use crate::descriptor_table::FileDescriptorTable;
 use crate::disk_block::DiskBlock;
 use crate::file::{BLOCKS_FILE, TOP_LEVEL_INDEX_FILE};
 use crate::value::UserKey;
+use crate::version::Version;
+use crate::CompressionType;
 use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::BufReader;
+use std::io::{Read, Seek};
 use std::path::Path;
 use std::sync::Arc;
 use top_level::{BlockHandleBlockHandle, TopLevelIndex};
@@ -18,6 +19,41 @@ use top_level::{BlockHandleBlockHandle, TopLevelIndex};
 pub type BlockHandleBlock = DiskBlock<BlockHandle>;
 
 impl BlockHandleBlock {
+    /// Reads an index block in the disk representation of the given segment version
+    fn from_reader_versioned<R: Read>(
+        reader: &mut R,
+        size: u32,
+        version: Version,
+    ) -> crate::Result<Self> {
+        // NOTE: Index blocks are always LZ4 compressed,
+        // regardless of the segment's data block compression
+        if version < Version::V1 {
+            let block = DiskBlock::<BlockHandleV0>::from_reader_compressed(
+                reader,
+                size,
+                CompressionType::Lz4,
+            )?;
+
+            return Ok(Self {
+                items: block.items.into_iter().map(|item| item.0).collect(),
+                crc: block.crc,
+            });
+        }
+
+        Self::from_reader_compressed(reader, size, CompressionType::Lz4)
+    }
+
+    /// Reads an index block at the given file offset, in the disk representation of the given segment version
+    fn from_file_versioned<R: Read + Seek>(
+        reader: &mut R,
+        offset: u64,
+        size: u32,
+        version: Version,
+    ) -> crate::Result<Self> {
+        reader.seek(std::io::SeekFrom::Start(offset))?;
+        Self::from_reader_versioned(reader, size, version)
+    }
+
     pub(crate) fn get_previous_block_info(&self, key: &[u8]) -> Option<&BlockHandle> {
         self.items.iter().rev().find(|x| &*x.start_key < key)
     }
@@ -57,6 +93,9 @@ pub struct BlockIndex {
     /// Segment ID
     segment_id: Arc<str>,
 
+    /// Segment version, which determines the disk representation of index blocks
+    version: Version,
+
     /// Level-0 index ("fence pointers"). Is read-only and always fully loaded.
     ///
     /// This index points to index blocks inside the level-1 index.
@@ -215,10 +254,11 @@ impl BlockIndex {
 
             let mut file_reader = self.descriptor_table.access();
 
-            let block = BlockHandleBlock::from_file_compressed(
+            let block = BlockHandleBlock::from_file_versioned(
                 &mut *file_reader,
                 block_handle.offset,
                 block_handle.size,
+                self.version,
             )?;
 
             drop(file_reader);
@@ -260,6 +300,7 @@ impl BlockIndex {
                 FileDescriptorTable::new("Cargo.toml").expect("should open"),
             ),
             segment_id,
+            version: Version::V1,
             blocks: index_block_index,
             top_level_index: TopLevelIndex::new(BTreeMap::default()),
         }
@@ -279,6 +320,7 @@ impl BlockIndex {
         segment_id: Arc<str>,
         descriptor_table: Arc<FileDescriptorTable>,
         path: P,
+        version: Version,
         block_cache: Arc<BlockCache>,
     ) -> crate::Result<Self> {
         log::debug!("Reading block index from {}", path.as_ref().display());
@@ -299,13 +341,23 @@ impl BlockIndex {
             path.as_ref().display()
         );
 
-        let file_size = std::fs::metadata(path.as_ref().join(TOP_LEVEL_INDEX_FILE))?.len();
+        let bytes = std::fs::read(path.as_ref().join(TOP_LEVEL_INDEX_FILE))?;
+
+        Self::from_bytes(segment_id, descriptor_table, &bytes, version, block_cache)
+    }
 
-        let index = BlockHandleBlock::from_file_compressed(
-            &mut BufReader::new(File::open(path.as_ref().join(TOP_LEVEL_INDEX_FILE))?),
-            0,
-            file_size as u32,
-        )?;
+    /// Loads the block index of a segment, using its top level index
+    pub fn from_bytes(
+        segment_id: Arc<str>,
+        descriptor_table: Arc<FileDescriptorTable>,
+        bytes: &[u8],
+        version: Version,
+        block_cache: Arc<BlockCache>,
+    ) -> crate::Result<Self> {
+        // NOTE: The top level index is never bigger than 4 GB anyway
+        #[allow(clippy::cast_possible_truncation)]
+        let index =
+            BlockHandleBlock::from_reader_versioned(&mut &bytes[..], bytes.len() as u32, version)?;
 
         debug_assert!(!index.items.is_empty());
 
@@ -324,6 +376,7 @@ impl BlockIndex {
         Ok(Self {
             descriptor_table,
             segment_id,
+            version,
             top_level_index: TopLevelIndex::new(tree),
             blocks: BlockHandleBlockIndex(block_cache),
         })
@@ -1,39 +1,13 @@
 use super::BlockHandle;
-use crate::{
-    disk_block::DiskBlock,
-    file::{BLOCKS_FILE, INDEX_BLOCKS_FILE, TOP_LEVEL_INDEX_FILE},
-    serde::Serializable,
-    value::UserKey,
-};
+use crate::{disk_block::DiskBlock, serde::Serializable, value::UserKey};
 use lz4_flex::compress_prepend_size;
-use std::{
-    fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Write},
-    path::{Path, PathBuf},
-};
-
-fn concat_files<P: AsRef<Path>>(src_path: P, dest_path: P) -> crate::Result<()> {
-    let reader = File::open(src_path)?;
-    let mut reader = BufReader::new(reader);
-
-    let writer = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(dest_path)?;
-    let mut writer = BufWriter::new(writer);
-
-    std::io::copy(&mut reader, &mut writer)?;
-    writer.flush()?;
-
-    Ok(())
-}
 
+/// Builds the index blocks and top level index of a segment
+///
+/// Both are buffered in memory, and then written after the data blocks.
 pub struct Writer {
-    path: PathBuf,
     file_pos: u64,
-    block_writer: Option<BufWriter<File>>,
-    index_writer: BufWriter<File>,
+    index_blocks: Vec<u8>,
     block_size: u32,
     block_counter: u32,
     block_chunk: DiskBlock<BlockHandle>,
@@ -41,13 +15,7 @@ pub struct Writer {
 }
 
 impl Writer {
-    pub fn new<P: AsRef<Path>>(path: P, block_size: u32) -> crate::Result<Self> {
-        let block_writer = File::create(path.as_ref().join(INDEX_BLOCKS_FILE))?;
-        let block_writer = BufWriter::with_capacity(u16::MAX.into(), block_writer);
-
-        let index_writer = File::create(path.as_ref().join(TOP_LEVEL_INDEX_FILE))?;
-        let index_writer = BufWriter::new(index_writer);
-
+    pub fn new(block_size: u32) -> Self {
         let block_chunk = DiskBlock {
             items: vec![],
             crc: 0,
@@ -58,16 +26,14 @@ impl Writer {
             crc: 0,
         };
 
-        Ok(Self {
-            path: path.as_ref().into(),
+        Self {
             file_pos: 0,
-            block_writer: Some(block_writer),
-            index_writer,
+            index_blocks: Vec::new(),
             block_counter: 0,
             block_size,
             block_chunk,
             index_chunk,
-        })
+        }
     }
 
     fn write_block(&mut self) -> crate::Result<()> {
@@ -81,11 +47,7 @@ impl Writer {
         // Compress using LZ4
         let bytes = compress_prepend_size(&bytes);
 
-        // Write to file
-        self.block_writer
-            .as_mut()
-            .expect("should exist")
-            .write_all(&bytes)?;
+        self.index_blocks.extend_from_slice(&bytes);
 
         // Expect is fine, because the chunk is not empty
         let first = self
@@ -138,19 +100,7 @@ impl Writer {
         Ok(())
     }
 
-    fn write_top_level_index(&mut self, block_file_size: u64) -> crate::Result<()> {
-        // TODO: I hate this, but we need to drop the writer
-        // so the file is closed
-        // so it can be replaced when using Windows
-        self.block_writer = None;
-
-        concat_files(
-            self.path.join(INDEX_BLOCKS_FILE),
-            self.path.join(BLOCKS_FILE),
-        )?;
-
-        log::debug!("Concatted index blocks onto blocks file");
-
+    fn write_top_level_index(&mut self, block_file_size: u64) -> crate::Result<Vec<u8>> {
         for item in &mut self.index_chunk.items {
             item.offset += block_file_size;
         }
@@ -165,33 +115,25 @@ impl Writer {
         // Compress using LZ4
         let bytes = compress_prepend_size(&bytes);
 
-        // Write to file
-        self.index_writer.write_all(&bytes)?;
-        self.index_writer.flush()?;
-
         log::debug!(
-            "Written top level index to {}, with {} pointers ({} bytes)",
-            self.path.join(TOP_LEVEL_INDEX_FILE).display(),
+            "Written top level index, with {} pointers ({} bytes)",
             self.index_chunk.items.len(),
             bytes.len(),
         );
 
-        Ok(())
+        Ok(bytes)
     }
 
-    pub fn finish(&mut self, block_file_size: u64) -> crate::Result<()> {
+    /// Finishes the index, given the size of the data blocks that precede the index blocks
+    ///
+    /// Returns the index blocks and the top level index.
+    pub fn finish(&mut self, block_file_size: u64) -> crate::Result<(Vec<u8>, Vec<u8>)> {
         if self.block_counter > 0 {
             self.write_block()?;
         }
 
-        self.block_writer.as_mut().expect("should exist").flush()?;
-        self.write_top_level_index(block_file_size)?;
+        let top_level_index = self.write_top_level_index(block_file_size)?;
 
-        self.index_writer.get_mut().sync_all()?;
-
-        // TODO: add test to make sure writer is deleting index_blocks
-        std::fs::remove_file(self.path.join(INDEX_BLOCKS_FILE))?;
-
-        Ok(())
+        Ok((std::mem::take(&mut self.index_blocks), top_level_index))
     }
 }
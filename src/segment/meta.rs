@@ -1,26 +1,65 @@
-use super::writer::Writer;
+use super::{footer::Footer, writer::Writer};
+pub use crate::compression::CompressionType;
 use crate::{
-    file::SEGMENT_METADATA_FILE,
+    file::{
+        rewrite_atomic, segment_sidecar_path, BLOCKS_FILE, LEGACY_SEGMENT_METADATA_FILE,
+        SEGMENT_METADATA_FILE,
+    },
+    serde::{read_exact_len, Deserializable, Serializable},
     time::unix_timestamp,
     value::{SeqNo, UserKey},
+    varint::{read_varint, write_varint},
     version::Version,
 };
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::OpenOptions,
-    io::Write,
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, Write},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub enum CompressionType {
-    Lz4,
+fn corrupted(msg: &str) -> crate::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("corrupted segment metadata: {msg}"),
+    )
+    .into()
 }
 
-impl std::fmt::Display for CompressionType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "lz4")
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> std::io::Result<()> {
+    write_varint(writer, bytes.len() as u64)?;
+    writer.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> crate::Result<Vec<u8>> {
+    let len = read_varint(reader)?;
+    Ok(read_exact_len(reader, len)?)
+}
+
+fn read_str<R: Read>(reader: &mut R) -> crate::Result<Arc<str>> {
+    let bytes = read_bytes(reader)?;
+    let str = String::from_utf8(bytes).map_err(|_| corrupted("invalid UTF-8"))?;
+    Ok(str.into())
+}
+
+fn write_option<W: Write>(writer: &mut W, value: Option<u64>) -> std::io::Result<()> {
+    match value {
+        Some(value) => {
+            writer.write_u8(1)?;
+            writer.write_u64::<BigEndian>(value)
+        }
+        None => writer.write_u8(0),
+    }
+}
+
+fn read_option<R: Read>(reader: &mut R) -> crate::Result<Option<u64>> {
+    match reader.read_u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(reader.read_u64::<BigEndian>()?)),
+        _ => Err(corrupted("invalid option tag")),
     }
 }
 
@@ -28,7 +67,7 @@ impl std::fmt::Display for CompressionType {
 pub struct Metadata {
     pub version: Version,
 
-    /// Path of segment folder
+    /// Path of segment file (or folder, before [`Version::V3`])
     pub path: PathBuf,
 
     /// Segment ID
@@ -70,6 +109,26 @@ pub struct Metadata {
 
     /// Number of tombstones
     pub tombstone_count: u64,
+
+    /// Bytes referenced in each blob file
+    #[serde(default)]
+    pub blob_refs: HashMap<Arc<str>, u64>,
+
+    /// Unix timestamp (in ms) at which all items of the segment have expired
+    ///
+    /// None if any item does not expire
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+
+    /// Seqno of all items of the segment, overriding the seqnos stored in its blocks
+    ///
+    /// Set when an externally built segment is ingested (see `Tree::ingest`)
+    #[serde(default)]
+    pub global_seqno: Option<SeqNo>,
+
+    /// Sections of a single-file segment
+    #[serde(skip)]
+    pub(crate) footer: Footer,
 }
 
 impl Metadata {
@@ -77,7 +136,8 @@ impl Metadata {
     pub fn from_writer(id: Arc<str>, writer: Writer) -> crate::Result<Self> {
         Ok(Self {
             id,
-            version: Version::V0,
+            version: Version::V4,
+            footer: writer.footer,
             path: writer.opts.path,
             block_count: writer.block_count as u32,
             block_size: writer.opts.block_size,
@@ -87,7 +147,7 @@ impl Metadata {
             created_at: unix_timestamp().as_micros(),
 
             file_size: writer.file_pos,
-            compression: CompressionType::Lz4,
+            compression: writer.opts.compression,
             item_count: writer.item_count as u64,
             key_count: writer.key_count as u64,
 
@@ -102,31 +162,188 @@ impl Metadata {
             seqnos: (writer.lowest_seqno, writer.highest_seqno),
             tombstone_count: writer.tombstone_count as u64,
             uncompressed_size: writer.uncompressed_size,
+            blob_refs: writer.blob_refs,
+            expires_at: writer.expires_at,
+            global_seqno: None,
         })
     }
 
+    /// Returns `true` if all items of the segment have expired at the given unix timestamp (in ms)
+    pub(crate) fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|x| x <= now)
+    }
+
     pub(crate) fn key_range_contains<K: AsRef<[u8]>>(&self, key: K) -> bool {
         let key = key.as_ref();
         key >= &self.key_range.0 && key <= &self.key_range.1
     }
 
-    /// Stores segment metadata in a file
+    /// Returns `true` if the segment is stored as a single file,
+    /// instead of a folder
+    pub(crate) fn is_single_file(&self) -> bool {
+        !matches!(self.version, Version::V0 | Version::V1 | Version::V2)
+    }
+
+    /// Returns the path of the file that contains the data blocks
+    pub(crate) fn blocks_path(&self) -> PathBuf {
+        if self.is_single_file() {
+            self.path.clone()
+        } else {
+            self.path.join(BLOCKS_FILE)
+        }
+    }
+
+    /// Encodes the metadata in its binary disk representation
     ///
-    /// Will be stored as JSON
-    pub fn write_to_file(&self) -> std::io::Result<()> {
-        let mut writer = OpenOptions::new()
-            .truncate(true)
-            .create(true)
-            .write(true)
-            .open(self.path.join(SEGMENT_METADATA_FILE))?;
-
-        writer.write_all(
-            serde_json::to_string_pretty(self)
-                .expect("Failed to serialize to JSON")
-                .as_bytes(),
-        )?;
-        writer.flush()?;
-        writer.sync_all()?;
+    /// # Disk representation
+    ///
+    /// \[version header; 5 bytes\] \[fields\] \[crc; 4 bytes\]
+    ///
+    /// The CRC covers the version header and fields.
+    /// The path is not stored, because it depends on where the segment is located.
+    pub fn encode(&self) -> crate::Result<Vec<u8>> {
+        let mut bytes = vec![];
+
+        self.version.write_file_header(&mut bytes)?;
+
+        write_bytes(&mut bytes, self.id.as_bytes())?;
+        bytes.write_u128::<BigEndian>(self.created_at)?;
+        bytes.write_u64::<BigEndian>(self.item_count)?;
+        bytes.write_u64::<BigEndian>(self.key_count)?;
+        bytes.write_u32::<BigEndian>(self.block_size)?;
+        bytes.write_u32::<BigEndian>(self.block_count)?;
+        Serializable::serialize(&self.compression, &mut bytes)?;
+        bytes.write_u64::<BigEndian>(self.file_size)?;
+        bytes.write_u64::<BigEndian>(self.uncompressed_size)?;
+        write_bytes(&mut bytes, &self.key_range.0)?;
+        write_bytes(&mut bytes, &self.key_range.1)?;
+        bytes.write_u64::<BigEndian>(self.seqnos.0)?;
+        bytes.write_u64::<BigEndian>(self.seqnos.1)?;
+        bytes.write_u64::<BigEndian>(self.tombstone_count)?;
+
+        write_varint(&mut bytes, self.blob_refs.len() as u64)?;
+        for (blob_file_id, referenced_bytes) in &self.blob_refs {
+            write_bytes(&mut bytes, blob_file_id.as_bytes())?;
+            bytes.write_u64::<BigEndian>(*referenced_bytes)?;
+        }
+
+        write_option(&mut bytes, self.expires_at)?;
+        write_option(&mut bytes, self.global_seqno)?;
+
+        let crc = crc32fast::hash(&bytes);
+        bytes.write_u32::<BigEndian>(crc)?;
+
+        Ok(bytes)
+    }
+
+    /// Decodes metadata that was encoded using [`Metadata::encode`]
+    ///
+    /// The path needs to be set by the caller.
+    pub fn decode(bytes: &[u8]) -> crate::Result<Self> {
+        let header_len = usize::from(Version::len());
+
+        if bytes.len() < header_len + 4 {
+            return Err(corrupted("too short"));
+        }
+
+        let (bytes, mut crc) = bytes.split_at(bytes.len() - 4);
+
+        if crc32fast::hash(bytes) != crc.read_u32::<BigEndian>()? {
+            return Err(corrupted("checksum mismatch"));
+        }
+
+        let version =
+            Version::parse_file_header(bytes).ok_or_else(|| corrupted("invalid version header"))?;
+
+        let mut reader = &bytes[header_len..];
+
+        let id = read_str(&mut reader)?;
+        let created_at = reader.read_u128::<BigEndian>()?;
+        let item_count = reader.read_u64::<BigEndian>()?;
+        let key_count = reader.read_u64::<BigEndian>()?;
+        let block_size = reader.read_u32::<BigEndian>()?;
+        let block_count = reader.read_u32::<BigEndian>()?;
+        let compression = <CompressionType as Deserializable>::deserialize(&mut reader)?;
+        let file_size = reader.read_u64::<BigEndian>()?;
+        let uncompressed_size = reader.read_u64::<BigEndian>()?;
+        let key_range = (
+            read_bytes(&mut reader)?.into(),
+            read_bytes(&mut reader)?.into(),
+        );
+        let seqnos = (
+            reader.read_u64::<BigEndian>()?,
+            reader.read_u64::<BigEndian>()?,
+        );
+        let tombstone_count = reader.read_u64::<BigEndian>()?;
+
+        let blob_ref_count = read_varint(&mut reader)?;
+        let mut blob_refs = HashMap::new();
+        for _ in 0..blob_ref_count {
+            let blob_file_id = read_str(&mut reader)?;
+            blob_refs.insert(blob_file_id, reader.read_u64::<BigEndian>()?);
+        }
+
+        let expires_at = read_option(&mut reader)?;
+        let global_seqno = read_option(&mut reader)?;
+
+        if !reader.is_empty() {
+            return Err(corrupted("trailing bytes"));
+        }
+
+        Ok(Self {
+            version,
+            path: PathBuf::new(),
+            id,
+            created_at,
+            item_count,
+            key_count,
+            block_size,
+            block_count,
+            compression,
+            file_size,
+            uncompressed_size,
+            key_range,
+            seqnos,
+            tombstone_count,
+            blob_refs,
+            expires_at,
+            global_seqno,
+            footer: Footer::default(),
+        })
+    }
+
+    /// Stores segment metadata
+    ///
+    /// Single-file segments get a metadata section and footer appended when they are written.
+    /// Rewritten metadata (e.g. of an ingested segment) is stored in a sidecar file next to the
+    /// segment file instead, so segment files are never modified, and may be hard linked.
+    ///
+    /// Older segments store it in a file in the segment folder, which is replaced atomically
+    pub fn write_to_file(&self) -> crate::Result<()> {
+        if self.is_single_file() {
+            if self.footer.metadata.is_empty() {
+                return self.append_to_segment_file();
+            }
+
+            rewrite_atomic(segment_sidecar_path(&self.path), &self.encode()?)?;
+
+            #[cfg(not(target_os = "windows"))]
+            {
+                // fsync folder on Unix
+                File::open(self.parent_folder()?)?.sync_all()?;
+            }
+
+            return Ok(());
+        }
+
+        rewrite_atomic(self.path.join(SEGMENT_METADATA_FILE), &self.encode()?)?;
+
+        // NOTE: The binary metadata file takes precedence, so a crash
+        // before removing the JSON file is fine
+        let legacy_path = self.path.join(LEGACY_SEGMENT_METADATA_FILE);
+        if legacy_path.try_exists()? {
+            std::fs::remove_file(legacy_path)?;
+        }
 
         #[cfg(not(target_os = "windows"))]
         {
@@ -138,13 +355,93 @@ impl Metadata {
         Ok(())
     }
 
-    /// Reads and parses a Segment metadata file
+    fn parent_folder(&self) -> std::io::Result<&Path> {
+        self.path.parent().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "segment path has no parent folder",
+            )
+        })
+    }
+
+    fn append_to_segment_file(&self) -> crate::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        let offset = file.seek(std::io::SeekFrom::End(0))?;
+
+        let bytes = self.encode()?;
+
+        let mut footer = self.footer;
+        footer.metadata = super::footer::SectionHandle::write(&mut file, offset, &bytes)?;
+        footer.write(&mut file, self.version)?;
+
+        file.flush()?;
+        file.sync_all()?;
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            // fsync folder on Unix
+            File::open(self.parent_folder()?)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads and parses a JSON segment metadata file, as written before [`Version::V4`]
     pub fn from_disk<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
         let file_content = std::fs::read_to_string(path)?;
         let item = serde_json::from_str(&file_content)?;
         Ok(item)
     }
 
+    /// Reads the metadata of a segment file, or of a segment folder
+    pub fn recover<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+
+        if path.is_dir() {
+            return Self::recover_folder(path);
+        }
+
+        let mut reader = BufReader::new(File::open(path)?);
+        let (footer, version) = Footer::read(&mut reader)?;
+
+        // NOTE: Rewritten metadata takes precedence over the metadata section
+        let mut metadata = match std::fs::read(segment_sidecar_path(path)) {
+            Ok(bytes) => Self::decode(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let bytes = footer.metadata.read(&mut reader)?;
+
+                // NOTE: V3 segments store the metadata as JSON
+                if Version::parse_file_header(&bytes).is_some() {
+                    Self::decode(&bytes)?
+                } else {
+                    serde_json::from_slice(&bytes).map_err(std::io::Error::from)?
+                }
+            }
+            Err(e) => return Err(e.into()),
+        };
+        metadata.version = version;
+        metadata.footer = footer;
+
+        // NOTE: The segment may have been moved, e.g. when restoring a backup
+        metadata.path = path.to_path_buf();
+
+        Ok(metadata)
+    }
+
+    fn recover_folder(path: &Path) -> crate::Result<Self> {
+        let mut metadata = match std::fs::read(path.join(SEGMENT_METADATA_FILE)) {
+            Ok(bytes) => Self::decode(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Self::from_disk(path.join(LEGACY_SEGMENT_METADATA_FILE))?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        metadata.path = path.to_path_buf();
+
+        Ok(metadata)
+    }
+
     pub(crate) fn check_prefix_overlap(&self, prefix: &[u8]) -> bool {
         if prefix.is_empty() {
             return true;
@@ -160,6 +457,7 @@ impl Metadata {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_log::test;
 
     fn fixture_meta(key_range: (UserKey, UserKey)) -> Metadata {
         Metadata {
@@ -177,6 +475,10 @@ mod tests {
             tombstone_count: 0,
             uncompressed_size: 0,
             seqnos: (0, 0),
+            blob_refs: std::collections::HashMap::default(),
+            expires_at: None,
+            global_seqno: None,
+            footer: Footer::default(),
         }
     }
 
@@ -212,4 +514,72 @@ mod tests {
         let fixture10 = fixture_meta(((*b"abc").into(), (*b"b").into()));
         assert!(fixture10.check_prefix_overlap(b"a"));
     }
+
+    #[test]
+    fn metadata_binary_round_trip() -> crate::Result<()> {
+        let mut metadata = fixture_meta(((*b"a").into(), (*b"zz").into()));
+        metadata.version = Version::V4;
+        metadata.created_at = 123_456;
+        metadata.seqnos = (5, 10);
+        metadata.blob_refs.insert("blob".into(), 1_000);
+        metadata.expires_at = Some(7);
+
+        let bytes = metadata.encode()?;
+        let decoded = Metadata::decode(&bytes)?;
+
+        assert_eq!(Version::V4, decoded.version);
+        assert_eq!(metadata.id, decoded.id);
+        assert_eq!(metadata.created_at, decoded.created_at);
+        assert_eq!(metadata.compression, decoded.compression);
+        assert_eq!(metadata.key_range, decoded.key_range);
+        assert_eq!(metadata.seqnos, decoded.seqnos);
+        assert_eq!(metadata.blob_refs, decoded.blob_refs);
+        assert_eq!(Some(7), decoded.expires_at);
+        assert_eq!(None, decoded.global_seqno);
+
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_binary_corrupted() -> crate::Result<()> {
+        let metadata = fixture_meta(((*b"a").into(), (*b"z").into()));
+        let bytes = metadata.encode()?;
+
+        for idx in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[idx] ^= 1;
+            assert!(Metadata::decode(&corrupted).is_err());
+        }
+
+        // NOTE: Half-written metadata
+        for len in 0..bytes.len() {
+            assert!(Metadata::decode(&bytes[..len]).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_legacy_json() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+
+        let mut metadata = fixture_meta(((*b"a").into(), (*b"z").into()));
+        metadata.path = folder.path().to_path_buf();
+
+        let json = serde_json::to_vec(&metadata).map_err(std::io::Error::from)?;
+        std::fs::write(folder.path().join(LEGACY_SEGMENT_METADATA_FILE), &json)?;
+
+        let recovered = Metadata::recover(folder.path())?;
+        assert_eq!(metadata.id, recovered.id);
+        assert_eq!(Version::V0, recovered.version);
+
+        // NOTE: A half-written JSON file is reported as an error, instead of panicking
+        std::fs::write(
+            folder.path().join(LEGACY_SEGMENT_METADATA_FILE),
+            &json[..json.len() / 2],
+        )?;
+        assert!(Metadata::recover(folder.path()).is_err());
+
+        Ok(())
+    }
 }
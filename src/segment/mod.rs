@@ -1,4 +1,6 @@
 pub mod block;
+pub mod builder;
+pub mod footer;
 pub mod index;
 pub mod meta;
 pub mod prefix;
@@ -12,12 +14,16 @@ use self::{
 };
 use crate::{
     block_cache::BlockCache,
+    bloom::{BloomFilter, CompositeHash},
     descriptor_table::FileDescriptorTable,
-    file::{BLOCKS_FILE, SEGMENT_METADATA_FILE},
+    file::{BLOOM_FILTER_FILE, RANGE_TOMBSTONES_FILE},
+    range_tombstone::RangeTombstone,
+    serde::Deserializable,
     value::{SeqNo, UserKey},
+    version::Version,
     Value,
 };
-use std::{ops::Bound, path::Path, sync::Arc};
+use std::{fs::File, io::BufReader, ops::Bound, path::Path, sync::Arc};
 
 /// Represents a `LSMT` segment (a.k.a. `SSTable`, `sorted string table`) that is located on disk.
 /// A segment is an immutable list of key-value pairs, split into compressed blocks (see [`block::SegmentBlock`]).
@@ -29,7 +35,7 @@ use std::{ops::Bound, path::Path, sync::Arc};
 pub struct Segment {
     pub descriptor_table: Arc<FileDescriptorTable>,
 
-    /// Segment metadata object (will be stored in a JSON file)
+    /// Segment metadata object
     pub metadata: meta::Metadata,
 
     /// Translates key (first item of a block) to block offset (address inside file) and (compressed) size
@@ -39,30 +45,109 @@ pub struct Segment {
     ///
     /// Stores index and data blocks
     pub block_cache: Arc<BlockCache>,
+
+    /// Bloom filter
+    ///
+    /// Used to skip the segment for point reads of keys that are definitely not contained
+    pub bloom_filter: Option<BloomFilter>,
+
+    /// Range tombstones
+    ///
+    /// Range tombstones may cover keys outside the segment's key range
+    pub range_tombstones: Vec<RangeTombstone>,
+}
+
+/// Loads the bloom filter of a segment folder, if it exists.
+fn load_bloom_filter<P: AsRef<Path>>(folder: P) -> crate::Result<Option<BloomFilter>> {
+    let path = folder.as_ref().join(BLOOM_FILTER_FILE);
+
+    if path.try_exists()? {
+        log::debug!("Reading bloom filter from {}", path.display());
+        Ok(Some(BloomFilter::from_file(path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Loads the range tombstones of a segment folder, if there are any.
+fn load_range_tombstones<P: AsRef<Path>>(
+    folder: P,
+    version: Version,
+) -> crate::Result<Vec<RangeTombstone>> {
+    let path = folder.as_ref().join(RANGE_TOMBSTONES_FILE);
+
+    if path.try_exists()? {
+        log::debug!("Reading range tombstones from {}", path.display());
+        crate::range_tombstone::read_from_file(path, version)
+    } else {
+        Ok(Vec::new())
+    }
 }
 
 impl Segment {
-    /// Tries to recover a segment from a folder.
-    pub fn recover<P: AsRef<Path>>(
-        folder: P,
-        block_cache: Arc<BlockCache>,
-        descriptor_table: Arc<FileDescriptorTable>,
-    ) -> crate::Result<Self> {
-        let folder = folder.as_ref();
-
-        let metadata = Metadata::from_disk(folder.join(SEGMENT_METADATA_FILE))?;
-        let block_index = BlockIndex::from_file(
+    /// Tries to recover a segment from a file (or folder, before [`crate::version::Version::V3`]).
+    pub fn recover<P: AsRef<Path>>(path: P, block_cache: Arc<BlockCache>) -> crate::Result<Self> {
+        let metadata = Metadata::recover(path)?;
+        Self::load(metadata, block_cache)
+    }
+
+    /// Loads the block index, bloom filter and range tombstones of a segment that has been written.
+    pub fn load(metadata: Metadata, block_cache: Arc<BlockCache>) -> crate::Result<Self> {
+        let descriptor_table = Arc::new(FileDescriptorTable::new(metadata.blocks_path())?);
+
+        if !metadata.is_single_file() {
+            let folder = &metadata.path;
+
+            return Ok(Self {
+                block_index: Arc::new(BlockIndex::from_file(
+                    metadata.id.clone(),
+                    Arc::clone(&descriptor_table),
+                    folder,
+                    metadata.version,
+                    Arc::clone(&block_cache),
+                )?),
+                bloom_filter: load_bloom_filter(folder)?,
+                range_tombstones: load_range_tombstones(folder, metadata.version)?,
+                descriptor_table,
+                metadata,
+                block_cache,
+            });
+        }
+
+        log::debug!("Reading segment sections from {}", metadata.path.display());
+
+        let mut reader = BufReader::new(File::open(&metadata.path)?);
+        let footer = metadata.footer;
+
+        let block_index = BlockIndex::from_bytes(
             metadata.id.clone(),
-            descriptor_table,
-            folder,
+            Arc::clone(&descriptor_table),
+            &footer.top_level_index.read(&mut reader)?,
+            metadata.version,
             Arc::clone(&block_cache),
         )?;
 
+        let bloom_filter = if footer.bloom_filter.is_empty() {
+            None
+        } else {
+            let bytes = footer.bloom_filter.read(&mut reader)?;
+            Some(BloomFilter::deserialize(&mut &bytes[..])?)
+        };
+
+        let range_tombstones = if footer.range_tombstones.is_empty() {
+            Vec::new()
+        } else {
+            let bytes = footer.range_tombstones.read(&mut reader)?;
+            crate::range_tombstone::read_from(&mut &bytes[..], metadata.version)?
+        };
+
         Ok(Self {
-            descriptor_table: Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+            descriptor_table,
             metadata,
             block_index: Arc::new(block_index),
             block_cache,
+            bloom_filter,
+            range_tombstones,
         })
     }
 
@@ -75,6 +160,23 @@ impl Segment {
         &self,
         key: K,
         seqno: Option<SeqNo>,
+    ) -> crate::Result<Option<Value>> {
+        let key = key.as_ref();
+        self.get_with_hash(key, seqno, BloomFilter::get_hash(key))
+    }
+
+    /// Retrieves an item from the segment, using a precomputed bloom filter hash of the key.
+    ///
+    /// This allows hashing the key only once when checking multiple segments.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn get_with_hash<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        seqno: Option<SeqNo>,
+        hash: CompositeHash,
     ) -> crate::Result<Option<Value>> {
         if let Some(seqno) = seqno {
             if self.metadata.seqnos.0 >= seqno {
@@ -86,7 +188,11 @@ impl Segment {
             return Ok(None);
         }
 
-        // TODO: bloom filter query here
+        if let Some(bf) = &self.bloom_filter {
+            if !bf.contains_hash(hash) {
+                return Ok(None);
+            }
+        }
 
         let key = key.as_ref();
 
@@ -104,21 +210,12 @@ impl Segment {
                         &self.block_cache,
                         &self.metadata.id,
                         &block_handle,
+                        self.metadata.compression,
+                        self.metadata.version,
+                        self.metadata.global_seqno,
                     )?;
 
-                    let item = block.map_or_else(
-                        || Ok(None),
-                        |block| {
-                            // TODO: maybe binary search can be used, but it needs to find the max seqno
-                            Ok(block
-                                .items
-                                .iter()
-                                .find(|item| item.key == key.as_ref().into())
-                                .cloned())
-                        },
-                    );
-
-                    item
+                    block.map_or_else(|| Ok(None), |block| block.get(key, None))
                 } else {
                     Ok(None)
                 }
@@ -131,18 +228,14 @@ impl Segment {
                         &self.block_cache,
                         &self.metadata.id,
                         &block_handle,
+                        self.metadata.compression,
+                        self.metadata.version,
+                        self.metadata.global_seqno,
                     )?;
 
                     if let Some(block) = block {
-                        for item in block
-                            .items
-                            .iter()
-                            // TODO: maybe binary search can be used, but it needs to find the max seqno
-                            .filter(|item| item.key == key.as_ref().into())
-                        {
-                            if item.seqno < seqno {
-                                return Ok(Some(item.clone()));
-                            }
+                        if let Some(item) = block.get(key, Some(seqno))? {
+                            return Ok(Some(item));
                         }
                     }
 
@@ -174,6 +267,9 @@ impl Segment {
                         self.metadata.id.clone(),
                         Arc::clone(&self.block_cache),
                         Arc::clone(&self.block_index),
+                        self.metadata.compression,
+                        self.metadata.version,
+                        self.metadata.global_seqno,
                         Some(&next_block_handle.start_key),
                         None,
                     );
@@ -210,6 +306,9 @@ impl Segment {
             self.metadata.id.clone(),
             Arc::clone(&self.block_cache),
             Arc::clone(&self.block_index),
+            self.metadata.compression,
+            self.metadata.version,
+            self.metadata.global_seqno,
             None,
             None,
         )
@@ -226,6 +325,9 @@ impl Segment {
             self.metadata.id.clone(),
             Arc::clone(&self.block_cache),
             Arc::clone(&self.block_index),
+            self.metadata.compression,
+            self.metadata.version,
+            self.metadata.global_seqno,
             range,
         )
     }
@@ -241,6 +343,9 @@ impl Segment {
             self.metadata.id.clone(),
             Arc::clone(&self.block_cache),
             Arc::clone(&self.block_index),
+            self.metadata.compression,
+            self.metadata.version,
+            self.metadata.global_seqno,
             prefix,
         )
     }
@@ -308,3 +413,196 @@ impl Segment {
         lo_included && hi_included
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file::{
+            BLOCKS_FILE, LEGACY_SEGMENT_METADATA_FILE, SEGMENT_METADATA_FILE, TOP_LEVEL_INDEX_FILE,
+        },
+        segment::writer::{Options, Writer},
+        value::ValueType,
+        version::Version,
+        CompressionType,
+    };
+    use std::io::{Read, Seek, SeekFrom};
+    use test_log::test;
+
+    const ITEM_COUNT: u64 = 1_000;
+
+    fn write_segment(path: &Path) -> crate::Result<Metadata> {
+        let metadata = write_segment_without_metadata(path)?;
+        metadata.write_to_file()?;
+        Ok(metadata)
+    }
+
+    fn write_segment_without_metadata(path: &Path) -> crate::Result<Metadata> {
+        let mut writer = Writer::new(Options {
+            path: path.to_path_buf(),
+            evict_tombstones: false,
+            block_size: 4096,
+            bloom_bits_per_key: 10,
+            compression: CompressionType::Lz4,
+        })?;
+
+        writer.set_range_tombstones(vec![RangeTombstone {
+            start: (*b"z").into(),
+            end: None,
+            seqno: 5,
+        }]);
+
+        for x in 0..ITEM_COUNT {
+            writer.write(Value::new(
+                x.to_be_bytes(),
+                nanoid::nanoid!().as_bytes(),
+                0,
+                ValueType::Value,
+            ))?;
+        }
+
+        writer.finish()?;
+
+        Metadata::from_writer(nanoid::nanoid!().into(), writer)
+    }
+
+    #[test]
+    fn segment_single_file() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("segment");
+
+        let metadata = write_segment(&path)?;
+        assert!(path.is_file());
+        assert_eq!(Version::V4, metadata.version);
+
+        let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
+        let segment = Segment::recover(&path, block_cache)?;
+
+        assert_eq!(metadata.id, segment.metadata.id);
+        // NOTE: The range tombstone is anchored by a point tombstone
+        assert_eq!(ITEM_COUNT + 1, segment.metadata.item_count);
+        assert!(segment.bloom_filter.is_some());
+        assert_eq!(1, segment.range_tombstones.len());
+
+        for x in 0..ITEM_COUNT {
+            assert!(segment.get(x.to_be_bytes(), None)?.is_some());
+        }
+        assert!(segment.get(ITEM_COUNT.to_be_bytes(), None)?.is_none());
+
+        // NOTE: Rewriting the metadata stores it in a sidecar, leaving the segment file untouched
+        let file_size = std::fs::metadata(&path)?.len();
+
+        let mut metadata = segment.metadata.clone();
+        metadata.global_seqno = Some(7);
+        metadata.write_to_file()?;
+        metadata.global_seqno = Some(8);
+        metadata.write_to_file()?;
+
+        assert_eq!(file_size, std::fs::metadata(&path)?.len());
+        assert!(crate::file::segment_sidecar_path(&path).exists());
+
+        let metadata = Metadata::recover(&path)?;
+        assert_eq!(Some(8), metadata.global_seqno);
+        assert_eq!(ITEM_COUNT + 1, metadata.item_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn segment_v3_json_metadata() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("segment");
+
+        let mut metadata = write_segment_without_metadata(&path)?;
+        metadata.version = Version::V3;
+
+        // NOTE: V3 segments stored the metadata section as JSON
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path)?;
+        let offset = file.seek(SeekFrom::End(0))?;
+        let json = serde_json::to_vec(&metadata).map_err(std::io::Error::from)?;
+
+        let mut footer = metadata.footer;
+        footer.metadata = footer::SectionHandle::write(&mut file, offset, &json)?;
+        footer.write(&mut file, Version::V3)?;
+        drop(file);
+
+        let mut metadata = Metadata::recover(&path)?;
+        assert_eq!(Version::V3, metadata.version);
+        assert_eq!(None, metadata.global_seqno);
+
+        // NOTE: Rewritten metadata of a V3 segment is stored as binary in a sidecar
+        metadata.global_seqno = Some(7);
+        metadata.write_to_file()?;
+
+        let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
+        let segment = Segment::recover(&path, block_cache)?;
+        assert_eq!(Some(7), segment.metadata.global_seqno);
+        assert_eq!(ITEM_COUNT + 1, segment.metadata.item_count);
+
+        for x in 0..ITEM_COUNT {
+            assert!(segment.get(x.to_be_bytes(), None)?.is_some());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn segment_legacy_folder() -> crate::Result<()> {
+        let folder = tempfile::tempdir()?;
+        let path = folder.path().join("segment");
+        let legacy_path = folder.path().join("legacy");
+
+        let metadata = write_segment(&path)?;
+        let footer = metadata.footer;
+
+        // NOTE: Split the segment file into the folder layout of older versions
+        let mut file = File::open(&path)?;
+        let mut blocks = vec![0; footer.top_level_index.offset as usize];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut blocks)?;
+
+        std::fs::create_dir(&legacy_path)?;
+        std::fs::write(legacy_path.join(BLOCKS_FILE), blocks)?;
+
+        for (handle, name) in [
+            (footer.top_level_index, TOP_LEVEL_INDEX_FILE),
+            (footer.bloom_filter, BLOOM_FILTER_FILE),
+            (footer.range_tombstones, RANGE_TOMBSTONES_FILE),
+        ] {
+            std::fs::write(legacy_path.join(name), handle.read(&mut file)?)?;
+        }
+
+        // NOTE: Older versions stored the metadata as JSON
+        let mut legacy_metadata = metadata.clone();
+        legacy_metadata.version = Version::V2;
+        legacy_metadata.path.clone_from(&legacy_path);
+        std::fs::write(
+            legacy_path.join(LEGACY_SEGMENT_METADATA_FILE),
+            serde_json::to_vec(&legacy_metadata).map_err(std::io::Error::from)?,
+        )?;
+
+        let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
+        let segment = Segment::recover(&legacy_path, Arc::clone(&block_cache))?;
+
+        assert_eq!(Version::V2, segment.metadata.version);
+        assert!(segment.bloom_filter.is_some());
+        assert_eq!(1, segment.range_tombstones.len());
+
+        for x in 0..ITEM_COUNT {
+            assert!(segment.get(x.to_be_bytes(), None)?.is_some());
+        }
+
+        // NOTE: Rewriting the metadata replaces the JSON file with a binary one
+        legacy_metadata.global_seqno = Some(7);
+        legacy_metadata.write_to_file()?;
+        assert!(legacy_path.join(SEGMENT_METADATA_FILE).exists());
+        assert!(!legacy_path.join(LEGACY_SEGMENT_METADATA_FILE).exists());
+
+        let segment = Segment::recover(&legacy_path, block_cache)?;
+        assert_eq!(Version::V2, segment.metadata.version);
+        assert_eq!(Some(7), segment.metadata.global_seqno);
+        assert!(segment.get(0u64.to_be_bytes(), None)?.is_some());
+
+        Ok(())
+    }
+}
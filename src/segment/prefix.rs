@@ -1,6 +1,10 @@
 use super::{index::BlockIndex, range::Range};
 use crate::{
-    block_cache::BlockCache, descriptor_table::FileDescriptorTable, value::UserKey, Value,
+    block_cache::BlockCache,
+    descriptor_table::FileDescriptorTable,
+    value::{SeqNo, UserKey},
+    version::Version,
+    CompressionType, Value,
 };
 use std::{
     ops::Bound::{Excluded, Included, Unbounded},
@@ -13,6 +17,9 @@ pub struct PrefixedReader {
     block_index: Arc<BlockIndex>,
     block_cache: Arc<BlockCache>,
     segment_id: Arc<str>,
+    compression: CompressionType,
+    version: Version,
+    global_seqno: Option<SeqNo>,
 
     prefix: UserKey,
 
@@ -20,11 +27,15 @@ pub struct PrefixedReader {
 }
 
 impl PrefixedReader {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<K: Into<UserKey>>(
         descriptor_table: Arc<FileDescriptorTable>,
         segment_id: Arc<str>,
         block_cache: Arc<BlockCache>,
         block_index: Arc<BlockIndex>,
+        compression: CompressionType,
+        version: Version,
+        global_seqno: Option<SeqNo>,
         prefix: K,
     ) -> Self {
         Self {
@@ -32,6 +43,9 @@ impl PrefixedReader {
             block_index,
             descriptor_table,
             segment_id,
+            compression,
+            version,
+            global_seqno,
 
             iterator: None,
 
@@ -48,6 +62,9 @@ impl PrefixedReader {
             self.segment_id.clone(),
             self.block_cache.clone(),
             self.block_index.clone(),
+            self.compression,
+            self.version,
+            self.global_seqno,
             (Included(self.prefix.clone()), upper_bound),
         );
         self.iterator = Some(iterator);
@@ -132,16 +149,16 @@ mod tests {
     use crate::{
         block_cache::BlockCache,
         descriptor_table::FileDescriptorTable,
-        file::BLOCKS_FILE,
         segment::{
-            index::BlockIndex,
             meta::Metadata,
             prefix::PrefixedReader,
             reader::Reader,
             writer::{Options, Writer},
+            Segment,
         },
         value::{SeqNo, ValueType},
-        Value,
+        version::Version,
+        CompressionType, Value,
     };
     use std::sync::Arc;
     use test_log::test;
@@ -149,12 +166,14 @@ mod tests {
     #[test]
     fn test_lots_of_prefixed() -> crate::Result<()> {
         for item_count in [1, 10, 100, 1_000, 10_000] {
-            let folder = tempfile::tempdir()?.into_path();
+            let path = tempfile::tempdir()?.into_path().join("segment");
 
             let mut writer = Writer::new(Options {
-                path: folder.clone(),
+                path: path.clone(),
                 evict_tombstones: false,
                 block_size: 4096,
+                bloom_bits_per_key: 0,
+                compression: CompressionType::Lz4,
             })?;
 
             for x in 0_u64..item_count {
@@ -205,38 +224,43 @@ mod tests {
             metadata.write_to_file()?;
 
             let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
-            let block_index = Arc::new(BlockIndex::from_file(
-                metadata.id.clone(),
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
-                &folder,
-                Arc::clone(&block_cache),
-            )?);
+            let block_index =
+                Arc::clone(&Segment::load(metadata.clone(), Arc::clone(&block_cache))?.block_index);
 
             let iter = Reader::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 None,
                 None,
             );
             assert_eq!(iter.count() as u64, item_count * 3);
 
             let iter = PrefixedReader::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 b"a/b/".to_vec(),
             );
 
             assert_eq!(iter.count() as u64, item_count);
 
             let iter = PrefixedReader::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 b"a/b/".to_vec(),
             );
 
@@ -248,12 +272,14 @@ mod tests {
 
     #[test]
     fn test_prefixed() -> crate::Result<()> {
-        let folder = tempfile::tempdir()?.into_path();
+        let path = tempfile::tempdir()?.into_path().join("segment");
 
         let mut writer = Writer::new(Options {
-            path: folder.clone(),
+            path: path.clone(),
             evict_tombstones: false,
             block_size: 4096,
+            bloom_bits_per_key: 0,
+            compression: CompressionType::Lz4,
         })?;
 
         let items = [
@@ -290,12 +316,8 @@ mod tests {
         metadata.write_to_file()?;
 
         let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
-        let block_index = Arc::new(BlockIndex::from_file(
-            metadata.id.clone(),
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
-            &folder,
-            Arc::clone(&block_cache),
-        )?);
+        let block_index =
+            Arc::clone(&Segment::load(metadata.clone(), Arc::clone(&block_cache))?.block_index);
 
         let expected = [
             (b"a".to_vec(), 9),
@@ -310,10 +332,13 @@ mod tests {
 
         for (prefix_key, item_count) in expected {
             let iter = PrefixedReader::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 prefix_key,
             );
 
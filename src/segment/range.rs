@@ -2,8 +2,9 @@ use super::index::BlockIndex;
 use super::reader::Reader;
 use crate::block_cache::BlockCache;
 use crate::descriptor_table::FileDescriptorTable;
-use crate::value::UserKey;
-use crate::Value;
+use crate::value::{SeqNo, UserKey};
+use crate::version::Version;
+use crate::{CompressionType, Value};
 use std::ops::Bound;
 use std::ops::RangeBounds;
 use std::sync::Arc;
@@ -13,6 +14,9 @@ pub struct Range {
     block_index: Arc<BlockIndex>,
     block_cache: Arc<BlockCache>,
     segment_id: Arc<str>,
+    compression: CompressionType,
+    version: Version,
+    global_seqno: Option<SeqNo>,
 
     range: (Bound<UserKey>, Bound<UserKey>),
 
@@ -20,11 +24,15 @@ pub struct Range {
 }
 
 impl Range {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         descriptor_table: Arc<FileDescriptorTable>,
         segment_id: Arc<str>,
         block_cache: Arc<BlockCache>,
         block_index: Arc<BlockIndex>,
+        compression: CompressionType,
+        version: Version,
+        global_seqno: Option<SeqNo>,
         range: (Bound<UserKey>, Bound<UserKey>),
     ) -> Self {
         Self {
@@ -32,6 +40,9 @@ impl Range {
             block_cache,
             block_index,
             segment_id,
+            compression,
+            version,
+            global_seqno,
 
             iterator: None,
             range,
@@ -60,6 +71,9 @@ impl Range {
             self.segment_id.clone(),
             self.block_cache.clone(),
             self.block_index.clone(),
+            self.compression,
+            self.version,
+            self.global_seqno,
             offset_lo.as_ref(),
             offset_hi.as_ref(),
         );
@@ -190,15 +204,15 @@ mod tests {
     use crate::{
         block_cache::BlockCache,
         descriptor_table::FileDescriptorTable,
-        file::BLOCKS_FILE,
         segment::{
-            index::BlockIndex,
             meta::Metadata,
             range::Range,
             writer::{Options, Writer},
+            Segment,
         },
         value::{UserKey, ValueType},
-        Value,
+        version::Version,
+        CompressionType, Value,
     };
     use std::ops::{
         Bound::{self, *},
@@ -212,12 +226,14 @@ mod tests {
     #[test]
     #[allow(clippy::expect_used)]
     fn test_unbounded_range() -> crate::Result<()> {
-        let folder = tempfile::tempdir()?.into_path();
+        let path = tempfile::tempdir()?.into_path().join("segment");
 
         let mut writer = Writer::new(Options {
-            path: folder.clone(),
+            path: path.clone(),
             evict_tombstones: false,
             block_size: 4096,
+            bloom_bits_per_key: 0,
+            compression: CompressionType::Lz4,
         })?;
 
         let items = (0u64..ITEM_COUNT).map(|i| {
@@ -239,21 +255,20 @@ mod tests {
         metadata.write_to_file()?;
 
         let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
-        let block_index = Arc::new(BlockIndex::from_file(
-            metadata.id.clone(),
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
-            &folder,
-            Arc::clone(&block_cache),
-        )?);
+        let block_index =
+            Arc::clone(&Segment::load(metadata.clone(), Arc::clone(&block_cache))?.block_index);
 
         {
             log::info!("Getting every item");
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 range_bounds_to_tuple(&..),
             );
 
@@ -265,10 +280,13 @@ mod tests {
             log::info!("Getting every item in reverse");
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 range_bounds_to_tuple(&..),
             );
 
@@ -284,10 +302,13 @@ mod tests {
             let end: Arc<[u8]> = 5_000_u64.to_be_bytes().into();
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 range_bounds_to_tuple::<UserKey>(&..end),
             );
 
@@ -301,10 +322,13 @@ mod tests {
             let end: Arc<[u8]> = 5_000_u64.to_be_bytes().into();
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 range_bounds_to_tuple(&..end),
             );
 
@@ -320,10 +344,13 @@ mod tests {
             let start: Arc<[u8]> = 1_000_u64.to_be_bytes().into();
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 range_bounds_to_tuple(&(start..)),
             );
 
@@ -338,10 +365,13 @@ mod tests {
             let end: Arc<[u8]> = 5_000_u64.to_be_bytes().into();
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id,
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 range_bounds_to_tuple(&(start..end)),
             );
 
@@ -404,12 +434,14 @@ mod tests {
 
     #[test]
     fn test_bounded_ranges() -> crate::Result<()> {
-        let folder = tempfile::tempdir()?.into_path();
+        let path = tempfile::tempdir()?.into_path().join("segment");
 
         let mut writer = Writer::new(Options {
-            path: folder.clone(),
+            path: path.clone(),
             evict_tombstones: false,
             block_size: 4096,
+            bloom_bits_per_key: 0,
+            compression: CompressionType::Lz4,
         })?;
 
         let items = (0u64..ITEM_COUNT).map(|i| {
@@ -431,12 +463,8 @@ mod tests {
         metadata.write_to_file()?;
 
         let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
-        let block_index = Arc::new(BlockIndex::from_file(
-            metadata.id.clone(),
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
-            &folder,
-            Arc::clone(&block_cache),
-        )?);
+        let block_index =
+            Arc::clone(&Segment::load(metadata.clone(), Arc::clone(&block_cache))?.block_index);
 
         let ranges: Vec<(Bound<u64>, Bound<u64>)> = vec![
             range_bounds_to_tuple(&(0..1_000)),
@@ -456,10 +484,13 @@ mod tests {
             let range = std::ops::Range { start, end };
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 bounds_u64_to_bytes(&bounds),
             );
 
@@ -475,10 +506,13 @@ mod tests {
             let range = std::ops::Range { start, end };
 
             let mut iter = Range::new(
-                Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+                Arc::new(FileDescriptorTable::new(&path)?),
                 metadata.id.clone(),
                 Arc::clone(&block_cache),
                 Arc::clone(&block_index),
+                CompressionType::Lz4,
+                Version::V4,
+                None,
                 bounds_u64_to_bytes(&bounds),
             );
 
@@ -1,6 +1,10 @@
 use super::{block::load_and_cache_block_by_item_key, index::BlockIndex};
 use crate::{
-    block_cache::BlockCache, descriptor_table::FileDescriptorTable, value::UserKey, Value,
+    block_cache::BlockCache,
+    descriptor_table::FileDescriptorTable,
+    value::{SeqNo, UserKey},
+    version::Version,
+    CompressionType, Value,
 };
 use std::{
     collections::{HashMap, VecDeque},
@@ -16,6 +20,9 @@ pub struct Reader {
 
     segment_id: Arc<str>,
     block_cache: Arc<BlockCache>,
+    compression: CompressionType,
+    version: Version,
+    global_seqno: Option<SeqNo>,
 
     blocks: HashMap<UserKey, VecDeque<Value>>,
     current_lo: Option<UserKey>,
@@ -27,11 +34,15 @@ pub struct Reader {
 }
 
 impl Reader {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         descriptor_table: Arc<FileDescriptorTable>,
         segment_id: Arc<str>,
         block_cache: Arc<BlockCache>,
         block_index: Arc<BlockIndex>,
+        compression: CompressionType,
+        version: Version,
+        global_seqno: Option<SeqNo>,
         start_offset: Option<&UserKey>,
         end_offset: Option<&UserKey>,
     ) -> Self {
@@ -42,6 +53,9 @@ impl Reader {
             block_cache,
 
             block_index,
+            compression,
+            version,
+            global_seqno,
 
             blocks: HashMap::with_capacity(2),
             current_lo: None,
@@ -80,8 +94,11 @@ impl Reader {
                 &self.block_cache,
                 &self.segment_id,
                 key,
+                self.compression,
+                self.version,
+                self.global_seqno,
             )? {
-                let items = block.items.clone().into();
+                let items = block.items()?.into();
                 self.blocks.insert(key.to_vec().into(), items);
                 Some(())
             } else {
@@ -253,15 +270,15 @@ mod tests {
     use crate::{
         block_cache::BlockCache,
         descriptor_table::FileDescriptorTable,
-        file::BLOCKS_FILE,
         segment::{
-            index::BlockIndex,
             meta::Metadata,
             reader::Reader,
             writer::{Options, Writer},
+            Segment,
         },
         value::ValueType,
-        Value,
+        version::Version,
+        CompressionType, Value,
     };
     use std::sync::Arc;
     use test_log::test;
@@ -273,12 +290,14 @@ mod tests {
     fn test_get_all() -> crate::Result<()> {
         const ITEM_COUNT: u64 = 100_000;
 
-        let folder = tempfile::tempdir()?.into_path();
+        let path = tempfile::tempdir()?.into_path().join("segment");
 
         let mut writer = Writer::new(Options {
-            path: folder.clone(),
+            path: path.clone(),
             evict_tombstones: false,
             block_size: 4096,
+            bloom_bits_per_key: 0,
+            compression: CompressionType::Lz4,
         })?;
 
         let items = (0u64..ITEM_COUNT).map(|i| {
@@ -300,20 +319,19 @@ mod tests {
         metadata.write_to_file()?;
 
         let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
-        let block_index = Arc::new(BlockIndex::from_file(
-            metadata.id.clone(),
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
-            &folder,
-            Arc::clone(&block_cache),
-        )?);
+        let block_index =
+            Arc::clone(&Segment::load(metadata.clone(), Arc::clone(&block_cache))?.block_index);
 
         log::info!("Getting every item");
 
         let mut iter = Reader::new(
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+            Arc::new(FileDescriptorTable::new(&path)?),
             metadata.id.clone(),
             Arc::clone(&block_cache),
             Arc::clone(&block_index),
+            CompressionType::Lz4,
+            Version::V4,
+            None,
             None,
             None,
         );
@@ -326,10 +344,13 @@ mod tests {
         log::info!("Getting every item in reverse");
 
         let mut iter = Reader::new(
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+            Arc::new(FileDescriptorTable::new(&path)?),
             metadata.id,
             Arc::clone(&block_cache),
             Arc::clone(&block_index),
+            CompressionType::Lz4,
+            Version::V4,
+            None,
             None,
             None,
         );
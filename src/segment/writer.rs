@@ -1,14 +1,21 @@
-use super::{block::ValueBlock, meta::Metadata};
+use super::{
+    block::ValueBlock,
+    footer::{Footer, SectionHandle},
+    meta::{CompressionType, Metadata},
+};
 use crate::{
-    file::BLOCKS_FILE,
+    blob::BlobPointer,
+    bloom::{BloomFilter, CompositeHash},
     id::generate_segment_id,
+    range_tombstone::RangeTombstone,
     segment::index::writer::Writer as IndexWriter,
     serde::Serializable,
-    value::{SeqNo, UserKey},
+    value::{SeqNo, UserKey, ValueType},
     Value,
 };
-use lz4_flex::compress_prepend_size;
 use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
     fs::File,
     io::{BufWriter, Write},
     path::PathBuf,
@@ -42,6 +49,8 @@ impl MultiWriter {
             path: opts.path.join(&*segment_id),
             evict_tombstones: opts.evict_tombstones,
             block_size: opts.block_size,
+            bloom_bits_per_key: opts.bloom_bits_per_key,
+            compression: opts.compression,
         })?;
 
         Ok(Self {
@@ -57,17 +66,24 @@ impl MultiWriter {
     fn rotate(&mut self) -> crate::Result<()> {
         log::debug!("Rotating segment writer");
 
+        // NOTE: Range tombstones that were not written yet belong to the next segment
+        let pending_range_tombstones = std::mem::take(&mut self.writer.pending_range_tombstones);
+
         // Flush segment, and start new one
         self.writer.finish()?;
 
         let new_segment_id = generate_segment_id();
 
-        let new_writer = Writer::new(Options {
+        let mut new_writer = Writer::new(Options {
             path: self.opts.path.join(&*new_segment_id),
             evict_tombstones: self.opts.evict_tombstones,
             block_size: self.opts.block_size,
+            bloom_bits_per_key: self.opts.bloom_bits_per_key,
+            compression: self.opts.compression,
         })?;
 
+        new_writer.pending_range_tombstones = pending_range_tombstones;
+
         let old_writer = std::mem::replace(&mut self.writer, new_writer);
         let old_segment_id = std::mem::replace(&mut self.current_segment_id, new_segment_id);
 
@@ -79,6 +95,12 @@ impl MultiWriter {
         Ok(())
     }
 
+    /// Sets the range tombstones that will be written into the segments,
+    /// see [`Writer::set_range_tombstones`].
+    pub fn set_range_tombstones(&mut self, tombstones: Vec<RangeTombstone>) {
+        self.writer.set_range_tombstones(tombstones);
+    }
+
     /// Writes an item
     pub fn write(&mut self, item: Value) -> crate::Result<()> {
         self.writer.write(item)?;
@@ -115,7 +137,7 @@ pub struct Writer {
 
     block_writer: BufWriter<File>,
     index_writer: IndexWriter,
-    chunk: ValueBlock,
+    chunk: Vec<Value>,
 
     pub block_count: usize,
     pub item_count: usize,
@@ -134,28 +156,49 @@ pub struct Writer {
 
     pub key_count: usize,
     current_key: Option<UserKey>,
+
+    /// Hashes of all written keys, used to build the bloom filter
+    bloom_hash_buffer: Vec<CompositeHash>,
+
+    /// Range tombstones that have been written into the segment
+    pub range_tombstones: Vec<RangeTombstone>,
+
+    /// Range tombstones that still need to be written, sorted by start key
+    pending_range_tombstones: VecDeque<RangeTombstone>,
+
+    /// Bytes referenced in each blob file
+    pub blob_refs: HashMap<Arc<str>, u64>,
+
+    /// Unix timestamp (in ms) at which all written items have expired
+    ///
+    /// None if any item does not expire
+    pub expires_at: Option<u64>,
+
+    /// Sections that were written after the data blocks
+    pub footer: Footer,
 }
 
 pub struct Options {
     pub path: PathBuf,
     pub evict_tombstones: bool,
     pub block_size: u32,
+
+    /// Bits per key for the bloom filter, 0 = no bloom filter
+    pub bloom_bits_per_key: u8,
+
+    /// Compression to use for data blocks
+    pub compression: CompressionType,
 }
 
 impl Writer {
-    /// Sets up a new `MultiWriter` at the given segments folder
+    /// Sets up a new `Writer` that writes a segment file at the given path
     pub fn new(opts: Options) -> crate::Result<Self> {
-        std::fs::create_dir_all(&opts.path)?;
-
-        let block_writer = File::create(opts.path.join(BLOCKS_FILE))?;
+        let block_writer = File::create(&opts.path)?;
         let block_writer = BufWriter::with_capacity(512_000, block_writer);
 
-        let index_writer = IndexWriter::new(&opts.path, opts.block_size)?;
+        let index_writer = IndexWriter::new(opts.block_size);
 
-        let chunk = ValueBlock {
-            items: Vec::with_capacity(1_000),
-            crc: 0,
-        };
+        let chunk = Vec::with_capacity(1_000);
 
         Ok(Self {
             opts,
@@ -179,6 +222,18 @@ impl Writer {
 
             current_key: None,
             key_count: 0,
+
+            bloom_hash_buffer: Vec::with_capacity(10_000),
+
+            range_tombstones: Vec::new(),
+            pending_range_tombstones: VecDeque::new(),
+
+            blob_refs: HashMap::new(),
+
+            // NOTE: Nothing written yet, so nothing that does not expire
+            expires_at: Some(0),
+
+            footer: Footer::default(),
         })
     }
 
@@ -186,11 +241,10 @@ impl Writer {
     ///
     /// This is triggered when a `Writer::write` causes the buffer to grow to the configured `block_size`
     fn write_block(&mut self) -> crate::Result<()> {
-        debug_assert!(!self.chunk.items.is_empty());
+        debug_assert!(!self.chunk.is_empty());
 
         let uncompressed_chunk_size = self
             .chunk
-            .items
             .iter()
             .map(|item| item.size() as u64)
             .sum::<u64>();
@@ -198,14 +252,10 @@ impl Writer {
         self.uncompressed_size += uncompressed_chunk_size;
 
         // Serialize block
-        let mut bytes = Vec::with_capacity(u16::MAX.into());
-        self.chunk.crc = ValueBlock::create_crc(&self.chunk.items)?;
-        self.chunk
-            .serialize(&mut bytes)
-            .expect("should serialize block");
+        let bytes = ValueBlock::encode(&self.chunk)?;
 
-        // Compress using LZ4
-        let bytes = compress_prepend_size(&bytes);
+        // Compress using the configured compression
+        let bytes = self.opts.compression.compress(&bytes)?;
 
         // Write to file
         self.block_writer.write_all(&bytes)?;
@@ -215,14 +265,13 @@ impl Writer {
         #[allow(clippy::cast_possible_truncation)]
         let bytes_written = bytes.len() as u32;
 
-        // Expect is fine, because the chunk is not empty
-        let first = self.chunk.items.first().expect("Chunk should not be empty");
+        // NOTE: Expect is fine, because the chunk is not empty
+        #[allow(clippy::expect_used)]
+        let first = self.chunk.first().expect("Chunk should not be empty");
 
         self.index_writer
             .register_block(first.key.clone(), self.file_pos, bytes_written)?;
 
-        // TODO: add to bloom filter
-
         // Adjust metadata
         log::trace!(
             "Written data block @ {} ({} bytes, uncompressed: {} bytes)",
@@ -232,33 +281,95 @@ impl Writer {
         );
 
         self.file_pos += u64::from(bytes_written);
-        self.item_count += self.chunk.items.len();
+        self.item_count += self.chunk.len();
         self.block_count += 1;
-        self.chunk.items.clear();
+        self.chunk.clear();
+
+        Ok(())
+    }
+
+    /// Sets the range tombstones that will be written into the segment.
+    ///
+    /// Each range tombstone is written together with a point tombstone at its start key
+    /// (its "anchor"), interleaved with the items in key order. The anchor makes sure a segment
+    /// never consists of range tombstones only. Anchors that are written as
+    /// items (e.g. when rewriting segments) are deduplicated.
+    pub fn set_range_tombstones(&mut self, mut tombstones: Vec<RangeTombstone>) {
+        tombstones.sort_by(|a, b| (&a.start, Reverse(a.seqno)).cmp(&(&b.start, Reverse(b.seqno))));
+        self.pending_range_tombstones = tombstones.into();
+    }
+
+    /// Writes a range tombstone and its anchor
+    fn write_range_tombstone(&mut self, tombstone: RangeTombstone) -> crate::Result<()> {
+        self.write_item(Value::new(
+            tombstone.start.clone(),
+            vec![],
+            tombstone.seqno,
+            ValueType::Tombstone,
+        ))?;
+
+        self.range_tombstones.push(tombstone);
 
         Ok(())
     }
 
     /// Writes an item
     pub fn write(&mut self, item: Value) -> crate::Result<()> {
-        if item.is_tombstone() {
-            if self.opts.evict_tombstones {
-                return Ok(());
+        while let Some(tombstone) = self.pending_range_tombstones.pop_front() {
+            let is_anchor =
+                item.is_tombstone() && item.seqno == tombstone.seqno && item.key == tombstone.start;
+
+            if is_anchor {
+                return self.write_range_tombstone(tombstone);
             }
 
+            if (&tombstone.start, Reverse(tombstone.seqno)) < (&item.key, Reverse(item.seqno)) {
+                self.write_range_tombstone(tombstone)?;
+            } else {
+                self.pending_range_tombstones.push_front(tombstone);
+                break;
+            }
+        }
+
+        if item.is_tombstone() && self.opts.evict_tombstones {
+            return Ok(());
+        }
+
+        self.write_item(item)
+    }
+
+    fn write_item(&mut self, item: Value) -> crate::Result<()> {
+        if item.is_tombstone() {
             self.tombstone_count += 1;
         }
 
+        self.expires_at = match (self.expires_at, item.expires_at) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+
+        if item.value_type == ValueType::Indirection {
+            let pointer = BlobPointer::from_value(&item)?;
+
+            let entry_size = pointer.entry_size(&item.key);
+            *self.blob_refs.entry(pointer.blob_id).or_default() += entry_size;
+        }
+
         if Some(&item.key) != self.current_key.as_ref() {
             self.key_count += 1;
             self.current_key = Some(item.key.clone());
+
+            if self.opts.bloom_bits_per_key > 0 {
+                self.bloom_hash_buffer
+                    .push(BloomFilter::get_hash(&item.key));
+            }
         }
 
         let item_key = item.key.clone();
         let seqno = item.seqno;
 
         self.chunk_size += item.size();
-        self.chunk.items.push(item);
+        self.chunk.push(item);
 
         if self.chunk_size >= self.opts.block_size as usize {
             self.write_block()?;
@@ -283,35 +394,64 @@ impl Writer {
 
     /// Finishes the segment, making sure all data is written durably
     pub fn finish(&mut self) -> crate::Result<()> {
-        if !self.chunk.items.is_empty() {
+        while let Some(tombstone) = self.pending_range_tombstones.pop_front() {
+            self.write_range_tombstone(tombstone)?;
+        }
+
+        if !self.chunk.is_empty() {
             self.write_block()?;
         }
 
-        // No items written! Just delete segment folder and return nothing
+        // No items written! Just delete segment file and return nothing
         if self.item_count == 0 {
             log::debug!(
-                "Deleting empty segment folder ({}) because no items were written",
+                "Deleting empty segment file ({}) because no items were written",
                 self.opts.path.display()
             );
-            std::fs::remove_dir_all(&self.opts.path)?;
+            std::fs::remove_file(&self.opts.path)?;
             return Ok(());
         }
 
-        self.block_writer.flush()?;
+        // NOTE: The index blocks are written right after the data blocks
+        let (index_blocks, top_level_index) = self.index_writer.finish(self.file_pos)?;
+        self.block_writer.write_all(&index_blocks)?;
 
-        self.index_writer.finish(self.file_pos)?;
+        let mut offset = self.file_pos + index_blocks.len() as u64;
 
-        self.block_writer.get_mut().sync_all()?;
+        self.footer.top_level_index =
+            SectionHandle::write(&mut self.block_writer, offset, &top_level_index)?;
+        offset += u64::from(self.footer.top_level_index.size);
+
+        if self.opts.bloom_bits_per_key > 0 {
+            let mut filter = BloomFilter::with_bits_per_key(
+                self.bloom_hash_buffer.len(),
+                self.opts.bloom_bits_per_key,
+            );
+
+            for hash in std::mem::take(&mut self.bloom_hash_buffer) {
+                filter.set_with_hash(hash);
+            }
+
+            let mut bytes = vec![];
+            filter.serialize(&mut bytes)?;
+
+            self.footer.bloom_filter =
+                SectionHandle::write(&mut self.block_writer, offset, &bytes)?;
+            offset += u64::from(self.footer.bloom_filter.size);
+        }
 
-        // TODO: write (& sync) bloom filter
+        if !self.range_tombstones.is_empty() {
+            let mut bytes = vec![];
+            crate::range_tombstone::write_into(&mut bytes, &self.range_tombstones)?;
 
-        #[cfg(not(target_os = "windows"))]
-        {
-            // fsync folder on Unix
-            let folder = std::fs::File::open(&self.opts.path)?;
-            folder.sync_all()?;
+            self.footer.range_tombstones =
+                SectionHandle::write(&mut self.block_writer, offset, &bytes)?;
         }
 
+        // NOTE: The metadata and footer are appended by `Metadata::write_to_file`
+        self.block_writer.flush()?;
+        self.block_writer.get_mut().sync_all()?;
+
         log::debug!(
             "Written {} items in {} blocks into new segment file, written {} MB",
             self.item_count,
@@ -327,10 +467,11 @@ impl Writer {
 mod tests {
     use super::*;
     use crate::value::ValueType;
+    use crate::version::Version;
     use crate::{
         block_cache::BlockCache,
         descriptor_table::FileDescriptorTable,
-        segment::{index::BlockIndex, meta::Metadata, reader::Reader},
+        segment::{meta::Metadata, reader::Reader, Segment},
         Value,
     };
     use std::sync::Arc;
@@ -340,12 +481,14 @@ mod tests {
     fn test_write_and_read() -> crate::Result<()> {
         const ITEM_COUNT: u64 = 100;
 
-        let folder = tempfile::tempdir()?.into_path();
+        let path = tempfile::tempdir()?.into_path().join("segment");
 
         let mut writer = Writer::new(Options {
-            path: folder.clone(),
+            path: path.clone(),
             evict_tombstones: false,
             block_size: 4096,
+            bloom_bits_per_key: 0,
+            compression: CompressionType::Lz4,
         })?;
 
         let items = (0u64..ITEM_COUNT).map(|i| {
@@ -369,17 +512,16 @@ mod tests {
         assert_eq!(ITEM_COUNT, metadata.key_count);
 
         let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
-        let block_index = Arc::new(BlockIndex::from_file(
-            metadata.id.clone(),
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
-            &folder,
-            Arc::clone(&block_cache),
-        )?);
+        let block_index =
+            Arc::clone(&Segment::load(metadata.clone(), Arc::clone(&block_cache))?.block_index);
         let iter = Reader::new(
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+            Arc::new(FileDescriptorTable::new(&path)?),
             metadata.id,
             Arc::clone(&block_cache),
             Arc::clone(&block_index),
+            CompressionType::Lz4,
+            Version::V4,
+            None,
             None,
             None,
         );
@@ -394,12 +536,14 @@ mod tests {
         const ITEM_COUNT: u64 = 1_000;
         const VERSION_COUNT: u64 = 5;
 
-        let folder = tempfile::tempdir()?.into_path();
+        let path = tempfile::tempdir()?.into_path().join("segment");
 
         let mut writer = Writer::new(Options {
-            path: folder.clone(),
+            path: path.clone(),
             evict_tombstones: false,
             block_size: 4096,
+            bloom_bits_per_key: 0,
+            compression: CompressionType::Lz4,
         })?;
 
         for key in 0u64..ITEM_COUNT {
@@ -423,18 +567,17 @@ mod tests {
         assert_eq!(ITEM_COUNT, metadata.key_count);
 
         let block_cache = Arc::new(BlockCache::with_capacity_blocks(usize::MAX));
-        let block_index = Arc::new(BlockIndex::from_file(
-            metadata.id.clone(),
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
-            &folder,
-            Arc::clone(&block_cache),
-        )?);
+        let block_index =
+            Arc::clone(&Segment::load(metadata.clone(), Arc::clone(&block_cache))?.block_index);
 
         let iter = Reader::new(
-            Arc::new(FileDescriptorTable::new(folder.join(BLOCKS_FILE))?),
+            Arc::new(FileDescriptorTable::new(&path)?),
             metadata.id,
             Arc::clone(&block_cache),
             Arc::clone(&block_index),
+            CompressionType::Lz4,
+            Version::V4,
+            None,
             None,
             None,
         );
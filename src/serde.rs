@@ -40,3 +40,44 @@ pub trait Deserializable {
     where
         Self: Sized;
 }
+
+/// Maximum amount of bytes that are allocated up front when reading length-prefixed data
+const PREALLOCATION_LIMIT: u64 = 64 * 1_024;
+
+/// Reads exactly `len` bytes of length-prefixed data
+///
+/// The buffer only grows as bytes are actually read, so a corrupted
+/// length can not cause a huge allocation.
+pub fn read_exact_len<R: Read>(reader: &mut R, len: u64) -> std::io::Result<Vec<u8>> {
+    // NOTE: The preallocation is limited to 64 KiB, so it fits into usize
+    #[allow(clippy::cast_possible_truncation)]
+    let mut bytes = Vec::with_capacity(len.min(PREALLOCATION_LIMIT) as usize);
+
+    reader.take(len).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 != len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "length-prefixed data is truncated",
+        ));
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn read_exact_len_truncated() -> std::io::Result<()> {
+        assert_eq!(b"abc", &*read_exact_len(&mut &b"abcd"[..], 3)?);
+        assert!(read_exact_len(&mut &b"abc"[..], 4).is_err());
+
+        // NOTE: A corrupted length does not allocate 2^64 bytes
+        assert!(read_exact_len(&mut &b"abc"[..], u64::MAX).is_err());
+
+        Ok(())
+    }
+}
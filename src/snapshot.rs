@@ -8,23 +8,34 @@ use std::ops::RangeBounds;
 
 /// A snapshot captures a read-only point-in-time view of the tree at the time the snapshot was created.
 ///
-/// As long as the snapshot is open, old versions of objects will not be evicted as to
+/// As long as the snapshot is open, the versions of objects it can see will not be evicted as to
 /// keep the snapshot consistent. Thus, snapshots should only be kept around for as little as possible.
 ///
-/// Snapshots do not persist across restarts.
+/// Snapshots do not persist across restarts, unless they are created
+/// using [`Tree::create_persistent_snapshot`].
 #[derive(Clone)]
 pub struct Snapshot {
     tree: Tree,
-    seqno: SeqNo,
+    pub(crate) seqno: SeqNo,
 }
 
 impl Snapshot {
     /// Creates a snapshot
     pub(crate) fn new(tree: Tree) -> Self {
-        tree.open_snapshots
-            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+        // NOTE: The versions need to be pinned before they can be evicted
+        let seqno = tree.open_snapshots.open_current(&tree.next_lsn);
 
-        let seqno = tree.next_lsn.load(std::sync::atomic::Ordering::Acquire);
+        log::debug!("Opening snapshot with seqno: {seqno}");
+
+        Self { tree, seqno }
+    }
+
+    /// Creates a snapshot with a given seqno
+    ///
+    /// The versions the snapshot can see need to be pinned already,
+    /// e.g. by a persistent snapshot.
+    pub(crate) fn with_seqno(tree: Tree, seqno: SeqNo) -> Self {
+        tree.open_snapshots.open(seqno);
 
         log::debug!("Opening snapshot with seqno: {seqno}");
 
@@ -314,9 +325,7 @@ impl Snapshot {
 
 impl Drop for Snapshot {
     fn drop(&mut self) {
-        log::debug!("Closing snapshot");
-        self.tree
-            .open_snapshots
-            .fetch_sub(1, std::sync::atomic::Ordering::AcqRel);
+        log::debug!("Closing snapshot with seqno: {}", self.seqno);
+        self.tree.open_snapshots.close(self.seqno);
     }
 }
@@ -0,0 +1,88 @@
+use crate::value::SeqNo;
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::AtomicU64, Mutex},
+};
+
+/// Keeps track of the seqnos of all open snapshots
+///
+/// Compactions use the seqnos to only keep the versions that are visible to some snapshot.
+#[derive(Default)]
+pub struct SnapshotTracker {
+    /// Seqno -> amount of open snapshots with that seqno
+    seqnos: Mutex<BTreeMap<SeqNo, usize>>,
+}
+
+// NOTE: Only counters are updated while the seqnos are locked, which cannot panic
+#[allow(clippy::expect_used)]
+impl SnapshotTracker {
+    /// Registers a snapshot
+    pub fn open(&self, seqno: SeqNo) {
+        let mut seqnos = self.seqnos.lock().expect("lock is poisoned");
+        *seqnos.entry(seqno).or_default() += 1;
+    }
+
+    /// Registers a snapshot at the current seqno, and returns the seqno
+    ///
+    /// The seqno is read while holding the lock, so a compaction can not
+    /// read the open snapshots between reading the seqno and registering it.
+    pub fn open_current(&self, next_lsn: &AtomicU64) -> SeqNo {
+        let mut seqnos = self.seqnos.lock().expect("lock is poisoned");
+        let seqno = next_lsn.load(std::sync::atomic::Ordering::Acquire);
+        *seqnos.entry(seqno).or_default() += 1;
+        seqno
+    }
+
+    /// Unregisters a snapshot
+    pub fn close(&self, seqno: SeqNo) {
+        let mut seqnos = self.seqnos.lock().expect("lock is poisoned");
+
+        if let Some(count) = seqnos.get_mut(&seqno) {
+            *count -= 1;
+
+            if *count == 0 {
+                seqnos.remove(&seqno);
+            }
+        }
+    }
+
+    /// Returns the distinct seqnos of all open snapshots, in ascending order
+    pub fn seqnos(&self) -> Vec<SeqNo> {
+        self.seqnos
+            .lock()
+            .expect("lock is poisoned")
+            .keys()
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn snapshot_tracker() {
+        let tracker = SnapshotTracker::default();
+        assert!(tracker.seqnos().is_empty());
+
+        tracker.open(5);
+        tracker.open(3);
+        tracker.open(5);
+        assert_eq!(vec![3, 5], tracker.seqnos());
+
+        tracker.close(3);
+        assert_eq!(vec![5], tracker.seqnos());
+
+        tracker.close(5);
+        assert_eq!(vec![5], tracker.seqnos());
+
+        tracker.close(5);
+        assert!(tracker.seqnos().is_empty());
+
+        let next_lsn = AtomicU64::new(7);
+        assert_eq!(7, tracker.open_current(&next_lsn));
+        assert_eq!(vec![7], tracker.seqnos());
+    }
+}
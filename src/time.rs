@@ -7,3 +7,21 @@ pub fn unix_timestamp() -> std::time::Duration {
     now.duration_since(std::time::SystemTime::UNIX_EPOCH)
         .unwrap()
 }
+
+/// Gets the unix timestamp in milliseconds, which is used for item expiry
+pub fn unix_timestamp_millis() -> u64 {
+    // NOTE: Truncation is okay, u64 millis last for millions of years
+    #[allow(clippy::cast_possible_truncation)]
+    let millis = unix_timestamp().as_millis() as u64;
+
+    millis
+}
+
+/// Converts a time-to-live into an expiry timestamp (in ms)
+pub fn expiry_from_ttl(ttl: std::time::Duration) -> u64 {
+    // NOTE: Truncation is okay, see above
+    #[allow(clippy::cast_possible_truncation)]
+    let ttl = ttl.as_millis() as u64;
+
+    unix_timestamp_millis().saturating_add(ttl)
+}
@@ -0,0 +1,197 @@
+use crate::value::UserKey;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+pub type TransactionId = u64;
+
+#[derive(Default)]
+struct LockTable {
+    /// Transaction that holds the lock of each locked key
+    owners: HashMap<UserKey, TransactionId>,
+
+    /// Wait-for graph: waiting transaction -> transaction that holds the lock it waits for
+    ///
+    /// Every transaction waits for at most one lock at a time
+    waiting_for: HashMap<TransactionId, TransactionId>,
+}
+
+impl LockTable {
+    /// Returns `true` if `tx` waiting for `owner` would close a cycle in the wait-for graph
+    fn would_deadlock(&self, tx: TransactionId, owner: TransactionId) -> bool {
+        let mut current = owner;
+
+        // NOTE: Edges are only added if they do not close a cycle,
+        // so following the chain always terminates
+        loop {
+            if current == tx {
+                return true;
+            }
+
+            match self.waiting_for.get(&current) {
+                Some(next) => current = *next,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Exclusive per-key locks of pessimistic transactions
+///
+/// Deadlocks are detected using a wait-for graph: a transaction that would
+/// wait for a lock held by a transaction that (transitively) waits for it fails immediately.
+#[derive(Default)]
+pub struct LockManager {
+    next_id: AtomicU64,
+    table: Mutex<LockTable>,
+    released: Condvar,
+}
+
+// NOTE: No user code runs while the lock table is locked,
+// so it is never poisoned
+#[allow(clippy::expect_used)]
+impl LockManager {
+    /// Returns a new, unique transaction ID
+    pub fn next_transaction_id(&self) -> TransactionId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Acquires the lock of a key, waiting at most for the given timeout
+    ///
+    /// Returns `true` if the lock was newly acquired, and `false` if the transaction already held it.
+    pub fn lock(&self, tx: TransactionId, key: &[u8], timeout: Duration) -> crate::Result<bool> {
+        let deadline = Instant::now() + timeout;
+
+        let mut table = self.table.lock().expect("lock is poisoned");
+
+        loop {
+            let Some(owner) = table.owners.get(key).copied() else {
+                table.owners.insert(key.into(), tx);
+                table.waiting_for.remove(&tx);
+                return Ok(true);
+            };
+
+            if owner == tx {
+                table.waiting_for.remove(&tx);
+                return Ok(false);
+            }
+
+            if table.would_deadlock(tx, owner) {
+                log::debug!("Transaction {tx} would deadlock waiting for transaction {owner}");
+                table.waiting_for.remove(&tx);
+                return Err(crate::Error::Deadlock);
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                log::debug!("Transaction {tx} timed out waiting for transaction {owner}");
+                table.waiting_for.remove(&tx);
+                return Err(crate::Error::LockTimeout);
+            }
+
+            table.waiting_for.insert(tx, owner);
+
+            table = self
+                .released
+                .wait_timeout(table, deadline - now)
+                .expect("lock is poisoned")
+                .0;
+        }
+    }
+
+    /// Releases the given locks of a transaction, and wakes up waiting transactions
+    pub fn unlock_all<'a, I: IntoIterator<Item = &'a UserKey>>(&self, tx: TransactionId, keys: I) {
+        let mut table = self.table.lock().expect("lock is poisoned");
+
+        for key in keys {
+            if table.owners.get(key) == Some(&tx) {
+                table.owners.remove(key);
+            }
+        }
+
+        // NOTE: Waiting transactions will re-evaluate who they wait for when they wake up
+        table.waiting_for.remove(&tx);
+        table.waiting_for.retain(|_, owner| *owner != tx);
+
+        drop(table);
+
+        self.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use test_log::test;
+
+    #[test]
+    fn lock_reentrant() -> crate::Result<()> {
+        let locks = LockManager::default();
+        let tx = locks.next_transaction_id();
+
+        assert!(locks.lock(tx, b"a", Duration::ZERO)?);
+        assert!(!locks.lock(tx, b"a", Duration::ZERO)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lock_timeout() -> crate::Result<()> {
+        let locks = LockManager::default();
+        let tx1 = locks.next_transaction_id();
+        let tx2 = locks.next_transaction_id();
+
+        assert!(locks.lock(tx1, b"a", Duration::ZERO)?);
+        assert!(matches!(
+            locks.lock(tx2, b"a", Duration::from_millis(10)),
+            Err(crate::Error::LockTimeout)
+        ));
+
+        locks.unlock_all(tx1, [&UserKey::from(*b"a")]);
+        assert!(locks.lock(tx2, b"a", Duration::ZERO)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lock_deadlock() -> crate::Result<()> {
+        let locks = Arc::new(LockManager::default());
+        let tx1 = locks.next_transaction_id();
+        let tx2 = locks.next_transaction_id();
+
+        assert!(locks.lock(tx1, b"a", Duration::ZERO)?);
+        assert!(locks.lock(tx2, b"b", Duration::ZERO)?);
+
+        let waiter = {
+            let locks = locks.clone();
+            std::thread::spawn(move || locks.lock(tx1, b"b", Duration::from_secs(10)))
+        };
+
+        while !locks
+            .table
+            .lock()
+            .expect("lock is poisoned")
+            .waiting_for
+            .contains_key(&tx1)
+        {
+            std::thread::yield_now();
+        }
+
+        assert!(matches!(
+            locks.lock(tx2, b"a", Duration::from_secs(10)),
+            Err(crate::Error::Deadlock)
+        ));
+
+        locks.unlock_all(tx2, [&UserKey::from(*b"b")]);
+        assert!(waiter.join().expect("should join")?);
+
+        Ok(())
+    }
+}
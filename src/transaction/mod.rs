@@ -0,0 +1,6 @@
+pub mod lock;
+mod optimistic;
+mod pessimistic;
+
+pub use optimistic::Transaction;
+pub use pessimistic::PessimisticTransaction;
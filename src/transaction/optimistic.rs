@@ -0,0 +1,191 @@
+use crate::{
+    value::{SeqNo, UserData, UserKey},
+    Batch, Snapshot, Tree,
+};
+use std::collections::{BTreeMap, HashSet};
+
+/// An optimistic transaction
+///
+/// Reads see the tree as of the time the transaction was started (plus the transaction's own writes),
+/// writes are buffered until the transaction is committed.
+///
+/// On commit, the transaction fails with [`Error::TransactionConflict`](crate::Error::TransactionConflict)
+/// if any key it has read was written by someone else in the meantime. In that case, nothing is written,
+/// and the transaction can be retried.
+///
+/// Only point reads ([`Transaction::get`], [`Transaction::contains_key`]) are tracked for conflicts.
+///
+/// Dropping the transaction will not commit items to the tree.
+pub struct Transaction {
+    tree: Tree,
+    snapshot: Snapshot,
+
+    /// Buffered writes (None = tombstone), the last write of a key wins
+    writes: BTreeMap<UserKey, Option<UserData>>,
+
+    /// Keys that were read from the tree
+    read_set: HashSet<UserKey>,
+}
+
+impl Transaction {
+    /// Starts a transaction
+    /// This function is called by [`Tree::transaction`]
+    pub(crate) fn new(tree: Tree) -> Self {
+        Self {
+            snapshot: tree.snapshot(),
+            tree,
+            writes: BTreeMap::new(),
+            read_set: HashSet::new(),
+        }
+    }
+
+    /// Retrieves an item from the transaction's view of the tree.
+    ///
+    /// The key is added to the read set, so the commit fails if
+    /// the key is written by someone else before the transaction is committed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// tree.insert("a", "my_value")?;
+    ///
+    /// let mut tx = tree.transaction();
+    /// tx.insert("b", "my_value2");
+    ///
+    /// assert_eq!(Some("my_value".as_bytes().into()), tx.get("a")?);
+    /// assert_eq!(Some("my_value2".as_bytes().into()), tx.get("b")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn get<K: AsRef<[u8]>>(&mut self, key: K) -> crate::Result<Option<UserData>> {
+        let key = key.as_ref();
+
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+
+        self.read_set.insert(key.into());
+        self.snapshot.get(key)
+    }
+
+    /// Returns `true` if the transaction's view of the tree contains the specified key.
+    ///
+    /// The key is added to the read set, see [`Transaction::get`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn contains_key<K: AsRef<[u8]>>(&mut self, key: K) -> crate::Result<bool> {
+        self.get(key).map(|x| x.is_some())
+    }
+
+    /// Inserts a key-value pair into the transaction.
+    pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) {
+        self.writes
+            .insert(key.as_ref().into(), Some(value.as_ref().into()));
+    }
+
+    /// Adds a tombstone marker for a key into the transaction.
+    pub fn remove<K: AsRef<[u8]>>(&mut self, key: K) {
+        self.writes.insert(key.as_ref().into(), None);
+    }
+
+    /// Commits the transaction to the LSM-tree atomically.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Error, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// tree.insert("balance", "10")?;
+    ///
+    /// let mut tx = tree.transaction();
+    /// tx.get("balance")?;
+    /// tx.insert("balance", "5");
+    ///
+    /// // Someone else writes the key in the meantime
+    /// tree.insert("balance", "20")?;
+    ///
+    /// assert!(matches!(tx.commit(), Err(Error::TransactionConflict)));
+    /// assert_eq!(Some("20".as_bytes().into()), tree.get("balance")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or [`Error::TransactionConflict`](crate::Error::TransactionConflict)
+    /// if a key that was read by the transaction has been written since the transaction was started.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the journal lock is poisoned.
+    // NOTE: The journal always has shards, and all shards need to stay locked
+    // until the batch is written, so no write can happen after checking for conflicts
+    #[allow(clippy::expect_used, clippy::significant_drop_tightening)]
+    pub fn commit(self) -> crate::Result<()> {
+        let tree = self.tree;
+        tree.check_writable()?;
+
+        let mut batch = Batch::new(tree.clone());
+
+        for (key, value) in self.writes {
+            match value {
+                Some(value) => batch.insert(key, value),
+                None => batch.remove(key),
+            }
+        }
+
+        // NOTE: Lock all shards, so no other write can happen
+        // between checking for conflicts and writing the batch (see compare_and_swap)
+        let mut journal_lock = tree.journal.shards.full_lock().expect("lock is poisoned");
+
+        for key in &self.read_set {
+            if has_newer_version(&tree, key, self.snapshot.seqno)? {
+                log::debug!("Transaction conflict on key {key:?}");
+                return Err(crate::Error::TransactionConflict);
+            }
+        }
+
+        let shard = journal_lock
+            .first_mut()
+            .expect("journal should have shards");
+        let memtable_size = batch.write_to_shard(shard)?;
+        drop(journal_lock);
+
+        if memtable_size > tree.config.max_memtable_size {
+            log::debug!("Memtable reached threshold size");
+            crate::flush::start(&tree)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `true` if the key has been written (or deleted) at or after the given seqno
+fn has_newer_version(tree: &Tree, key: &[u8], seqno: SeqNo) -> crate::Result<bool> {
+    if let Some(item) = tree.find_internal_entry(key, None)? {
+        if item.seqno >= seqno {
+            return Ok(true);
+        }
+    }
+
+    // NOTE: If the seqno is 0, the tree was empty when the key was read,
+    // so a range tombstone cannot have changed the result
+    let Some(read_seqno) = seqno.checked_sub(1) else {
+        return Ok(false);
+    };
+
+    // NOTE: A range tombstone that is newer than the read deletes the key
+    Ok(tree.is_covered_by_range_tombstone(key, read_seqno, None))
+}
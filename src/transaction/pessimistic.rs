@@ -0,0 +1,185 @@
+use super::lock::TransactionId;
+use crate::{
+    value::{UserData, UserKey},
+    Batch, Tree,
+};
+use std::collections::{BTreeMap, HashSet};
+
+/// A pessimistic (lock-based) transaction
+///
+/// Keys are locked exclusively when they are read using [`PessimisticTransaction::get_for_update`],
+/// or written. Other pessimistic transactions that want to lock the same key wait until the
+/// transaction is committed or rolled back, so commits never fail because of conflicts.
+///
+/// Waiting for a lock fails with [`Error::LockTimeout`](crate::Error::LockTimeout) after
+/// the configured [`Config::lock_timeout`](crate::Config::lock_timeout), and with
+/// [`Error::Deadlock`](crate::Error::Deadlock) if waiting would result in a deadlock.
+/// In both cases, the transaction should be rolled back.
+///
+/// Locks are only respected by pessimistic transactions - other writes to the tree are not blocked.
+///
+/// Dropping the transaction will not commit items to the tree, and releases all its locks.
+pub struct PessimisticTransaction {
+    tree: Tree,
+    id: TransactionId,
+
+    /// Buffered writes (None = tombstone), the last write of a key wins
+    writes: BTreeMap<UserKey, Option<UserData>>,
+
+    /// Keys locked by the transaction
+    locked_keys: HashSet<UserKey>,
+}
+
+impl PessimisticTransaction {
+    /// Starts a pessimistic transaction
+    /// This function is called by [`Tree::pessimistic_transaction`]
+    pub(crate) fn new(tree: Tree) -> Self {
+        Self {
+            id: tree.lock_manager.next_transaction_id(),
+            tree,
+            writes: BTreeMap::new(),
+            locked_keys: HashSet::new(),
+        }
+    }
+
+    fn lock(&mut self, key: &[u8]) -> crate::Result<()> {
+        if self.locked_keys.contains(key) {
+            return Ok(());
+        }
+
+        self.tree
+            .lock_manager
+            .lock(self.id, key, self.tree.config.lock_timeout)?;
+
+        self.locked_keys.insert(key.into());
+
+        Ok(())
+    }
+
+    /// Retrieves the latest version of an item, without locking it.
+    ///
+    /// Writes of the transaction itself are visible.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> crate::Result<Option<UserData>> {
+        let key = key.as_ref();
+
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+
+        self.tree.get(key)
+    }
+
+    /// Locks a key, and retrieves the latest version of the item.
+    ///
+    /// Because the key stays locked until the transaction is finished,
+    /// no other pessimistic transaction can change it in the meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// tree.insert("stock", 10u64.to_be_bytes())?;
+    ///
+    /// let mut tx = tree.pessimistic_transaction();
+    ///
+    /// let stock = tx.get_for_update("stock")?.expect("should exist");
+    /// let stock = u64::from_be_bytes((*stock).try_into().expect("should be u64"));
+    ///
+    /// tx.insert("stock", (stock - 1).to_be_bytes())?;
+    /// tx.commit()?;
+    ///
+    /// assert_eq!(Some(9u64.to_be_bytes().into()), tree.get("stock")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the lock could not be acquired.
+    pub fn get_for_update<K: AsRef<[u8]>>(&mut self, key: K) -> crate::Result<Option<UserData>> {
+        let key = key.as_ref();
+        self.lock(key)?;
+        self.get(key)
+    }
+
+    /// Locks a key, and inserts a key-value pair into the transaction.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the lock could not be acquired.
+    pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> crate::Result<()> {
+        let key = key.as_ref();
+        self.lock(key)?;
+        self.writes.insert(key.into(), Some(value.as_ref().into()));
+        Ok(())
+    }
+
+    /// Locks a key, and adds a tombstone marker for the key into the transaction.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the lock could not be acquired.
+    pub fn remove<K: AsRef<[u8]>>(&mut self, key: K) -> crate::Result<()> {
+        let key = key.as_ref();
+        self.lock(key)?;
+        self.writes.insert(key.into(), None);
+        Ok(())
+    }
+
+    /// Commits the transaction to the LSM-tree atomically, and releases its locks.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn commit(mut self) -> crate::Result<()> {
+        self.tree.check_writable()?;
+
+        let mut batch = Batch::new(self.tree.clone());
+
+        for (key, value) in std::mem::take(&mut self.writes) {
+            match value {
+                Some(value) => batch.insert(key, value),
+                None => batch.remove(key),
+            }
+        }
+
+        let mut shard = self.tree.journal.lock_shard();
+        let memtable_size = batch.write_to_shard(&mut shard)?;
+        drop(shard);
+
+        // NOTE: Release the locks before flushing, so other transactions can continue
+        let tree = self.tree.clone();
+        drop(self);
+
+        if memtable_size > tree.config.max_memtable_size {
+            log::debug!("Memtable reached threshold size");
+            crate::flush::start(&tree)?;
+        }
+
+        Ok(())
+    }
+
+    /// Discards the writes of the transaction, and releases its locks.
+    pub fn rollback(self) {
+        drop(self);
+    }
+}
+
+impl Drop for PessimisticTransaction {
+    fn drop(&mut self) {
+        self.tree
+            .lock_manager
+            .unlock_all(self.id, &self.locked_keys);
+    }
+}
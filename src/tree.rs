@@ -1,20 +1,34 @@
 use crate::{
+    blob::BlobStore,
+    bloom::BloomFilter,
+    change_feed::{ChangeBatch, Subscription},
     compaction::CompactionStrategy,
-    file::{JOURNALS_FOLDER, LEVELS_MANIFEST_FILE, LSM_MARKER, SEGMENTS_FOLDER},
+    file::{
+        rename_file_or_folder, rewrite_atomic, BLOBS_FOLDER, FOLLOWER_MARKER, JOURNALS_FOLDER,
+        LSM_MARKER, SEGMENTS_FOLDER, SNAPSHOTS_FILE,
+    },
     id::generate_segment_id,
     journal::{shard::JournalShard, Journal},
     levels::Levels,
     memtable::MemTable,
+    merge_operator::MergeOperator,
+    persistent_snapshot::PersistentSnapshots,
     prefix::Prefix,
     range::{MemTableGuard, Range},
+    range_tombstone::{prefix_bounds, RangeTombstone},
+    segment::builder::SegmentBuilder,
+    time::{expiry_from_ttl, unix_timestamp_millis},
+    transaction::lock::LockManager,
     tree_inner::TreeInner,
     value::{SeqNo, UserData, UserKey, ValueType},
     version::Version,
-    Batch, Config, Snapshot, Value,
+    Batch, Config, PessimisticTransaction, Snapshot, Transaction, Value,
 };
 use std::{
     ops::RangeBounds,
-    sync::{Arc, RwLock, RwLockWriteGuard},
+    path::Path,
+    sync::{Arc, Mutex, RwLock, RwLockWriteGuard},
+    time::Duration,
 };
 use std_semaphore::Semaphore;
 
@@ -189,6 +203,120 @@ impl Tree {
         Snapshot::new(self.clone())
     }
 
+    /// Creates a named snapshot that survives restarts.
+    ///
+    /// Like a normal snapshot, a persistent snapshot prevents old versions of objects from being
+    /// evicted, until it is deleted using [`Tree::delete_persistent_snapshot`].
+    ///
+    /// If a persistent snapshot with the given name already exists, it is returned unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// {
+    ///     let tree = Config::new(&folder).open()?;
+    ///     tree.insert("a", "abc")?;
+    ///
+    ///     tree.create_persistent_snapshot("export")?;
+    ///
+    ///     tree.insert("b", "abc")?;
+    /// }
+    ///
+    /// let tree = Config::new(&folder).open()?;
+    ///
+    /// let snapshot = tree.persistent_snapshot("export").expect("should exist");
+    /// assert_eq!(1, snapshot.len()?);
+    /// assert_eq!(2, tree.len()?);
+    ///
+    /// tree.delete_persistent_snapshot("export")?;
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    // NOTE: The snapshot list stays locked until it is persisted,
+    // so concurrent changes cannot be written out of order
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn create_persistent_snapshot(&self, name: &str) -> crate::Result<Snapshot> {
+        self.check_not_read_only()?;
+
+        let mut snapshots = self.persistent_snapshots.write();
+
+        if let Some(seqno) = snapshots.get(name) {
+            return Ok(Snapshot::with_seqno(self.clone(), *seqno));
+        }
+
+        // NOTE: Make sure all items the snapshot can see are durable
+        self.journal.flush()?;
+
+        // NOTE: Pin the versions before persisting the snapshot
+        let seqno = self.open_snapshots.open_current(&self.next_lsn);
+        snapshots.insert(name.into(), seqno);
+
+        if let Err(e) = self.persistent_snapshots.write_to_disk(&snapshots) {
+            snapshots.remove(name);
+            self.open_snapshots.close(seqno);
+            return Err(e);
+        }
+
+        log::debug!("Created persistent snapshot {name:?} with seqno: {seqno}");
+
+        Ok(Snapshot::with_seqno(self.clone(), seqno))
+    }
+
+    /// Opens a persistent snapshot that was created using [`Tree::create_persistent_snapshot`].
+    ///
+    /// Returns `None` if the snapshot does not exist.
+    #[must_use]
+    pub fn persistent_snapshot(&self, name: &str) -> Option<Snapshot> {
+        self.persistent_snapshots
+            .get(name)
+            .map(|seqno| Snapshot::with_seqno(self.clone(), seqno))
+    }
+
+    /// Returns the names of all persistent snapshots.
+    #[must_use]
+    pub fn persistent_snapshots(&self) -> Vec<String> {
+        self.persistent_snapshots.names()
+    }
+
+    /// Deletes a persistent snapshot, so the versions only it can see can be evicted.
+    ///
+    /// Snapshots that were opened from it stay readable until they are dropped.
+    ///
+    /// Returns `true` if the snapshot existed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    // NOTE: See `Tree::create_persistent_snapshot`
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn delete_persistent_snapshot(&self, name: &str) -> crate::Result<bool> {
+        self.check_not_read_only()?;
+
+        let mut snapshots = self.persistent_snapshots.write();
+
+        let Some(seqno) = snapshots.remove(name) else {
+            return Ok(false);
+        };
+
+        if let Err(e) = self.persistent_snapshots.write_to_disk(&snapshots) {
+            snapshots.insert(name.into(), seqno);
+            return Err(e);
+        }
+
+        self.open_snapshots.close(seqno);
+
+        log::debug!("Deleted persistent snapshot {name:?}");
+
+        Ok(true)
+    }
+
     /// Initializes a new, atomic write batch.
     ///
     /// Call [`Batch::commit`] to commit the batch to the tree.
@@ -220,6 +348,465 @@ impl Tree {
         Batch::new(self.clone())
     }
 
+    /// Starts an optimistic transaction.
+    ///
+    /// The transaction reads a consistent view of the tree and buffers its writes.
+    /// Call [`Transaction::commit`] to commit the transaction to the tree, which fails
+    /// if any key the transaction has read was written in the meantime.
+    ///
+    /// Dropping the transaction will not commit items to the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// tree.insert("a", 10u64.to_be_bytes())?;
+    ///
+    /// let mut tx = tree.transaction();
+    ///
+    /// let balance = tx.get("a")?.expect("should exist");
+    /// let balance = u64::from_be_bytes((*balance).try_into().expect("should be u64"));
+    ///
+    /// tx.insert("a", (balance - 5).to_be_bytes());
+    /// tx.insert("b", 5u64.to_be_bytes());
+    ///
+    /// tx.commit()?;
+    ///
+    /// assert_eq!(2, tree.len()?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    #[must_use]
+    pub fn transaction(&self) -> Transaction {
+        Transaction::new(self.clone())
+    }
+
+    /// Starts a pessimistic transaction.
+    ///
+    /// The transaction locks every key it writes or reads using [`PessimisticTransaction::get_for_update`],
+    /// so it never has to be retried because of conflicting writes of other pessimistic transactions.
+    /// Call [`PessimisticTransaction::commit`] to commit the transaction to the tree.
+    ///
+    /// Dropping the transaction will not commit items to the tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    ///
+    /// let mut tx = tree.pessimistic_transaction();
+    /// assert!(tx.get_for_update("a")?.is_none());
+    ///
+    /// tx.insert("a", "abc")?;
+    /// tx.commit()?;
+    ///
+    /// assert!(tree.contains_key("a")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    #[must_use]
+    pub fn pessimistic_transaction(&self) -> PessimisticTransaction {
+        PessimisticTransaction::new(self.clone())
+    }
+
+    /// Creates a consistent copy of the tree in the given folder, while the tree stays usable.
+    ///
+    /// Segments and blob files are hard linked, so the checkpoint takes almost no
+    /// additional disk space (as long as the tree does not delete them).
+    /// If the folder is on another file system, they are copied instead.
+    /// The journals are copied.
+    ///
+    /// The checkpoint can be opened as a tree using [`Config::new`], and contains all
+    /// items that were written before the checkpoint was created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder.path().join("tree")).open()?;
+    /// tree.insert("a", "abc")?;
+    ///
+    /// tree.checkpoint(folder.path().join("checkpoint"))?;
+    /// tree.insert("b", "abc")?;
+    ///
+    /// let checkpoint = Config::new(folder.path().join("checkpoint")).open()?;
+    /// assert_eq!(1, checkpoint.len()?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the folder already exists.
+    pub fn checkpoint<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        crate::checkpoint::create(self, path.as_ref())?;
+        Ok(())
+    }
+
+    /// Atomically adds segments that were built using [`crate::SegmentBuilder`] to the tree,
+    /// without going through the journal or memtable.
+    ///
+    /// The segment files are hard linked (or copied, if that is not possible) into the tree,
+    /// and the segments are inserted into the deepest level they do not overlap with.
+    /// All ingested items get a new seqno, so they shadow all items that were written before.
+    ///
+    /// The ingested segments may not overlap each other.
+    /// Items that are written concurrently to the ingestion may or may not be shadowed
+    /// by the ingested items.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the segments overlap.
+    pub fn ingest<P: AsRef<Path>>(&self, paths: &[P]) -> crate::Result<()> {
+        self.check_writable()?;
+        crate::ingest::ingest(self, paths)
+    }
+
+    /// Writes all items in the given key range into a new segment file at the given path,
+    /// which can then be ingested into another tree using [`Tree::ingest`].
+    ///
+    /// The items are read from a snapshot, so the export is consistent,
+    /// while the tree stays usable. Deleted and expired items are not exported,
+    /// all other items keep their expiry (see [`Tree::insert_with_ttl`]).
+    ///
+    /// Returns the amount of exported items. If the range is empty, no segment is created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder.path().join("a")).open()?;
+    /// tree.insert("tenant1:a", "abc")?;
+    /// tree.insert("tenant2:a", "abc")?;
+    ///
+    /// let count = tree.export_range("tenant1:".."tenant2:", folder.path().join("export"))?;
+    /// assert_eq!(1, count);
+    ///
+    /// let other = Config::new(folder.path().join("b")).open()?;
+    /// other.ingest(&[folder.path().join("export")])?;
+    /// assert!(other.contains_key("tenant1:a")?);
+    /// assert!(!other.contains_key("tenant2:a")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the file already exists.
+    pub fn export_range<K: AsRef<[u8]>, R: RangeBounds<K>, P: AsRef<Path>>(
+        &self,
+        range: R,
+        path: P,
+    ) -> crate::Result<usize> {
+        let path = path.as_ref();
+
+        log::info!("Exporting range into {}", path.display());
+
+        if path.try_exists()? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "segment file already exists",
+            )
+            .into());
+        }
+
+        // NOTE: Build the segment in a temporary folder first, so a partially written
+        // segment is never mistaken for an exported one
+        let folder = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(folder)?;
+
+        let temp_folder = tempfile::tempdir_in(folder)?;
+        let temp_path = temp_folder.path().join("segment");
+
+        let snapshot = self.snapshot();
+        let mut builder = SegmentBuilder::new(&temp_path, &self.config)?;
+        let mut count = 0;
+
+        // NOTE: Use the items instead of key-value pairs, so their TTL is exported as well
+        for item in (&snapshot.range(range)).into_iter().into_values() {
+            let item = item?;
+            builder.write(Value { seqno: 0, ..item })?;
+            count += 1;
+        }
+
+        if count == 0 {
+            log::debug!("Exported range is empty");
+            return Ok(0);
+        }
+
+        builder.finish()?;
+        rename_file_or_folder(&temp_path, path)?;
+
+        log::info!("Exported {count} items into {}", path.display());
+
+        Ok(count)
+    }
+
+    /// Subscribes to all writes that are committed after subscribing.
+    ///
+    /// Every insert, remove and [`Batch`] is delivered as a [`ChangeBatch`](crate::ChangeBatch)
+    /// with its seqno, in commit order. A batch may be delivered slightly before it becomes
+    /// visible to reads. The subscription ends when the tree is dropped.
+    ///
+    /// While there are subscribers, writes do not use multiple journal shards concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// let mut subscription = tree.subscribe()?;
+    ///
+    /// tree.insert("a", "abc")?;
+    /// tree.remove("a")?;
+    ///
+    /// let batch = subscription.next().expect("should receive batch");
+    /// assert_eq!(0, batch.seqno);
+    /// assert_eq!(b"a", &*batch.items[0].key);
+    ///
+    /// let batch = subscription.next().expect("should receive batch");
+    /// assert_eq!(1, batch.seqno);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn subscribe(&self) -> crate::Result<Subscription> {
+        crate::change_feed::subscribe(self, None)
+    }
+
+    /// Subscribes to all writes with a seqno `>= seqno`, e.g. to resume a
+    /// subscription after a disconnect.
+    ///
+    /// The already committed writes are read from the journals first, followed by
+    /// all writes that are committed after subscribing (see [`Tree::subscribe`]).
+    /// Writes are only kept in journals until their memtable is flushed,
+    /// unless journals are retained using [`Config::max_retained_journals`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the journals containing the
+    /// requested writes are not retained anymore.
+    pub fn subscribe_from(&self, seqno: SeqNo) -> crate::Result<Subscription> {
+        crate::change_feed::subscribe(self, Some(seqno))
+    }
+
+    /// Applies a batch of writes of a leader tree, keeping its seqno.
+    ///
+    /// This is used to replicate a leader into a follower (see [`Config::open_follower`]).
+    /// Batches need to be applied in commit order. Batches with a seqno lower than
+    /// [`Tree::next_seqno`] have already been applied and are skipped, so a subscription
+    /// may overlap with the checkpoint the follower was created from.
+    ///
+    /// Returns `false` if the batch was skipped.
+    ///
+    /// Segments that are ingested into the leader (see [`Tree::ingest`]) are not replicated,
+    /// so the follower cannot apply the batches after an ingestion.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let leader = Config::new(folder.path().join("leader")).open()?;
+    /// let mut subscription = leader.subscribe()?;
+    /// leader.insert("a", "abc")?;
+    ///
+    /// leader.checkpoint(folder.path().join("follower"))?;
+    /// let follower = Config::new(folder.path().join("follower")).open_follower()?;
+    /// assert!(follower.insert("b", "abc").is_err());
+    ///
+    /// leader.insert("b", "abc")?;
+    ///
+    /// // NOTE: "a" is already contained in the checkpoint
+    /// let batch = subscription.next().expect("should receive batch");
+    /// assert!(!follower.apply_batch(&batch)?);
+    ///
+    /// let batch = subscription.next().expect("should receive batch");
+    /// assert!(follower.apply_batch(&batch)?);
+    ///
+    /// assert!(follower.contains_key("b")?);
+    /// assert_eq!(leader.next_seqno(), follower.next_seqno());
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or [`Error::ReplicationGap`](crate::Error::ReplicationGap)
+    /// if the batch does not directly follow the last applied batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    // NOTE: All shards stay locked until the batch is written,
+    // and the journal is always created with at least one shard
+    #[allow(clippy::expect_used, clippy::significant_drop_tightening)]
+    pub fn apply_batch(&self, batch: &ChangeBatch) -> crate::Result<bool> {
+        self.check_not_read_only()?;
+
+        if batch.items.is_empty() {
+            return Ok(false);
+        }
+
+        // NOTE: Lock all shards, so no other write can take a seqno while applying
+        let mut journal_lock = self.journal.shards.full_lock().expect("lock is poisoned");
+
+        let next_seqno = self.next_lsn.load(std::sync::atomic::Ordering::Acquire);
+
+        if batch.seqno < next_seqno {
+            log::trace!("Skipping already applied batch with seqno {}", batch.seqno);
+            return Ok(false);
+        }
+
+        // NOTE: The batch takes the next seqno, so it only gets
+        // the seqno of the leader if no batch is missing
+        if batch.seqno > next_seqno {
+            log::error!(
+                "Cannot apply batch with seqno {}, expected seqno {next_seqno}",
+                batch.seqno
+            );
+            return Err(crate::Error::ReplicationGap);
+        }
+
+        let shard = journal_lock
+            .first_mut()
+            .expect("journal should have shards");
+        let memtable_size =
+            Batch::from_items(self.clone(), batch.items.clone()).write_to_shard(shard)?;
+        drop(journal_lock);
+
+        if memtable_size > self.config.max_memtable_size {
+            log::debug!("Memtable reached threshold size");
+            crate::flush::start(self)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Returns `true` if the tree was opened as a follower, and was not promoted yet.
+    #[must_use]
+    pub fn is_follower(&self) -> bool {
+        self.is_follower.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// Turns a follower into a normal tree that accepts writes, e.g. to fail over
+    /// to a standby.
+    ///
+    /// New writes continue with the seqnos after the last applied batch.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    pub fn promote(&self) -> crate::Result<()> {
+        log::info!("Promoting follower at {}", self.config.path.display());
+
+        let marker = self.config.path.join(FOLLOWER_MARKER);
+
+        if marker.try_exists()? {
+            std::fs::remove_file(marker)?;
+
+            #[cfg(not(target_os = "windows"))]
+            {
+                // fsync folder on Unix
+                let folder = std::fs::File::open(&self.config.path)?;
+                folder.sync_all()?;
+            }
+        }
+
+        self.is_follower
+            .store(false, std::sync::atomic::Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Returns the seqno the next write will get.
+    ///
+    /// A follower has applied all batches with lower seqnos.
+    #[must_use]
+    pub fn next_seqno(&self) -> SeqNo {
+        self.next_lsn.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// Reloads the levels manifest, new segments and blob files, and reads
+    /// the writes that were appended to the journals since the last call.
+    ///
+    /// This allows using a tree that was opened using [`Config::open_read_only`]
+    /// as a secondary instance of a tree that is written by another process,
+    /// by calling this method periodically.
+    ///
+    /// Does nothing if the tree is not read-only.
+    ///
+    /// Writes only become visible after the writer has flushed them to the journal,
+    /// see [`Config::fsync_ms`] and [`Tree::flush`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::Config;
+    ///
+    /// let tree = Config::new(&folder).open()?;
+    /// let secondary = Config::new(&folder).open_read_only()?;
+    ///
+    /// tree.insert("a", "abc")?;
+    /// tree.flush()?;
+    /// assert!(!secondary.contains_key("a")?);
+    ///
+    /// secondary.try_catch_up()?;
+    /// assert!(secondary.contains_key("a")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the writer keeps
+    /// flushing or compacting while catching up, in which case it can be retried.
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    pub fn try_catch_up(&self) -> crate::Result<()> {
+        crate::secondary::try_catch_up(self)
+    }
+
+    /// Returns an error if the tree does not accept writes
+    pub(crate) fn check_writable(&self) -> crate::Result<()> {
+        if self.is_follower() {
+            return Err(crate::Error::ReadOnly);
+        }
+
+        self.check_not_read_only()
+    }
+
+    /// Returns an error if the tree was opened read-only
+    pub(crate) fn check_not_read_only(&self) -> crate::Result<()> {
+        if self.read_only {
+            return Err(crate::Error::ReadOnly);
+        }
+
+        Ok(())
+    }
+
     /// Returns `true` if there are some segments that are being compacted.
     #[doc(hidden)]
     #[must_use]
@@ -244,7 +831,7 @@ impl Tree {
         self.levels.read().expect("lock is poisoned").len()
     }
 
-    /// Sums the disk space usage of the tree (segments + journals).
+    /// Sums the disk space usage of the tree (segments + blob files + journals).
     ///
     /// # Examples
     ///
@@ -270,7 +857,14 @@ impl Tree {
         let active_journal_size = fs_extra::dir::get_size(self.config().path.join(JOURNALS_FOLDER))
             .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "fs_extra error"))?;
 
-        Ok(segment_size + active_journal_size)
+        Ok(segment_size + self.blobs.disk_space() + active_journal_size)
+    }
+
+    /// Counts the amount of blob files currently in the tree.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn blob_file_count(&self) -> usize {
+        self.blobs.len()
     }
 
     /// Approximates the item count of the tree.
@@ -413,7 +1007,7 @@ impl Tree {
     /// - Will return `Err` if an IO error occurs
     /// - Will fail, if the folder already occupied
     fn create_new(config: Config) -> crate::Result<Self> {
-        use std::sync::atomic::{AtomicU32, AtomicU64};
+        use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64};
 
         log::info!("Creating LSM-tree at {}", config.path.display());
 
@@ -425,13 +1019,19 @@ impl Tree {
         let marker = config.path.join(LSM_MARKER);
         assert!(!marker.try_exists()?);
 
+        let is_follower = config.path.join(FOLLOWER_MARKER).try_exists()?;
+
         let first_journal_path = config
             .path
             .join(JOURNALS_FOLDER)
             .join(&*generate_segment_id());
 
-        let levels =
-            Levels::create_new(config.level_count, config.path.join(LEVELS_MANIFEST_FILE))?;
+        let levels = Levels::create_new(config.level_count, &config.path)?;
+
+        let blobs = BlobStore::create_new(config.path.join(BLOBS_FOLDER))?;
+
+        let persistent_snapshots =
+            PersistentSnapshots::create_new(config.path.join(SNAPSHOTS_FILE));
 
         let block_cache = Arc::clone(&config.block_cache);
 
@@ -440,6 +1040,7 @@ impl Tree {
 
         let inner = TreeInner {
             config,
+            blobs: Arc::new(blobs),
             journal: Arc::new(Journal::create_new(first_journal_path)?),
             active_memtable: Arc::new(RwLock::new(MemTable::default())),
             immutable_memtables: Arc::default(),
@@ -449,8 +1050,13 @@ impl Tree {
             flush_semaphore: Arc::new(Semaphore::new(flush_threads)),
             compaction_semaphore: Arc::new(Semaphore::new(compaction_threads)), // TODO: config
             approx_active_memtable_size: AtomicU32::default(),
-            open_snapshots: Arc::new(AtomicU32::new(0)),
+            open_snapshots: Arc::default(),
+            persistent_snapshots,
+            lock_manager: LockManager::default(),
             stop_signal: crate::stop_signal::StopSignal::default(),
+            is_follower: AtomicBool::new(is_follower),
+            read_only: false,
+            journal_tail: Mutex::default(),
         };
 
         #[cfg(not(target_os = "windows"))]
@@ -463,7 +1069,7 @@ impl Tree {
         // NOTE: Lastly, fsync .lsm marker, which contains the version
         // -> the LSM is fully initialized
         let mut file = std::fs::File::create(marker)?;
-        Version::V0.write_file_header(&mut file)?;
+        Version::V1.write_file_header(&mut file)?;
         file.sync_all()?;
 
         Ok(Self(Arc::new(inner)))
@@ -475,7 +1081,31 @@ impl Tree {
     ///
     /// Will return `Err` if an IO error occurs.
     fn recover(config: Config) -> crate::Result<Self> {
-        crate::recovery::recover_tree(config)
+        crate::recovery::recover_tree(config, false)
+    }
+
+    /// Opens a tree as a follower
+    ///
+    /// See [`Config::open_follower`]
+    pub(crate) fn open_follower(config: Config) -> crate::Result<Self> {
+        std::fs::create_dir_all(&config.path)?;
+
+        // NOTE: The follower marker is written before opening the tree,
+        // so the tree never accepts writes, and stays a follower after reopening
+        let marker = config.path.join(FOLLOWER_MARKER);
+        if !marker.try_exists()? {
+            rewrite_atomic(marker, &[])?;
+        }
+
+        Self::open(config)
+    }
+
+    /// Opens an existing tree without modifying its folder
+    ///
+    /// See [`Config::open_read_only`]
+    pub(crate) fn open_read_only(config: Config) -> crate::Result<Self> {
+        log::info!("Opening LSM-tree at {} (read-only)", config.path.display());
+        crate::recovery::recover_tree(config, true)
     }
 
     fn append_entry(
@@ -484,6 +1114,7 @@ impl Tree {
         value: Value,
     ) -> crate::Result<()> {
         let bytes_written_to_disk = shard.write(&value)?;
+        self.journal.changes.publish(&[&value]);
         drop(shard);
 
         let memtable_lock = self.active_memtable.read().expect("lock is poisoned");
@@ -542,6 +1173,57 @@ impl Tree {
     ///
     /// Will return `Err` if an IO error occurs.
     pub fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> crate::Result<()> {
+        self.check_writable()?;
+
+        let shard = self.journal.lock_shard();
+
+        let value = Value::new(
+            key.as_ref(),
+            value.as_ref(),
+            self.next_lsn
+                .fetch_add(1, std::sync::atomic::Ordering::AcqRel),
+            ValueType::Value,
+        )
+        .expires_at(self.default_expiry());
+
+        self.append_entry(shard, value)?;
+
+        Ok(())
+    }
+
+    /// Inserts a key-value pair into the tree, which expires after the given time-to-live.
+    ///
+    /// Expired items are not returned by reads anymore, and are
+    /// physically dropped by compactions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    /// use std::time::Duration;
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// tree.insert_with_ttl("a", "my_value", Duration::from_secs(60))?;
+    ///
+    /// assert!(tree.contains_key("a")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    // NOTE: The shard lock needs to be held while taking the seqno (see `Tree::merge`)
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn insert_with_ttl<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> crate::Result<()> {
+        self.check_writable()?;
+
         let shard = self.journal.lock_shard();
 
         let value = Value::new(
@@ -550,6 +1232,75 @@ impl Tree {
             self.next_lsn
                 .fetch_add(1, std::sync::atomic::Ordering::AcqRel),
             ValueType::Value,
+        )
+        .expires_at(Some(expiry_from_ttl(ttl)));
+
+        self.append_entry(shard, value)?;
+
+        Ok(())
+    }
+
+    /// Returns the expiry timestamp of items that are inserted without explicit TTL
+    pub(crate) fn default_expiry(&self) -> Option<u64> {
+        self.config.default_ttl.map(expiry_from_ttl)
+    }
+
+    /// Writes a merge operand for a key.
+    ///
+    /// The operand is folded together with the existing value (and other operands)
+    /// using the configured [`MergeOperator`](crate::MergeOperator) when the key is read
+    /// or compacted, so no read is needed before writing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, MergeOperator, Tree};
+    /// use std::sync::Arc;
+    ///
+    /// struct Append;
+    ///
+    /// impl MergeOperator for Append {
+    ///     fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8> {
+    ///         let mut value = existing.unwrap_or_default().to_vec();
+    ///         for operand in operands {
+    ///             value.extend_from_slice(operand);
+    ///         }
+    ///         value
+    ///     }
+    /// }
+    ///
+    /// let tree = Config::new(folder).merge_operator(Arc::new(Append)).open()?;
+    /// tree.insert("a", "abc")?;
+    /// tree.merge("a", "def")?;
+    ///
+    /// let item = tree.get("a")?.expect("should exist");
+    /// assert_eq!("abcdef".as_bytes(), &*item);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or no merge operator is configured.
+    // NOTE: The shard lock needs to be held while taking the seqno,
+    // so items are written into the journal in seqno order
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn merge<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, operand: V) -> crate::Result<()> {
+        if self.config.merge_operator.is_none() {
+            return Err(crate::Error::MergeOperatorMissing);
+        }
+
+        self.check_writable()?;
+
+        let shard = self.journal.lock_shard();
+
+        let value = Value::new(
+            key.as_ref(),
+            operand.as_ref(),
+            self.next_lsn
+                .fetch_add(1, std::sync::atomic::Ordering::AcqRel),
+            ValueType::Merge,
         );
 
         self.append_entry(shard, value)?;
@@ -583,6 +1334,8 @@ impl Tree {
     ///
     /// Will return `Err` if an IO error occurs.
     pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> crate::Result<()> {
+        self.check_writable()?;
+
         let shard = self.journal.lock_shard();
 
         let value = Value::new(
@@ -598,6 +1351,132 @@ impl Tree {
         Ok(())
     }
 
+    /// Deletes all items in a range of keys.
+    ///
+    /// The deletion is recorded as a single range tombstone, so it has O(1) complexity
+    /// regardless of the amount of items in the range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// tree.insert("a", "abc")?;
+    /// tree.insert("b", "abc")?;
+    /// tree.insert("c", "abc")?;
+    ///
+    /// tree.remove_range("a".."c")?;
+    ///
+    /// assert!(!tree.contains_key("a")?);
+    /// assert!(!tree.contains_key("b")?);
+    /// assert!(tree.contains_key("c")?);
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or a bound's key is empty or longer than 2^32 bytes.
+    pub fn remove_range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> crate::Result<()> {
+        self.check_writable()?;
+
+        let shard = self.journal.lock_shard();
+
+        let bounds = (range.start_bound(), range.end_bound());
+
+        let Some(mut tombstone) = RangeTombstone::from_bounds(bounds, 0) else {
+            // NOTE: Empty range, nothing to delete
+            // No seqno is taken, so followers do not see a gap
+            return Ok(());
+        };
+
+        Self::check_range_tombstone(&tombstone)?;
+
+        tombstone.seqno = self
+            .next_lsn
+            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+
+        self.append_range_tombstone(shard, tombstone)
+    }
+
+    /// Deletes all items whose key starts with the given prefix.
+    ///
+    /// Like [`Tree::remove_range`], the deletion is recorded as a single range tombstone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let folder = tempfile::tempdir()?;
+    /// use lsm_tree::{Config, Tree};
+    ///
+    /// let tree = Config::new(folder).open()?;
+    /// tree.insert("a", "abc")?;
+    /// tree.insert("ab", "abc")?;
+    /// tree.insert("abc", "abc")?;
+    ///
+    /// tree.remove_prefix("ab")?;
+    ///
+    /// assert!(tree.contains_key("a")?);
+    /// assert_eq!(0, tree.prefix("ab").into_iter().count());
+    /// #
+    /// # Ok::<(), lsm_tree::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs, or the prefix is longer than 2^32 bytes.
+    pub fn remove_prefix<K: AsRef<[u8]>>(&self, prefix: K) -> crate::Result<()> {
+        self.check_writable()?;
+
+        let mut tombstone = RangeTombstone::from_prefix(prefix.as_ref(), 0);
+        Self::check_range_tombstone(&tombstone)?;
+
+        let shard = self.journal.lock_shard();
+
+        tombstone.seqno = self
+            .next_lsn
+            .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+
+        self.append_range_tombstone(shard, tombstone)
+    }
+
+    /// Checks if the range tombstone's keys can be stored in a single item
+    ///
+    /// Must be called before a seqno is taken, so followers do not see a gap.
+    fn check_range_tombstone(tombstone: &RangeTombstone) -> crate::Result<()> {
+        let end_len = tombstone.end.as_ref().map_or(0, |end| end.len());
+
+        if tombstone.start.is_empty()
+            || u32::try_from(tombstone.start.len()).is_err()
+            || u32::try_from(end_len).is_err()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "range tombstone key is empty or too long",
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    fn append_range_tombstone(
+        &self,
+        shard: RwLockWriteGuard<'_, JournalShard>,
+        tombstone: RangeTombstone,
+    ) -> crate::Result<()> {
+        let value = Value::new(
+            tombstone.start,
+            tombstone.end.unwrap_or_else(|| Arc::from([])),
+            tombstone.seqno,
+            ValueType::RangeTombstone,
+        );
+
+        self.append_entry(shard, value)
+    }
+
     /// Removes the item and returns its value if it was previously in the tree.
     ///
     /// This is less efficient than just deleting because it needs to do a read before deleting.
@@ -715,6 +1594,11 @@ impl Tree {
             .cloned()
             .collect::<Vec<_>>();
 
+        let range_tombstones = lock
+            .get_overlapping_range_tombstones(&bounds)
+            .cloned()
+            .collect();
+
         Range::new(
             crate::range::MemTableGuard {
                 active: self.active_memtable.read().expect("lock is poisoned"),
@@ -722,7 +1606,10 @@ impl Tree {
             },
             bounds,
             segment_info,
+            range_tombstones,
             seqno,
+            self.config.merge_operator.clone(),
+            self.blobs.clone(),
         )
     }
 
@@ -769,6 +1656,11 @@ impl Tree {
             .cloned()
             .collect();
 
+        let range_tombstones = lock
+            .get_overlapping_range_tombstones(&prefix_bounds(&prefix))
+            .cloned()
+            .collect();
+
         Prefix::new(
             MemTableGuard {
                 active: self.active_memtable.read().expect("lock is poisoned"),
@@ -776,7 +1668,10 @@ impl Tree {
             },
             prefix,
             segment_info,
+            range_tombstones,
             seqno,
+            self.config.merge_operator.clone(),
+            self.blobs.clone(),
         )
     }
 
@@ -865,19 +1760,46 @@ impl Tree {
         self.iter().into_iter().next_back().transpose()
     }
 
-    #[doc(hidden)]
-    pub fn get_internal_entry<K: AsRef<[u8]>>(
+    /// Returns `true` if the item is deleted by any range tombstone in the tree
+    // NOTE: Locks are only poisoned if another thread panicked while holding them
+    #[allow(clippy::expect_used)]
+    pub(crate) fn is_covered_by_range_tombstone(
+        &self,
+        key: &[u8],
+        item_seqno: SeqNo,
+        seqno: Option<SeqNo>,
+    ) -> bool {
+        // NOTE: The order is important: a range tombstone moves from the active memtable
+        // to the immutable memtables, and then into a segment, so we cannot miss it
+        let memtable_lock = self.active_memtable.read().expect("lock is poisoned");
+        if memtable_lock.is_covered_by_range_tombstone(key, item_seqno, seqno) {
+            return true;
+        }
+        drop(memtable_lock);
+
+        let memtable_lock = self.immutable_memtables.read().expect("lock is poisoned");
+        if memtable_lock
+            .values()
+            .any(|x| x.is_covered_by_range_tombstone(key, item_seqno, seqno))
+        {
+            return true;
+        }
+        drop(memtable_lock);
+
+        self.levels
+            .read()
+            .expect("lock is poisoned")
+            .is_covered_by_range_tombstone(key, item_seqno, seqno)
+    }
+
+    pub(crate) fn find_internal_entry<K: AsRef<[u8]>>(
         &self,
         key: K,
-        evict_tombstone: bool,
         seqno: Option<SeqNo>,
     ) -> crate::Result<Option<Value>> {
         let memtable_lock = self.active_memtable.read().expect("lock is poisoned");
 
         if let Some(item) = memtable_lock.get(&key, seqno) {
-            if evict_tombstone {
-                return Ok(ignore_tombstone_value(item));
-            }
             return Ok(Some(item));
         };
         drop(memtable_lock);
@@ -886,9 +1808,6 @@ impl Tree {
         let memtable_lock = self.immutable_memtables.read().expect("lock is poisoned");
         for (_, memtable) in memtable_lock.iter().rev() {
             if let Some(item) = memtable.get(&key, seqno) {
-                if evict_tombstone {
-                    return Ok(ignore_tombstone_value(item));
-                }
                 return Ok(Some(item));
             }
         }
@@ -898,11 +1817,11 @@ impl Tree {
         let segment_lock = self.levels.read().expect("lock is poisoned");
         let segments = &segment_lock.get_all_segments_flattened();
 
+        // NOTE: Hash the key once, instead of once per segment
+        let key_hash = BloomFilter::get_hash(key.as_ref());
+
         for segment in segments {
-            if let Some(item) = segment.get(&key, seqno)? {
-                if evict_tombstone {
-                    return Ok(ignore_tombstone_value(item));
-                }
+            if let Some(item) = segment.get_with_hash(&key, seqno, key_hash)? {
                 return Ok(Some(item));
             }
         }
@@ -910,6 +1829,88 @@ impl Tree {
         Ok(None)
     }
 
+    /// Folds a merge operand with the older versions of its key into a value
+    ///
+    /// The older versions are looked up one by one, until a version is found
+    /// that is not a merge operand.
+    fn fold_merge_operands(
+        &self,
+        item: Value,
+        seqno: Option<SeqNo>,
+        merge_operator: &dyn MergeOperator,
+    ) -> crate::Result<Value> {
+        let now = unix_timestamp_millis();
+        let key = item.key.clone();
+        let mut oldest_seqno = item.seqno;
+        let mut operands = vec![item];
+
+        let base = loop {
+            let Some(version) = self.find_internal_entry(&key, Some(oldest_seqno))? else {
+                break None;
+            };
+
+            if matches!(
+                version.value_type,
+                ValueType::Tombstone | ValueType::RangeTombstone
+            ) || version.is_expired(now)
+                || self.is_covered_by_range_tombstone(&key, version.seqno, seqno)
+            {
+                break None;
+            }
+
+            match version.value_type {
+                ValueType::Merge => {
+                    oldest_seqno = version.seqno;
+                    operands.push(version);
+                }
+                ValueType::Indirection => break Some(self.blobs.resolve(version)?.value),
+                _ => break Some(version.value),
+            }
+        };
+
+        let merged = merge_operator.merge(
+            &key,
+            base.as_deref(),
+            &operands.iter().rev().map(|x| &*x.value).collect::<Vec<_>>(),
+        );
+
+        Ok(Value::new(key, merged, operands[0].seqno, ValueType::Value))
+    }
+
+    #[doc(hidden)]
+    pub fn get_internal_entry<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        evict_tombstone: bool,
+        seqno: Option<SeqNo>,
+    ) -> crate::Result<Option<Value>> {
+        let Some(mut item) = self.find_internal_entry(&key, seqno)? else {
+            return Ok(None);
+        };
+
+        if !item.is_tombstone()
+            && (item.is_expired(unix_timestamp_millis())
+                || self.is_covered_by_range_tombstone(key.as_ref(), item.seqno, seqno))
+        {
+            item = Value::new(item.key, vec![], item.seqno, ValueType::Tombstone);
+        }
+
+        if item.value_type == ValueType::Indirection {
+            item = self.blobs.resolve(item)?;
+        }
+
+        if item.value_type == ValueType::Merge {
+            if let Some(merge_operator) = &self.config.merge_operator {
+                item = self.fold_merge_operands(item, seqno, &**merge_operator)?;
+            }
+        }
+
+        if evict_tombstone {
+            return Ok(ignore_tombstone_value(item));
+        }
+        Ok(Some(item))
+    }
+
     /// Retrieves an item from the tree.
     ///
     /// # Examples
@@ -954,6 +1955,8 @@ impl Tree {
         expected: Option<&UserData>,
         next: Option<&UserData>,
     ) -> crate::Result<CompareAndSwapResult> {
+        self.check_writable()?;
+
         let key = key.as_ref();
 
         // NOTE: Not sure if this is the implementation
@@ -999,6 +2002,7 @@ impl Tree {
                                     value: next_value.clone(),
                                     seqno: self.increment_lsn(),
                                     value_type: ValueType::Value,
+                                    expires_at: self.default_expiry(),
                                 },
                             )?;
                         } else {
@@ -1009,6 +2013,7 @@ impl Tree {
                                     value: [].into(),
                                     seqno: self.increment_lsn(),
                                     value_type: ValueType::Tombstone,
+                                    expires_at: None,
                                 },
                             )?;
                         }
@@ -1046,6 +2051,7 @@ impl Tree {
                                 value: next_value.clone(),
                                 seqno: self.increment_lsn(),
                                 value_type: ValueType::Value,
+                                expires_at: self.default_expiry(),
                             },
                         )?;
                         Ok(Ok(()))
@@ -1183,12 +2189,17 @@ impl Tree {
         &self,
         target_size: u64,
     ) -> std::thread::JoinHandle<crate::Result<()>> {
+        if self.read_only {
+            return std::thread::spawn(|| Err(crate::Error::ReadOnly));
+        }
+
         let config = self.config();
         let levels = Arc::clone(&self.levels);
         let stop_signal = self.stop_signal.clone();
         let immutable_memtables = Arc::clone(&self.immutable_memtables);
         let open_snapshots = Arc::clone(&self.open_snapshots);
         let block_cache = Arc::clone(&self.block_cache);
+        let blobs = Arc::clone(&self.blobs);
 
         log::info!("Starting major compaction thread");
 
@@ -1207,13 +2218,87 @@ impl Tree {
                     &immutable_memtables,
                     &open_snapshots,
                     &block_cache,
+                    &blobs,
                     &payload,
                 )?;
+
+                // NOTE: Locks are only poisoned if another thread panicked while holding them
+                #[allow(clippy::expect_used)]
+                blobs.drop_unreferenced(&levels.write().expect("lock is poisoned"))?;
             }
             Ok(())
         })
     }
 
+    /// Runs garbage collection on blob files (see [`Config::blob_threshold`]).
+    ///
+    /// Blob files whose ratio of stale (overwritten or deleted) data is at least
+    /// `stale_threshold` are rewritten: all segments that point into them are compacted,
+    /// moving their live values into new blob files.
+    ///
+    /// Relocated items keep their seqnos, so they do not conflict with transactions,
+    /// and are not published to change subscribers.
+    ///
+    /// The old blob files are deleted once no segment points into them anymore.
+    ///
+    /// Returns the amount of relocated bytes.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an IO error occurs.
+    ///
+    /// # Panics
+    ///
+    /// Panics on lock poisoning
+    #[allow(clippy::expect_used)]
+    pub fn blob_gc(&self, stale_threshold: f32) -> crate::Result<u64> {
+        self.check_not_read_only()?;
+
+        let levels = self.levels.read().expect("lock is poisoned");
+        let stale_files = self.blobs.get_stale_files(&levels, stale_threshold);
+        let segments = levels.get_segments_referencing_blobs(&stale_files);
+        drop(levels);
+
+        if stale_files.is_empty() {
+            return Ok(0);
+        }
+
+        log::debug!(
+            "Relocating live values of {} blob files by compacting {} segments",
+            stale_files.len(),
+            segments.len()
+        );
+
+        self.blobs.mark_for_relocation(&stale_files);
+
+        let config = self.config();
+
+        let result = segments.into_iter().try_for_each(|(level, segment_id)| {
+            crate::compaction::worker::do_compaction(
+                &config,
+                &self.levels,
+                &self.stop_signal,
+                &self.immutable_memtables,
+                &self.open_snapshots,
+                &self.block_cache,
+                &self.blobs,
+                &crate::compaction::Input {
+                    segment_ids: vec![segment_id],
+                    dest_level: level,
+                    target_size: u64::MAX,
+                },
+            )
+        });
+
+        let relocated_bytes = self.blobs.unmark_for_relocation(&stale_files);
+        result?;
+
+        self.blobs
+            .drop_unreferenced(&self.levels.write().expect("lock is poisoned"))?;
+
+        Ok(relocated_bytes)
+    }
+
     /// Flushes the journal to disk, making sure all written data
     /// is persisted and crash-safe.
     ///
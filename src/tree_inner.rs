@@ -1,12 +1,14 @@
 use crate::{
-    block_cache::BlockCache, journal::Journal, levels::Levels, memtable::MemTable,
-    stop_signal::StopSignal, Config,
+    blob::BlobStore, block_cache::BlockCache, journal::Journal, levels::Levels, memtable::MemTable,
+    persistent_snapshot::PersistentSnapshots, secondary::JournalTail,
+    snapshot_tracker::SnapshotTracker, stop_signal::StopSignal, transaction::lock::LockManager,
+    Config,
 };
 use std::{
     collections::BTreeMap,
     sync::{
-        atomic::{AtomicU32, AtomicU64},
-        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU32, AtomicU64},
+        Arc, Mutex, RwLock,
     },
 };
 use std_semaphore::Semaphore;
@@ -34,6 +36,9 @@ pub struct TreeInner {
     /// Tree levels that contain segments
     pub(crate) levels: Arc<RwLock<Levels>>,
 
+    /// Blob files of separated values
+    pub(crate) blobs: Arc<BlobStore>,
+
     /// Concurrent block cache
     pub(crate) block_cache: Arc<BlockCache>,
 
@@ -43,11 +48,26 @@ pub struct TreeInner {
     /// Semaphore to notify compaction threads
     pub(crate) compaction_semaphore: Arc<Semaphore>,
 
-    /// Keeps track of open snapshots
-    pub(crate) open_snapshots: Arc<AtomicU32>,
+    /// Keeps track of the seqnos of open snapshots
+    pub(crate) open_snapshots: Arc<SnapshotTracker>,
+
+    /// Named snapshots that survive restarts
+    pub(crate) persistent_snapshots: PersistentSnapshots,
+
+    /// Key locks of pessimistic transactions
+    pub(crate) lock_manager: LockManager,
 
     /// Notifies compaction threads that the tree is dropping
     pub(crate) stop_signal: StopSignal,
+
+    /// If set, the tree only accepts writes that are applied from a leader
+    pub(crate) is_follower: AtomicBool,
+
+    /// If set, the tree does not modify its folder at all
+    pub(crate) read_only: bool,
+
+    /// Read positions in the journals, if the tree is read-only
+    pub(crate) journal_tail: Mutex<JournalTail>,
 }
 
 impl Drop for TreeInner {
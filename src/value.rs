@@ -1,4 +1,7 @@
-use crate::serde::{Deserializable, DeserializeError, Serializable, SerializeError};
+use crate::{
+    serde::{read_exact_len, Deserializable, DeserializeError, Serializable, SerializeError},
+    varint::{read_varint, write_varint},
+};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::{
     cmp::Reverse,
@@ -24,12 +27,33 @@ pub enum ValueType {
 
     /// Deleted value
     Tombstone,
+
+    /// Deleted key range
+    ///
+    /// The key is the (inclusive) start of the range, the value the (exclusive) end
+    /// of the range, or empty if the range is unbounded
+    ///
+    /// Only used to write range tombstones into the journal
+    RangeTombstone,
+
+    /// Merge operand
+    ///
+    /// Folded together with older versions of the key using the configured merge operator
+    Merge,
+
+    /// Value that is stored in a blob file
+    ///
+    /// The value is a pointer into the blob file
+    Indirection,
 }
 
 impl From<u8> for ValueType {
     fn from(value: u8) -> Self {
         match value {
             0 => Self::Value,
+            2 => Self::RangeTombstone,
+            3 => Self::Merge,
+            4 => Self::Indirection,
             _ => Self::Tombstone,
         }
     }
@@ -40,8 +64,46 @@ impl From<ValueType> for u8 {
         match value {
             ValueType::Value => 0,
             ValueType::Tombstone => 1,
+            ValueType::RangeTombstone => 2,
+            ValueType::Merge => 3,
+            ValueType::Indirection => 4,
+        }
+    }
+}
+
+/// Bit of the serialized value type that is set if an expiry timestamp follows
+const EXPIRY_FLAG: u8 = 0b1000_0000;
+
+/// Writes the value type, followed by the expiry timestamp, if there is one
+pub fn write_value_type<W: Write>(
+    writer: &mut W,
+    value_type: ValueType,
+    expires_at: Option<u64>,
+) -> std::io::Result<()> {
+    match expires_at {
+        Some(expires_at) => {
+            writer.write_u8(u8::from(value_type) | EXPIRY_FLAG)?;
+            writer.write_u64::<BigEndian>(expires_at)?;
+        }
+        None => {
+            writer.write_u8(u8::from(value_type))?;
         }
     }
+
+    Ok(())
+}
+
+/// Reads a value type and expiry timestamp that were written by [`write_value_type`]
+pub fn read_value_type<R: Read>(reader: &mut R) -> std::io::Result<(ValueType, Option<u64>)> {
+    let tag = reader.read_u8()?;
+
+    let expires_at = if tag & EXPIRY_FLAG == 0 {
+        None
+    } else {
+        Some(reader.read_u64::<BigEndian>()?)
+    };
+
+    Ok(((tag & !EXPIRY_FLAG).into(), expires_at))
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -49,6 +111,9 @@ pub struct ParsedInternalKey {
     pub user_key: UserKey,
     pub seqno: SeqNo,
     pub value_type: ValueType,
+
+    /// Expiry time as unix timestamp (in ms), if the item has a time-to-live
+    pub expires_at: Option<u64>,
 }
 
 impl std::fmt::Debug for ParsedInternalKey {
@@ -69,6 +134,7 @@ impl ParsedInternalKey {
             user_key: user_key.into(),
             seqno,
             value_type,
+            expires_at: None,
         }
     }
 
@@ -98,17 +164,22 @@ impl Ord for ParsedInternalKey {
 ///
 /// # Disk representation
 ///
-/// \[seqno; 8 bytes] \[tombstone; 1 byte] \[key length; 2 bytes] \[key; N bytes] \[value length; 2 bytes] \[value: N bytes]
+/// \[seqno; 8 bytes] \[tombstone; 1 byte] \[expiry; 8 bytes, optional] \[key length; varint] \[key; N bytes] \[value length; varint] \[value: N bytes]
+///
+/// The highest bit of the tombstone byte is set if the expiry timestamp is present.
+///
+/// In the legacy disk representation, the key and value lengths are stored as 2 bytes,
+/// and there is no expiry timestamp.
 #[derive(Clone, PartialEq, Eq)]
 pub struct Value {
     /// User-defined key - an arbitrary byte array
     ///
-    /// Supports up to 2^16 bytes
+    /// Supports up to 2^32 bytes
     pub key: UserKey,
 
     /// User-defined value - an arbitrary byte array
     ///
-    /// Supports up to 2^16 bytes
+    /// Supports up to 2^32 bytes
     pub value: UserData,
 
     /// Sequence number
@@ -116,6 +187,11 @@ pub struct Value {
 
     /// Tombstone marker - if this is true, the value has been deleted
     pub value_type: ValueType,
+
+    /// Expiry time as unix timestamp (in ms), if the item has a time-to-live
+    ///
+    /// Expired items are treated like tombstones.
+    pub expires_at: Option<u64>,
 }
 
 impl std::fmt::Debug for Value {
@@ -128,6 +204,9 @@ impl std::fmt::Debug for Value {
             match self.value_type {
                 ValueType::Value => "V",
                 ValueType::Tombstone => "T",
+                ValueType::RangeTombstone => "R",
+                ValueType::Merge => "M",
+                ValueType::Indirection => "I",
             },
             self.value
         )
@@ -143,6 +222,7 @@ impl From<(ParsedInternalKey, UserData)> for Value {
             seqno: key.seqno,
             value_type: key.value_type,
             value: val.1,
+            expires_at: key.expires_at,
         }
     }
 }
@@ -167,7 +247,7 @@ impl Value {
     ///
     /// # Panics
     ///
-    /// Panics if the key length is empty or greater than 2^16, or the value length is greater than 2^16
+    /// Panics if the key length is empty or greater than 2^32, or the value length is greater than 2^32
     pub fn new<K: Into<UserKey>, V: Into<UserData>>(
         key: K,
         value: V,
@@ -178,17 +258,31 @@ impl Value {
         let v = value.into();
 
         assert!(!k.is_empty());
-        assert!(k.len() <= u16::MAX.into());
-        assert!(v.len() <= u16::MAX.into());
+        assert!(u32::try_from(k.len()).is_ok());
+        assert!(u32::try_from(v.len()).is_ok());
 
         Self {
             key: k,
             value: v,
             value_type,
             seqno,
+            expires_at: None,
         }
     }
 
+    /// Sets the expiry time as unix timestamp (in ms)
+    #[must_use]
+    pub fn expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    /// Returns `true` if the item has expired at the given unix timestamp (in ms)
+    #[must_use]
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|x| x <= now)
+    }
+
     #[doc(hidden)]
     #[must_use]
     pub fn size(&self) -> usize {
@@ -210,6 +304,7 @@ impl From<Value> for ParsedInternalKey {
             user_key: val.key,
             seqno: val.seqno,
             value_type: val.value_type,
+            expires_at: val.expires_at,
         }
     }
 }
@@ -217,36 +312,73 @@ impl From<Value> for ParsedInternalKey {
 impl Serializable for Value {
     fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
         writer.write_u64::<BigEndian>(self.seqno)?;
-        writer.write_u8(u8::from(self.value_type))?;
+        write_value_type(writer, self.value_type, self.expires_at)?;
+
+        write_varint(writer, self.key.len() as u64)?;
+        writer.write_all(&self.key)?;
+
+        write_varint(writer, self.value.len() as u64)?;
+        writer.write_all(&self.value)?;
+
+        Ok(())
+    }
+}
+
+impl Deserializable for Value {
+    fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
+        let seqno = reader.read_u64::<BigEndian>()?;
+        let (value_type, expires_at) = read_value_type(reader)?;
+
+        let key_len = read_varint(reader)?;
+        let key = read_exact_len(reader, key_len)?;
+
+        let value_len = read_varint(reader)?;
+        let value = read_exact_len(reader, value_len)?;
+
+        Ok(Self::new(key, value, seqno, value_type).expires_at(expires_at))
+    }
+}
+
+/// [`Value`] in the [`Version::V0`](crate::version::Version::V0) disk representation,
+/// used to read segments that were written before lengths were varint-encoded
+///
+/// # Disk representation
+///
+/// \[seqno; 8 bytes] \[tombstone; 1 byte] \[key length; 2 bytes] \[key; N bytes] \[value length; 2 bytes] \[value: N bytes]
+#[derive(Clone)]
+pub struct ValueV0(pub Value);
+
+impl Serializable for ValueV0 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError> {
+        writer.write_u64::<BigEndian>(self.0.seqno)?;
+        writer.write_u8(u8::from(self.0.value_type))?;
 
         // NOTE: Truncation is okay and actually needed
         #[allow(clippy::cast_possible_truncation)]
-        writer.write_u16::<BigEndian>(self.key.len() as u16)?;
-        writer.write_all(&self.key)?;
+        writer.write_u16::<BigEndian>(self.0.key.len() as u16)?;
+        writer.write_all(&self.0.key)?;
 
         // NOTE: Truncation is okay and actually needed
         #[allow(clippy::cast_possible_truncation)]
-        writer.write_u16::<BigEndian>(self.value.len() as u16)?;
-        writer.write_all(&self.value)?;
+        writer.write_u16::<BigEndian>(self.0.value.len() as u16)?;
+        writer.write_all(&self.0.value)?;
 
         Ok(())
     }
 }
 
-impl Deserializable for Value {
+impl Deserializable for ValueV0 {
     fn deserialize<R: Read>(reader: &mut R) -> Result<Self, DeserializeError> {
         let seqno = reader.read_u64::<BigEndian>()?;
         let value_type = reader.read_u8()?.into();
 
         let key_len = reader.read_u16::<BigEndian>()?;
-        let mut key = vec![0; key_len.into()];
-        reader.read_exact(&mut key)?;
+        let key = read_exact_len(reader, key_len.into())?;
 
         let value_len = reader.read_u16::<BigEndian>()?;
-        let mut value = vec![0; value_len as usize];
-        reader.read_exact(&mut value)?;
+        let value = read_exact_len(reader, value_len.into())?;
 
-        Ok(Self::new(key, value, seqno, value_type))
+        Ok(Self(Value::new(key, value, seqno, value_type)))
     }
 }
 
@@ -272,4 +404,50 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_large_value() -> crate::Result<()> {
+        let value = Value::new(vec![1, 2, 3], vec![7; 100_000], 42, ValueType::Value);
+
+        let mut serialized = Vec::new();
+        value.serialize(&mut serialized)?;
+
+        let deserialized = Value::deserialize(&mut &serialized[..])?;
+        assert_eq!(value, deserialized);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_v0() -> crate::Result<()> {
+        let value = Value::new(vec![1, 2, 3], vec![4, 5, 6], 42, ValueType::Value);
+
+        let mut serialized = Vec::new();
+        ValueV0(value.clone()).serialize(&mut serialized)?;
+        assert_eq!(8 + 1 + 2 + 3 + 2 + 3, serialized.len());
+
+        let deserialized = ValueV0::deserialize(&mut &serialized[..])?;
+        assert_eq!(value, deserialized.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_expiry() -> crate::Result<()> {
+        let value =
+            Value::new(vec![1, 2, 3], vec![4, 5, 6], 42, ValueType::Value).expires_at(Some(1_000));
+
+        let mut serialized = Vec::new();
+        value.serialize(&mut serialized)?;
+        assert_eq!(8 + 1 + 8 + 1 + 3 + 1 + 3, serialized.len());
+
+        let deserialized = Value::deserialize(&mut &serialized[..])?;
+        assert_eq!(value, deserialized);
+        assert_eq!(ValueType::Value, deserialized.value_type);
+
+        assert!(!deserialized.is_expired(999));
+        assert!(deserialized.is_expired(1_000));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,97 @@
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+/// Maximum amount of bytes of a varint-encoded u64
+const MAX_LEN: usize = 10;
+
+/// Writes an unsigned integer using LEB128 (7 bits per byte, MSB = continuation bit)
+pub fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> std::io::Result<usize> {
+    let mut bytes_written = 0;
+
+    loop {
+        // NOTE: Truncation is okay and actually needed
+        #[allow(clippy::cast_possible_truncation)]
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        bytes_written += 1;
+
+        if value == 0 {
+            writer.write_u8(byte)?;
+            return Ok(bytes_written);
+        }
+
+        writer.write_u8(byte | 0x80)?;
+    }
+}
+
+/// Returns the amount of bytes [`write_varint`] writes for the given integer
+pub fn varint_len(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.div_ceil(7).max(1)
+}
+
+/// Reads an unsigned integer that was written by [`write_varint`]
+pub fn read_varint<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut value = 0;
+
+    for idx in 0..MAX_LEN {
+        let byte = reader.read_u8()?;
+        value |= u64::from(byte & 0x7F) << (7 * idx);
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "varint is too long",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn varint_round_trip() -> crate::Result<()> {
+        for value in [
+            0,
+            1,
+            127,
+            128,
+            300,
+            65_535,
+            65_536,
+            u64::from(u32::MAX),
+            u64::MAX,
+        ] {
+            let mut bytes = vec![];
+            let len = write_varint(&mut bytes, value)?;
+            assert_eq!(len, bytes.len());
+            assert_eq!(len, super::varint_len(value));
+
+            assert_eq!(value, read_varint(&mut &bytes[..])?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn varint_len() -> crate::Result<()> {
+        let mut bytes = vec![];
+        assert_eq!(1, write_varint(&mut bytes, 127)?);
+        assert_eq!(2, write_varint(&mut bytes, 128)?);
+        assert_eq!(MAX_LEN, write_varint(&mut bytes, u64::MAX)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn varint_too_long() {
+        let bytes = [0xFF; MAX_LEN + 1];
+        assert!(read_varint(&mut &bytes[..]).is_err());
+    }
+}
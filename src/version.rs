@@ -2,14 +2,35 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use serde::{Deserialize, Serialize};
 use std::io::Cursor;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum Version {
+    /// Keys and values use 2-byte length prefixes (up to 2^16 bytes)
     V0,
+
+    /// Keys and values use varint length prefixes (up to 2^32 bytes)
+    ///
+    /// This covers data blocks, index blocks, range tombstones, journals and blob files.
+    /// Blob files start with a version header.
+    V1,
+
+    /// Like [`Version::V1`], but segment data blocks store keys prefix-compressed,
+    /// with restart points to binary search in them
+    ///
+    /// Journals are still written in [`Version::V1`].
+    V2,
+
+    /// Like [`Version::V2`], but a segment is stored as a single file,
+    /// with a footer pointing to its index, filter and metadata sections
+    V3,
+
+    /// Like [`Version::V3`], but segment metadata is stored in a checksummed
+    /// binary encoding, instead of JSON
+    V4,
 }
 
 impl std::fmt::Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "0")
+        write!(f, "{}", u16::from(*self))
     }
 }
 
@@ -17,6 +38,10 @@ impl From<Version> for u16 {
     fn from(value: Version) -> Self {
         match value {
             Version::V0 => 0,
+            Version::V1 => 1,
+            Version::V2 => 2,
+            Version::V3 => 3,
+            Version::V4 => 4,
         }
     }
 }
@@ -26,6 +51,10 @@ impl TryFrom<u16> for Version {
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
             0 => Ok(Self::V0),
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            3 => Ok(Self::V3),
+            4 => Ok(Self::V4),
             _ => Err(()),
         }
     }
@@ -37,6 +66,10 @@ impl Version {
     }
 
     pub fn parse_file_header(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < usize::from(Self::len()) {
+            return None;
+        }
+
         if bytes[0..3] == [b'L', b'S', b'M'] {
             let slice = &bytes[3..5];
 
@@ -73,6 +106,19 @@ mod tests {
 
         let version = Version::parse_file_header(&buf).expect("should parse");
         assert_eq!(version, Version::V0);
+
+        let mut buf = vec![];
+        Version::V1.write_file_header(&mut buf).expect("can't fail");
+
+        let version = Version::parse_file_header(&buf).expect("should parse");
+        assert_eq!(version, Version::V1);
+        assert_eq!("1", version.to_string());
+    }
+
+    #[test]
+    pub fn version_parse_invalid() {
+        assert!(Version::parse_file_header(b"LSM").is_none());
+        assert!(Version::parse_file_header(&[0, 0, 0, 0, 0]).is_none());
     }
 
     #[test]
@@ -10,6 +10,7 @@ fn memtable_mvcc_point_read() -> lsm_tree::Result<()> {
         value: "hello-value-999991".as_bytes().into(),
         seqno: 0,
         value_type: ValueType::Value,
+        expires_at: None,
     });
 
     let item = memtable.get("hello-key-99999".as_bytes(), None);
@@ -23,6 +24,7 @@ fn memtable_mvcc_point_read() -> lsm_tree::Result<()> {
         value: "hello-value-999991-2".as_bytes().into(),
         seqno: 1,
         value_type: ValueType::Value,
+        expires_at: None,
     });
 
     let item = memtable.get("hello-key-99999".as_bytes(), None);
@@ -0,0 +1,96 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+const VERSION_COUNT: usize = 10;
+
+#[test]
+fn snapshot_gc_old_versions() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!(1_000))?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    let version_size = tree.disk_space()?;
+    let snapshot = tree.snapshot();
+    let snapshot_value = tree.get(5u64.to_be_bytes())?;
+
+    for _ in 0..VERSION_COUNT {
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!(1_000))?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+    }
+
+    let latest_value = tree.get(5u64.to_be_bytes())?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    // NOTE: Only the versions visible to the snapshot and the latest versions are kept
+    assert!(tree.disk_space()? < version_size * 3);
+
+    assert_eq!(ITEM_COUNT, snapshot.len()?);
+    assert_eq!(snapshot_value, snapshot.get(5u64.to_be_bytes())?);
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(latest_value, tree.get(5u64.to_be_bytes())?);
+
+    drop(snapshot);
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert!(tree.disk_space()? < version_size * 2);
+    assert_eq!(latest_value, tree.get(5u64.to_be_bytes())?);
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_gc_tombstone() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    tree.insert("a", "abc")?;
+    tree.insert("b", "abc")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    let snapshot = tree.snapshot();
+
+    tree.remove("a")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    // NOTE: The tombstone is compacted into the last level,
+    // but still needs to shadow the version that is kept for the snapshot
+    assert!(tree.get("a")?.is_none());
+    assert_eq!(1, tree.len()?);
+    assert_eq!(Some((*b"abc").into()), snapshot.get("a")?);
+    assert_eq!(2, snapshot.len()?);
+
+    drop(snapshot);
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert!(tree.get("a")?.is_none());
+    assert_eq!(1, tree.len()?);
+    assert_eq!(1, tree.approximate_len()?);
+
+    Ok(())
+}
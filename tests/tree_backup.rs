@@ -0,0 +1,189 @@
+use lsm_tree::{BackupEngine, Config};
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+fn dir_len(path: &std::path::Path) -> std::io::Result<usize> {
+    Ok(std::fs::read_dir(path)?.count())
+}
+
+#[test]
+fn tree_backup_incremental() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let backup_path = folder.path().join("backup");
+
+    let tree = Config::new(&path).blob_threshold(100).open()?;
+    let engine = BackupEngine::open(&backup_path)?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("a:{x}"), nanoid::nanoid!())?;
+        tree.insert(format!("b:{x}"), nanoid::nanoid!(200))?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    let first = engine.create_backup(&tree)?;
+    assert_eq!(0, first.id);
+    assert_eq!(1, first.segment_ids.len());
+    assert_eq!(1, first.blob_ids.len());
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("c:{x}"), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    // NOTE: These items are only in the journal
+    tree.insert("d", "abc")?;
+
+    let second = engine.create_backup(&tree)?;
+    assert_eq!(1, second.id);
+    assert_eq!(2, second.segment_ids.len());
+
+    // NOTE: The first segment is shared between both backups
+    assert!(second.segment_ids.contains(&first.segment_ids[0]));
+    assert_eq!(2, dir_len(&backup_path.join("segments"))?);
+    assert_eq!(1, dir_len(&backup_path.join("blobs"))?);
+
+    assert_eq!(vec![first.clone(), second.clone()], engine.list_backups()?);
+
+    let restored_path = folder.path().join("restored_0");
+    engine.restore_backup(first.id, &restored_path)?;
+    let restored = Config::new(&restored_path).open()?;
+    assert_eq!(ITEM_COUNT * 2, restored.len()?);
+    assert_eq!(tree.get("b:5")?, restored.get("b:5")?);
+    assert!(!restored.contains_key("c:5")?);
+
+    let restored_path = folder.path().join("restored_1");
+    engine.restore_backup(second.id, &restored_path)?;
+    let restored = Config::new(&restored_path).open()?;
+    assert_eq!(ITEM_COUNT * 3 + 1, restored.len()?);
+    assert_eq!(tree.get("b:5")?, restored.get("b:5")?);
+    assert!(restored.contains_key("c:5")?);
+    assert!(restored.contains_key("d")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_backup_purge() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let backup_path = folder.path().join("backup");
+
+    let tree = Config::new(&path).open()?;
+    let engine = BackupEngine::open(&backup_path)?;
+
+    for batch in 0..3 {
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(format!("{batch}:{x}"), nanoid::nanoid!())?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        engine.create_backup(&tree)?;
+    }
+    assert_eq!(3, dir_len(&backup_path.join("segments"))?);
+
+    // NOTE: Compaction replaces all segments, so the next backup shares nothing
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+    let latest = engine.create_backup(&tree)?;
+    assert_eq!(4, dir_len(&backup_path.join("segments"))?);
+
+    assert_eq!(3, engine.purge_old_backups(1)?);
+    assert_eq!(vec![latest.clone()], engine.list_backups()?);
+    assert_eq!(1, dir_len(&backup_path.join("segments"))?);
+    assert_eq!(0, engine.purge_old_backups(1)?);
+
+    let restored_path = folder.path().join("restored");
+    engine.restore_backup(latest.id, &restored_path)?;
+    let restored = Config::new(&restored_path).open()?;
+    assert_eq!(ITEM_COUNT * 3, restored.len()?);
+
+    assert!(engine.delete_backup(latest.id)?);
+    assert!(!engine.delete_backup(latest.id)?);
+    assert!(engine.list_backups()?.is_empty());
+    assert_eq!(0, dir_len(&backup_path.join("segments"))?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_backup_reopen() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let backup_path = folder.path().join("backup");
+
+    let tree = Config::new(&path).open()?;
+    tree.insert("a", "abc")?;
+
+    {
+        let engine = BackupEngine::open(&backup_path)?;
+        engine.create_backup(&tree)?;
+    }
+
+    // NOTE: Simulate a backup that crashed before writing its manifest
+    std::fs::create_dir_all(backup_path.join("backups").join("1"))?;
+
+    let engine = BackupEngine::open(&backup_path)?;
+    assert_eq!(1, engine.list_backups()?.len());
+    assert!(!backup_path.join("backups").join("1").exists());
+
+    let backup = engine.create_backup(&tree)?;
+    assert_eq!(1, backup.id);
+
+    let restored_path = folder.path().join("restored");
+    engine.restore_backup(backup.id, &restored_path)?;
+    assert!(engine.restore_backup(backup.id, &restored_path).is_err());
+    assert!(engine
+        .restore_backup(5, folder.path().join("restored_5"))
+        .is_err());
+
+    let restored = Config::new(&restored_path).open()?;
+    assert!(restored.contains_key("a")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_backup_concurrent() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let backup_path = folder.path().join("backup");
+
+    let tree = Config::new(&path).open()?;
+    let engine = BackupEngine::open(&backup_path)?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("a:{x}"), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    let tree_entries = dir_len(&path)?;
+
+    let mut ids = std::thread::scope(|scope| {
+        let handles = (0..4)
+            .map(|_| scope.spawn(|| engine.create_backup(&tree)))
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("should join").map(|info| info.id))
+            .collect::<lsm_tree::Result<Vec<_>>>()
+    })?;
+    ids.sort_unstable();
+
+    // NOTE: Every backup gets its own ID
+    assert_eq!(vec![0, 1, 2, 3], ids);
+    assert_eq!(4, engine.list_backups()?.len());
+
+    // NOTE: The temporary checkpoints are neither created in the tree folder, nor left behind
+    assert_eq!(tree_entries, dir_len(&path)?);
+    assert_eq!(3, dir_len(&backup_path)?);
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 10_000;
+
+#[test]
+fn tree_bloom_point_reads() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).block_size(1_024).open()?;
+
+        for x in (0..ITEM_COUNT as u64).map(|x| x * 2) {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        for x in (0..ITEM_COUNT as u64).map(|x| x * 2) {
+            assert!(tree.contains_key(x.to_be_bytes())?);
+            assert!(!tree.contains_key((x + 1).to_be_bytes())?);
+        }
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for x in (0..ITEM_COUNT as u64).map(|x| x * 2) {
+            assert!(tree.contains_key(x.to_be_bytes())?);
+            assert!(!tree.contains_key((x + 1).to_be_bytes())?);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_bloom_disabled() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .block_size(1_024)
+        .bloom_bits_per_key(0)
+        .open()?;
+
+    for x in (0..ITEM_COUNT as u64).map(|x| x * 2) {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    for x in (0..ITEM_COUNT as u64).map(|x| x * 2) {
+        assert!(tree.contains_key(x.to_be_bytes())?);
+        assert!(!tree.contains_key((x + 1).to_be_bytes())?);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use lsm_tree::{ChangeBatch, Config, Error, ValueType};
+use std::time::Duration;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+#[test]
+fn tree_change_feed_order() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let tree = Config::new(&folder).open()?;
+
+    tree.insert("before", "abc")?;
+
+    let mut subscription = tree.subscribe()?;
+
+    let threads = (0..4)
+        .map(|idx| {
+            let tree = tree.clone();
+
+            std::thread::spawn(move || {
+                for x in 0..ITEM_COUNT {
+                    tree.insert(format!("{idx}:{x}"), nanoid::nanoid!())?;
+                }
+                Ok::<_, lsm_tree::Error>(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().expect("should join")?;
+    }
+
+    let mut batch = tree.batch();
+    batch.insert("x", "abc");
+    batch.remove("y");
+    batch.commit()?;
+
+    let batches = (0..ITEM_COUNT * 4 + 1)
+        .map(|_| subscription.next().expect("should receive batch"))
+        .collect::<Vec<_>>();
+
+    // NOTE: Writes before subscribing are not delivered
+    assert_eq!(1, batches[0].seqno);
+
+    for window in batches.windows(2) {
+        assert_eq!(window[0].seqno + 1, window[1].seqno);
+    }
+
+    let last = batches.last().expect("should exist");
+    assert_eq!(2, last.items.len());
+    assert_eq!(ValueType::Value, last.items[0].value_type);
+    assert_eq!(ValueType::Tombstone, last.items[1].value_type);
+    assert!(last.items.iter().all(|item| item.seqno == last.seqno));
+
+    assert!(subscription.try_next().is_none());
+
+    drop(tree);
+    assert!(subscription.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn tree_change_feed_resume() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let tree = Config::new(&folder).max_retained_journals(2).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), "abc")?;
+    }
+    tree.wait_for_memtable_flush()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), "def")?;
+    }
+
+    let mut subscription = tree.subscribe_from(50)?;
+    tree.remove(0u64.to_be_bytes())?;
+
+    for seqno in 50..ITEM_COUNT as u64 * 2 + 1 {
+        let batch = subscription
+            .next_timeout(Duration::from_secs(1))
+            .expect("should receive batch");
+        assert_eq!(seqno, batch.seqno);
+    }
+    assert!(subscription.try_next().is_none());
+
+    // NOTE: Only the last 2 flushed journals are retained
+    for _ in 0..2 {
+        tree.insert("a", "abc")?;
+        tree.wait_for_memtable_flush()?;
+    }
+
+    assert!(matches!(
+        tree.subscribe_from(50),
+        Err(Error::ChangesNotRetained)
+    ));
+    assert_eq!(2, tree.subscribe_from(100)?.take(2).count());
+
+    Ok(())
+}
+
+#[test]
+fn tree_change_feed_not_retained() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), "abc")?;
+        }
+
+        // NOTE: Changes are read from the active journal
+        assert_eq!(ITEM_COUNT, tree.subscribe_from(0)?.take(ITEM_COUNT).count());
+
+        tree.wait_for_memtable_flush()?;
+
+        assert!(matches!(
+            tree.subscribe_from(0),
+            Err(Error::ChangesNotRetained)
+        ));
+        assert!(tree.subscribe_from(ITEM_COUNT as u64)?.try_next().is_none());
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        assert!(matches!(
+            tree.subscribe_from(0),
+            Err(Error::ChangesNotRetained)
+        ));
+
+        let mut subscription = tree.subscribe_from(ITEM_COUNT as u64)?;
+        tree.insert("a", "abc")?;
+        assert_eq!(
+            ITEM_COUNT as u64,
+            subscription.next().expect("should receive batch").seqno
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_change_feed_invalid_batch() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let tree = Config::new(&folder).open()?;
+
+    let mut subscription = tree.subscribe_from(0)?;
+    tree.insert("a", "abc")?;
+    tree.insert("b", "def")?;
+    let bytes = subscription
+        .next()
+        .expect("should receive batch")
+        .to_bytes()?;
+
+    // NOTE: Start marker claiming 2^32 - 1 items, without any items following
+    let oversized = [0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    for bytes in [&oversized[..], &bytes[..bytes.len() - 1], &bytes[..14]] {
+        assert!(matches!(
+            ChangeBatch::from_bytes(bytes),
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+#[test]
+fn tree_checkpoint() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let checkpoint_path = folder.path().join("checkpoint");
+
+    let tree = Config::new(&path).blob_threshold(100).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("a:{x}"), nanoid::nanoid!())?;
+        tree.insert(format!("b:{x}"), nanoid::nanoid!(200))?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    // NOTE: These items are only in the journal
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("c:{x}"), nanoid::nanoid!())?;
+    }
+    tree.remove_prefix("a:")?;
+    tree.create_persistent_snapshot("export")?;
+
+    tree.checkpoint(&checkpoint_path)?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("d:{x}"), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    // NOTE: Compaction deletes the segments of the tree, but not the checkpoint's
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(ITEM_COUNT * 3, tree.len()?);
+
+    let checkpoint = Config::new(&checkpoint_path).open()?;
+    assert_eq!(ITEM_COUNT * 2, checkpoint.len()?);
+    assert_eq!(0, checkpoint.prefix("a:").into_iter().count());
+    assert_eq!(ITEM_COUNT, checkpoint.prefix("b:").into_iter().count());
+    assert_eq!(ITEM_COUNT, checkpoint.prefix("c:").into_iter().count());
+    assert_eq!(0, checkpoint.prefix("d:").into_iter().count());
+    assert_eq!(tree.get("b:5")?, checkpoint.get("b:5")?);
+    assert_eq!(vec!["export"], checkpoint.persistent_snapshots());
+
+    // NOTE: The checkpoint is a tree of its own
+    checkpoint.insert("e", "abc")?;
+    assert!(!tree.contains_key("e")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_checkpoint_existing_folder() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let checkpoint_path = folder.path().join("checkpoint");
+
+    let tree = Config::new(&path).open()?;
+    tree.insert("a", "abc")?;
+
+    std::fs::create_dir_all(&checkpoint_path)?;
+    assert!(tree.checkpoint(&checkpoint_path).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tree_checkpoint_concurrent_writes() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let checkpoint_path = folder.path().join("checkpoint");
+
+    let tree = Config::new(&path).max_memtable_size(4_096).open()?;
+
+    let writer = {
+        let tree = tree.clone();
+
+        std::thread::spawn(move || -> lsm_tree::Result<()> {
+            for x in 0..ITEM_COUNT as u64 * 10 {
+                tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+            }
+            Ok(())
+        })
+    };
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    tree.checkpoint(&checkpoint_path)?;
+
+    writer.join().expect("should join")?;
+
+    let checkpoint = Config::new(&checkpoint_path).open()?;
+    let len = checkpoint.len()?;
+    assert!(len <= ITEM_COUNT * 10);
+
+    // NOTE: Items are written in order, so the checkpoint contains a prefix of them
+    for x in 0..len as u64 {
+        assert!(checkpoint.contains_key(x.to_be_bytes())?);
+    }
+
+    Ok(())
+}
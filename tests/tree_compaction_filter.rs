@@ -0,0 +1,145 @@
+use lsm_tree::{
+    compaction::{CompactionFilter, FilterContext, FilterDecision},
+    Config, Value,
+};
+use std::sync::{Arc, Mutex};
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+/// Drops tenant "a", uppercases tenant "b", keeps everything else
+#[derive(Default)]
+struct TenantFilter {
+    contexts: Mutex<Vec<FilterContext>>,
+}
+
+impl CompactionFilter for TenantFilter {
+    fn filter(&self, context: &FilterContext, item: &Value) -> FilterDecision {
+        self.contexts
+            .lock()
+            .expect("lock is poisoned")
+            .push(*context);
+
+        if item.key.starts_with(b"a:") {
+            FilterDecision::Drop
+        } else if item.key.starts_with(b"b:") {
+            FilterDecision::Rewrite(item.value.to_ascii_uppercase().into())
+        } else {
+            FilterDecision::Keep
+        }
+    }
+}
+
+fn fill_tree(tree: &lsm_tree::Tree) -> lsm_tree::Result<()> {
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("a:{x}"), "abc")?;
+        tree.insert(format!("b:{x}"), "abc")?;
+        tree.insert(format!("c:{x}"), "abc")?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    Ok(())
+}
+
+#[test]
+fn tree_compaction_filter() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let filter = Arc::new(TenantFilter::default());
+
+    let tree = Config::new(&folder)
+        .level_count(4)
+        .compaction_filter(filter.clone())
+        .open()?;
+
+    fill_tree(&tree)?;
+    assert_eq!(ITEM_COUNT * 3, tree.len()?);
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(ITEM_COUNT * 2, tree.len()?);
+    assert_eq!(0, tree.prefix("a:").into_iter().count());
+    assert_eq!(Some((*b"ABC").into()), tree.get("b:5")?);
+    assert_eq!(Some((*b"abc").into()), tree.get("c:5")?);
+
+    // NOTE: Dropped items are not written into the last level at all
+    assert_eq!(ITEM_COUNT as u64 * 2, tree.approximate_len()?);
+
+    let contexts = filter.contexts.lock().expect("lock is poisoned");
+    assert_eq!(ITEM_COUNT * 3, contexts.len());
+    assert!(contexts
+        .iter()
+        .all(|x| x.dest_level == 3 && x.is_last_level));
+
+    Ok(())
+}
+
+#[test]
+fn tree_compaction_filter_reload() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder)
+            .compaction_filter(Arc::new(TenantFilter::default()))
+            .open()?;
+
+        fill_tree(&tree)?;
+
+        tree.do_major_compaction(u64::MAX)
+            .join()
+            .expect("should join")?;
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(ITEM_COUNT * 2, tree.len()?);
+        assert!(tree.get("a:5")?.is_none());
+        assert_eq!(Some((*b"ABC").into()), tree.get("b:5")?);
+    }
+
+    Ok(())
+}
+
+/// Drops every item whose value starts with "x"
+struct ValueFilter;
+
+impl CompactionFilter for ValueFilter {
+    fn filter(&self, _: &FilterContext, item: &Value) -> FilterDecision {
+        if item.value.starts_with(b"x") {
+            FilterDecision::Drop
+        } else {
+            FilterDecision::Keep
+        }
+    }
+}
+
+#[test]
+fn tree_compaction_filter_blob() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .blob_threshold(64)
+        .compaction_filter(Arc::new(ValueFilter))
+        .open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("a:{x}"), [b'x'; 100])?;
+        tree.insert(format!("b:{x}"), [b'y'; 100])?;
+    }
+    tree.wait_for_memtable_flush()?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    // NOTE: The filter sees the values in the blob files, not the blob pointers
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert!(tree.get("a:5")?.is_none());
+    assert_eq!(Some([b'y'; 100].into()), tree.get("b:5")?);
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use lsm_tree::{CompressionType, Config};
+use test_log::test;
+
+const ITEM_COUNT: usize = 1_000;
+
+#[test]
+fn tree_compression_mixed() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder)
+            .block_size(1_024)
+            .compression(CompressionType::None)
+            .open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    {
+        let tree = Config::new(&folder)
+            .block_size(1_024)
+            .compression(CompressionType::Lz4)
+            .open()?;
+
+        for x in ITEM_COUNT as u64..(ITEM_COUNT * 2) as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        assert_eq!(2, tree.segment_count());
+        assert_eq!(ITEM_COUNT * 2, tree.len()?);
+        assert_eq!(ITEM_COUNT * 2, tree.iter().into_iter().rev().count());
+    }
+
+    {
+        let tree = Config::new(&folder)
+            .compression_per_level(vec![CompressionType::Lz4, CompressionType::None])
+            .open()?;
+
+        assert_eq!(ITEM_COUNT * 2, tree.len()?);
+
+        tree.do_major_compaction(u64::MAX)
+            .join()
+            .expect("should join")?;
+
+        assert_eq!(1, tree.segment_count());
+        assert_eq!(ITEM_COUNT * 2, tree.len()?);
+
+        for x in 0..(ITEM_COUNT * 2) as u64 {
+            assert!(tree.contains_key(x.to_be_bytes())?);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn tree_compression_zstd() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder)
+            .block_size(1_024)
+            .compression(CompressionType::Zstd(3))
+            .open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(ITEM_COUNT, tree.len()?);
+
+        for x in 0..ITEM_COUNT as u64 {
+            assert!(tree.contains_key(x.to_be_bytes())?);
+        }
+    }
+
+    Ok(())
+}
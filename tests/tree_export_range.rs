@@ -0,0 +1,102 @@
+use lsm_tree::Config;
+use std::time::Duration;
+use test_log::test;
+
+const ITEM_COUNT: usize = 1_000;
+
+#[test]
+fn tree_export_range() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let export_path = folder.path().join("export");
+
+    let src = Config::new(folder.path().join("src"))
+        .blob_threshold(100)
+        .open()?;
+
+    for tenant in ["a", "b", "c"] {
+        for x in 0..ITEM_COUNT as u64 {
+            src.insert(format!("{tenant}:{x:0>5}"), nanoid::nanoid!())?;
+        }
+    }
+    src.insert("b:large", nanoid::nanoid!(1_000))?;
+    src.flush()?;
+    src.wait_for_memtable_flush()?;
+
+    // NOTE: Deleted items are not exported
+    src.remove("b:00000")?;
+    src.remove_range("b:00010".."b:00020")?;
+
+    let count = src.export_range("b:".."c:", &export_path)?;
+    assert_eq!(ITEM_COUNT + 1 - 11, count);
+
+    let dest = Config::new(folder.path().join("dest")).open()?;
+    dest.insert("b:00001", "old")?;
+    dest.insert("z", "abc")?;
+
+    dest.ingest(&[&export_path])?;
+
+    assert_eq!(count + 1, dest.len()?);
+    assert_eq!(count, dest.prefix("b:").into_iter().count());
+    assert_eq!(src.get("b:00001")?, dest.get("b:00001")?);
+    assert_eq!(src.get("b:large")?, dest.get("b:large")?);
+    assert!(!dest.contains_key("b:00000")?);
+    assert!(!dest.contains_key("b:00015")?);
+    assert!(!dest.contains_key("a:00001")?);
+
+    for (src_item, dest_item) in src.prefix("b:").into_iter().zip(&dest.prefix("b:")) {
+        assert_eq!(src_item?, dest_item?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_export_range_empty() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let export_path = folder.path().join("export");
+
+    let tree = Config::new(folder.path().join("tree")).open()?;
+    tree.insert("a", "abc")?;
+
+    assert_eq!(0, tree.export_range("b".., &export_path)?);
+    assert!(!export_path.exists());
+
+    assert_eq!(1, tree.export_range("a"..="a", &export_path)?);
+    assert!(tree.export_range("a"..="a", &export_path).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tree_export_range_ttl() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let export_path = folder.path().join("export");
+
+    let src = Config::new(folder.path().join("src")).open()?;
+    src.insert("a", "abc")?;
+    src.insert_with_ttl("b", "def", Duration::from_secs(3_600))?;
+
+    assert_eq!(2, src.export_range::<&str, _, _>(.., &export_path)?);
+
+    let dest = Config::new(folder.path().join("dest")).open()?;
+    dest.ingest(&[&export_path])?;
+
+    let item = dest
+        .get_internal_entry("a", true, None)?
+        .expect("should exist");
+    assert_eq!(None, item.expires_at);
+
+    let expected = src
+        .get_internal_entry("b", true, None)?
+        .expect("should exist");
+    let item = dest
+        .get_internal_entry("b", true, None)?
+        .expect("should exist");
+    assert!(item.expires_at.is_some());
+    assert_eq!(expected.expires_at, item.expires_at);
+
+    // NOTE: Only src, dest and the exported segment, no temporary files are left behind
+    assert_eq!(3, std::fs::read_dir(folder.path())?.count());
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use lsm_tree::{ChangeBatch, Config, Error};
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+fn assert_same_items(a: &lsm_tree::Tree, b: &lsm_tree::Tree) -> lsm_tree::Result<()> {
+    let a = a.iter().into_iter().collect::<lsm_tree::Result<Vec<_>>>()?;
+    let b = b.iter().into_iter().collect::<lsm_tree::Result<Vec<_>>>()?;
+    assert_eq!(a, b);
+    Ok(())
+}
+
+#[test]
+fn tree_follower_replication() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let leader = Config::new(folder.path().join("leader")).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        leader.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+    leader.wait_for_memtable_flush()?;
+
+    let subscription = leader.subscribe()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        leader.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+
+    leader.checkpoint(folder.path().join("follower"))?;
+    let follower = Config::new(folder.path().join("follower")).open_follower()?;
+    assert!(follower.is_follower());
+
+    let mut batch = leader.batch();
+    batch.insert("a", "abc");
+    batch.insert("b", "abc");
+    batch.remove(0u64.to_be_bytes());
+    batch.commit()?;
+    leader.remove_range(10u64.to_be_bytes()..20u64.to_be_bytes())?;
+    leader.insert(u64::MAX.to_be_bytes(), "abc")?;
+
+    let mut applied = 0;
+
+    for batch in subscription.take(ITEM_COUNT + 3) {
+        // NOTE: Batches are shipped in the journal format
+        let batch = ChangeBatch::from_bytes(&batch.to_bytes()?)?;
+
+        if follower.apply_batch(&batch)? {
+            applied += 1;
+        }
+    }
+
+    // NOTE: The first batches were already contained in the checkpoint
+    assert_eq!(3, applied);
+    assert_eq!(leader.next_seqno(), follower.next_seqno());
+    assert_eq!(ITEM_COUNT - 11 + 3, follower.len()?);
+    assert_same_items(&leader, &follower)?;
+
+    Ok(())
+}
+
+#[test]
+fn tree_follower_read_only() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let follower = Config::new(&folder).open_follower()?;
+
+    assert!(matches!(follower.insert("a", "abc"), Err(Error::ReadOnly)));
+    assert!(matches!(follower.remove("a"), Err(Error::ReadOnly)));
+    assert!(matches!(
+        follower.compare_and_swap("a", None, Some(&"abc".as_bytes().into())),
+        Err(Error::ReadOnly)
+    ));
+    assert!(matches!(follower.remove_entry("a"), Err(Error::ReadOnly)));
+
+    let mut batch = follower.batch();
+    batch.insert("a", "abc");
+    assert!(matches!(batch.commit(), Err(Error::ReadOnly)));
+
+    let mut tx = follower.transaction();
+    tx.insert("a", "abc");
+    assert!(matches!(tx.commit(), Err(Error::ReadOnly)));
+
+    // NOTE: Rejected writes do not take a seqno
+    assert_eq!(0, follower.next_seqno());
+    assert!(follower.is_empty()?);
+
+    let batch = ChangeBatch {
+        seqno: 5,
+        items: vec![lsm_tree::Value::new(
+            *b"a",
+            *b"abc",
+            0,
+            lsm_tree::ValueType::Value,
+        )],
+    };
+
+    // NOTE: The batches before seqno 5 are missing
+    assert!(matches!(
+        follower.apply_batch(&batch),
+        Err(Error::ReplicationGap)
+    ));
+    assert_eq!(0, follower.next_seqno());
+
+    follower.apply_batch(&ChangeBatch { seqno: 0, ..batch })?;
+    assert_eq!(1, follower.next_seqno());
+
+    follower.promote()?;
+    assert!(!follower.is_follower());
+
+    follower.insert("b", "abc")?;
+    assert_eq!(2, follower.next_seqno());
+    assert_eq!(2, follower.len()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_follower_resume() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let leader = Config::new(folder.path().join("leader"))
+        .max_retained_journals(4)
+        .open()?;
+    let follower_path = folder.path().join("follower");
+
+    leader.insert("a", "abc")?;
+    leader.checkpoint(&follower_path)?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        leader.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+    leader.wait_for_memtable_flush()?;
+
+    {
+        let follower = Config::new(&follower_path).open_follower()?;
+
+        let subscription = leader.subscribe_from(follower.next_seqno())?;
+        for batch in subscription.take(ITEM_COUNT / 2) {
+            assert!(follower.apply_batch(&batch)?);
+        }
+    }
+
+    // NOTE: The follower continues after its last applied batch after reopening
+    let follower = Config::new(&follower_path).open_follower()?;
+    assert_eq!(ITEM_COUNT as u64 / 2 + 1, follower.next_seqno());
+
+    let subscription = leader.subscribe_from(follower.next_seqno())?;
+    for batch in subscription.take(ITEM_COUNT / 2) {
+        assert!(follower.apply_batch(&batch)?);
+    }
+
+    assert_eq!(leader.next_seqno(), follower.next_seqno());
+    assert_same_items(&leader, &follower)?;
+
+    Ok(())
+}
+
+#[test]
+fn tree_follower_persisted() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let follower = Config::new(&folder).open_follower()?;
+        assert!(follower.is_follower());
+    }
+
+    {
+        // NOTE: The follower mode survives reopening the tree normally
+        let follower = Config::new(&folder).open()?;
+        assert!(follower.is_follower());
+        assert!(matches!(follower.insert("a", "abc"), Err(Error::ReadOnly)));
+
+        follower.promote()?;
+        follower.insert("a", "abc")?;
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert!(!tree.is_follower());
+        assert!(tree.contains_key("a")?);
+    }
+
+    Ok(())
+}
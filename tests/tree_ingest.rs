@@ -0,0 +1,223 @@
+use lsm_tree::{BackupEngine, Config, SegmentBuilder};
+use test_log::test;
+
+const ITEM_COUNT: usize = 1_000;
+
+#[test]
+fn tree_ingest() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let segment_path = folder.path().join("segment");
+
+    let config = Config::new(&path);
+
+    let mut builder = SegmentBuilder::new(&segment_path, &config)?;
+    for x in 0..ITEM_COUNT as u64 {
+        builder.insert(x.to_be_bytes(), "ingested")?;
+    }
+    builder.finish()?;
+
+    let segment_size = std::fs::metadata(&segment_path)?.len();
+
+    {
+        let tree = config.clone().open()?;
+        tree.ingest(&[&segment_path])?;
+
+        // NOTE: The segment file is hard linked, not copied
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(2, std::fs::metadata(&segment_path)?.nlink());
+        }
+
+        assert_eq!(1, tree.segment_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+        assert_eq!(Some((*b"ingested").into()), tree.get(5u64.to_be_bytes())?);
+
+        // NOTE: Items written after the ingestion shadow the ingested items
+        tree.insert(5u64.to_be_bytes(), "new")?;
+        assert_eq!(Some((*b"new").into()), tree.get(5u64.to_be_bytes())?);
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    {
+        let tree = config.open()?;
+        assert_eq!(ITEM_COUNT, tree.len()?);
+        assert_eq!(Some((*b"new").into()), tree.get(5u64.to_be_bytes())?);
+        assert_eq!(Some((*b"ingested").into()), tree.get(6u64.to_be_bytes())?);
+    }
+
+    // NOTE: The built segment is left untouched
+    assert_eq!(segment_size, std::fs::metadata(&segment_path)?.len());
+
+    Ok(())
+}
+
+#[test]
+fn tree_ingest_shadowing() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let segment_path = folder.path().join("segment");
+
+    let config = Config::new(&path);
+    let tree = config.clone().open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), "old")?;
+    }
+    tree.wait_for_memtable_flush()?;
+
+    // NOTE: These items are still in the memtable
+    tree.insert(0u64.to_be_bytes(), "memtable")?;
+    tree.insert(u64::MAX.to_be_bytes(), "memtable")?;
+
+    let snapshot = tree.snapshot();
+
+    let mut builder = SegmentBuilder::new(&segment_path, &config)?;
+    for x in 0..ITEM_COUNT as u64 / 2 {
+        builder.insert(x.to_be_bytes(), "ingested")?;
+    }
+    builder.remove((ITEM_COUNT as u64 / 2).to_be_bytes())?;
+    builder.finish()?;
+
+    tree.ingest(&[&segment_path])?;
+
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(Some((*b"ingested").into()), tree.get(0u64.to_be_bytes())?);
+    assert_eq!(Some((*b"ingested").into()), tree.get(5u64.to_be_bytes())?);
+    assert!(!tree.contains_key((ITEM_COUNT as u64 / 2).to_be_bytes())?);
+    assert_eq!(
+        Some((*b"old").into()),
+        tree.get((ITEM_COUNT as u64 - 1).to_be_bytes())?
+    );
+    assert_eq!(
+        Some((*b"memtable").into()),
+        tree.get(u64::MAX.to_be_bytes())?
+    );
+
+    // NOTE: The snapshot was taken before the ingestion, so it does not see the ingested items
+    assert_eq!(ITEM_COUNT + 1, snapshot.len()?);
+    assert_eq!(
+        Some((*b"memtable").into()),
+        snapshot.get(0u64.to_be_bytes())?
+    );
+    assert_eq!(Some((*b"old").into()), snapshot.get(5u64.to_be_bytes())?);
+    drop(snapshot);
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(Some((*b"ingested").into()), tree.get(5u64.to_be_bytes())?);
+    assert!(!tree.contains_key((ITEM_COUNT as u64 / 2).to_be_bytes())?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_ingest_multiple() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+
+    let config = Config::new(&path);
+    let tree = config.clone().open()?;
+
+    let paths = (0..4u64)
+        .map(|idx| folder.path().join(format!("segment_{idx}")))
+        .collect::<Vec<_>>();
+
+    for (idx, segment_path) in paths.iter().enumerate() {
+        let mut builder = SegmentBuilder::new(segment_path, &config)?;
+        for x in 0..ITEM_COUNT as u64 {
+            builder.insert(format!("{idx}:{x:0>5}"), nanoid::nanoid!())?;
+        }
+        builder.finish()?;
+    }
+
+    tree.ingest(&paths)?;
+    assert_eq!(4, tree.segment_count());
+    assert_eq!(ITEM_COUNT * 4, tree.len()?);
+
+    // NOTE: Overlapping segments can not be ingested together
+    let overlap_paths = [
+        folder.path().join("overlap_a"),
+        folder.path().join("overlap_b"),
+    ];
+    for segment_path in &overlap_paths {
+        let mut builder = SegmentBuilder::new(segment_path, &config)?;
+        builder.insert("a", "abc")?;
+        builder.insert("b", "abc")?;
+        builder.finish()?;
+    }
+
+    assert!(tree.ingest(&overlap_paths).is_err());
+    assert_eq!(4, tree.segment_count());
+    assert!(!tree.contains_key("a")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_ingest_builder_unsorted() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let config = Config::new(folder.path().join("tree"));
+
+    let mut builder = SegmentBuilder::new(folder.path().join("segment"), &config)?;
+    builder.insert("b", "abc")?;
+    assert!(builder.insert("a", "abc").is_err());
+    assert!(builder.insert("b", "abc").is_err());
+    builder.insert("c", "abc")?;
+    builder.finish()?;
+
+    let builder = SegmentBuilder::new(folder.path().join("empty"), &config)?;
+    assert!(builder.finish().is_err());
+
+    assert!(SegmentBuilder::new(folder.path().join("segment"), &config).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn tree_ingest_checkpoint_backup() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let path = folder.path().join("tree");
+    let segment_path = folder.path().join("segment");
+
+    let config = Config::new(&path);
+    let tree = config.clone().open()?;
+
+    tree.insert(5u64.to_be_bytes(), "old")?;
+    tree.wait_for_memtable_flush()?;
+
+    let mut builder = SegmentBuilder::new(&segment_path, &config)?;
+    builder.insert(5u64.to_be_bytes(), "ingested")?;
+    builder.finish()?;
+
+    tree.ingest(&[&segment_path])?;
+
+    // NOTE: The rewritten metadata of the ingested segment needs to be carried over,
+    // otherwise the ingested item loses its seqno, and is shadowed by the old item
+    let checkpoint_path = folder.path().join("checkpoint");
+    tree.checkpoint(&checkpoint_path)?;
+
+    let checkpoint = Config::new(&checkpoint_path).open()?;
+    assert_eq!(
+        Some((*b"ingested").into()),
+        checkpoint.get(5u64.to_be_bytes())?
+    );
+
+    let engine = BackupEngine::open(folder.path().join("backups"))?;
+    let backup = engine.create_backup(&tree)?;
+
+    let restored_path = folder.path().join("restored");
+    engine.restore_backup(backup.id, &restored_path)?;
+
+    let restored = Config::new(&restored_path).open()?;
+    assert_eq!(
+        Some((*b"ingested").into()),
+        restored.get(5u64.to_be_bytes())?
+    );
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+const BLOB_THRESHOLD: u32 = 1_024;
+
+fn big_value(x: u64) -> Vec<u8> {
+    let mut value = x.to_be_bytes().to_vec();
+    value.resize(4_096, x as u8);
+    value
+}
+
+#[test]
+fn tree_kv_separation() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).blob_threshold(BLOB_THRESHOLD).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), big_value(x))?;
+    }
+    tree.insert("small", "abc")?;
+
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    assert_eq!(1, tree.segment_count());
+    assert_eq!(1, tree.blob_file_count());
+
+    assert_eq!(Some(big_value(7).into()), tree.get(7u64.to_be_bytes())?);
+    assert_eq!(Some((*b"abc").into()), tree.get("small")?);
+
+    assert_eq!(ITEM_COUNT + 1, tree.len()?);
+    assert_eq!(ITEM_COUNT + 1, tree.iter().into_iter().rev().count());
+
+    for item in &tree.range(0u64.to_be_bytes()..10u64.to_be_bytes()) {
+        let (key, value) = item?;
+        let x = u64::from_be_bytes((*key).try_into().expect("should be u64"));
+        assert_eq!(&*big_value(x), &*value);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_kv_separation_reload() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).blob_threshold(BLOB_THRESHOLD).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), big_value(x))?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        assert_eq!(1, tree.blob_file_count());
+    }
+
+    {
+        let tree = Config::new(&folder).blob_threshold(BLOB_THRESHOLD).open()?;
+
+        assert_eq!(1, tree.blob_file_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+        assert_eq!(Some(big_value(42).into()), tree.get(42u64.to_be_bytes())?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_kv_separation_major_compaction() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).blob_threshold(BLOB_THRESHOLD).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), big_value(x))?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    for x in 0..(ITEM_COUNT / 2) as u64 {
+        tree.remove(x.to_be_bytes())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    // NOTE: Compaction only rewrites the pointers, not the blob files
+    assert_eq!(1, tree.segment_count());
+    assert_eq!(1, tree.blob_file_count());
+    assert_eq!(ITEM_COUNT / 2, tree.len()?);
+    assert_eq!(Some(big_value(99).into()), tree.get(99u64.to_be_bytes())?);
+
+    tree.remove_range::<[u8; 8], _>(..)?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    // NOTE: No segment points into the blob file anymore
+    assert_eq!(0, tree.segment_count());
+    assert_eq!(0, tree.blob_file_count());
+
+    Ok(())
+}
+
+#[test]
+fn tree_kv_separation_blob_gc() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).blob_threshold(BLOB_THRESHOLD).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), big_value(x))?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    // NOTE: Overwrite 90% of the values
+    for x in 0..(ITEM_COUNT * 9 / 10) as u64 {
+        tree.insert(x.to_be_bytes(), "abc")?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(1, tree.blob_file_count());
+
+    // NOTE: Not stale enough
+    assert_eq!(0, tree.blob_gc(0.95)?);
+
+    let next_seqno = tree.next_seqno();
+    let mut subscription = tree.subscribe()?;
+
+    let relocated = tree.blob_gc(0.5)?;
+    assert_eq!(ITEM_COUNT as u64 / 10 * 4_096, relocated);
+
+    // NOTE: Relocated values keep their seqnos, and are not published as writes
+    assert_eq!(next_seqno, tree.next_seqno());
+    assert!(subscription.try_next().is_none());
+
+    let item = tree
+        .get_internal_entry(95u64.to_be_bytes(), true, None)?
+        .expect("should exist");
+    assert_eq!(95, item.seqno);
+
+    // NOTE: The old blob file is not referenced anymore
+    assert_eq!(1, tree.blob_file_count());
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(Some((*b"abc").into()), tree.get(5u64.to_be_bytes())?);
+    assert_eq!(Some(big_value(95).into()), tree.get(95u64.to_be_bytes())?);
+
+    // NOTE: Nothing is stale anymore
+    assert_eq!(0, tree.blob_gc(0.5)?);
+
+    Ok(())
+}
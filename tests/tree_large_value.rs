@@ -0,0 +1,119 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const VALUE_SIZE: usize = 200_000;
+
+#[test]
+fn tree_large_value() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        tree.insert("a", vec![1; VALUE_SIZE])?;
+        tree.insert("b", vec![2; VALUE_SIZE])?;
+        tree.flush()?;
+    }
+
+    {
+        // NOTE: Recover from journal
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(Some(vec![1; VALUE_SIZE].into()), tree.get("a")?);
+        assert_eq!(Some(vec![2; VALUE_SIZE].into()), tree.get("b")?);
+
+        tree.wait_for_memtable_flush()?;
+        assert_eq!(1, tree.segment_count());
+
+        assert_eq!(Some(vec![1; VALUE_SIZE].into()), tree.get("a")?);
+        assert_eq!(2, tree.len()?);
+    }
+
+    {
+        // NOTE: Recover from segment
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(Some(vec![2; VALUE_SIZE].into()), tree.get("b")?);
+        assert_eq!(2, tree.iter().into_iter().rev().count());
+    }
+
+    Ok(())
+}
+
+const KEY_SIZE: usize = 100_000;
+
+fn large_key(byte: u8) -> Vec<u8> {
+    vec![byte; KEY_SIZE]
+}
+
+#[test]
+fn tree_large_key() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for byte in 1..=5 {
+            tree.insert(large_key(byte), [byte])?;
+        }
+        tree.remove_range(large_key(2)..large_key(4))?;
+        tree.flush()?;
+    }
+
+    {
+        // NOTE: Recover from journal
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(Some([1].into()), tree.get(large_key(1))?);
+        assert_eq!(None, tree.get(large_key(2))?);
+
+        tree.wait_for_memtable_flush()?;
+        assert_eq!(1, tree.segment_count());
+
+        assert_eq!(Some([5].into()), tree.get(large_key(5))?);
+        assert_eq!(3, tree.len()?);
+    }
+
+    {
+        // NOTE: Recover from segment
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(None, tree.get(large_key(3))?);
+        assert_eq!(Some([4].into()), tree.get(large_key(4))?);
+
+        let keys = tree
+            .range(large_key(1)..large_key(5))
+            .into_iter()
+            .map(|item| item.map(|(key, _)| key.to_vec()))
+            .collect::<lsm_tree::Result<Vec<_>>>()?;
+        assert_eq!(vec![large_key(1), large_key(4)], keys);
+
+        assert_eq!(3, tree.iter().into_iter().rev().count());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_large_key_blob() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).blob_threshold(1_024).open()?;
+
+        tree.insert(large_key(1), vec![1; VALUE_SIZE])?;
+        tree.insert(large_key(2), vec![2; VALUE_SIZE])?;
+        tree.wait_for_memtable_flush()?;
+
+        assert_eq!(Some(vec![1; VALUE_SIZE].into()), tree.get(large_key(1))?);
+    }
+
+    {
+        let tree = Config::new(&folder).blob_threshold(1_024).open()?;
+
+        assert_eq!(Some(vec![2; VALUE_SIZE].into()), tree.get(large_key(2))?);
+        assert_eq!(2, tree.len()?);
+    }
+
+    Ok(())
+}
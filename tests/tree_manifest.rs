@@ -0,0 +1,120 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 10;
+
+fn segment_ids(folder: &std::path::Path) -> std::io::Result<Vec<String>> {
+    std::fs::read_dir(folder.join("segments"))?
+        .map(|dirent| Ok(dirent?.file_name().to_string_lossy().into_owned()))
+        .collect()
+}
+
+#[test]
+fn tree_manifest_reload() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for round in 0..3 {
+            for x in 0..ITEM_COUNT as u64 {
+                tree.insert(x.to_be_bytes(), format!("{round}"))?;
+            }
+            tree.wait_for_memtable_flush()?;
+        }
+        assert_eq!(3, tree.segment_count());
+
+        tree.do_major_compaction(u64::MAX)
+            .join()
+            .expect("should join")?;
+        assert_eq!(1, tree.segment_count());
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), "3")?;
+        }
+        tree.wait_for_memtable_flush()?;
+        assert_eq!(2, tree.segment_count());
+    }
+
+    assert!(folder.path().join("manifest").try_exists()?);
+    assert!(!folder.path().join("levels.json").try_exists()?);
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert_eq!(2, tree.segment_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+        assert_eq!(Some("3".as_bytes().into()), tree.get(0u64.to_be_bytes())?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_manifest_legacy_upgrade() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).level_count(3).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.wait_for_memtable_flush()?;
+    }
+
+    // NOTE: Replace the manifest log with a JSON manifest, as written by older versions
+    let ids = segment_ids(folder.path())?;
+    assert_eq!(1, ids.len());
+
+    std::fs::remove_file(folder.path().join("manifest"))?;
+    std::fs::write(
+        folder.path().join("levels.json"),
+        format!(r#"[["{}"],[],[]]"#, ids[0]),
+    )?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert_eq!(1, tree.segment_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    assert!(folder.path().join("manifest").try_exists()?);
+    assert!(!folder.path().join("levels.json").try_exists()?);
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert_eq!(1, tree.segment_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_manifest_corrupted() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.wait_for_memtable_flush()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.wait_for_memtable_flush()?;
+    }
+
+    // NOTE: Corrupt the first record, which is followed by other records
+    let path = folder.path().join("manifest");
+    let mut bytes = std::fs::read(&path)?;
+    bytes[15] ^= 1;
+    std::fs::write(&path, bytes)?;
+
+    assert!(Config::new(&folder).open().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use lsm_tree::{Config, MergeOperator, ValueType};
+use std::sync::Arc;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+struct Counter;
+
+impl MergeOperator for Counter {
+    fn merge(&self, _: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8> {
+        let mut counter = existing
+            .map(|x| u64::from_be_bytes(x.try_into().expect("should be u64")))
+            .unwrap_or_default();
+
+        for operand in operands {
+            counter += u64::from_be_bytes((*operand).try_into().expect("should be u64"));
+        }
+
+        counter.to_be_bytes().to_vec()
+    }
+}
+
+fn get_counter(tree: &lsm_tree::Tree, key: &str) -> lsm_tree::Result<Option<u64>> {
+    Ok(tree
+        .get(key)?
+        .map(|x| u64::from_be_bytes((*x).try_into().expect("should be u64"))))
+}
+
+#[test]
+fn tree_merge_operator_memtable() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .merge_operator(Arc::new(Counter))
+        .open()?;
+
+    for _ in 0..ITEM_COUNT {
+        tree.merge("a", 1u64.to_be_bytes())?;
+    }
+    assert_eq!(Some(ITEM_COUNT as u64), get_counter(&tree, "a")?);
+
+    tree.insert("b", 10u64.to_be_bytes())?;
+    tree.merge("b", 5u64.to_be_bytes())?;
+    assert_eq!(Some(15), get_counter(&tree, "b")?);
+
+    tree.remove("b")?;
+    tree.merge("b", 5u64.to_be_bytes())?;
+    assert_eq!(Some(5), get_counter(&tree, "b")?);
+
+    assert_eq!(2, tree.len()?);
+    assert_eq!(2, tree.iter().into_iter().rev().count());
+
+    Ok(())
+}
+
+#[test]
+fn tree_merge_operator_snapshot() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .merge_operator(Arc::new(Counter))
+        .open()?;
+
+    tree.merge("a", 1u64.to_be_bytes())?;
+    tree.merge("a", 1u64.to_be_bytes())?;
+
+    let snapshot = tree.snapshot();
+
+    tree.merge("a", 1u64.to_be_bytes())?;
+
+    assert_eq!(Some(3), get_counter(&tree, "a")?);
+    assert_eq!(
+        Some(2u64.to_be_bytes().to_vec()),
+        snapshot.get("a")?.map(|x| x.to_vec())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn tree_merge_operator_flush_compact() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder)
+            .merge_operator(Arc::new(Counter))
+            .open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(format!("{x:0>3}"), x.to_be_bytes())?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.merge(format!("{x:0>3}"), 1u64.to_be_bytes())?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.merge(format!("{x:0>3}"), 1u64.to_be_bytes())?;
+        }
+
+        assert_eq!(Some(2), get_counter(&tree, "000")?);
+        assert_eq!(Some(101), get_counter(&tree, "099")?);
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    {
+        let tree = Config::new(&folder)
+            .merge_operator(Arc::new(Counter))
+            .open()?;
+
+        assert_eq!(Some(51), get_counter(&tree, "049")?);
+
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+        tree.do_major_compaction(u64::MAX)
+            .join()
+            .expect("should join")?;
+
+        assert_eq!(1, tree.segment_count());
+        assert_eq!(Some(51), get_counter(&tree, "049")?);
+
+        // NOTE: Compaction folded the operands into a value
+        let item = tree
+            .get_internal_entry("049", true, None)?
+            .expect("should exist");
+        assert_eq!(ValueType::Value, item.value_type);
+
+        for (idx, item) in tree.prefix("0").into_iter().enumerate() {
+            let (_, value) = item?;
+            assert_eq!(
+                idx as u64 + 2,
+                u64::from_be_bytes((*value).try_into().expect("should be u64"))
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_merge_operator_range_tombstone() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .merge_operator(Arc::new(Counter))
+        .open()?;
+
+    tree.insert("a", 10u64.to_be_bytes())?;
+    tree.merge("a", 1u64.to_be_bytes())?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.remove_range("a"..="b")?;
+    tree.merge("a", 1u64.to_be_bytes())?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.merge("a", 1u64.to_be_bytes())?;
+
+    // NOTE: The range tombstone deleted the older versions
+    assert_eq!(Some(2), get_counter(&tree, "a")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_merge_operator_missing() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    assert!(matches!(
+        tree.merge("a", 1u64.to_be_bytes()),
+        Err(lsm_tree::Error::MergeOperatorMissing)
+    ));
+    assert!(tree.is_empty()?);
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+#[test]
+fn tree_persistent_snapshot_reload() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), "old")?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        tree.create_persistent_snapshot("export")?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), "new")?;
+        }
+        tree.flush()?;
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert_eq!(vec!["export"], tree.persistent_snapshots());
+
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        // NOTE: Old versions need to survive compaction after restart
+        tree.do_major_compaction(u64::MAX)
+            .join()
+            .expect("should join")?;
+
+        assert_eq!(1, tree.segment_count());
+
+        let snapshot = tree.persistent_snapshot("export").expect("should exist");
+        assert_eq!(ITEM_COUNT, snapshot.len()?);
+        assert_eq!(Some((*b"old").into()), snapshot.get(5u64.to_be_bytes())?);
+        assert_eq!(Some((*b"new").into()), tree.get(5u64.to_be_bytes())?);
+        drop(snapshot);
+
+        assert!(tree.delete_persistent_snapshot("export")?);
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert!(tree.persistent_snapshots().is_empty());
+        assert!(tree.persistent_snapshot("export").is_none());
+
+        tree.do_major_compaction(u64::MAX)
+            .join()
+            .expect("should join")?;
+
+        assert_eq!(ITEM_COUNT, tree.len()?);
+        assert_eq!(Some((*b"new").into()), tree.get(5u64.to_be_bytes())?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_persistent_snapshot_create_twice() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+
+    let snapshot = tree.create_persistent_snapshot("export")?;
+    tree.insert("b", "abc")?;
+
+    // NOTE: The existing snapshot is not moved
+    let snapshot2 = tree.create_persistent_snapshot("export")?;
+    assert_eq!(1, snapshot.len()?);
+    assert_eq!(1, snapshot2.len()?);
+
+    tree.create_persistent_snapshot("export2")?;
+    assert_eq!(vec!["export", "export2"], tree.persistent_snapshots());
+
+    assert!(tree.delete_persistent_snapshot("export")?);
+    assert!(!tree.delete_persistent_snapshot("export")?);
+    assert_eq!(vec!["export2"], tree.persistent_snapshots());
+
+    // NOTE: Opened snapshots stay readable
+    assert_eq!(1, snapshot.len()?);
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use lsm_tree::{Config, Error};
+use std::{sync::Arc, time::Duration};
+use test_log::test;
+
+const THREAD_COUNT: usize = 4;
+const INCREMENTS: u64 = 100;
+
+fn get_u64(value: Option<Arc<[u8]>>) -> u64 {
+    value
+        .map(|x| u64::from_be_bytes((*x).try_into().expect("should be u64")))
+        .unwrap_or_default()
+}
+
+#[test]
+fn tree_pessimistic_transaction_commit() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+    tree.insert("b", "def")?;
+
+    let mut tx = tree.pessimistic_transaction();
+    assert_eq!(Some((*b"abc").into()), tx.get_for_update("a")?);
+
+    tx.insert("c", "ghi")?;
+    tx.remove("b")?;
+
+    // NOTE: Read your own writes
+    assert_eq!(Some((*b"ghi").into()), tx.get("c")?);
+    assert!(tx.get("b")?.is_none());
+
+    // NOTE: Nothing is visible before commit
+    assert!(tree.get("c")?.is_none());
+    assert!(tree.get("b")?.is_some());
+
+    tx.commit()?;
+
+    assert_eq!(2, tree.len()?);
+    assert_eq!(Some((*b"ghi").into()), tree.get("c")?);
+    assert!(tree.get("b")?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn tree_pessimistic_transaction_rollback() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .lock_timeout(Duration::from_millis(10))
+        .open()?;
+
+    let mut tx = tree.pessimistic_transaction();
+    tx.insert("a", "abc")?;
+    tx.rollback();
+
+    assert!(tree.is_empty()?);
+
+    // NOTE: The lock is released after rollback
+    let mut tx = tree.pessimistic_transaction();
+    tx.insert("a", "def")?;
+    tx.commit()?;
+
+    assert_eq!(Some((*b"def").into()), tree.get("a")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_pessimistic_transaction_lock_timeout() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .lock_timeout(Duration::from_millis(50))
+        .open()?;
+
+    let mut tx1 = tree.pessimistic_transaction();
+    tx1.get_for_update("a")?;
+
+    let mut tx2 = tree.pessimistic_transaction();
+    assert!(matches!(tx2.get_for_update("a"), Err(Error::LockTimeout)));
+    assert!(matches!(tx2.insert("a", "abc"), Err(Error::LockTimeout)));
+
+    // NOTE: Reads without locking are not blocked
+    assert!(tx2.get("a")?.is_none());
+
+    tx1.commit()?;
+    tx2.insert("a", "abc")?;
+    tx2.commit()?;
+
+    assert_eq!(Some((*b"abc").into()), tree.get("a")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_pessimistic_transaction_deadlock() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .lock_timeout(Duration::from_secs(10))
+        .open()?;
+
+    let mut tx1 = tree.pessimistic_transaction();
+    tx1.insert("a", "tx1")?;
+
+    let mut tx2 = tree.pessimistic_transaction();
+    tx2.insert("b", "tx2")?;
+
+    let waiter = std::thread::spawn(move || -> lsm_tree::Result<()> {
+        // NOTE: Blocks until tx2 is rolled back
+        tx1.insert("b", "tx1")?;
+        tx1.commit()
+    });
+
+    // NOTE: Give tx1 time to start waiting for the lock of "b"
+    std::thread::sleep(Duration::from_millis(250));
+
+    assert!(matches!(tx2.insert("a", "tx2"), Err(Error::Deadlock)));
+    tx2.rollback();
+
+    waiter.join().expect("should join")?;
+
+    assert_eq!(Some((*b"tx1").into()), tree.get("a")?);
+    assert_eq!(Some((*b"tx1").into()), tree.get("b")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_pessimistic_transaction_concurrent_increments() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .lock_timeout(Duration::from_secs(10))
+        .open()?;
+
+    let threads = (0..THREAD_COUNT)
+        .map(|_| {
+            let tree = tree.clone();
+
+            std::thread::spawn(move || -> lsm_tree::Result<()> {
+                for _ in 0..INCREMENTS {
+                    let mut tx = tree.pessimistic_transaction();
+
+                    let counter = get_u64(tx.get_for_update("counter")?);
+                    tx.insert("counter", (counter + 1).to_be_bytes())?;
+
+                    tx.commit()?;
+                }
+
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().expect("should join")?;
+    }
+
+    assert_eq!(
+        THREAD_COUNT as u64 * INCREMENTS,
+        get_u64(tree.get("counter")?)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,253 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+#[test]
+fn tree_range_tombstone_memtable() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+
+    tree.remove_range(10u64.to_be_bytes()..20u64.to_be_bytes())?;
+
+    assert_eq!(ITEM_COUNT - 10, tree.len()?);
+    assert!(tree.contains_key(9u64.to_be_bytes())?);
+    assert!(!tree.contains_key(10u64.to_be_bytes())?);
+    assert!(!tree.contains_key(19u64.to_be_bytes())?);
+    assert!(tree.contains_key(20u64.to_be_bytes())?);
+
+    // NOTE: Newer items are not affected by the range tombstone
+    tree.insert(15u64.to_be_bytes(), "abc")?;
+    assert!(tree.contains_key(15u64.to_be_bytes())?);
+    assert_eq!(ITEM_COUNT - 9, tree.len()?);
+    assert_eq!(ITEM_COUNT - 9, tree.iter().into_iter().rev().count());
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_unbounded() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+
+    tree.remove_range(50u64.to_be_bytes()..)?;
+    assert_eq!(50, tree.len()?);
+
+    tree.remove_range::<&[u8], _>(..)?;
+    assert!(tree.is_empty()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_flush_reload() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        // NOTE: The range tombstone is flushed into a segment
+        // that does not overlap with the deleted items
+        tree.insert("z", "abc")?;
+        tree.remove_range(10u64.to_be_bytes()..=19u64.to_be_bytes())?;
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        assert_eq!(2, tree.segment_count());
+        assert_eq!(ITEM_COUNT - 10 + 1, tree.len()?);
+        assert!(!tree.contains_key(19u64.to_be_bytes())?);
+        assert!(tree.contains_key(20u64.to_be_bytes())?);
+        assert_eq!(
+            10,
+            tree.range(5u64.to_be_bytes()..25u64.to_be_bytes())
+                .into_iter()
+                .count()
+        );
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(ITEM_COUNT - 10 + 1, tree.len()?);
+        assert_eq!(ITEM_COUNT - 10 + 1, tree.iter().into_iter().rev().count());
+        assert!(!tree.contains_key(15u64.to_be_bytes())?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_journal_recover() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        tree.insert("a", "abc")?;
+        tree.insert("ab", "abc")?;
+        tree.insert("abc", "abc")?;
+        tree.insert("b", "abc")?;
+        tree.remove_range("ab".."b")?;
+
+        assert_eq!(2, tree.len()?);
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(2, tree.len()?);
+        assert_eq!(0, tree.prefix("ab").into_iter().count());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_snapshot() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+
+    let snapshot = tree.snapshot();
+
+    tree.remove_range::<[u8; 8], _>(..)?;
+    assert!(tree.is_empty()?);
+    assert_eq!(ITEM_COUNT, snapshot.len()?);
+
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert!(tree.is_empty()?);
+    assert_eq!(ITEM_COUNT, snapshot.len()?);
+    assert!(snapshot.get(5u64.to_be_bytes())?.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_major_compaction() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.remove_range(0u64.to_be_bytes()..50u64.to_be_bytes())?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(1, tree.segment_count());
+    assert_eq!(50, tree.len()?);
+
+    tree.remove_range::<[u8; 8], _>(..)?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(0, tree.segment_count());
+    assert!(tree.is_empty()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_recover_seqno() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        tree.insert("a", "abc")?;
+        tree.remove_range("a"..="z")?;
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert!(tree.is_empty()?);
+
+        // NOTE: The snapshot needs to see the range tombstone
+        let snapshot = tree.snapshot();
+        assert!(snapshot.get("a")?.is_none());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_invalid_key() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+
+    let seqno = tree.next_seqno();
+
+    assert!(tree.remove_range(""..="z").is_err());
+
+    // NOTE: No seqno is taken for rejected writes
+    assert_eq!(seqno, tree.next_seqno());
+    assert!(tree.contains_key("a")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_range_tombstone_other_segment() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    tree.insert("a", "abc")?;
+    tree.insert("b", "abc")?;
+    tree.insert("c", "abc")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    // NOTE: The tombstone is stored in a segment that does not contain any of the keys it deletes
+    tree.remove_range("b".."c")?;
+    tree.insert("z", "abc")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    assert_eq!(2, tree.segment_count());
+    assert_eq!(3, tree.range("a"..).into_iter().count());
+    assert_eq!(0, tree.range("b".."c").into_iter().count());
+    assert_eq!(0, tree.prefix("b").into_iter().count());
+    assert_eq!(1, tree.prefix("c").into_iter().count());
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use lsm_tree::{Config, Error};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+/// Returns the content of all files in a folder (recursively)
+fn read_folder(path: &Path) -> std::io::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+
+    for dirent in std::fs::read_dir(path)? {
+        let path = dirent?.path();
+
+        if path.is_dir() {
+            files.insert(path.clone(), vec![]);
+            files.extend(read_folder(&path)?);
+        } else {
+            files.insert(path.clone(), std::fs::read(&path)?);
+        }
+    }
+
+    Ok(files)
+}
+
+#[test]
+fn tree_read_only() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).blob_threshold(100).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!(200))?;
+        }
+        tree.wait_for_memtable_flush()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(format!("journal:{x}"), nanoid::nanoid!())?;
+        }
+        tree.remove(0u64.to_be_bytes())?;
+        tree.flush()?;
+    }
+
+    // NOTE: Simulate a crash during a flush, the journal is then read as an immutable memtable
+    let journal_folder = std::fs::read_dir(folder.path().join("journals"))?
+        .next()
+        .expect("should have journal")?
+        .path();
+    std::fs::File::create(journal_folder.join(".flush"))?;
+
+    let files_before = read_folder(folder.path())?;
+
+    {
+        let tree = Config::new(&folder).open_read_only()?;
+
+        assert_eq!(ITEM_COUNT * 2 - 1, tree.len()?);
+        assert!(tree.get(5u64.to_be_bytes())?.is_some());
+        assert!(!tree.contains_key(0u64.to_be_bytes())?);
+        assert_eq!(ITEM_COUNT as u64 * 2 + 1, tree.next_seqno());
+
+        assert!(matches!(tree.insert("a", "abc"), Err(Error::ReadOnly)));
+        assert!(matches!(tree.remove("a"), Err(Error::ReadOnly)));
+        assert!(matches!(tree.remove_range("a".."z"), Err(Error::ReadOnly)));
+        assert!(matches!(
+            tree.remove_entry("journal:5"),
+            Err(Error::ReadOnly)
+        ));
+
+        let mut batch = tree.batch();
+        batch.insert("a", "abc");
+        assert!(matches!(batch.commit(), Err(Error::ReadOnly)));
+
+        assert!(matches!(
+            tree.create_persistent_snapshot("snapshot"),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(tree.blob_gc(0.0), Err(Error::ReadOnly)));
+        assert!(matches!(
+            tree.wait_for_memtable_flush(),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(
+            tree.do_major_compaction(u64::MAX)
+                .join()
+                .expect("should join"),
+            Err(Error::ReadOnly)
+        ));
+
+        tree.flush()?;
+        assert_eq!(ITEM_COUNT * 2 - 1, tree.iter().into_iter().count());
+    }
+
+    assert_eq!(files_before, read_folder(folder.path())?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_read_only_live() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+
+    let files_before = read_folder(folder.path())?;
+
+    let read_only = Config::new(&folder).open_read_only()?;
+    assert_eq!(ITEM_COUNT, read_only.len()?);
+    assert_eq!(files_before, read_folder(folder.path())?);
+
+    // NOTE: The writer is not affected
+    tree.insert("a", "abc")?;
+    tree.wait_for_memtable_flush()?;
+    assert_eq!(ITEM_COUNT + 1, tree.len()?);
+    assert_eq!(ITEM_COUNT, read_only.len()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_read_only_corrupt_journal() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+        tree.insert("a", "abc")?;
+        tree.insert("b", "abc")?;
+    }
+
+    let journal_folder = std::fs::read_dir(folder.path().join("journals"))?
+        .next()
+        .expect("should have journal")?
+        .path();
+
+    for dirent in std::fs::read_dir(&journal_folder)? {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dirent?.path())?;
+        file.write_all(b"09pmu35w3a9mp53bao9upw3ab5up")?;
+        file.sync_all()?;
+    }
+
+    let files_before = read_folder(folder.path())?;
+
+    // NOTE: The corrupt bytes are ignored, but the journal is not truncated
+    let tree = Config::new(&folder).open_read_only()?;
+    assert_eq!(2, tree.len()?);
+    assert_eq!(files_before, read_folder(folder.path())?);
+
+    assert!(Config::new(folder.path().join("missing"))
+        .open_read_only()
+        .is_err());
+    assert!(!folder.path().join("missing").exists());
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+#[test]
+fn tree_remove_prefix() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("a:{x}"), nanoid::nanoid!())?;
+        tree.insert(format!("b:{x}"), nanoid::nanoid!())?;
+    }
+
+    let snapshot = tree.snapshot();
+
+    tree.remove_prefix("a:")?;
+
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(0, tree.prefix("a:").into_iter().count());
+    assert_eq!(ITEM_COUNT, tree.prefix("b:").into_iter().count());
+    assert!(tree.get("a:5")?.is_none());
+    assert!(tree.get("b:5")?.is_some());
+
+    assert_eq!(ITEM_COUNT, snapshot.prefix("a:").into_iter().count());
+    assert!(snapshot.get("a:5")?.is_some());
+
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(0, tree.prefix("a:").into_iter().count());
+    assert_eq!(ITEM_COUNT, snapshot.prefix("a:").into_iter().count());
+
+    Ok(())
+}
+
+#[test]
+fn tree_remove_prefix_drop_segment() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("a:{x}"), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(format!("b:{x}"), nanoid::nanoid!())?;
+    }
+    tree.remove_prefix("a:")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    assert_eq!(2, tree.segment_count());
+    assert_eq!(ITEM_COUNT, tree.len()?);
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(1, tree.segment_count());
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(0, tree.prefix("a:").into_iter().count());
+
+    Ok(())
+}
+
+#[test]
+fn tree_remove_prefix_reload() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(format!("a:{x}"), nanoid::nanoid!())?;
+            tree.insert(format!("b:{x}"), nanoid::nanoid!())?;
+        }
+        tree.flush()?;
+        tree.wait_for_memtable_flush()?;
+
+        tree.remove_prefix("b:")?;
+    }
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        assert_eq!(ITEM_COUNT, tree.len()?);
+        assert_eq!(0, tree.prefix("b:").into_iter().count());
+        assert_eq!(ITEM_COUNT, tree.iter().into_iter().rev().count());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use lsm_tree::{Config, Error};
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+
+#[test]
+fn tree_secondary_catch_up() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).blob_threshold(100).open()?;
+    tree.insert("a", "abc")?;
+    tree.flush()?;
+
+    let secondary = Config::new(&folder).open_read_only()?;
+    assert_eq!(1, secondary.len()?);
+
+    // NOTE: Writes to the active journal
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+
+    assert_eq!(1, secondary.len()?);
+    secondary.try_catch_up()?;
+    assert_eq!(ITEM_COUNT + 1, secondary.len()?);
+    assert_eq!(tree.next_seqno(), secondary.next_seqno());
+
+    // NOTE: Flushes, which rotate the journal and write blob files
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!(200))?;
+    }
+    tree.wait_for_memtable_flush()?;
+
+    tree.remove("a")?;
+    tree.flush()?;
+
+    secondary.try_catch_up()?;
+    assert_eq!(ITEM_COUNT, secondary.len()?);
+    assert!(!secondary.contains_key("a")?);
+    assert_eq!(
+        tree.get(5u64.to_be_bytes())?,
+        secondary.get(5u64.to_be_bytes())?
+    );
+    assert_eq!(tree.segment_count(), secondary.segment_count());
+
+    // NOTE: Compactions delete the old segments
+    tree.wait_for_memtable_flush()?;
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+    assert_eq!(1, tree.segment_count());
+
+    secondary.try_catch_up()?;
+    assert_eq!(1, secondary.segment_count());
+    assert_eq!(ITEM_COUNT, secondary.len()?);
+
+    for x in 0..ITEM_COUNT as u64 {
+        assert_eq!(tree.get(x.to_be_bytes())?, secondary.get(x.to_be_bytes())?);
+    }
+
+    assert!(matches!(secondary.insert("a", "abc"), Err(Error::ReadOnly)));
+
+    Ok(())
+}
+
+#[test]
+fn tree_secondary_concurrent_writer() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).max_memtable_size(4_000).open()?;
+    let secondary = Config::new(&folder).open_read_only()?;
+
+    let writer = {
+        let tree = tree.clone();
+
+        std::thread::spawn(move || {
+            for x in 0..ITEM_COUNT as u64 * 10 {
+                let mut batch = tree.batch();
+                batch.insert(x.to_be_bytes(), nanoid::nanoid!());
+                batch.commit()?;
+            }
+            Ok::<_, lsm_tree::Error>(())
+        })
+    };
+
+    let mut last_len = 0;
+
+    while !writer.is_finished() {
+        // NOTE: Catching up may fail if the writer keeps changing the tree
+        if secondary.try_catch_up().is_ok() {
+            // NOTE: Batches are inserted in order, so no write may get lost
+            let len = secondary.len()?;
+            assert!(len >= last_len);
+            last_len = len;
+        }
+    }
+
+    writer.join().expect("should join")?;
+
+    tree.wait_for_memtable_flush()?;
+    secondary.try_catch_up()?;
+    assert_eq!(ITEM_COUNT * 10, secondary.len()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_secondary_writable_noop() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+    tree.try_catch_up()?;
+    assert_eq!(1, tree.len()?);
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use lsm_tree::Config;
+use test_log::test;
+
+const ITEM_COUNT: usize = 1_000;
+
+#[test]
+fn tree_segment_single_file() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).block_size(1_024).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.remove_range(0u64.to_be_bytes()..10u64.to_be_bytes())?;
+        tree.wait_for_memtable_flush()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.wait_for_memtable_flush()?;
+
+        assert_eq!(2, tree.segment_count());
+    }
+
+    // NOTE: Every segment is stored as a single file
+    let segments = std::fs::read_dir(folder.path().join("segments"))?
+        .map(|dirent| dirent.map(|dirent| dirent.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(2, segments.len());
+    assert!(segments.iter().all(|path| path.is_file()));
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert_eq!(2, tree.segment_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+
+        tree.do_major_compaction(u64::MAX)
+            .join()
+            .expect("should join")?;
+        assert_eq!(1, tree.segment_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    let segments = std::fs::read_dir(folder.path().join("segments"))?
+        .map(|dirent| dirent.map(|dirent| dirent.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(1, segments.len());
+    assert!(segments[0].is_file());
+
+    {
+        let tree = Config::new(&folder).open()?;
+        assert_eq!(1, tree.segment_count());
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_segment_corrupted_metadata() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.wait_for_memtable_flush()?;
+    }
+
+    let segments = std::fs::read_dir(folder.path().join("segments"))?
+        .map(|dirent| dirent.map(|dirent| dirent.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(1, segments.len());
+
+    // NOTE: Flip a bit in the metadata section, which is located right before the footer
+    let mut bytes = std::fs::read(&segments[0])?;
+    let idx = bytes.len() - 100;
+    bytes[idx] ^= 1;
+    std::fs::write(&segments[0], bytes)?;
+
+    assert!(Config::new(&folder).open().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use lsm_tree::{Config, Error};
+use std::sync::Arc;
+use test_log::test;
+
+const THREAD_COUNT: usize = 4;
+const INCREMENTS: u64 = 100;
+
+fn get_u64(value: Option<Arc<[u8]>>) -> u64 {
+    value
+        .map(|x| u64::from_be_bytes((*x).try_into().expect("should be u64")))
+        .unwrap_or_default()
+}
+
+#[test]
+fn tree_transaction_commit() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+    tree.insert("b", "def")?;
+
+    let mut tx = tree.transaction();
+    assert_eq!(Some((*b"abc").into()), tx.get("a")?);
+
+    tx.insert("c", "ghi");
+    tx.remove("b");
+
+    // NOTE: Read your own writes
+    assert_eq!(Some((*b"ghi").into()), tx.get("c")?);
+    assert!(!tx.contains_key("b")?);
+
+    // NOTE: Nothing is visible before commit
+    assert!(tree.get("c")?.is_none());
+    assert!(tree.get("b")?.is_some());
+
+    tx.commit()?;
+
+    assert_eq!(2, tree.len()?);
+    assert_eq!(Some((*b"ghi").into()), tree.get("c")?);
+    assert!(tree.get("b")?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn tree_transaction_conflict() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+
+    let mut tx = tree.transaction();
+    tx.get("a")?;
+    tx.insert("b", "def");
+
+    tree.remove("a")?;
+
+    assert!(matches!(tx.commit(), Err(Error::TransactionConflict)));
+    assert!(tree.get("b")?.is_none());
+
+    // NOTE: A key that did not exist when it was read
+    let mut tx = tree.transaction();
+    assert!(tx.get("c")?.is_none());
+    tx.insert("c", "ghi");
+
+    tree.insert("c", "xyz")?;
+
+    assert!(matches!(tx.commit(), Err(Error::TransactionConflict)));
+    assert_eq!(Some((*b"xyz").into()), tree.get("c")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_transaction_conflict_range_tombstone() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+
+    let mut tx = tree.transaction();
+    tx.get("a")?;
+    tx.insert("b", "def");
+
+    tree.remove_prefix("a")?;
+
+    assert!(matches!(tx.commit(), Err(Error::TransactionConflict)));
+
+    Ok(())
+}
+
+#[test]
+fn tree_transaction_no_conflict() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+    tree.insert("a", "abc")?;
+
+    let mut tx = tree.transaction();
+    tx.get("a")?;
+    tx.insert("b", "def");
+
+    // NOTE: Keys that were not read do not conflict
+    tree.insert("b", "xyz")?;
+    tree.insert("c", "xyz")?;
+
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tx.commit()?;
+    assert_eq!(Some((*b"def").into()), tree.get("b")?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_transaction_concurrent_increments() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    let threads = (0..THREAD_COUNT)
+        .map(|_| {
+            let tree = tree.clone();
+
+            std::thread::spawn(move || -> lsm_tree::Result<()> {
+                for _ in 0..INCREMENTS {
+                    loop {
+                        let mut tx = tree.transaction();
+
+                        let counter = get_u64(tx.get("counter")?);
+                        tx.insert("counter", (counter + 1).to_be_bytes());
+
+                        match tx.commit() {
+                            Ok(()) => break,
+                            Err(Error::TransactionConflict) => continue,
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().expect("should join")?;
+    }
+
+    assert_eq!(
+        THREAD_COUNT as u64 * INCREMENTS,
+        get_u64(tree.get("counter")?)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use lsm_tree::{compaction::Fifo, Config};
+use std::time::Duration;
+use test_log::test;
+
+const ITEM_COUNT: usize = 100;
+const TTL: Duration = Duration::from_millis(500);
+
+#[test]
+fn tree_ttl() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert_with_ttl(format!("a:{x}"), nanoid::nanoid!(), TTL)?;
+        tree.insert(format!("b:{x}"), nanoid::nanoid!())?;
+    }
+
+    assert_eq!(ITEM_COUNT * 2, tree.len()?);
+    assert!(tree.get("a:5")?.is_some());
+
+    std::thread::sleep(TTL);
+
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert_eq!(ITEM_COUNT, tree.iter().into_iter().rev().count());
+    assert_eq!(0, tree.prefix("a:").into_iter().count());
+    assert_eq!(0, tree.range("a:0".."a:9").into_iter().count());
+    assert!(tree.get("a:5")?.is_none());
+    assert!(tree.get("b:5")?.is_some());
+
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    assert_eq!(ITEM_COUNT, tree.len()?);
+    assert!(tree.get("a:5")?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn tree_ttl_shadows_older_version() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    tree.insert("a", "old")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.insert_with_ttl("a", "new", TTL)?;
+    assert_eq!(Some((*b"new").into()), tree.get("a")?);
+
+    std::thread::sleep(TTL);
+
+    assert!(tree.get("a")?.is_none());
+    assert_eq!(0, tree.len()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_ttl_default() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    {
+        let tree = Config::new(&folder).default_ttl(TTL).open()?;
+
+        for x in 0..ITEM_COUNT as u64 {
+            tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+        }
+        tree.flush()?;
+
+        assert_eq!(ITEM_COUNT, tree.len()?);
+    }
+
+    std::thread::sleep(TTL);
+
+    {
+        // NOTE: Expiry needs to survive journal recovery
+        let tree = Config::new(&folder).open()?;
+        assert_eq!(0, tree.len()?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tree_ttl_major_compaction() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder).open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert_with_ttl(format!("a:{x}"), nanoid::nanoid!(), TTL)?;
+        tree.insert(format!("b:{x}"), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    std::thread::sleep(TTL);
+
+    tree.do_major_compaction(u64::MAX)
+        .join()
+        .expect("should join")?;
+
+    assert_eq!(1, tree.segment_count());
+    assert_eq!(ITEM_COUNT, tree.len()?);
+
+    // NOTE: Expired items are physically dropped
+    assert_eq!(ITEM_COUNT as u64, tree.approximate_len()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_ttl_fifo_drop_segment() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .compaction_strategy(Fifo::new(u64::MAX))
+        .default_ttl(TTL)
+        .open()?;
+
+    for x in 0..ITEM_COUNT as u64 {
+        tree.insert(x.to_be_bytes(), nanoid::nanoid!())?;
+    }
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    assert_eq!(1, tree.segment_count());
+
+    std::thread::sleep(TTL);
+
+    tree.insert("a", "b")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    // NOTE: The flush starts the compaction thread in the background
+    for _ in 0..100 {
+        if tree.segment_count() == 1 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // NOTE: The expired segment is dropped without being compacted
+    assert_eq!(1, tree.segment_count());
+    assert_eq!(1, tree.len()?);
+
+    Ok(())
+}
+
+#[test]
+fn tree_ttl_fifo_keep_shadowing_segment() -> lsm_tree::Result<()> {
+    let folder = tempfile::tempdir()?;
+
+    let tree = Config::new(&folder)
+        .compaction_strategy(Fifo::new(u64::MAX))
+        .open()?;
+
+    tree.insert("a", "old")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    tree.insert_with_ttl("a", "new", TTL)?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+
+    std::thread::sleep(TTL);
+
+    // NOTE: The flush starts the compaction thread in the background
+    tree.insert("b", "b")?;
+    tree.flush()?;
+    tree.wait_for_memtable_flush()?;
+    std::thread::sleep(Duration::from_millis(200));
+
+    // NOTE: The expired segment still shadows the older version
+    assert_eq!(3, tree.segment_count());
+    assert!(tree.get("a")?.is_none());
+    assert_eq!(1, tree.len()?);
+
+    Ok(())
+}